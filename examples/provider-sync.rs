@@ -69,7 +69,7 @@ async fn add_items_and_sync_again(provider: &mut CalDavProvider) {
 
     // ...and add a task in it
     let new_name = "This is a new task in a new calendar";
-    let new_task = Task::new(String::from(new_name), true, &new_calendar_url);
+    let new_task = Task::new(String::from(new_name), true, &new_calendar_url).unwrap();
     provider
         .local()
         .get_calendar(&new_calendar_url)
@@ -84,7 +84,7 @@ async fn add_items_and_sync_again(provider: &mut CalDavProvider) {
     // Also create a task in a previously existing calendar
     let changed_calendar_url: Url = EXAMPLE_EXISTING_CALENDAR_URL.parse().unwrap();
     let new_task_name = "This is a new task we're adding as an example, with ÜTF-8 characters";
-    let new_task = Task::new(String::from(new_task_name), false, &changed_calendar_url);
+    let new_task = Task::new(String::from(new_task_name), false, &changed_calendar_url).unwrap();
     let new_url = new_task.url().clone();
     provider
         .local()
@@ -0,0 +1,167 @@
+//! Management of several accounts, each one being synced by its own [`Provider`]
+//!
+//! Usually, every account is a [`CalDavProvider`](crate::CalDavProvider), with its own server credentials and its own cache folder.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::error::KFResult;
+use crate::provider::sync_progress::SyncResult;
+use crate::provider::Provider;
+use crate::traits::{CalDavSource, CompleteCalendar, DavCalendar};
+
+pub mod unified;
+use unified::UnifiedView;
+
+/// A local calendar, and the account it belongs to
+#[derive(Debug)]
+pub struct AccountCalendar<T> {
+    pub account_id: String,
+    pub url: Url,
+    pub calendar: Arc<Mutex<T>>,
+}
+
+/// A set of accounts, identified by unique IDs
+pub struct AccountManager<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    /// The accounts, in the order they have been added
+    accounts: Vec<(String, Provider<L, T, R, U>)>,
+    /// The views to refresh after every sync
+    views: Vec<Weak<Mutex<UnifiedView>>>,
+}
+
+impl<L, T, R, U> Default for AccountManager<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    fn default() -> Self {
+        Self {
+            accounts: Vec::new(),
+            views: Vec::new(),
+        }
+    }
+}
+
+impl<L, T, R, U> AccountManager<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an account. Adding an account with an existing ID replaces (and returns) the former one
+    pub fn add_account(
+        &mut self,
+        account_id: String,
+        provider: Provider<L, T, R, U>,
+    ) -> Option<Provider<L, T, R, U>> {
+        match self.accounts.iter_mut().find(|(id, _)| *id == account_id) {
+            Some(existing) => Some(std::mem::replace(&mut existing.1, provider)),
+            None => {
+                self.accounts.push((account_id, provider));
+                None
+            }
+        }
+    }
+
+    /// Remove an account, and return its provider
+    pub fn remove_account(&mut self, account_id: &str) -> Option<Provider<L, T, R, U>> {
+        let index = self.accounts.iter().position(|(id, _)| id == account_id)?;
+        Some(self.accounts.remove(index).1)
+    }
+
+    /// The IDs of the accounts, in the order they have been added
+    pub fn account_ids(&self) -> impl Iterator<Item = &str> {
+        self.accounts.iter().map(|(id, _)| id.as_str())
+    }
+
+    pub fn account(&self, account_id: &str) -> Option<&Provider<L, T, R, U>> {
+        self.accounts
+            .iter()
+            .find(|(id, _)| id == account_id)
+            .map(|(_, provider)| provider)
+    }
+
+    pub fn account_mut(&mut self, account_id: &str) -> Option<&mut Provider<L, T, R, U>> {
+        self.accounts
+            .iter_mut()
+            .find(|(id, _)| id == account_id)
+            .map(|(_, provider)| provider)
+    }
+
+    /// Sync every account, one after the other. See [`Provider::sync_with_result`]
+    pub async fn sync_all(&mut self) -> HashMap<String, SyncResult> {
+        let mut results = HashMap::new();
+        for (id, provider) in &mut self.accounts {
+            results.insert(id.clone(), provider.sync_with_result(None).await);
+        }
+        self.refresh_views().await;
+        results
+    }
+
+    /// Sync every account at the same time. See [`Provider::sync_with_result`]
+    pub async fn sync_all_concurrently(&mut self) -> HashMap<String, SyncResult> {
+        let syncs = self.accounts.iter_mut().map(|(id, provider)| async move {
+            (id.clone(), provider.sync_with_result(None).await)
+        });
+        let results = futures_util::future::join_all(syncs)
+            .await
+            .into_iter()
+            .collect();
+        self.refresh_views().await;
+        results
+    }
+
+    /// Refresh a view after every sync (see [`Self::sync_all`] and [`Self::sync_all_concurrently`]), until it is dropped
+    pub fn register_view(&mut self, view: &Arc<Mutex<UnifiedView>>) {
+        self.views.push(Arc::downgrade(view));
+    }
+
+    async fn refresh_views(&mut self) {
+        self.views.retain(|view| view.strong_count() > 0);
+        for view in self.views.iter().filter_map(|view| view.upgrade()) {
+            if let Err(err) = view.lock().await.refresh(self).await {
+                log::warn!("Unable to refresh a unified view: {}", err);
+            }
+        }
+    }
+
+    /// The local calendars of every account, sorted by account (in the order they have been added) then by URL
+    pub async fn calendars(&self) -> KFResult<Vec<AccountCalendar<T>>> {
+        let mut all = Vec::new();
+        for (id, provider) in &self.accounts {
+            let mut calendars: Vec<(Url, Arc<Mutex<T>>)> = provider
+                .local()
+                .get_calendars()
+                .await?
+                .into_iter()
+                .collect();
+            calendars.sort_by(|a, b| a.0.cmp(&b.0));
+            all.extend(
+                calendars
+                    .into_iter()
+                    .map(|(url, calendar)| AccountCalendar {
+                        account_id: id.clone(),
+                        url,
+                        calendar,
+                    }),
+            );
+        }
+        Ok(all)
+    }
+}
@@ -0,0 +1,160 @@
+//! A read-only view of the tasks of several calendars, across accounts
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use chrono::{DateTime, TimeZone, Utc};
+use url::Url;
+
+use crate::accounts::AccountManager;
+use crate::error::KFResult;
+use crate::item::Item;
+use crate::traits::{CalDavSource, CompleteCalendar, DavCalendar};
+use crate::utils::sync::{SyncStatus, Syncable};
+use crate::Task;
+
+/// A task of a [`UnifiedView`], and where it comes from
+#[derive(Clone, Debug)]
+pub struct UnifiedTask {
+    pub account_id: String,
+    pub calendar_url: Url,
+    pub task: Task,
+}
+
+/// The tasks of selected calendars (from any account), merged together.
+///
+/// Tasks that have the same UID (e.g. because a calendar is shared between two accounts) are only listed once, using their most recently modified version.
+/// Tasks that are marked for deletion are not listed.
+///
+/// This is a snapshot, which is refreshed by [`UnifiedView::refresh`], and automatically after every sync in case it has been registered with [`AccountManager::register_view`]
+#[derive(Clone, Debug, Default)]
+pub struct UnifiedView {
+    /// The `(account ID, calendar URL)` of the selected calendars. `None` selects every calendar
+    selection: Option<HashSet<(String, Url)>>,
+    tasks: Vec<UnifiedTask>,
+}
+
+impl UnifiedView {
+    /// A view of every calendar of every account
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// A view of some calendars, given as `(account ID, calendar URL)`
+    pub fn new(calendars: Vec<(String, Url)>) -> Self {
+        Self {
+            selection: Some(calendars.into_iter().collect()),
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Whether this view shows the tasks of this calendar
+    pub fn is_selected(&self, account_id: &str, calendar_url: &Url) -> bool {
+        match &self.selection {
+            None => true,
+            Some(selection) => selection.contains(&(account_id.to_string(), calendar_url.clone())),
+        }
+    }
+
+    /// Get the current content of the selected calendars
+    pub async fn refresh<L, T, R, U>(
+        &mut self,
+        accounts: &AccountManager<L, T, R, U>,
+    ) -> KFResult<()>
+    where
+        L: CalDavSource<T>,
+        T: CompleteCalendar + Sync + Send,
+        R: CalDavSource<U>,
+        U: DavCalendar + Sync + Send,
+    {
+        let mut by_uid: HashMap<String, UnifiedTask> = HashMap::new();
+        let mut order = Vec::new();
+        for cal in accounts.calendars().await? {
+            if !self.is_selected(&cal.account_id, &cal.url) {
+                continue;
+            }
+            let calendar = cal.calendar.lock().await;
+            let mut tasks: Vec<&Task> = calendar
+                .get_items()
+                .await?
+                .into_values()
+                .filter_map(|item| match item {
+                    Item::Task(task) => Some(task),
+                    Item::Event(_) => None,
+                })
+                .filter(|task| !matches!(task.sync_status(), SyncStatus::LocallyDeleted(_)))
+                .collect();
+            tasks.sort_by(|a, b| a.url().cmp(b.url()));
+
+            for task in tasks {
+                let newer = match by_uid.get(task.uid()) {
+                    None => {
+                        order.push(task.uid().to_string());
+                        true
+                    }
+                    Some(existing) => task.last_modified() > existing.task.last_modified(),
+                };
+                if newer {
+                    by_uid.insert(
+                        task.uid().to_string(),
+                        UnifiedTask {
+                            account_id: cal.account_id.clone(),
+                            calendar_url: cal.url.clone(),
+                            task: task.clone(),
+                        },
+                    );
+                }
+            }
+        }
+
+        self.tasks = order
+            .into_iter()
+            .filter_map(|uid| by_uid.remove(&uid))
+            .collect();
+        Ok(())
+    }
+
+    /// Every task of this view, sorted by account, then calendar, then URL
+    pub fn tasks(&self) -> &[UnifiedTask] {
+        &self.tasks
+    }
+
+    /// The tasks that are due in `[start, end)`. See [`Task::due`](crate::task::Task::due) about dates without a time zone
+    pub fn due_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<&UnifiedTask> {
+        self.tasks
+            .iter()
+            .filter(|t| matches!(t.task.due(), Some(due) if start <= due && due < end))
+            .collect()
+    }
+
+    /// The tasks that are due in `[start, end)`, for a user in the time zone `tz`.
+    /// Tasks that are due on a given day are included whenever this day overlaps the range (see [`DateMaybeTime::overlaps`](crate::ical::DateMaybeTime::overlaps))
+    pub fn due_between_in<Tz: TimeZone>(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        tz: &Tz,
+    ) -> Vec<&UnifiedTask> {
+        self.tasks
+            .iter()
+            .filter(|t| {
+                t.task
+                    .due_at()
+                    .is_some_and(|due| due.overlaps(start, end, tz))
+            })
+            .collect()
+    }
+
+    /// The tasks that have this category (case-insensitive)
+    pub fn with_category(&self, category: &str) -> Vec<&UnifiedTask> {
+        self.tasks
+            .iter()
+            .filter(|t| {
+                t.task
+                    .categories()
+                    .iter()
+                    .any(|c| c.eq_ignore_ascii_case(category))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,309 @@
+//! A command-line companion for kitchen-fridge, to sync and inspect a cache.
+//!
+//! This is built with the `cli` feature. Run it without arguments to know how to use it.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use tokio::sync::Mutex;
+use url::Url;
+
+use kitchen_fridge::cache::Cache;
+use kitchen_fridge::calendar::cached_calendar::CachedCalendar;
+use kitchen_fridge::calendar::completion::CompletionCascade;
+use kitchen_fridge::client::Client;
+use kitchen_fridge::ical::DateMaybeTime;
+use kitchen_fridge::item::Item;
+use kitchen_fridge::task::{CompletionStatus, Task};
+use kitchen_fridge::traits::{BaseCalendar, CalDavSource, CompleteCalendar};
+use kitchen_fridge::utils::sync::Syncable;
+use kitchen_fridge::CalDavProvider;
+
+const DEFAULT_CACHE_FOLDER: &str = "kitchen-fridge-cache";
+
+const USAGE: &str = "Usage: kitchen-fridge-cli [--cache <folder>] <command>
+
+Commands:
+    sync                                    Sync the cache with the server
+    list calendars                          List the calendars of the cache
+    list tasks [--due-before <date>]        List the tasks of the cache (dates are either RFC 3339 or YYYY-MM-DD)
+               [--calendar <url>]
+    add task <calendar url> <name>          Add a task to the cache (the next sync pushes it to the server)
+    complete <task url>                     Mark a task of the cache as completed (the next sync pushes it to the server)
+    inspect cache                           Print a JSON report about the cache
+
+The cache folder is kitchen-fridge-cache by default.
+The server is read from the KF_URL, KF_USERNAME and KF_PASSWORD environment variables. Only `sync` connects to it.";
+
+/// What the command line asks for
+enum Command {
+    Sync,
+    ListCalendars,
+    ListTasks {
+        due_before: Option<DateTime<Utc>>,
+        calendar: Option<Url>,
+    },
+    AddTask {
+        calendar: Url,
+        name: String,
+    },
+    Complete {
+        task: Url,
+    },
+    InspectCache,
+}
+
+struct Args {
+    cache_folder: PathBuf,
+    command: Command,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let args = match parse_args(args) {
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+        Ok(args) => args,
+    };
+
+    match run(args).await {
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::FAILURE
+        }
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Args, String> {
+    let mut cache_folder = PathBuf::from(DEFAULT_CACHE_FOLDER);
+    let mut due_before = None;
+    let mut calendar = None;
+    let mut positional = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", option))
+        };
+        match arg.as_str() {
+            "--cache" => cache_folder = PathBuf::from(value("--cache")?),
+            "--due-before" => due_before = Some(parse_date(&value("--due-before")?)?),
+            "--calendar" => calendar = Some(parse_url(&value("--calendar")?)?),
+            option if option.starts_with("--") => return Err(format!("Unknown option {}", option)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let positional: Vec<&str> = positional.iter().map(String::as_str).collect();
+    let command = match positional.as_slice() {
+        ["sync"] => Command::Sync,
+        ["list", "calendars"] => Command::ListCalendars,
+        ["list", "tasks"] => Command::ListTasks {
+            due_before: due_before.take(),
+            calendar: calendar.take(),
+        },
+        ["add", "task", calendar, name] => Command::AddTask {
+            calendar: parse_url(calendar)?,
+            name: name.to_string(),
+        },
+        ["complete", task] => Command::Complete {
+            task: parse_url(task)?,
+        },
+        ["inspect", "cache"] => Command::InspectCache,
+        [] => return Err("Missing command".to_string()),
+        _ => return Err(format!("Unknown command {:?}", positional.join(" "))),
+    };
+    if due_before.is_some() || calendar.is_some() {
+        return Err("--due-before and --calendar are only valid for `list tasks`".to_string());
+    }
+
+    Ok(Args {
+        cache_folder,
+        command,
+    })
+}
+
+fn parse_url(s: &str) -> Result<Url, String> {
+    Url::parse(s).map_err(|err| format!("Invalid URL {:?}: {}", s, err))
+}
+
+/// Either an RFC 3339 date, or a day (in which case its start in the local time zone is used)
+fn parse_date(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| DateMaybeTime::Date(day).start_in(&Local))
+        .ok_or_else(|| format!("Invalid date {:?}", s))
+}
+
+fn open_cache(folder: &Path) -> Cache {
+    match Cache::from_folder(folder) {
+        Ok(cache) => cache,
+        Err(err) => {
+            log::warn!("Invalid cache folder: {}. Using an empty cache", err);
+            Cache::new(folder)
+        }
+    }
+}
+
+fn client_from_env() -> Result<Client, String> {
+    let var = |name: &str| {
+        std::env::var(name).map_err(|_| format!("The {} environment variable is not set", name))
+    };
+    Client::new(var("KF_URL")?, var("KF_USERNAME")?, var("KF_PASSWORD")?)
+        .map_err(|err| format!("Invalid server URL: {}", err))
+}
+
+/// Returns whether the command has been successful
+async fn run(args: Args) -> Result<bool, Box<dyn std::error::Error>> {
+    let cache = open_cache(&args.cache_folder);
+
+    match args.command {
+        Command::Sync => {
+            let mut provider = CalDavProvider::new(client_from_env()?, cache);
+            let result = provider.sync_with_result(None).await;
+            provider.local().save_to_folder().await?;
+
+            println!("{}", result.summary());
+            for skipped in result.skipped() {
+                println!("{}", skipped);
+            }
+            for issue in result.issues() {
+                println!("{}", issue);
+            }
+            return Ok(result.is_success());
+        }
+
+        Command::ListCalendars => {
+            for (url, cal) in sorted_calendars(&cache).await? {
+                let cal = cal.lock().await;
+                println!(
+                    "{}\t{:?}\t{} items\t{}",
+                    cal.display_name(),
+                    cal.supported_components(),
+                    cal.get_items().await?.len(),
+                    url
+                );
+            }
+        }
+
+        Command::ListTasks {
+            due_before,
+            calendar,
+        } => {
+            for (url, cal) in sorted_calendars(&cache).await? {
+                if calendar.as_ref().is_some_and(|wanted| wanted != &url) {
+                    continue;
+                }
+                let cal = cal.lock().await;
+                let tasks: Vec<&Task> = cal
+                    .tasks_in_sort_order()
+                    .await?
+                    .into_iter()
+                    .filter(|task| match due_before {
+                        None => true,
+                        Some(limit) => task
+                            .due_at()
+                            .is_some_and(|due| due.is_before(limit, &Local)),
+                    })
+                    .collect();
+                if tasks.is_empty() {
+                    continue;
+                }
+                println!("{} ({})", cal.display_name(), url);
+                for task in tasks {
+                    print_task(task);
+                }
+            }
+        }
+
+        Command::AddTask { calendar, name } => {
+            let cal = cache
+                .get_calendar(&calendar)
+                .await
+                .ok_or_else(|| format!("No calendar {} in the cache", calendar))?;
+            let task = Task::new(name, false, &calendar)?;
+            let url = task.url().clone();
+            cal.lock().await.add_item(Item::Task(task)).await?;
+            cache.save_to_folder().await?;
+            println!("{}", url);
+        }
+
+        Command::Complete { task } => {
+            let mut found = false;
+            for cal in cache.get_calendars().await?.values() {
+                let mut cal = cal.lock().await;
+                if cal.get_item_by_url(&task).await.is_some() {
+                    cal.set_completion_status_cascading(
+                        &task,
+                        CompletionStatus::Completed(Some(Utc::now())),
+                        CompletionCascade::None,
+                    )
+                    .await?;
+                    found = true;
+                    break;
+                }
+            }
+            if !found {
+                return Err(format!("No task {} in the cache", task).into());
+            }
+            cache.save_to_folder().await?;
+        }
+
+        Command::InspectCache => {
+            let report = kitchen_fridge::cache::inspect::inspect(&cache).await;
+            println!("{}", report.to_json()?);
+        }
+    }
+
+    Ok(true)
+}
+
+async fn sorted_calendars(
+    cache: &Cache,
+) -> Result<Vec<(Url, Arc<Mutex<CachedCalendar>>)>, Box<dyn std::error::Error>> {
+    let mut cals: Vec<_> = cache.get_calendars().await?.into_iter().collect();
+    cals.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(cals)
+}
+
+fn print_task(task: &Task) {
+    let completion = if task.completed() { "✓" } else { " " };
+    let due = match task.due_at() {
+        None => String::new(),
+        Some(DateMaybeTime::Date(day)) => format!(" (due {})", day.format("%Y-%m-%d")),
+        Some(due) => due
+            .start_in(&Local)
+            .map(|due| {
+                format!(
+                    " (due {})",
+                    due.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+                )
+            })
+            .unwrap_or_default(),
+    };
+    println!(
+        "    {}{} {}{}\t{}",
+        completion,
+        task.sync_status().symbol(),
+        task.name(),
+        due,
+        task.url()
+    );
+}
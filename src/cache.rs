@@ -1,13 +1,13 @@
 //! This module provides a local cache for CalDAV data
 
-use std::collections::HashMap;
-use std::ffi::OsStr;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use async_trait::async_trait;
 use csscolorparser::Color;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use url::Url;
@@ -17,15 +17,37 @@ use crate::calendar::SupportedComponents;
 use crate::error::KFError;
 use crate::error::KFResult;
 use crate::item::ItemType;
+#[cfg(feature = "local_calendar_mocks_remote_calendars")]
+use crate::resource::TransferCounter;
 use crate::traits::BaseCalendar;
 use crate::traits::CalDavSource;
 use crate::traits::CompleteCalendar;
+use crate::traits::CompleteCalendarFactory;
+#[cfg(any(test, feature = "integration_tests"))]
+use crate::utils::diff::{ContentDiff, ContentDifference, Side};
+use crate::utils::{lock_ignoring_poison, stable_hash};
+use lock::FolderLock;
+use storage::{CacheStorage, FolderStorage};
 
 #[cfg(feature = "local_calendar_mocks_remote_calendars")]
 use crate::mock_behaviour::MockBehaviour;
 
+pub mod archive;
+pub mod in_memory;
+pub mod inspect;
+pub mod integrity;
+pub mod lock;
+pub mod storage;
+pub mod transaction;
+
 const MAIN_FILE: &str = "data.json";
 
+/// The version of the on-disk format of the cache.
+///
+/// Caches written by older versions of this crate are upgraded when they are loaded, caches written by newer versions are refused with [`CacheError::UnsupportedSchemaVersion`].
+/// Version `0` is the one of the caches written before this version was recorded, version `2` added the calendar files named after a hash of their URL and many calendar and item fields
+pub const SCHEMA_VERSION: u32 = 2;
+
 #[derive(thiserror::Error, Debug)]
 pub enum CacheError {
     #[error("IO error: {0}")]
@@ -36,30 +58,127 @@ pub enum CacheError {
 
     #[error("Unable to open file {path:?}: {err}")]
     UnableToOpenFile { path: PathBuf, err: std::io::Error },
+
+    #[error("Unsupported archive format version {0}")]
+    UnsupportedArchiveVersion(u64),
+
+    #[error(
+        "Unsupported cache schema version {0}, this version of the crate reads versions up to {}",
+        SCHEMA_VERSION
+    )]
+    UnsupportedSchemaVersion(u64),
+
+    #[error("Calendars {calendars:?} have not been saved, since their sync has been interrupted")]
+    InterruptedSync { calendars: Vec<Url> },
 }
 
 pub type CacheResult<T> = Result<T, CacheError>;
 
-/// A CalDAV source that stores its items in a local folder.
+/// A CalDAV source that stores its items in a local folder (or in another [`CacheStorage`], see [`Cache::with_storage`]).
+///
+/// Its content is only written to the folder by [`Cache::save_to_folder`] (and by the checkpoints of a sync, see [`Provider::set_checkpoint_interval`](crate::provider::Provider::set_checkpoint_interval)).
+/// Nothing is written when it is dropped, since calendars may then be in the middle of a sync
 ///
-/// It automatically updates the content of the folder when dropped (see its `Drop` implementation), but you can also manually call [`Cache::save_to_folder`]
+/// Calendars of a cache read from a folder (or a storage) are only loaded when they are first accessed (see [`Cache::preload_all`]).
 ///
 /// Most of its functionality is provided by the `CalDavSource` async trait it implements.
 /// However, since these functions do not _need_ to be actually async, non-async versions of them are also provided for better convenience. See [`Cache::get_calendar_sync`] for example
 #[derive(Debug)]
 pub struct Cache {
     backing_folder: PathBuf,
+    storage: Arc<dyn CacheStorage>,
     data: CachedData,
+    /// The storage keys of the calendars that have not been loaded yet
+    unloaded: std::sync::Mutex<HashSet<String>>,
+    /// The calendars that have been loaded from their legacy storage key (see [`Self::legacy_calendar_key`]), that is removed once they are saved again
+    legacy_keys: std::sync::Mutex<HashMap<Url, String>>,
+    /// The lock of the backing folder, while this cache is being synced or saved
+    folder_lock: std::sync::Mutex<FolderLockState>,
+    undo_stack: transaction::UndoStack,
 
     /// In tests, we may add forced errors to this object
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
     mock_behaviour: Option<Arc<Mutex<MockBehaviour>>>,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 struct CachedData {
+    /// Missing from caches that were written before this version was recorded
+    #[serde(default)]
+    schema_version: u32,
+    /// How many previous versions of each item the calendars keep
+    #[serde(default)]
+    item_history_depth: usize,
+    /// The calendars that have been removed locally only, that syncs must not download again
+    #[serde(default)]
+    ignored_calendars: HashSet<Url>,
+    /// Application-defined metadata, see [`Cache::set_meta`]
+    #[serde(default)]
+    metadata: HashMap<String, serde_json::Value>,
+    /// The calendars that have been loaded
     #[serde(skip)]
-    calendars: HashMap<Url, Arc<Mutex<CachedCalendar>>>,
+    calendars: std::sync::Mutex<HashMap<Url, Arc<Mutex<CachedCalendar>>>>,
+}
+
+/// Parse the general data of a cache, once its schema version has been checked, and upgrade it in case it has been written by an older version of this crate
+fn parse_data(content: &[u8]) -> CacheResult<CachedData> {
+    let value: serde_json::Value = serde_json::from_slice(content)?;
+    check_schema_version(
+        value
+            .get("schema_version")
+            .and_then(|v| v.as_u64())
+            .unwrap_or_default(),
+    )?;
+    let mut data: CachedData = serde_json::from_value(value)?;
+    if data.schema_version < SCHEMA_VERSION {
+        // Older caches only lack fields, that take their default values
+        log::info!(
+            "Upgrading cache data from schema version {} to {}",
+            data.schema_version,
+            SCHEMA_VERSION
+        );
+        data.schema_version = SCHEMA_VERSION;
+    }
+    Ok(data)
+}
+
+/// Refuse the caches that have been written by newer versions of this crate
+fn check_schema_version(version: u64) -> CacheResult<()> {
+    if version > SCHEMA_VERSION as u64 {
+        return Err(CacheError::UnsupportedSchemaVersion(version));
+    }
+    Ok(())
+}
+
+/// The IO error of a save that could not lock the backing folder, which wraps the [`KFError`]
+fn lock_error(err: KFError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::WouldBlock, err)
+}
+
+#[derive(Clone, Copy)]
+enum LockReason {
+    Sync,
+    Save,
+}
+
+/// Why a [`Cache`] holds the lock of its backing folder (see [`lock`])
+#[derive(Debug, Default)]
+struct FolderLockState {
+    lock: Option<FolderLock>,
+    syncing: bool,
+    saves: usize,
+}
+
+impl Default for CachedData {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            item_history_depth: 0,
+            ignored_calendars: HashSet::new(),
+            metadata: HashMap::new(),
+            calendars: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
 }
 
 impl Cache {
@@ -69,112 +188,373 @@ impl Cache {
         self.mock_behaviour = mock_behaviour;
     }
 
+    /// Keep the last `depth` versions of every item of every calendar (including calendars that will be created later), so that they can be
+    /// retrieved with [`CompleteCalendar::item_history`](crate::traits::CompleteCalendar::item_history). `0` (the default) disables the history
+    pub async fn set_item_history_depth(&mut self, depth: usize) {
+        self.data.item_history_depth = depth;
+        // Calendars that are not loaded yet will get it when they are
+        let loaded: Vec<_> = self.calendar_map().values().cloned().collect();
+        for cal in loaded {
+            cal.lock().await.set_history_depth(depth);
+        }
+    }
+
+    /// The URLs of the calendars that are marked for deletion, and that the next sync will delete from the server.
+    /// They can be kept with [`CompleteCalendar::unmark_for_deletion`] until then
+    pub async fn calendars_marked_for_deletion(&self) -> Vec<Url> {
+        let mut urls = Vec::new();
+        for (url, cal) in self.all_calendars() {
+            if cal.lock().await.marked_for_deletion().await {
+                urls.push(url);
+            }
+        }
+        urls.sort();
+        urls
+    }
+
+    /// Read the application-defined metadata stored under `key` (see [`Self::set_meta`]).
+    /// Returns an error in case it cannot be deserialized as a `T`
+    pub fn get_meta<T: DeserializeOwned>(&self, key: &str) -> CacheResult<Option<T>> {
+        match self.data.metadata.get(key) {
+            None => Ok(None),
+            Some(value) => Ok(Some(T::deserialize(value)?)),
+        }
+    }
+
+    /// Store application-defined data (view preferences, UI state of a calendar, sync cursors...) under `key`, replacing any previous value.
+    ///
+    /// Metadata is saved together with the rest of the cache (see [`Self::save_to_folder`]), so that applications do not need a storage of their own
+    pub fn set_meta<T: Serialize>(&mut self, key: &str, value: &T) -> CacheResult<()> {
+        let value = serde_json::to_value(value)?;
+        self.data.metadata.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    /// Remove the metadata stored under `key`. Returns whether there was any
+    pub fn remove_meta(&mut self, key: &str) -> bool {
+        self.data.metadata.remove(key).is_some()
+    }
+
+    /// The keys of every stored metadata
+    pub fn meta_keys(&self) -> impl Iterator<Item = &String> {
+        self.data.metadata.keys()
+    }
+
     /// Get the path to the cache folder
     pub fn cache_folder() -> PathBuf {
         PathBuf::from(String::from("~/.config/my-tasks/cache/"))
     }
 
     /// Initialize a cache from the content of a valid backing folder if it exists.
-    /// Returns an error otherwise
+    /// Returns an error otherwise.
+    ///
+    /// Calendars are only read when they are first accessed, see [`Self::preload_all`]
     pub fn from_folder(folder: &Path) -> CacheResult<Self> {
+        let mut cache = match Self::from_storage(Arc::new(FolderStorage::new(folder))) {
+            Err(CacheError::UnableToOpenFile { err, .. }) => {
+                return Err(CacheError::UnableToOpenFile {
+                    path: folder.join(MAIN_FILE),
+                    err,
+                })
+            }
+            result => result?,
+        };
+        cache.backing_folder = PathBuf::from(folder);
+        Ok(cache)
+    }
+
+    /// Initialize a cache from the content of a storage.
+    /// Returns an error in case it does not contain a valid cache.
+    ///
+    /// Calendars are only read when they are first accessed, see [`Self::preload_all`]
+    pub fn from_storage(storage: Arc<dyn CacheStorage>) -> CacheResult<Self> {
         // Load shared data...
-        let main_file = folder.join(MAIN_FILE);
-        let mut data: CachedData = match std::fs::File::open(&main_file) {
+        let data: CachedData = match storage.read(MAIN_FILE) {
             Err(err) => {
                 return Err(CacheError::UnableToOpenFile {
-                    path: main_file,
+                    path: PathBuf::from(MAIN_FILE),
                     err,
-                });
+                })
+            }
+            Ok(None) => {
+                return Err(CacheError::UnableToOpenFile {
+                    path: PathBuf::from(MAIN_FILE),
+                    err: std::io::Error::new(std::io::ErrorKind::NotFound, "No cache data"),
+                })
             }
-            Ok(file) => serde_json::from_reader(file)?,
+            Ok(Some(content)) => parse_data(&content)?,
         };
 
-        // ...and every calendar
-        for entry in std::fs::read_dir(folder)? {
-            match entry {
-                Err(err) => {
-                    log::error!("Unable to read dir: {:?}", err);
-                    continue;
-                }
-                Ok(entry) => {
-                    let cal_path = entry.path();
-                    log::debug!("Considering {:?}", cal_path);
-                    if cal_path.extension() == Some(OsStr::new("cal")) {
-                        match Self::load_calendar(&cal_path) {
-                            Err(err) => {
-                                log::error!(
-                                    "Unable to load calendar {:?} from cache: {:?}",
-                                    cal_path,
-                                    err
-                                );
-                                continue;
-                            }
-                            Ok(cal) => data
-                                .calendars
-                                .insert(cal.url().clone(), Arc::new(Mutex::new(cal))),
-                        };
+        // ...and list every calendar
+        let mut unloaded = HashSet::new();
+        for key in storage.keys()? {
+            log::debug!("Considering {:?}", key);
+            if key.ends_with(".cal") {
+                unloaded.insert(key);
+            }
+        }
+
+        let mut cache = Self::with_storage(storage);
+        cache.data = data;
+        cache.unloaded = std::sync::Mutex::new(unloaded);
+        Ok(cache)
+    }
+
+    /// Load every calendar that has not been accessed yet.
+    ///
+    /// Caches read from a folder (or a storage) only load their calendars when they are first accessed, so that opening a large cache is fast.
+    /// This is automatically done by the functions that need every calendar (e.g. [`CalDavSource::get_calendars`]).
+    /// Calendars that cannot be read are skipped (and an error is logged)
+    pub fn preload_all(&self) {
+        let keys: Vec<String> = lock_ignoring_poison(&self.unloaded).drain().collect();
+        for key in keys {
+            self.load_unloaded(&key);
+        }
+    }
+
+    /// Load the calendar that is stored under `key`, in case it is not loaded yet
+    fn load_if_needed(&self, url: &Url) {
+        let key = {
+            let mut unloaded = lock_ignoring_poison(&self.unloaded);
+            vec![Self::calendar_key(url), Self::legacy_calendar_key(url)]
+                .into_iter()
+                .find(|key| unloaded.remove(key.as_str()))
+        };
+        if let Some(key) = key {
+            self.load_unloaded(&key);
+        }
+    }
+
+    fn load_unloaded(&self, key: &str) {
+        match Self::load_calendar(&*self.storage, key) {
+            Err(err) => log::error!("Unable to load calendar {:?} from cache: {:?}", key, err),
+            Ok(mut cal) => {
+                cal.set_history_depth(self.data.item_history_depth);
+                #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+                cal.set_mock_behaviour(self.mock_behaviour.clone());
+                let url = cal.url().clone();
+                if key != Self::calendar_key(&url) {
+                    if self.calendar_map().contains_key(&url) {
+                        // The cache has been interrupted while it was replacing this legacy entry, which is outdated
+                        return;
                     }
+                    lock_ignoring_poison(&self.legacy_keys).insert(url.clone(), key.to_string());
                 }
+                self.calendar_map().insert(url, Arc::new(Mutex::new(cal)));
             }
         }
+    }
 
-        Ok(Self {
-            backing_folder: PathBuf::from(folder),
-            data,
+    /// The calendars that have been loaded so far
+    pub(crate) fn calendar_map(
+        &self,
+    ) -> std::sync::MutexGuard<'_, HashMap<Url, Arc<Mutex<CachedCalendar>>>> {
+        // The map is never left in an inconsistent state
+        lock_ignoring_poison(&self.data.calendars)
+    }
 
-            #[cfg(feature = "local_calendar_mocks_remote_calendars")]
-            mock_behaviour: None,
-        })
+    /// Every calendar of this cache (that are loaded if needed)
+    pub(crate) fn all_calendars(&self) -> HashMap<Url, Arc<Mutex<CachedCalendar>>> {
+        self.preload_all();
+        self.calendar_map().clone()
     }
 
-    fn load_calendar(path: &Path) -> CacheResult<CachedCalendar> {
-        let file = std::fs::File::open(path)?;
-        Ok(serde_json::from_reader(file)?)
+    fn load_calendar(storage: &dyn CacheStorage, key: &str) -> CacheResult<CachedCalendar> {
+        let content = storage.read(key)?.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Calendar has vanished")
+        })?;
+        Ok(serde_json::from_slice(&content)?)
     }
 
     /// Initialize a cache with the default contents
     pub fn new(folder_path: &Path) -> Self {
+        let mut cache = Self::with_storage(Arc::new(FolderStorage::new(folder_path)));
+        cache.backing_folder = PathBuf::from(folder_path);
+        cache
+    }
+
+    /// Initialize a cache with the default contents, that will be saved to `storage` rather than to a folder.
+    /// Its [`backing folder`](Self::calendar_path) is then meaningless
+    pub fn with_storage(storage: Arc<dyn CacheStorage>) -> Self {
         Self {
-            backing_folder: PathBuf::from(folder_path),
+            backing_folder: PathBuf::new(),
+            storage,
             data: CachedData::default(),
+            unloaded: std::sync::Mutex::new(HashSet::new()),
+            legacy_keys: std::sync::Mutex::new(HashMap::new()),
+            folder_lock: std::sync::Mutex::new(FolderLockState::default()),
+            undo_stack: transaction::UndoStack::default(),
 
             #[cfg(feature = "local_calendar_mocks_remote_calendars")]
             mock_behaviour: None,
         }
     }
 
-    /// Store the current Cache to its backing folder
+    /// Store the current Cache to its backing folder (or its storage)
     ///
-    /// Note that this is automatically called when `self` is `drop`ped
+    /// Calendars that are being synced by another task are saved once their sync is done.
+    /// Calendars whose sync has been interrupted (see [`CompleteCalendar::sync_in_progress`]) are not saved: their previous version (or their last checkpoint) is kept, and the next sync resumes from there.
+    /// See [`Self::try_save_to_folder`] to know about them
+    ///
+    /// This fails in case another process holds the lock of the backing folder. The error then wraps a [`KFError::CacheLocked`]
     pub async fn save_to_folder(&self) -> Result<(), std::io::Error> {
-        let folder = &self.backing_folder;
-        std::fs::create_dir_all(folder)?;
+        let interrupted = self.save_consistent_calendars().await?;
+        if !interrupted.is_empty() {
+            log::warn!(
+                "Calendars {:?} have not been saved, since their sync has been interrupted",
+                interrupted
+            );
+        }
+        Ok(())
+    }
+
+    /// Same as [`Self::save_to_folder`], but returns a [`CacheError::InterruptedSync`] in case some calendars have not been saved because their sync has been interrupted
+    pub async fn try_save_to_folder(&self) -> CacheResult<()> {
+        let interrupted = self.save_consistent_calendars().await?;
+        if interrupted.is_empty() {
+            Ok(())
+        } else {
+            Err(CacheError::InterruptedSync {
+                calendars: interrupted,
+            })
+        }
+    }
 
+    /// Save everything but the calendars whose sync has been interrupted, and return their URLs
+    async fn save_consistent_calendars(&self) -> Result<Vec<Url>, std::io::Error> {
+        self.lock_folder(LockReason::Save).map_err(lock_error)?;
+        let result = self.save_consistent_calendars_locked().await;
+        self.unlock_folder(LockReason::Save);
+        result
+    }
+
+    async fn save_consistent_calendars_locked(&self) -> Result<Vec<Url>, std::io::Error> {
         // Save the general data
-        let main_file_path = folder.join(MAIN_FILE);
-        let file = std::fs::File::create(&main_file_path)?;
-        serde_json::to_writer(file, &self.data)?;
-
-        // Save each calendar
-        for (cal_url, cal_mutex) in &self.data.calendars {
-            let cal_file = self.calendar_path(cal_url);
-            let file = std::fs::File::create(&cal_file)?;
+        self.storage
+            .write(MAIN_FILE, &serde_json::to_vec(&self.data)?)?;
+
+        // Save each calendar (the ones that have not been loaded have not changed)
+        let loaded: Vec<_> = self
+            .calendar_map()
+            .iter()
+            .map(|(url, cal)| (url.clone(), cal.clone()))
+            .collect();
+        let mut interrupted = Vec::new();
+        for (cal_url, cal_mutex) in loaded {
+            // A sync holds this lock until it is done with this calendar
             let cal = cal_mutex.lock().await;
-            serde_json::to_writer(file, &*cal)?;
+            if cal.sync_in_progress() {
+                interrupted.push(cal_url);
+                continue;
+            }
+            self.storage
+                .write(&Self::calendar_key(&cal_url), &serde_json::to_vec(&*cal)?)?;
+            self.remove_legacy_entry(&cal_url)?;
+        }
+        interrupted.sort();
+
+        Ok(interrupted)
+    }
+
+    /// Store a single calendar, which is not necessarily unlocked (e.g. while it is being synced)
+    fn save_calendar(&self, calendar: &CachedCalendar) -> Result<(), std::io::Error> {
+        self.lock_folder(LockReason::Save).map_err(lock_error)?;
+        let result = self.save_calendar_locked(calendar);
+        self.unlock_folder(LockReason::Save);
+        result
+    }
+
+    fn save_calendar_locked(&self, calendar: &CachedCalendar) -> Result<(), std::io::Error> {
+        // A cache is not readable without its general data
+        if self.storage.read(MAIN_FILE)?.is_none() {
+            self.storage
+                .write(MAIN_FILE, &serde_json::to_vec(&self.data)?)?;
         }
+        self.storage.write(
+            &Self::calendar_key(calendar.url()),
+            &serde_json::to_vec(calendar)?,
+        )?;
+        self.remove_legacy_entry(calendar.url())
+    }
 
+    /// Take the lock of the backing folder (or refresh it, in case this cache already holds it).
+    /// Caches that are not stored in a folder are not locked
+    fn lock_folder(&self, reason: LockReason) -> KFResult<()> {
+        if self.backing_folder.as_os_str().is_empty() {
+            return Ok(());
+        }
+        let mut state = lock_ignoring_poison(&self.folder_lock);
+        match &mut state.lock {
+            Some(lock) => lock.refresh(),
+            None => state.lock = Some(FolderLock::acquire(&self.backing_folder)?),
+        }
+        match reason {
+            LockReason::Sync => state.syncing = true,
+            LockReason::Save => state.saves += 1,
+        }
         Ok(())
     }
 
+    /// Release the lock of the backing folder, unless it is still needed for another reason
+    fn unlock_folder(&self, reason: LockReason) {
+        let mut state = lock_ignoring_poison(&self.folder_lock);
+        match reason {
+            LockReason::Sync => state.syncing = false,
+            LockReason::Save => state.saves = state.saves.saturating_sub(1),
+        }
+        if !state.syncing && state.saves == 0 {
+            state.lock = None;
+        }
+    }
+
+    /// The name of the storage entry where the calendar with the given URL is serialized.
+    ///
+    /// It ends with a hash of the URL, since different URLs may be sanitized into the same file name (e.g. URLs that only differ by characters that are not allowed in file names).
+    /// It is truncated so that it stays within the 255 bytes file names are limited to
+    fn calendar_key(url: &Url) -> String {
+        let suffix = format!("-{:016x}.cal", stable_hash(url.as_str()));
+        let mut name = sanitize_filename::sanitize(url.as_str());
+        let mut len = name.len().min(255 - suffix.len());
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        name.truncate(len);
+        name + &suffix
+    }
+
+    /// The name of the storage entry of a calendar in the caches written by former versions of this crate, see [`Self::calendar_key`]
+    fn legacy_calendar_key(url: &Url) -> String {
+        sanitize_filename::sanitize(url.as_str()) + ".cal"
+    }
+
+    /// Remove the storage entries of a calendar. This fails with [`std::io::ErrorKind::NotFound`] in case it has none
+    fn remove_entries(&self, url: &Url) -> Result<(), std::io::Error> {
+        let had_legacy_entry = lock_ignoring_poison(&self.legacy_keys).contains_key(url);
+        self.remove_legacy_entry(url)?;
+        match self.storage.remove(&Self::calendar_key(url)) {
+            Err(err) if had_legacy_entry && err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        }
+    }
+
+    /// Remove the legacy entry the calendar has been loaded from (if any), now that it is stored under its current key
+    fn remove_legacy_entry(&self, url: &Url) -> Result<(), std::io::Error> {
+        let key = lock_ignoring_poison(&self.legacy_keys).remove(url);
+        match key.map(|key| self.storage.remove(&key)) {
+            Some(Err(err)) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
     /// The path of the file where the calendar with the given URL is serialized
     pub fn calendar_path(&self, url: &Url) -> PathBuf {
-        let file_name = sanitize_filename::sanitize(url.as_str()) + ".cal";
-        self.backing_folder.join(file_name)
+        self.backing_folder.join(Self::calendar_key(url))
     }
 
     /// Compares two Caches to check they have the same current content
     ///
-    /// This is not a complete equality test: some attributes (sync status...) may differ. This should mostly be used in tests
+    /// This is not a complete equality test: some attributes (sync status...) may differ. This should mostly be used in tests.
+    /// See [`Self::observable_diff`] to know what differs
     #[cfg(any(test, feature = "integration_tests"))]
     pub async fn has_same_observable_content_as(
         &self,
@@ -182,33 +562,46 @@ impl Cache {
         self_desc: &str,
         other_desc: &str,
     ) -> KFResult<bool> {
+        let diff = self.observable_diff(other, self_desc, other_desc).await?;
+        if !diff.is_empty() {
+            log::debug!("{}", diff);
+        }
+        Ok(diff.is_empty())
+    }
+
+    /// Every difference between two Caches, as far as [`Self::has_same_observable_content_as`] is concerned
+    #[cfg(any(test, feature = "integration_tests"))]
+    pub async fn observable_diff(
+        &self,
+        other: &Self,
+        self_desc: &str,
+        other_desc: &str,
+    ) -> KFResult<ContentDiff> {
         let calendars_l = self.get_calendars().await?;
         let calendars_r = other.get_calendars().await?;
 
-        if !crate::utils::keys_are_the_same(&calendars_l, &calendars_r) {
-            log::debug!("Different keys for calendars");
-            return Ok(false);
-        }
-
-        for (calendar_url, cal_l) in calendars_l {
-            log::debug!("Comparing calendars {}", calendar_url);
-            let cal_l = cal_l.lock().await;
-            let cal_r = calendars_r
-                .get(&calendar_url)
-                .expect("should not happen, we've just tested keys are the same")
-                .lock()
-                .await;
-
-            // TODO: check calendars have the same names/ID/whatever
-            if !(cal_l
-                .has_same_observable_content_as(&cal_r, self_desc, other_desc)
-                .await?)
-            {
-                log::debug!("Different calendars");
-                return Ok(false);
+        let mut diff = ContentDiff::new(self_desc, other_desc);
+        let mut urls: Vec<&Url> = calendars_l.keys().chain(calendars_r.keys()).collect();
+        urls.sort();
+        urls.dedup();
+        for url in urls {
+            match (calendars_l.get(url), calendars_r.get(url)) {
+                (Some(cal_l), Some(cal_r)) => {
+                    let cal_l = cal_l.lock().await;
+                    let cal_r = cal_r.lock().await;
+                    diff.extend(cal_l.observable_diff(&cal_r, self_desc, other_desc).await?);
+                }
+                (left, _) => diff.push(ContentDifference::MissingCalendar {
+                    url: url.clone(),
+                    missing_from: if left.is_none() {
+                        Side::Left
+                    } else {
+                        Side::Right
+                    },
+                }),
             }
         }
-        Ok(true)
+        Ok(diff)
     }
 }
 
@@ -267,17 +660,13 @@ impl Cache {
             b.lock().await.can_get_calendars()?;
         }
 
-        Ok(self
-            .data
-            .calendars
-            .iter()
-            .map(|(url, cal)| (url.clone(), cal.clone()))
-            .collect())
+        Ok(self.all_calendars())
     }
 
     /// The non-async version of [`crate::traits::CalDavSource::get_calendar`]
     pub fn get_calendar_sync(&self, url: &Url) -> Option<Arc<Mutex<CachedCalendar>>> {
-        self.data.calendars.get(url).cloned()
+        self.load_if_needed(url);
+        self.calendar_map().get(url).cloned()
     }
 
     /// The non-async version of [`crate::traits::CalDavSource::delete_calendar`]
@@ -285,15 +674,20 @@ impl Cache {
         &mut self,
         url: &Url,
     ) -> KFResult<Option<Arc<Mutex<CachedCalendar>>>> {
-        // First, remove from filesystem
-        let path = self.calendar_path(url);
-        std::fs::remove_file(&path).map_err(|source| KFError::IoError {
-            detail: format!("Could not remove calendar at path {}", path.display()),
-            source,
-        })?;
+        self.load_if_needed(url);
+
+        // First, remove from storage
+        self.remove_entries(url)
+            .map_err(|source| KFError::IoError {
+                detail: format!(
+                    "Could not remove calendar at path {}",
+                    self.calendar_path(url).display()
+                ),
+                source,
+            })?;
 
         // Then remove from memory
-        match self.data.calendars.remove(url) {
+        match self.calendar_map().remove(url) {
             Some(c) => Ok(Some(c)),
             None => Err(KFError::ItemDoesNotExist {
                 detail: "Can't delete calendar".into(),
@@ -302,9 +696,36 @@ impl Cache {
             }),
         }
     }
+
+    /// The non-async version of [`crate::traits::CalDavSource::remove_calendar_local_only`]
+    pub fn remove_calendar_local_only_sync(
+        &mut self,
+        url: &Url,
+    ) -> KFResult<Option<Arc<Mutex<CachedCalendar>>>> {
+        self.load_if_needed(url);
+
+        match self.remove_entries(url) {
+            // This calendar may have never been saved yet
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(source) => {
+                return Err(KFError::IoError {
+                    detail: format!(
+                        "Could not remove calendar at path {}",
+                        self.calendar_path(url).display()
+                    ),
+                    source,
+                })
+            }
+            Ok(()) => (),
+        }
+
+        self.data.ignored_calendars.insert(url.clone());
+        Ok(self.calendar_map().remove(url))
+    }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl CalDavSource<CachedCalendar> for Cache {
     async fn get_calendars(&self) -> KFResult<HashMap<Url, Arc<Mutex<CachedCalendar>>>> {
         self.get_calendars_sync().await
@@ -327,7 +748,13 @@ impl CalDavSource<CachedCalendar> for Cache {
             b.lock().await.can_create_calendar()?;
         }
 
-        let new_calendar = CachedCalendar::new(name, url.clone(), supported_components, color);
+        // In case it already exists, but has not been loaded yet
+        self.load_if_needed(&url);
+        // This calendar is wanted again
+        self.data.ignored_calendars.remove(&url);
+
+        let mut new_calendar = CachedCalendar::new(name, url.clone(), supported_components, color);
+        new_calendar.set_history_depth(self.data.item_history_depth);
         let arc = Arc::new(Mutex::new(new_calendar));
 
         #[cfg(feature = "local_calendar_mocks_remote_calendars")]
@@ -337,7 +764,8 @@ impl CalDavSource<CachedCalendar> for Cache {
                 .set_mock_behaviour(Some(Arc::clone(behaviour)));
         };
 
-        match self.data.calendars.insert(url.clone(), arc.clone()) {
+        let previous = self.calendar_map().insert(url.clone(), arc.clone());
+        match previous {
             Some(_) => Err(KFError::ItemAlreadyExists {
                 type_: ItemType::Calendar,
                 detail: "Attempt to insert calendar failed".into(),
@@ -350,6 +778,60 @@ impl CalDavSource<CachedCalendar> for Cache {
     async fn delete_calendar(&mut self, url: &Url) -> KFResult<Option<Arc<Mutex<CachedCalendar>>>> {
         Self::delete_calendar_sync(self, url)
     }
+
+    async fn remove_calendar_local_only(
+        &mut self,
+        url: &Url,
+    ) -> KFResult<Option<Arc<Mutex<CachedCalendar>>>> {
+        self.remove_calendar_local_only_sync(url)
+    }
+
+    fn ignored_calendars(&self) -> HashSet<Url> {
+        self.data.ignored_calendars.clone()
+    }
+
+    fn stop_ignoring_calendar(&mut self, url: &Url) -> bool {
+        self.data.ignored_calendars.remove(url)
+    }
+
+    /// Store this calendar (and the general data, if it has never been saved) to the backing folder (or the storage)
+    async fn checkpoint_calendar(&self, calendar: &CachedCalendar) -> KFResult<()> {
+        self.save_calendar(calendar)
+            .map_err(|source| KFError::IoError {
+                detail: format!("Unable to checkpoint calendar {}", calendar.url()),
+                source,
+            })
+    }
+
+    /// Caches stored in a folder hold its lock (see [`lock`]) until the sync is finished.
+    /// This fails with [`KFError::CacheLocked`] in case another process is syncing or saving the same folder
+    fn sync_started(&self) -> KFResult<()> {
+        self.lock_folder(LockReason::Sync)
+    }
+
+    /// Long syncs refresh the lock at every calendar, so that other processes do not take it over
+    fn sync_progressed(&self) {
+        let mut state = lock_ignoring_poison(&self.folder_lock);
+        if state.syncing {
+            if let Some(lock) = &mut state.lock {
+                lock.refresh();
+            }
+        }
+    }
+
+    fn sync_finished(&self) {
+        self.unlock_folder(LockReason::Sync)
+    }
+
+    /// Mocked caches account for the data a server would have transferred
+    #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+    fn transfers(&self) -> Option<TransferCounter> {
+        // Nothing else holds the mock behaviour between two mocked calls
+        self.mock_behaviour
+            .as_ref()
+            .and_then(|b| b.try_lock().ok())
+            .map(|b| b.transfers.clone())
+    }
 }
 
 #[cfg(test)]
@@ -359,6 +841,7 @@ mod tests {
     use crate::calendar::SupportedComponents;
     use crate::item::Item;
     use crate::task::Task;
+    use crate::utils::sync::{SyncStatus, VersionTag};
     use url::Url;
 
     async fn populate_cache(cache_path: &Path) -> Cache {
@@ -388,20 +871,22 @@ mod tests {
             let mut bucket_list = bucket_list.lock().await;
             let cal_url = bucket_list.url().clone();
             bucket_list
-                .add_item(Item::Task(Task::new(
-                    String::from("Attend a concert of JS Bach"),
-                    false,
-                    &cal_url,
-                )))
+                .add_item(Item::Task(
+                    Task::new(String::from("Attend a concert of JS Bach"), false, &cal_url)
+                        .unwrap(),
+                ))
                 .await
                 .unwrap();
 
             bucket_list
-                .add_item(Item::Task(Task::new(
-                    String::from("Climb the Lighthouse of Alexandria"),
-                    true,
-                    &cal_url,
-                )))
+                .add_item(Item::Task(
+                    Task::new(
+                        String::from("Climb the Lighthouse of Alexandria"),
+                        true,
+                        &cal_url,
+                    )
+                    .unwrap(),
+                ))
                 .await
                 .unwrap();
         }
@@ -426,6 +911,319 @@ mod tests {
         assert!(test.unwrap());
     }
 
+    #[tokio::test]
+    async fn cache_lazy_loading() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/lazy_test"));
+        let cache = populate_cache(&cache_path).await;
+        cache.save_to_folder().await.unwrap();
+
+        let retrieved_cache = Cache::from_folder(&cache_path).unwrap();
+        assert!(retrieved_cache.calendar_map().is_empty());
+
+        let bucket_list = Url::parse("https://caldav.com/bucket-list").unwrap();
+        let cal = retrieved_cache.get_calendar(&bucket_list).await.unwrap();
+        assert_eq!(cal.lock().await.get_items().await.unwrap().len(), 2);
+        assert_eq!(retrieved_cache.calendar_map().len(), 1);
+        // Further accesses return the same calendar
+        let again = retrieved_cache.get_calendar(&bucket_list).await.unwrap();
+        assert!(Arc::ptr_eq(&cal, &again));
+
+        retrieved_cache.preload_all();
+        assert_eq!(retrieved_cache.calendar_map().len(), 2);
+        assert!(retrieved_cache
+            .has_same_observable_content_as(&cache, "retrieved cache", "cache")
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn cache_archive() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/archive_test"));
+        let archive_path = PathBuf::from(String::from("test_cache/archive_test.tar.gz"));
+        let cache = populate_cache(&cache_path).await;
+        std::fs::create_dir_all("test_cache").unwrap();
+
+        let manifest = cache.export_archive(&archive_path).await.unwrap();
+        assert_eq!(manifest.calendar_count, 2);
+        assert_eq!(manifest.schema_version, SCHEMA_VERSION);
+        // A gzip stream
+        assert_eq!(std::fs::read(&archive_path).unwrap()[..2], [0x1f, 0x8b]);
+        assert_eq!(
+            Cache::read_archive_manifest(&archive_path).unwrap(),
+            manifest
+        );
+
+        let restored_path = PathBuf::from(String::from("test_cache/archive_restored"));
+        let restored = Cache::import_archive(&archive_path, &restored_path).unwrap();
+        assert_eq!(restored.backing_folder, restored_path);
+        assert!(cache
+            .has_same_observable_content_as(&restored, "cache", "restored cache")
+            .await
+            .unwrap());
+
+        let write_manifest = |manifest: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(manifest.len() as u64);
+            header.set_cksum();
+            let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+                std::fs::File::create(&archive_path).unwrap(),
+                flate2::Compression::default(),
+            ));
+            builder
+                .append_data(&mut header, "manifest.json", manifest)
+                .unwrap();
+            builder.into_inner().unwrap().finish().unwrap();
+        };
+        write_manifest(br#"{"format_version": 1000}"#);
+        assert!(matches!(
+            Cache::import_archive(&archive_path, &restored_path),
+            Err(CacheError::UnsupportedArchiveVersion(1000))
+        ));
+
+        // Archives of caches written by newer versions of the crate are refused
+        let newer = archive::ArchiveManifest {
+            schema_version: 1000,
+            ..manifest
+        };
+        write_manifest(&serde_json::to_vec(&newer).unwrap());
+        assert!(matches!(
+            Cache::import_archive(&archive_path, &restored_path),
+            Err(CacheError::UnsupportedSchemaVersion(1000))
+        ));
+    }
+
+    #[tokio::test]
+    async fn cache_schema_version() {
+        let storage_with = |data: &[u8]| {
+            let mut entries = HashMap::new();
+            entries.insert(MAIN_FILE.to_string(), data.to_vec());
+            Arc::new(storage::MemoryStorage::from_entries(entries))
+        };
+
+        // Caches written before the version was recorded are upgraded...
+        let storage = storage_with(br#"{"item_history_depth": 3}"#);
+        let legacy = Cache::from_storage(storage.clone()).unwrap();
+        assert_eq!(legacy.data.schema_version, SCHEMA_VERSION);
+        assert_eq!(legacy.data.item_history_depth, 3);
+        legacy.save_to_folder().await.unwrap();
+        let saved: serde_json::Value =
+            serde_json::from_slice(&storage.entries()[MAIN_FILE]).unwrap();
+        assert_eq!(saved["schema_version"], SCHEMA_VERSION);
+
+        // ...but caches written by newer versions of the crate are refused, even if they could be deserialized
+        assert!(matches!(
+            Cache::from_storage(storage_with(br#"{"schema_version": 1000}"#)),
+            Err(CacheError::UnsupportedSchemaVersion(1000))
+        ));
+    }
+
+    #[tokio::test]
+    async fn cache_metadata() {
+        let storage = Arc::new(storage::MemoryStorage::new());
+        let mut cache = Cache::with_storage(storage.clone());
+        assert_eq!(cache.get_meta::<String>("view").unwrap(), None);
+
+        cache.set_meta("view", &"agenda").unwrap();
+        cache
+            .set_meta("hidden", &vec!["https://caldav.com/a"])
+            .unwrap();
+        cache.save_to_folder().await.unwrap();
+
+        let mut restored = Cache::from_storage(Arc::new(storage::MemoryStorage::from_entries(
+            storage.entries(),
+        )))
+        .unwrap();
+        assert_eq!(
+            restored.get_meta::<String>("view").unwrap(),
+            Some("agenda".to_string())
+        );
+        assert_eq!(
+            restored.get_meta::<Vec<String>>("hidden").unwrap(),
+            Some(vec!["https://caldav.com/a".to_string()])
+        );
+        assert!(restored.get_meta::<u32>("view").is_err());
+
+        assert!(restored.remove_meta("view"));
+        assert!(!restored.remove_meta("view"));
+        assert_eq!(restored.meta_keys().collect::<Vec<_>>(), vec!["hidden"]);
+    }
+
+    #[tokio::test]
+    async fn cache_in_memory_storage() {
+        let storage = Arc::new(storage::MemoryStorage::new());
+        let mut cache = Cache::with_storage(storage.clone());
+        cache
+            .create_calendar(
+                Url::parse("https://caldav.com/memory").unwrap(),
+                "In memory".to_string(),
+                SupportedComponents::TODO,
+                None,
+            )
+            .await
+            .unwrap();
+        cache.save_to_folder().await.unwrap();
+        assert_eq!(storage.entries().len(), 2);
+
+        let restored = Cache::from_storage(Arc::new(storage::MemoryStorage::from_entries(
+            storage.entries(),
+        )))
+        .unwrap();
+        assert!(cache
+            .has_same_observable_content_as(&restored, "cache", "restored cache")
+            .await
+            .unwrap());
+        assert!(Cache::from_storage(Arc::new(storage::MemoryStorage::new())).is_err());
+    }
+
+    #[tokio::test]
+    async fn cache_file_names() {
+        let storage = Arc::new(storage::MemoryStorage::new());
+        let mut cache = Cache::with_storage(storage.clone());
+        // These are sanitized into the same file name
+        let urls = [
+            Url::parse("https://bücher.example/calendars/tâches:/").unwrap(),
+            Url::parse("https://bücher.example/calendars/tâches/").unwrap(),
+        ];
+        assert_eq!(urls[1].host_str(), Some("xn--bcher-kva.example"));
+        for url in &urls {
+            let cal = cache
+                .create_calendar(
+                    url.clone(),
+                    url.path().to_string(),
+                    SupportedComponents::TODO,
+                    None,
+                )
+                .await
+                .unwrap();
+            let task = Task::new("Lire".to_string(), false, url).unwrap();
+            cal.lock().await.add_item(Item::Task(task)).await.unwrap();
+        }
+        cache.save_to_folder().await.unwrap();
+        assert_eq!(storage.entries().len(), 3);
+
+        // Caches written by former versions used the sanitized file name only
+        let mut entries = storage.entries();
+        let content = entries.remove(&Cache::calendar_key(&urls[1])).unwrap();
+        let legacy_key = Cache::legacy_calendar_key(&urls[1]);
+        entries.insert(legacy_key.clone(), content);
+        let storage = Arc::new(storage::MemoryStorage::from_entries(entries));
+        let restored = Cache::from_storage(storage.clone()).unwrap();
+        assert!(cache
+            .has_same_observable_content_as(&restored, "cache", "restored cache")
+            .await
+            .unwrap());
+        restored.save_to_folder().await.unwrap();
+        let entries = storage.entries();
+        assert!(!entries.contains_key(&legacy_key));
+        assert!(entries.contains_key(&Cache::calendar_key(&urls[1])));
+
+        let mut restored = Cache::from_storage(storage.clone()).unwrap();
+        restored.delete_calendar(&urls[1]).await.unwrap();
+        assert_eq!(storage.entries().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn cache_long_urls() {
+        let cache_path = PathBuf::from(String::from("test_cache/long_urls"));
+        let _ = std::fs::remove_dir_all(&cache_path);
+        let mut cache = Cache::new(&cache_path);
+        let url = Url::parse(&format!("https://some.calend.ar/{}/", "é".repeat(200))).unwrap();
+        assert!(Cache::calendar_key(&url).len() <= 255);
+        cache
+            .create_calendar(
+                url.clone(),
+                "Long".to_string(),
+                SupportedComponents::TODO,
+                None,
+            )
+            .await
+            .unwrap();
+        cache.save_to_folder().await.unwrap();
+
+        let retrieved_cache = Cache::from_folder(&cache_path).unwrap();
+        assert!(retrieved_cache.get_calendar(&url).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn cache_folder_lock() {
+        let cache_path = PathBuf::from(String::from("test_cache/locked"));
+        let _ = std::fs::remove_dir_all(&cache_path);
+        let syncing = Cache::new(&cache_path);
+        let other = Cache::new(&cache_path);
+
+        syncing.sync_started().unwrap();
+        assert!(cache_path.join(lock::LOCK_FILE).exists());
+        // The cache that syncs can save itself...
+        syncing.save_to_folder().await.unwrap();
+        // ...but other ones can neither sync nor save
+        assert!(matches!(
+            other.sync_started(),
+            Err(KFError::CacheLocked { .. })
+        ));
+        let err = other.save_to_folder().await.unwrap_err();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<KFError>()),
+            Some(KFError::CacheLocked { .. })
+        ));
+        assert!(matches!(
+            other.try_save_to_folder().await,
+            Err(CacheError::IoError(_))
+        ));
+
+        syncing.sync_finished();
+        assert!(!cache_path.join(lock::LOCK_FILE).exists());
+        other.save_to_folder().await.unwrap();
+        assert!(!cache_path.join(lock::LOCK_FILE).exists());
+
+        // Caches that are not stored in a folder are not locked
+        let in_memory = Cache::with_storage(Arc::new(storage::MemoryStorage::new()));
+        in_memory.sync_started().unwrap();
+        in_memory.save_to_folder().await.unwrap();
+        in_memory.sync_finished();
+    }
+
+    #[tokio::test]
+    async fn cache_interrupted_sync_is_not_saved() {
+        let storage = Arc::new(storage::MemoryStorage::new());
+        let mut cache = Cache::with_storage(storage.clone());
+        let cal_url = Url::parse("https://caldav.com/interrupted").unwrap();
+        let cal = cache
+            .create_calendar(
+                cal_url.clone(),
+                "Interrupted".to_string(),
+                SupportedComponents::TODO,
+                None,
+            )
+            .await
+            .unwrap();
+        cache.try_save_to_folder().await.unwrap();
+        let saved = storage.entries();
+
+        // A sync that has been cancelled while it was applying changes
+        {
+            let mut cal = cal.lock().await;
+            cal.set_sync_in_progress(true);
+            let task = Task::new("Half-synced".to_string(), false, &cal_url).unwrap();
+            cal.add_item(Item::Task(task)).await.unwrap();
+        }
+        match cache.try_save_to_folder().await {
+            Err(CacheError::InterruptedSync { calendars }) => {
+                assert_eq!(calendars, vec![cal_url.clone()])
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        cache.save_to_folder().await.unwrap();
+        let key = Cache::calendar_key(&cal_url);
+        assert_eq!(storage.entries().get(&key), saved.get(&key));
+
+        // Until the next sync of this calendar is done
+        cal.lock().await.set_sync_in_progress(false);
+        cache.try_save_to_folder().await.unwrap();
+        assert_ne!(storage.entries().get(&key), saved.get(&key));
+    }
+
     #[tokio::test]
     async fn cache_sanity_checks() {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -443,4 +1241,156 @@ mod tests {
             .await;
         assert!(second_addition_same_calendar.is_err());
     }
+
+    #[tokio::test]
+    async fn cache_inspection() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/inspection_test"));
+        let cache = populate_cache(&cache_path).await;
+
+        let report = inspect::inspect(&cache).await;
+        assert_eq!(report.schema_version, SCHEMA_VERSION);
+        assert_eq!(report.calendars.len(), 2);
+        let bucket_list = report
+            .calendars
+            .iter()
+            .find(|c| c.name == "My bucket list")
+            .unwrap();
+        assert_eq!(bucket_list.item_count, 2);
+        assert_eq!(bucket_list.pending_changes.additions, 2);
+        assert_eq!(bucket_list.color.as_deref(), Some("#ff8000"));
+        assert_eq!(report.pending_changes(), 2);
+
+        // Reports survive a JSON round-trip
+        let json = report.to_json().unwrap();
+        let parsed: inspect::CacheReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, report);
+    }
+
+    #[tokio::test]
+    async fn cache_observable_diff() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/diff_test"));
+        let cache = populate_cache(&cache_path).await;
+        cache.save_to_folder().await.unwrap();
+        let mut other = Cache::from_folder(&cache_path).unwrap();
+        assert!(cache
+            .observable_diff(&other, "cache", "other")
+            .await
+            .unwrap()
+            .is_empty());
+
+        let shopping_url = Url::parse("https://caldav.com/shopping").unwrap();
+        let bucket_url = Url::parse("https://caldav.com/bucket-list").unwrap();
+        other.delete_calendar(&shopping_url).await.unwrap();
+        let renamed_url = {
+            let bucket_list = other.get_calendar(&bucket_url).await.unwrap();
+            let mut bucket_list = bucket_list.lock().await;
+            let mut urls: Vec<Url> = bucket_list
+                .get_item_urls()
+                .await
+                .unwrap()
+                .into_iter()
+                .collect();
+            urls.sort();
+            let item = bucket_list.get_item_by_url_mut(&urls[0]).await.unwrap();
+            if let Item::Task(task) = item {
+                task.set_name("Renamed".to_string());
+            }
+            urls[0].clone()
+        };
+
+        let diff = cache
+            .observable_diff(&other, "cache", "other")
+            .await
+            .unwrap();
+        assert_eq!(diff.differences.len(), 2);
+        assert!(diff
+            .differences
+            .contains(&ContentDifference::MissingCalendar {
+                url: shopping_url,
+                missing_from: Side::Right
+            }));
+        assert!(diff.differences.iter().any(|d| matches!(
+            d,
+            ContentDifference::ItemField { calendar, url, difference }
+                if *calendar == bucket_url && *url == renamed_url && difference.field == "name"
+        )));
+        assert!(diff.to_string().contains("Renamed"));
+        assert!(!cache
+            .has_same_observable_content_as(&other, "cache", "other")
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn cache_undo_deletions() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/undo_deletions_test"));
+        let cache = populate_cache(&cache_path).await;
+        assert!(cache.calendars_marked_for_deletion().await.is_empty());
+
+        let bucket_url = Url::parse("https://caldav.com/bucket-list").unwrap();
+        let bucket_list = cache.get_calendar(&bucket_url).await.unwrap();
+        bucket_list.lock().await.mark_for_deletion().await;
+        assert_eq!(
+            cache.calendars_marked_for_deletion().await,
+            vec![bucket_url.clone()]
+        );
+        bucket_list.lock().await.unmark_for_deletion().await;
+        assert!(cache.calendars_marked_for_deletion().await.is_empty());
+
+        let mut bucket_list = bucket_list.lock().await;
+        // Only items that have been synced are kept until the next sync, and can be restored
+        let urls: Vec<Url> = bucket_list
+            .get_item_urls()
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+        let tag = VersionTag::from("synced".to_string());
+        bucket_list
+            .get_item_by_url_mut(&urls[0])
+            .await
+            .unwrap()
+            .set_sync_status(SyncStatus::Synced(tag.clone()));
+        for url in &urls {
+            bucket_list.mark_item_for_deletion(url).await.unwrap();
+        }
+        assert_eq!(
+            bucket_list.items_marked_for_deletion(),
+            vec![urls[0].clone()]
+        );
+        assert!(bucket_list
+            .unmark_item_for_deletion(&urls[1])
+            .await
+            .is_err());
+
+        // Restored items get back the status they had before being marked
+        bucket_list
+            .unmark_item_for_deletion(&urls[0])
+            .await
+            .unwrap();
+        assert!(bucket_list.items_marked_for_deletion().is_empty());
+        let status = |cal: &CachedCalendar| {
+            cal.get_item_by_url_sync(&urls[0])
+                .unwrap()
+                .sync_status()
+                .clone()
+        };
+        assert_eq!(status(&bucket_list), SyncStatus::Synced(tag.clone()));
+
+        bucket_list
+            .get_item_by_url_mut(&urls[0])
+            .await
+            .unwrap()
+            .unwrap_task_mut()
+            .set_name("Renamed".to_string());
+        bucket_list.mark_item_for_deletion(&urls[0]).await.unwrap();
+        bucket_list
+            .unmark_item_for_deletion(&urls[0])
+            .await
+            .unwrap();
+        assert_eq!(status(&bucket_list), SyncStatus::LocallyModified(tag));
+    }
 }
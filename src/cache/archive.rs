@@ -0,0 +1,186 @@
+//! Single-file backups of a [`Cache`]
+//!
+//! The layout of a cache folder is an implementation detail that may change across versions of this crate.
+//! An archive gathers the whole content of a cache into a single gzipped tar file, along with a manifest that describes it, so that applications can offer "backup" and "restore on a new device" features.
+//!
+//! An archive contains `manifest.json` (its first entry), `data.json` and one `.cal` entry per calendar.
+
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::cache::{check_schema_version, parse_data, Cache, CacheError, CacheResult, MAIN_FILE};
+use crate::calendar::cached_calendar::CachedCalendar;
+use crate::traits::BaseCalendar;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// The version of the archive format written by this version of the crate
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+/// A description of the content of an archive
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    /// The version of the archive format. Archives written by newer versions of this crate may not be readable
+    pub format_version: u32,
+    /// The version of the on-disk format of the archived cache (see [`SCHEMA_VERSION`](crate::cache::SCHEMA_VERSION))
+    pub schema_version: u32,
+    /// The version of this crate that has written the archive
+    pub crate_version: String,
+    pub created_at: DateTime<Utc>,
+    pub calendar_count: usize,
+}
+
+impl Cache {
+    /// Write the whole content of this cache into a single archive file
+    pub async fn export_archive(&self, path: &Path) -> CacheResult<ArchiveManifest> {
+        let mut calendars = Vec::new();
+        for cal in self.all_calendars().values() {
+            calendars.push(cal.lock().await.clone());
+        }
+        calendars.sort_by(|a, b| a.url().cmp(b.url()));
+
+        let manifest = ArchiveManifest {
+            format_version: ARCHIVE_FORMAT_VERSION,
+            schema_version: self.data.schema_version,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: crate::clock::now(),
+            calendar_count: calendars.len(),
+        };
+
+        let file = std::fs::File::create(path)?;
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mtime = manifest.created_at.timestamp().max(0) as u64;
+        append_entry(
+            &mut builder,
+            MANIFEST_FILE,
+            &serde_json::to_vec(&manifest)?,
+            mtime,
+        )?;
+        append_entry(
+            &mut builder,
+            MAIN_FILE,
+            &serde_json::to_vec(&self.data)?,
+            mtime,
+        )?;
+        for cal in &calendars {
+            append_entry(
+                &mut builder,
+                &Self::calendar_key(cal.url()),
+                &serde_json::to_vec(cal)?,
+                mtime,
+            )?;
+        }
+        builder.into_inner()?.finish()?;
+        Ok(manifest)
+    }
+
+    /// Read the manifest of an archive, e.g. to show what a backup contains before restoring it
+    pub fn read_archive_manifest(path: &Path) -> CacheResult<ArchiveManifest> {
+        let mut manifest = None;
+        read_entries(path, |name, content| {
+            if name != MANIFEST_FILE {
+                return Ok(true);
+            }
+            manifest = Some(parse_manifest(&content)?);
+            Ok(false)
+        })?;
+        manifest.ok_or_else(|| missing_entry(MANIFEST_FILE))
+    }
+
+    /// Build a cache from the content of an archive.
+    ///
+    /// Its backing folder is `folder`, but nothing is written there until [`Cache::save_to_folder`] is called
+    pub fn import_archive(path: &Path, folder: &Path) -> CacheResult<Self> {
+        let mut manifest = None;
+        let mut data = None;
+        let mut calendars = Vec::new();
+        read_entries(path, |name, content| {
+            if name == MANIFEST_FILE {
+                let parsed = parse_manifest(&content)?;
+                check_schema_version(parsed.schema_version as u64)?;
+                manifest = Some(parsed);
+            } else if manifest.is_none() {
+                // The manifest comes first, so that the format and schema versions are checked before anything else is parsed
+                return Err(missing_entry(MANIFEST_FILE));
+            } else if name == MAIN_FILE {
+                data = Some(parse_data(&content)?);
+            } else if name.ends_with(".cal") {
+                calendars.push(serde_json::from_slice::<CachedCalendar>(&content)?);
+            }
+            Ok(true)
+        })?;
+
+        let data = data.ok_or_else(|| missing_entry(MAIN_FILE))?;
+        for cal in calendars {
+            crate::utils::lock_ignoring_poison(&data.calendars)
+                .insert(cal.url().clone(), Arc::new(Mutex::new(cal)));
+        }
+
+        let mut cache = Self::new(folder);
+        cache.data = data;
+        Ok(cache)
+    }
+}
+
+fn append_entry<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    content: &[u8],
+    mtime: u64,
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    builder.append_data(&mut header, name, content)
+}
+
+/// Call `f` with the name and the content of every entry of an archive, until it returns `false`
+fn read_entries<F>(path: &Path, mut f: F) -> CacheResult<()>
+where
+    F: FnMut(String, Vec<u8>) -> CacheResult<bool>,
+{
+    let file = std::fs::File::open(path).map_err(|err| CacheError::UnableToOpenFile {
+        path: path.to_path_buf(),
+        err,
+    })?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        if !f(name, content)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn parse_manifest(content: &[u8]) -> CacheResult<ArchiveManifest> {
+    let value: serde_json::Value = serde_json::from_slice(content)?;
+    let format_version = value
+        .get("format_version")
+        .and_then(|v| v.as_u64())
+        .unwrap_or_default();
+    if format_version == 0 || format_version > ARCHIVE_FORMAT_VERSION as u64 {
+        return Err(CacheError::UnsupportedArchiveVersion(format_version));
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+fn missing_entry(name: &str) -> CacheError {
+    CacheError::IoError(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Missing {} in archive", name),
+    ))
+}
@@ -0,0 +1,161 @@
+//! A CalDAV source that is only kept in memory
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::calendar::cached_calendar::CachedCalendar;
+use crate::calendar::SupportedComponents;
+use crate::error::{KFError, KFResult};
+use crate::item::ItemType;
+use crate::traits::{BaseCalendar, CalDavSource, CompleteCalendarFactory};
+
+/// A [`CalDavSource`] whose calendars only live in memory, e.g. for unit tests and examples.
+///
+/// Unlike a [`Cache`](crate::cache::Cache), it has no backing folder nor storage: nothing is ever written anywhere, and it is lost once dropped.
+/// Its calendars can be built programmatically, see [`Self::with_calendar`]
+#[derive(Debug, Default)]
+pub struct InMemorySource {
+    calendars: HashMap<Url, Arc<Mutex<CachedCalendar>>>,
+    ignored_calendars: HashSet<Url>,
+}
+
+impl InMemorySource {
+    /// An empty source
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a calendar (that may already contain items), replacing any calendar with the same URL
+    pub fn with_calendar(mut self, calendar: CachedCalendar) -> Self {
+        self.insert_calendar(calendar);
+        self
+    }
+
+    /// Add a calendar (that may already contain items), replacing any calendar with the same URL.
+    /// Returns the calendar, as it would be returned by [`CalDavSource::get_calendar`]
+    pub fn insert_calendar(&mut self, calendar: CachedCalendar) -> Arc<Mutex<CachedCalendar>> {
+        let url = calendar.url().clone();
+        let arc = Arc::new(Mutex::new(calendar));
+        self.ignored_calendars.remove(&url);
+        self.calendars.insert(url, arc.clone());
+        arc
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<CachedCalendar> for InMemorySource {
+    async fn get_calendars(&self) -> KFResult<HashMap<Url, Arc<Mutex<CachedCalendar>>>> {
+        Ok(self.calendars.clone())
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<CachedCalendar>>> {
+        self.calendars.get(url).cloned()
+    }
+
+    async fn create_calendar(
+        &mut self,
+        url: Url,
+        name: String,
+        supported_components: SupportedComponents,
+        color: Option<Color>,
+    ) -> KFResult<Arc<Mutex<CachedCalendar>>> {
+        if self.calendars.contains_key(&url) {
+            return Err(KFError::ItemAlreadyExists {
+                type_: ItemType::Calendar,
+                detail: "Attempt to insert calendar failed".into(),
+                url,
+            });
+        }
+        let calendar = CachedCalendar::new(name, url, supported_components, color);
+        Ok(self.insert_calendar(calendar))
+    }
+
+    async fn delete_calendar(&mut self, url: &Url) -> KFResult<Option<Arc<Mutex<CachedCalendar>>>> {
+        match self.calendars.remove(url) {
+            Some(cal) => Ok(Some(cal)),
+            None => Err(KFError::ItemDoesNotExist {
+                detail: "Can't delete calendar".into(),
+                url: url.clone(),
+                type_: Some(ItemType::Calendar),
+            }),
+        }
+    }
+
+    async fn remove_calendar_local_only(
+        &mut self,
+        url: &Url,
+    ) -> KFResult<Option<Arc<Mutex<CachedCalendar>>>> {
+        self.ignored_calendars.insert(url.clone());
+        Ok(self.calendars.remove(url))
+    }
+
+    fn ignored_calendars(&self) -> HashSet<Url> {
+        self.ignored_calendars.clone()
+    }
+
+    fn stop_ignoring_calendar(&mut self, url: &Url) -> bool {
+        self.ignored_calendars.remove(url)
+    }
+
+    async fn checkpoint_calendar(&self, _calendar: &CachedCalendar) -> KFResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::item::Item;
+    use crate::task::Task;
+    use crate::traits::CompleteCalendar;
+
+    #[tokio::test]
+    async fn test_in_memory_source() {
+        let url: Url = "https://some.calend.ar/memory/".parse().unwrap();
+        let mut calendar = CachedCalendar::new(
+            "Memory".to_string(),
+            url.clone(),
+            SupportedComponents::TODO,
+            None,
+        );
+        let task = Task::new("Buy milk".to_string(), false, &url).unwrap();
+        calendar.add_item(Item::Task(task)).await.unwrap();
+
+        let mut source = InMemorySource::new().with_calendar(calendar);
+        let cal = source.get_calendar(&url).await.unwrap();
+        assert_eq!(cal.lock().await.get_items().await.unwrap().len(), 1);
+        assert!(source
+            .create_calendar(
+                url.clone(),
+                "Again".to_string(),
+                SupportedComponents::TODO,
+                None
+            )
+            .await
+            .is_err());
+
+        source.remove_calendar_local_only(&url).await.unwrap();
+        assert!(source.get_calendars().await.unwrap().is_empty());
+        assert!(source.ignored_calendars().contains(&url));
+        source
+            .create_calendar(
+                url.clone(),
+                "Again".to_string(),
+                SupportedComponents::TODO,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(source.ignored_calendars().is_empty());
+
+        source.delete_calendar(&url).await.unwrap();
+        assert!(source.delete_calendar(&url).await.is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! Machine-readable reports about the content of a [`Cache`]
+//!
+//! These reports are meant for debugging tools (e.g. a companion CLI or a debugging UI), so that users with a broken cache can share a summary of it rather than raw JSON files.
+//! Their serialized format is stable: fields may be added, but existing fields are not renamed nor removed.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::cache::{Cache, CacheResult};
+use crate::calendar::cached_calendar::CachedCalendar;
+use crate::calendar::SupportedComponents;
+use crate::item::ItemType;
+use crate::traits::{BaseCalendar, CompleteCalendar};
+use crate::utils::sync::SyncStatus;
+
+/// A summary of a whole cache
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CacheReport {
+    /// The version of the on-disk format of the cache. Caches written by older versions of this crate are upgraded when they are loaded, this is then [`SCHEMA_VERSION`](crate::cache::SCHEMA_VERSION)
+    pub schema_version: u32,
+    pub backing_folder: PathBuf,
+    pub calendars: Vec<CalendarReport>,
+}
+
+impl CacheReport {
+    /// The number of local changes that the next sync will have to push, in every calendar
+    pub fn pending_changes(&self) -> usize {
+        self.calendars
+            .iter()
+            .map(|c| c.pending_changes.total())
+            .sum()
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+/// A summary of a cached calendar
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CalendarReport {
+    pub url: Url,
+    pub name: String,
+    /// The supported components, e.g. `"VTODO"`
+    pub supported_components: Vec<String>,
+    /// The color, as a hex string
+    pub color: Option<String>,
+    pub marked_for_deletion: bool,
+    pub item_count: usize,
+    pub property_count: usize,
+    pub conflict_count: usize,
+    pub pending_changes: PendingChanges,
+    /// The items, sorted by URL
+    pub items: Vec<ItemReport>,
+}
+
+/// The number of local changes that have not been synced yet
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PendingChanges {
+    pub additions: usize,
+    pub modifications: usize,
+    pub deletions: usize,
+}
+
+impl PendingChanges {
+    pub fn total(&self) -> usize {
+        self.additions + self.modifications + self.deletions
+    }
+
+    fn count(&mut self, sync_status: &SyncStatus) {
+        match sync_status {
+            SyncStatus::NotSynced => self.additions += 1,
+            SyncStatus::Synced(_) => (),
+            SyncStatus::LocallyModified(_) => self.modifications += 1,
+            SyncStatus::LocallyDeleted(_) => self.deletions += 1,
+        }
+    }
+}
+
+/// A summary of a cached item
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemReport {
+    pub url: Url,
+    pub name: String,
+    /// `"task"` or `"event"`
+    pub item_type: String,
+    /// One of `"not_synced"`, `"synced"`, `"locally_modified"`, `"locally_deleted"`
+    pub sync_status: String,
+    /// The version tag (etag) of the item when it was last synced, if any
+    pub version_tag: Option<String>,
+}
+
+/// Build a report about a cache
+pub async fn inspect(cache: &Cache) -> CacheReport {
+    let mut calendars = Vec::new();
+    for cal in cache.all_calendars().values() {
+        calendars.push(inspect_calendar(&*cal.lock().await).await);
+    }
+    calendars.sort_by(|a, b| a.url.cmp(&b.url));
+
+    CacheReport {
+        schema_version: cache.data.schema_version,
+        backing_folder: cache.backing_folder.clone(),
+        calendars,
+    }
+}
+
+/// Build a report about the cache stored in a folder, without modifying it
+pub async fn inspect_folder(folder: &Path) -> CacheResult<CacheReport> {
+    Ok(inspect(&Cache::from_folder(folder)?).await)
+}
+
+/// Build a report about a single calendar
+pub async fn inspect_calendar(cal: &CachedCalendar) -> CalendarReport {
+    let mut pending_changes = PendingChanges::default();
+    let mut items = Vec::new();
+    for item in cal.get_items_sync().values() {
+        pending_changes.count(item.sync_status());
+        let (sync_status, version_tag) = describe_sync_status(item.sync_status());
+        items.push(ItemReport {
+            url: item.url().clone(),
+            name: item.name().to_string(),
+            item_type: match item.type_() {
+                ItemType::Calendar => "calendar",
+                ItemType::Event => "event",
+                ItemType::Task => "task",
+            }
+            .to_string(),
+            sync_status: sync_status.to_string(),
+            version_tag,
+        });
+    }
+    items.sort_by(|a, b| a.url.cmp(&b.url));
+
+    let mut supported_components = Vec::new();
+    if cal
+        .supported_components()
+        .contains(SupportedComponents::EVENT)
+    {
+        supported_components.push("VEVENT".to_string());
+    }
+    if cal
+        .supported_components()
+        .contains(SupportedComponents::TODO)
+    {
+        supported_components.push("VTODO".to_string());
+    }
+
+    CalendarReport {
+        url: cal.url().clone(),
+        name: cal.name().to_string(),
+        supported_components,
+        color: cal.color().map(|c| c.to_hex_string()),
+        marked_for_deletion: cal.marked_for_deletion().await,
+        item_count: items.len(),
+        property_count: cal.get_properties().await.len(),
+        conflict_count: cal.get_conflicts().await.conflicts().len(),
+        pending_changes,
+        items,
+    }
+}
+
+pub(crate) fn describe_sync_status(sync_status: &SyncStatus) -> (&'static str, Option<String>) {
+    match sync_status {
+        SyncStatus::NotSynced => ("not_synced", None),
+        SyncStatus::Synced(vt) => ("synced", Some(vt.as_str().to_string())),
+        SyncStatus::LocallyModified(vt) => ("locally_modified", Some(vt.as_str().to_string())),
+        SyncStatus::LocallyDeleted(vt) => ("locally_deleted", Some(vt.as_str().to_string())),
+    }
+}
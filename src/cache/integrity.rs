@@ -0,0 +1,315 @@
+//! Consistency checks of the content of a [`Cache`]
+//!
+//! Long-lived caches may end up in states that the sync algorithm does not expect (e.g. after a crash in the middle of a sync, or after a bug in an older version of this crate).
+//! [`Cache::check_integrity`] lists them, and [`Cache::repair`] fixes the ones that can be fixed without losing data.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::cache::Cache;
+use crate::traits::CompleteCalendar;
+use crate::utils::sync::{SyncStatus, Syncable};
+use crate::Item;
+
+/// The iCal properties that must contain a DATE or a DATE-TIME
+const DATE_PROPERTIES: [&str; 4] = ["DUE", "DTSTART", "DTEND", "RECURRENCE-ID"];
+
+/// An inconsistency found in a cache
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IntegrityIssue {
+    /// An item is marked for deletion, in a calendar that is itself marked for deletion. Deleting the calendar will delete it anyway
+    OrphanedTombstone { calendar: Url, url: Url },
+    /// A task has never been synced, but remembers which of its fields have changed since its last sync
+    UnsyncedWithSyncState { calendar: Url, url: Url },
+    /// Several items have the same UID
+    DuplicateUid { uid: String, urls: Vec<Url> },
+    /// An item is stored under a URL that is not its own
+    UrlMismatch { calendar: Url, key: Url, url: Url },
+    /// The URL of an item that has never been synced is not inside the calendar that contains it.
+    /// Synced items may be anywhere, since some servers store items outside of their calendar collections
+    ItemOutsideCalendar { calendar: Url, url: Url },
+    /// A property of a task should contain a date, but its value cannot be parsed
+    UnparsableDate {
+        calendar: Url,
+        url: Url,
+        property: String,
+        value: String,
+    },
+}
+
+impl IntegrityIssue {
+    /// Whether [`Cache::repair`] is able to fix this issue
+    pub fn is_repairable(&self) -> bool {
+        match self {
+            Self::OrphanedTombstone { .. }
+            | Self::UnsyncedWithSyncState { .. }
+            | Self::DuplicateUid { .. }
+            | Self::UrlMismatch { .. } => true,
+            Self::ItemOutsideCalendar { .. } | Self::UnparsableDate { .. } => false,
+        }
+    }
+}
+
+/// The result of [`Cache::check_integrity`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Whether no issue has been found
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl Cache {
+    /// Look for inconsistencies in every calendar of this cache
+    pub async fn check_integrity(&self) -> IntegrityReport {
+        let mut issues = Vec::new();
+        let mut uids: HashMap<String, Vec<Url>> = HashMap::new();
+
+        let all_calendars = self.all_calendars();
+        let mut calendars: Vec<&Url> = all_calendars.keys().collect();
+        calendars.sort();
+        for cal_url in calendars {
+            let cal = all_calendars[cal_url].lock().await;
+            let cal_deleted = cal.marked_for_deletion().await;
+            let mut items: Vec<(Url, &Item)> = cal.get_items_sync().into_iter().collect();
+            items.sort_by(|a, b| a.0.cmp(&b.0));
+
+            for (key, item) in items {
+                let task = match item {
+                    Item::Task(task) => task,
+                    Item::Event(_) => continue,
+                };
+                let url = task.url();
+                if &key != url {
+                    issues.push(IntegrityIssue::UrlMismatch {
+                        calendar: cal_url.clone(),
+                        key: key.clone(),
+                        url: url.clone(),
+                    });
+                }
+                let outside =
+                    url.origin() != cal_url.origin() || !url.path().starts_with(cal_url.path());
+                if outside && task.sync_status() == &SyncStatus::NotSynced {
+                    issues.push(IntegrityIssue::ItemOutsideCalendar {
+                        calendar: cal_url.clone(),
+                        url: url.clone(),
+                    });
+                }
+                match task.sync_status() {
+                    SyncStatus::LocallyDeleted(_) if cal_deleted => {
+                        issues.push(IntegrityIssue::OrphanedTombstone {
+                            calendar: cal_url.clone(),
+                            url: key.clone(),
+                        })
+                    }
+                    SyncStatus::NotSynced if !task.local_changes().is_empty() => {
+                        issues.push(IntegrityIssue::UnsyncedWithSyncState {
+                            calendar: cal_url.clone(),
+                            url: key.clone(),
+                        })
+                    }
+                    _ => (),
+                }
+                for prop in task.extra_parameters() {
+                    if !DATE_PROPERTIES.contains(&prop.name.as_str()) {
+                        continue;
+                    }
+                    let value = prop.value.as_deref().unwrap_or_default();
+                    if crate::ical::parse_date_or_date_time(value).is_none() {
+                        issues.push(IntegrityIssue::UnparsableDate {
+                            calendar: cal_url.clone(),
+                            url: key.clone(),
+                            property: prop.name.clone(),
+                            value: value.to_string(),
+                        });
+                    }
+                }
+                uids.entry(task.uid().to_string()).or_default().push(key);
+            }
+        }
+
+        let mut duplicates: Vec<(String, Vec<Url>)> = uids
+            .into_iter()
+            .filter(|(_, urls)| urls.len() > 1)
+            .collect();
+        duplicates.sort();
+        for (uid, urls) in duplicates {
+            issues.push(IntegrityIssue::DuplicateUid { uid, urls });
+        }
+
+        IntegrityReport { issues }
+    }
+
+    /// Fix the repairable issues of a report (see [`IntegrityIssue::is_repairable`]), and return the issues that have been fixed.
+    ///
+    /// * orphaned tombstones are removed
+    /// * the sync state of unsynced tasks is forgotten
+    /// * tasks that share their UID with other ones are given new UIDs, provided they have never been synced (so that the server copies are left untouched)
+    /// * items stored under the wrong URL are moved to their own URL
+    pub async fn repair(&mut self, report: &IntegrityReport) -> Vec<IntegrityIssue> {
+        let mut repaired = Vec::new();
+        for issue in &report.issues {
+            if self.repair_issue(issue).await {
+                repaired.push(issue.clone());
+            }
+        }
+        repaired
+    }
+
+    async fn repair_issue(&mut self, issue: &IntegrityIssue) -> bool {
+        let calendars = self.all_calendars();
+        match issue {
+            IntegrityIssue::OrphanedTombstone { calendar, url } => match calendars.get(calendar) {
+                None => false,
+                Some(cal) => cal.lock().await.immediately_delete_item_sync(url).is_ok(),
+            },
+            IntegrityIssue::UnsyncedWithSyncState { calendar, url } => {
+                let cal = match calendars.get(calendar) {
+                    None => return false,
+                    Some(cal) => cal,
+                };
+                match cal.lock().await.get_item_by_url_mut_sync(url) {
+                    Some(Item::Task(task)) => {
+                        task.clear_local_changes();
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            IntegrityIssue::DuplicateUid { urls, .. } => {
+                let mut unsynced = Vec::new();
+                let mut any_synced = false;
+                for cal in calendars.values() {
+                    let cal = cal.lock().await;
+                    for url in urls {
+                        match cal.get_item_by_url_sync(url).map(|item| item.sync_status()) {
+                            None => (),
+                            Some(SyncStatus::NotSynced) => unsynced.push(url.clone()),
+                            Some(_) => any_synced = true,
+                        }
+                    }
+                }
+                unsynced.sort();
+                // The first unsynced task keeps its UID, unless a synced task already has it
+                let to_rename = if any_synced {
+                    &unsynced[..]
+                } else {
+                    unsynced.get(1..).unwrap_or_default()
+                };
+                if to_rename.is_empty() {
+                    return false;
+                }
+                for cal in calendars.values() {
+                    let mut cal = cal.lock().await;
+                    for url in to_rename {
+                        if let Some(Item::Task(task)) = cal.get_item_by_url_mut_sync(url) {
+                            task.set_uid(crate::uid::new_uid());
+                        }
+                    }
+                }
+                true
+            }
+            IntegrityIssue::UrlMismatch { calendar, key, .. } => match calendars.get(calendar) {
+                None => false,
+                Some(cal) => cal.lock().await.rekey_item(key),
+            },
+            IntegrityIssue::ItemOutsideCalendar { .. } | IntegrityIssue::UnparsableDate { .. } => {
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use ical::property::Property;
+
+    use crate::calendar::SupportedComponents;
+    use crate::traits::{BaseCalendar, CalDavSource};
+    use crate::utils::sync::VersionTag;
+    use crate::Task;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_and_repair_integrity() {
+        let cal_url: Url = "https://some.calend.ar/integrity/".parse().unwrap();
+        let mut cache = Cache::new(&PathBuf::from("test_cache/integrity_test"));
+        let cal = cache
+            .create_calendar(
+                cal_url.clone(),
+                "Integrity".to_string(),
+                SupportedComponents::TODO,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(cache.check_integrity().await.is_ok());
+
+        let first = Task::new("First".to_string(), false, &cal_url).unwrap();
+        let mut second = Task::new("Second".to_string(), false, &cal_url).unwrap();
+        second.set_uid(first.uid().to_string());
+        second.set_extra_parameters_named(
+            "DUE",
+            vec![Property {
+                name: "DUE".to_string(),
+                params: None,
+                value: Some("tomorrow".to_string()),
+            }],
+        );
+        let mut deleted = Task::new("Deleted".to_string(), false, &cal_url).unwrap();
+        deleted.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("v1"))));
+        let (first_url, second_url, deleted_url) = (
+            first.url().clone(),
+            second.url().clone(),
+            deleted.url().clone(),
+        );
+        {
+            let mut cal = cal.lock().await;
+            for task in [first, second, deleted] {
+                cal.add_item(Item::Task(task)).await.unwrap();
+            }
+            cal.mark_item_for_deletion(&deleted_url).await.unwrap();
+            cal.mark_for_deletion().await;
+        }
+
+        let report = cache.check_integrity().await;
+        let mut expected_urls = vec![first_url.clone(), second_url.clone()];
+        expected_urls.sort();
+        assert_eq!(report.issues.len(), 3);
+        assert!(report.issues.contains(&IntegrityIssue::OrphanedTombstone {
+            calendar: cal_url.clone(),
+            url: deleted_url.clone(),
+        }));
+        assert!(report.issues.contains(&IntegrityIssue::UnparsableDate {
+            calendar: cal_url.clone(),
+            url: second_url.clone(),
+            property: "DUE".to_string(),
+            value: "tomorrow".to_string(),
+        }));
+        assert!(matches!(
+            report.issues.last(),
+            Some(IntegrityIssue::DuplicateUid { urls, .. }) if urls == &expected_urls
+        ));
+
+        let repaired = cache.repair(&report).await;
+        assert_eq!(repaired.len(), 2);
+        assert!(repaired.iter().all(|issue| issue.is_repairable()));
+        let remaining = cache.check_integrity().await;
+        assert_eq!(remaining.issues.len(), 1);
+        assert!(!remaining.issues[0].is_repairable());
+        assert!(cal
+            .lock()
+            .await
+            .get_item_by_url_sync(&deleted_url)
+            .is_none());
+    }
+}
@@ -0,0 +1,241 @@
+//! An advisory lock on the folder of a [`Cache`](crate::cache::Cache), so that two processes do not sync or save the same cache at the same time
+//!
+//! The lock is a file of the folder, that tells who holds it. It is removed when the lock is released.
+//! A lock file that has been left behind (e.g. by a process that crashed) is considered stale, and is taken over, in case
+//! its holder is a process of this machine that does not run anymore, or in case its holder has not refreshed it for [`STALE_LOCK_AGE`].
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{KFError, KFResult};
+
+/// The name of the lock file, in the folder of the cache
+pub const LOCK_FILE: &str = ".lock";
+
+/// How long a lock can go without being refreshed before it is considered stale.
+/// Its holder refreshes it whenever it saves the cache, and after every calendar of a sync
+pub const STALE_LOCK_AGE: Duration = Duration::from_secs(30 * 60);
+
+/// Who holds a lock, as written in the lock file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct LockHolder {
+    pid: u32,
+    host: String,
+    /// Tells this lock from another lock taken later by the same process
+    token: String,
+    refreshed_at: DateTime<Utc>,
+}
+
+impl LockHolder {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            host: hostname(),
+            token: uuid::Uuid::new_v4().to_simple().to_string(),
+            refreshed_at: Utc::now(),
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        let age = Utc::now()
+            .signed_duration_since(self.refreshed_at)
+            .to_std()
+            .unwrap_or(Duration::ZERO);
+        age > STALE_LOCK_AGE
+            || (!self.host.is_empty()
+                && self.host == hostname()
+                && process_is_running(self.pid) == Some(false))
+    }
+
+    fn describe(&self) -> String {
+        let host = if self.host.is_empty() {
+            "an unknown host"
+        } else {
+            &self.host
+        };
+        format!(
+            "process {} on {} (last seen at {})",
+            self.pid, host, self.refreshed_at
+        )
+    }
+}
+
+/// A lock held on a cache folder. It is released when dropped
+#[derive(Debug)]
+pub struct FolderLock {
+    path: PathBuf,
+    holder: LockHolder,
+}
+
+impl FolderLock {
+    /// Take the lock of `folder` (which is created if needed), taking over a stale lock if needed.
+    /// Fails with [`KFError::CacheLocked`] in case another process holds it
+    pub fn acquire(folder: &Path) -> KFResult<Self> {
+        let io_error = |source: std::io::Error| KFError::IoError {
+            detail: format!("Unable to lock cache folder {:?}", folder),
+            source,
+        };
+        std::fs::create_dir_all(folder).map_err(io_error)?;
+        let path = folder.join(LOCK_FILE);
+        let holder = LockHolder::current();
+        let content = serde_json::to_vec(&holder).map_err(|err| io_error(err.into()))?;
+
+        // A second attempt is made once a stale lock has been removed
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(&content).map_err(io_error)?;
+                    return Ok(Self { path, holder });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(io_error(err)),
+            }
+            match read_holder(&path) {
+                Some(Ok(other)) if !other.is_stale() => {
+                    return Err(KFError::CacheLocked {
+                        folder: folder.to_path_buf(),
+                        holder: other.describe(),
+                    })
+                }
+                // The file may be being written by its holder right now
+                Some(Err(modified)) if !is_older_than(modified, STALE_LOCK_AGE) => {
+                    return Err(KFError::CacheLocked {
+                        folder: folder.to_path_buf(),
+                        holder: "another process".to_string(),
+                    })
+                }
+                None => continue,
+                _ => {}
+            }
+            log::warn!("Taking over the stale lock of cache folder {:?}", folder);
+            match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(io_error(err)),
+                _ => {}
+            }
+        }
+        Err(KFError::CacheLocked {
+            folder: folder.to_path_buf(),
+            holder: "another process".to_string(),
+        })
+    }
+
+    /// Tell other processes that this lock is still in use, so that they do not consider it stale
+    pub fn refresh(&mut self) {
+        if !self.is_still_held() {
+            log::warn!(
+                "The lock {:?} has been taken over by another process",
+                self.path
+            );
+            return;
+        }
+        self.holder.refreshed_at = Utc::now();
+        let result = serde_json::to_vec(&self.holder)
+            .map_err(std::io::Error::from)
+            .and_then(|content| std::fs::write(&self.path, content));
+        if let Err(err) = result {
+            log::warn!("Unable to refresh the lock {:?}: {}", self.path, err);
+        }
+    }
+
+    fn is_still_held(&self) -> bool {
+        matches!(read_holder(&self.path), Some(Ok(holder)) if holder.token == self.holder.token)
+    }
+}
+
+impl Drop for FolderLock {
+    fn drop(&mut self) {
+        // Do not remove a lock another process has taken over
+        if self.is_still_held() {
+            if let Err(err) = std::fs::remove_file(&self.path) {
+                log::warn!("Unable to release the lock {:?}: {}", self.path, err);
+            }
+        }
+    }
+}
+
+/// The holder of a lock file, or the last modification time of a lock file that cannot be read. `None` in case there is no lock file
+fn read_holder(path: &Path) -> Option<Result<LockHolder, SystemTime>> {
+    let content = match std::fs::read(path) {
+        Err(err) if err.kind() == ErrorKind::NotFound => return None,
+        Err(_) => Vec::new(),
+        Ok(content) => content,
+    };
+    match serde_json::from_slice(&content) {
+        Ok(holder) => Some(Ok(holder)),
+        Err(_) => Some(Err(std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH))),
+    }
+}
+
+fn is_older_than(time: SystemTime, age: Duration) -> bool {
+    time.elapsed().is_ok_and(|elapsed| elapsed > age)
+}
+
+/// The name of this machine, or an empty string in case it is unknown
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Whether a process of this machine is running, in case this can be known
+fn process_is_running(pid: u32) -> Option<bool> {
+    if cfg!(target_os = "linux") {
+        Some(Path::new("/proc").join(pid.to_string()).exists())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folder_lock() {
+        let folder = PathBuf::from("test_cache/folder_lock");
+        let _ = std::fs::remove_dir_all(&folder);
+
+        let mut lock = FolderLock::acquire(&folder).unwrap();
+        assert!(folder.join(LOCK_FILE).exists());
+        assert!(matches!(
+            FolderLock::acquire(&folder),
+            Err(KFError::CacheLocked { .. })
+        ));
+        lock.refresh();
+        assert!(lock.is_still_held());
+        drop(lock);
+        assert!(!folder.join(LOCK_FILE).exists());
+
+        // Locks left behind by dead processes, or that have not been refreshed for long, are taken over
+        let mut dead = LockHolder::current();
+        dead.pid = u32::MAX;
+        let mut old = LockHolder::current();
+        old.host = "another.host".to_string();
+        old.refreshed_at = old.refreshed_at - chrono::Duration::hours(1);
+        for stale in &[old, dead] {
+            let pid_is_checked = !hostname().is_empty() && process_is_running(stale.pid).is_some();
+            if stale.pid == u32::MAX && !pid_is_checked {
+                continue;
+            }
+            std::fs::write(folder.join(LOCK_FILE), serde_json::to_vec(stale).unwrap()).unwrap();
+            let lock = FolderLock::acquire(&folder).unwrap();
+            assert!(lock.is_still_held());
+        }
+
+        // Locks of other hosts are not stale as long as they are refreshed
+        let mut remote = LockHolder::current();
+        remote.host = "another.host".to_string();
+        std::fs::write(folder.join(LOCK_FILE), serde_json::to_vec(&remote).unwrap()).unwrap();
+        assert!(FolderLock::acquire(&folder).is_err());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
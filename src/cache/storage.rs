@@ -0,0 +1,133 @@
+//! Where a [`Cache`](crate::cache::Cache) persists its content
+//!
+//! A cache is stored as a set of named entries (one for the general data, one per calendar).
+//! By default, they are files in a folder (see [`FolderStorage`]), but other backends can be provided, e.g. for platforms that have no filesystem.
+//! In web browsers (wasm32), entries can be stored in IndexedDB (see `IndexedDbStorage`).
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+#[cfg(target_arch = "wasm32")]
+mod indexed_db;
+#[cfg(target_arch = "wasm32")]
+pub use indexed_db::IndexedDbStorage;
+
+/// A backend that stores named entries
+pub trait CacheStorage: std::fmt::Debug + Send + Sync {
+    /// The content of an entry, or `None` in case it does not exist
+    fn read(&self, key: &str) -> std::io::Result<Option<Vec<u8>>>;
+    /// Create or replace an entry
+    fn write(&self, key: &str, content: &[u8]) -> std::io::Result<()>;
+    /// Remove an entry. This fails in case it does not exist
+    fn remove(&self, key: &str) -> std::io::Result<()>;
+    /// The names of every entry
+    fn keys(&self) -> std::io::Result<Vec<String>>;
+}
+
+/// Entries are files of a folder
+#[derive(Clone, Debug)]
+pub struct FolderStorage {
+    folder: PathBuf,
+}
+
+impl FolderStorage {
+    pub fn new(folder: &Path) -> Self {
+        Self {
+            folder: PathBuf::from(folder),
+        }
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+}
+
+impl CacheStorage for FolderStorage {
+    fn read(&self, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.folder.join(key)) {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn write(&self, key: &str, content: &[u8]) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.folder)?;
+        std::fs::write(self.folder.join(key), content)
+    }
+
+    fn remove(&self, key: &str) -> std::io::Result<()> {
+        std::fs::remove_file(self.folder.join(key))
+    }
+
+    fn keys(&self) -> std::io::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for entry in std::fs::read_dir(&self.folder)? {
+            match entry {
+                Err(err) => log::error!("Unable to read dir: {:?}", err),
+                Ok(entry) => {
+                    if let Some(name) = entry.file_name().to_str() {
+                        keys.push(name.to_string());
+                    }
+                }
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// Entries are kept in memory.
+///
+/// This is useful on platforms without a filesystem: applications can persist the [`entries`](MemoryStorage::entries) wherever they can, and restore them with [`MemoryStorage::from_entries`]
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: std::sync::Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_entries(entries: HashMap<String, Vec<u8>>) -> Self {
+        Self {
+            entries: std::sync::Mutex::new(entries),
+        }
+    }
+
+    /// A copy of every entry
+    pub fn entries(&self) -> HashMap<String, Vec<u8>> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<u8>>> {
+        // The entries are always in a consistent state, even if another thread panicked
+        crate::utils::lock_ignoring_poison(&self.entries)
+    }
+}
+
+impl CacheStorage for MemoryStorage {
+    fn read(&self, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.lock().get(key).cloned())
+    }
+
+    fn write(&self, key: &str, content: &[u8]) -> std::io::Result<()> {
+        self.lock().insert(key.to_string(), content.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> std::io::Result<()> {
+        match self.lock().remove(key) {
+            Some(_) => Ok(()),
+            None => Err(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("No entry named {}", key),
+            )),
+        }
+    }
+
+    fn keys(&self) -> std::io::Result<Vec<String>> {
+        Ok(self.lock().keys().cloned().collect())
+    }
+}
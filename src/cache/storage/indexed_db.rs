@@ -0,0 +1,162 @@
+//! Entries stored in IndexedDB, in web browsers (wasm32)
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Mutex;
+
+use idb::{Database, DatabaseEvent, Factory, ObjectStoreParams, TransactionMode};
+use js_sys::Uint8Array;
+use wasm_bindgen::JsValue;
+
+use super::{CacheStorage, MemoryStorage};
+use crate::utils::lock_ignoring_poison;
+
+/// The object store that holds the entries, in every database
+const STORE_NAME: &str = "entries";
+
+/// A change that has not been written to the database yet
+#[derive(Debug)]
+enum PendingChange {
+    Write(Vec<u8>),
+    Remove,
+}
+
+/// Entries are stored in an IndexedDB database, the persistent storage of web browsers.
+///
+/// IndexedDB can only be accessed asynchronously, so every entry is read once by [`IndexedDbStorage::open`], then kept in memory.
+/// Changes are written to the database by [`IndexedDbStorage::flush`], that should be called once the cache has been saved (see [`Cache::save_to_folder`](crate::cache::Cache::save_to_folder))
+#[derive(Debug)]
+pub struct IndexedDbStorage {
+    name: String,
+    entries: MemoryStorage,
+    pending: Mutex<HashMap<String, PendingChange>>,
+}
+
+impl IndexedDbStorage {
+    /// Open (or create) the database `name`, and read every entry it contains
+    pub async fn open(name: &str) -> Result<Self> {
+        let database = open_database(name).await?;
+        let transaction = database
+            .transaction(&[STORE_NAME], TransactionMode::ReadOnly)
+            .map_err(idb_error)?;
+        let store = transaction.object_store(STORE_NAME).map_err(idb_error)?;
+        // Both requests are sent before waiting for either, so that the transaction is still active when the second one is sent
+        let keys = store.get_all_keys(None, None).map_err(idb_error)?;
+        let values = store.get_all(None, None).map_err(idb_error)?;
+        let keys = keys.await.map_err(idb_error)?;
+        let values = values.await.map_err(idb_error)?;
+        transaction.await.map_err(idb_error)?;
+        database.close();
+
+        // Both are sorted by key
+        let mut entries = HashMap::new();
+        for (key, value) in keys.into_iter().zip(values) {
+            let key = key
+                .as_string()
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Unexpected IndexedDB key"))?;
+            entries.insert(key, Uint8Array::new(&value).to_vec());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            entries: MemoryStorage::from_entries(entries),
+            pending: Mutex::default(),
+        })
+    }
+
+    /// Write the changes made since the last flush to the database
+    pub async fn flush(&self) -> Result<()> {
+        let pending: Vec<(String, PendingChange)> =
+            lock_ignoring_poison(&self.pending).drain().collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let result = self.write(&pending).await;
+        if result.is_err() {
+            // They will be written by the next flush, unless they have been changed in the meantime
+            let mut queue = lock_ignoring_poison(&self.pending);
+            for (key, change) in pending {
+                queue.entry(key).or_insert(change);
+            }
+        }
+        result
+    }
+
+    /// Whether some changes have not been written to the database yet
+    pub fn has_pending_changes(&self) -> bool {
+        !lock_ignoring_poison(&self.pending).is_empty()
+    }
+
+    async fn write(&self, changes: &[(String, PendingChange)]) -> Result<()> {
+        let database = open_database(&self.name).await?;
+        let transaction = database
+            .transaction(&[STORE_NAME], TransactionMode::ReadWrite)
+            .map_err(idb_error)?;
+        let store = transaction.object_store(STORE_NAME).map_err(idb_error)?;
+        // The requests are not awaited one by one: the transaction fails as a whole in case one of them fails
+        for (key, change) in changes {
+            let key = JsValue::from_str(key);
+            match change {
+                PendingChange::Write(content) => {
+                    store
+                        .put(&Uint8Array::from(&content[..]).into(), Some(&key))
+                        .map_err(idb_error)?;
+                }
+                PendingChange::Remove => {
+                    store.delete(key).map_err(idb_error)?;
+                }
+            }
+        }
+        let result = transaction
+            .commit()
+            .map_err(idb_error)?
+            .await
+            .map_err(idb_error)?;
+        database.close();
+        if !result.is_committed() {
+            return Err(Error::other("The IndexedDB transaction has been aborted"));
+        }
+        Ok(())
+    }
+}
+
+impl CacheStorage for IndexedDbStorage {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.entries.read(key)
+    }
+
+    fn write(&self, key: &str, content: &[u8]) -> Result<()> {
+        self.entries.write(key, content)?;
+        lock_ignoring_poison(&self.pending)
+            .insert(key.to_string(), PendingChange::Write(content.to_vec()));
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.entries.remove(key)?;
+        lock_ignoring_poison(&self.pending).insert(key.to_string(), PendingChange::Remove);
+        Ok(())
+    }
+
+    fn keys(&self) -> Result<Vec<String>> {
+        self.entries.keys()
+    }
+}
+
+async fn open_database(name: &str) -> Result<Database> {
+    let factory = Factory::new().map_err(idb_error)?;
+    let mut request = factory.open(name, Some(1)).map_err(idb_error)?;
+    request.on_upgrade_needed(|event| {
+        let created = event.database().and_then(|database| {
+            database.create_object_store(STORE_NAME, ObjectStoreParams::new())
+        });
+        if let Err(err) = created {
+            log::error!("Unable to create the IndexedDB object store: {}", err);
+        }
+    });
+    request.await.map_err(idb_error)
+}
+
+/// IndexedDB errors hold JS values, that cannot be sent to other threads
+fn idb_error(err: idb::Error) -> Error {
+    Error::other(err.to_string())
+}
@@ -0,0 +1,266 @@
+//! Transactions and undo/redo of the local changes made to a [`Cache`]
+//!
+//! Local changes are normally pushed to the server on the next sync, including accidental ones.
+//! Grouping changes into a [`Transaction`] makes it possible to roll them back, or to undo them later with [`Cache::undo`].
+//!
+//! An undone change is not necessarily a change that never happened: in case a sync has occurred in the meantime, undoing it
+//! marks the restored items as locally modified (or re-created, or deleted), so that the next sync reverts the server as well.
+
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::cache::Cache;
+use crate::calendar::cached_calendar::CachedCalendar;
+
+/// How many transactions can be undone
+pub const MAX_UNDO_STEPS: usize = 50;
+
+/// A copy of every calendar of a cache
+#[derive(Clone, Debug)]
+struct CacheSnapshot {
+    calendars: HashMap<Url, CachedCalendar>,
+}
+
+/// The snapshots taken before the last transactions, and after the last undone ones
+#[derive(Debug, Default)]
+pub(super) struct UndoStack {
+    undo: Vec<CacheSnapshot>,
+    redo: Vec<CacheSnapshot>,
+}
+
+impl UndoStack {
+    fn push(&mut self, before: CacheSnapshot) {
+        self.undo.push(before);
+        if self.undo.len() > MAX_UNDO_STEPS {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+}
+
+/// A group of local changes made to a [`Cache`], that can be rolled back as a whole.
+///
+/// It is created by [`Cache::begin_transaction`], and gives access to the cache it modifies.
+/// Dropping it without [`rolling it back`](Transaction::rollback) commits it.
+#[derive(Debug)]
+pub struct Transaction<'a> {
+    cache: &'a mut Cache,
+    /// `None` once this transaction has been committed or rolled back
+    before: Option<CacheSnapshot>,
+}
+
+impl<'a> Transaction<'a> {
+    /// Keep the changes made during this transaction. They can still be undone with [`Cache::undo`]
+    pub fn commit(self) {
+        // Dropping a transaction commits it
+    }
+
+    /// Discard every change made to the calendars since the transaction began
+    pub async fn rollback(mut self) {
+        if let Some(before) = self.before.take() {
+            self.cache.restore(&before).await;
+        }
+    }
+}
+
+impl<'a> Deref for Transaction<'a> {
+    type Target = Cache;
+    fn deref(&self) -> &Cache {
+        self.cache
+    }
+}
+
+impl<'a> DerefMut for Transaction<'a> {
+    fn deref_mut(&mut self) -> &mut Cache {
+        self.cache
+    }
+}
+
+impl<'a> Drop for Transaction<'a> {
+    fn drop(&mut self) {
+        if let Some(before) = self.before.take() {
+            self.cache.undo_stack.push(before);
+        }
+    }
+}
+
+impl Cache {
+    /// Start recording the changes made to the items and properties of the calendars of this cache (and the calendars created or removed), so that they can be rolled back.
+    ///
+    /// Note that this locks every calendar in turn: this must not be called while holding the lock of one of them
+    pub async fn begin_transaction(&mut self) -> Transaction<'_> {
+        let before = self.snapshot().await;
+        Transaction {
+            cache: self,
+            before: Some(before),
+        }
+    }
+
+    /// Whether there is a committed transaction to undo
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.undo.is_empty()
+    }
+
+    /// Whether there is an undone transaction to redo
+    pub fn can_redo(&self) -> bool {
+        !self.undo_stack.redo.is_empty()
+    }
+
+    /// Revert the changes of the last committed transaction (and of every change made since then outside of a transaction).
+    /// Returns `false` in case there was nothing to undo
+    pub async fn undo(&mut self) -> bool {
+        let before = match self.undo_stack.undo.pop() {
+            None => return false,
+            Some(before) => before,
+        };
+        let current = self.snapshot().await;
+        self.restore(&before).await;
+        self.undo_stack.redo.push(current);
+        true
+    }
+
+    /// Apply again the changes of the last undone transaction.
+    /// Returns `false` in case there was nothing to redo
+    pub async fn redo(&mut self) -> bool {
+        let after = match self.undo_stack.redo.pop() {
+            None => return false,
+            Some(after) => after,
+        };
+        let current = self.snapshot().await;
+        self.restore(&after).await;
+        self.undo_stack.undo.push(current);
+        true
+    }
+
+    async fn snapshot(&self) -> CacheSnapshot {
+        let mut calendars = HashMap::new();
+        for (url, cal) in self.all_calendars() {
+            calendars.insert(url, cal.lock().await.clone());
+        }
+        CacheSnapshot { calendars }
+    }
+
+    /// Bring the calendars back to a snapshot.
+    /// Calendars created since then are removed from the cache (in case they have been synced already, the next sync will download them again)
+    async fn restore(&mut self, snapshot: &CacheSnapshot) {
+        let current = self.all_calendars();
+        self.calendar_map()
+            .retain(|url, _| snapshot.calendars.contains_key(url));
+        for (url, old) in &snapshot.calendars {
+            match current.get(url) {
+                Some(cal) => cal.lock().await.restore(old),
+                None => {
+                    self.calendar_map().insert(
+                        url.clone(),
+                        Arc::new(Mutex::new(CachedCalendar::recreate(old))),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::calendar::SupportedComponents;
+    use crate::traits::{BaseCalendar, CalDavSource, CompleteCalendar};
+    use crate::utils::sync::{SyncStatus, Syncable, VersionTag};
+    use crate::{Item, Task};
+
+    use super::*;
+
+    async fn task_names(cache: &Cache, cal_url: &Url) -> Vec<String> {
+        let cal = cache.get_calendar(cal_url).await.unwrap();
+        let cal = cal.lock().await;
+        let mut names: Vec<String> = cal
+            .get_items()
+            .await
+            .unwrap()
+            .values()
+            .map(|item| item.name().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_transactions_and_undo() {
+        let cal_url: Url = "https://some.calend.ar/undo/".parse().unwrap();
+        let mut cache = Cache::new(&PathBuf::from("test_cache/undo_test"));
+        let cal = cache
+            .create_calendar(
+                cal_url.clone(),
+                "Undo".to_string(),
+                SupportedComponents::TODO,
+                None,
+            )
+            .await
+            .unwrap();
+        let mut task = Task::new("Synced task".to_string(), false, &cal_url).unwrap();
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("v1"))));
+        let synced_url = task.url().clone();
+        cal.lock().await.add_item(Item::Task(task)).await.unwrap();
+
+        // Rolled back changes
+        {
+            let transaction = cache.begin_transaction().await;
+            let mut cal = cal.lock().await;
+            cal.add_item(Item::Task(
+                Task::new("New task".to_string(), false, &cal_url).unwrap(),
+            ))
+            .await
+            .unwrap();
+            cal.mark_item_for_deletion(&synced_url).await.unwrap();
+            drop(cal);
+            transaction.rollback().await;
+        }
+        assert_eq!(task_names(&cache, &cal_url).await, vec!["Synced task"]);
+        let status = cal
+            .lock()
+            .await
+            .get_item_by_url_sync(&synced_url)
+            .unwrap()
+            .sync_status()
+            .clone();
+        assert!(matches!(status, SyncStatus::Synced(_)));
+        assert!(!cache.can_undo());
+
+        // Committed changes
+        let transaction = cache.begin_transaction().await;
+        cal.lock()
+            .await
+            .get_item_by_url_mut_sync(&synced_url)
+            .unwrap()
+            .unwrap_task_mut()
+            .set_name("Renamed task".to_string());
+        transaction.commit();
+        assert_eq!(task_names(&cache, &cal_url).await, vec!["Renamed task"]);
+
+        assert!(cache.undo().await);
+        assert_eq!(task_names(&cache, &cal_url).await, vec!["Synced task"]);
+        assert!(!cache.undo().await);
+        assert!(cache.redo().await);
+        assert_eq!(task_names(&cache, &cal_url).await, vec!["Renamed task"]);
+
+        // Undoing after a sync makes the next sync revert the server version
+        cal.lock()
+            .await
+            .get_item_by_url_mut_sync(&synced_url)
+            .unwrap()
+            .set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("v2"))));
+        assert!(cache.undo().await);
+        let cal = cal.lock().await;
+        let item = cal.get_item_by_url_sync(&synced_url).unwrap();
+        assert_eq!(item.name(), "Synced task");
+        assert_eq!(
+            item.sync_status(),
+            &SyncStatus::LocallyModified(VersionTag::from(String::from("v2")))
+        );
+    }
+}
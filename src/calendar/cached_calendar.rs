@@ -1,6 +1,7 @@
 use std::collections::{HashMap, HashSet};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use csscolorparser::Color;
 use serde::{Deserialize, Serialize};
 use serde_json_any_key::any_key_map;
@@ -8,11 +9,20 @@ use serde_json_any_key::any_key_map;
 use tokio::sync::Mutex;
 use url::Url;
 
+use crate::calendar::conflict::{Conflict, ConflictJournal};
+use crate::calendar::history::{ItemHistory, ItemVersion};
+use crate::calendar::item_url_policy::ItemUrlPolicy;
+use crate::calendar::modification_index::ModificationIndex;
 use crate::calendar::SupportedComponents;
 use crate::error::KFError;
 use crate::error::KFResult;
-use crate::traits::{BaseCalendar, CompleteCalendar};
+use crate::provider::multi::SourceState;
+use crate::task::CompletionStatus;
+use crate::traits::{BaseCalendar, CompleteCalendar, CompleteCalendarFactory};
+#[cfg(any(test, feature = "integration_tests"))]
+use crate::utils::diff::{ContentDiff, ContentDifference, FieldDifference, Side};
 use crate::utils::prop::Property;
+use crate::utils::sync::ItemSyncState;
 use crate::utils::sync::SyncStatus;
 use crate::utils::sync::Syncable;
 #[cfg(feature = "local_calendar_mocks_remote_calendars")]
@@ -25,20 +35,6 @@ use crate::mock_behaviour::MockBehaviour;
 #[cfg(feature = "local_calendar_mocks_remote_calendars")]
 use std::sync::Arc;
 
-#[cfg(any(test, feature = "local_calendar_mocks_remote_calendars"))]
-fn print_props(desc: &str, props: &HashMap<NamespacedName, Property>) {
-    let ordered = {
-        let mut p: Vec<(&NamespacedName, &Property)> = props.iter().collect();
-        p.sort_by(|a, b| a.0.cmp(b.0));
-        p
-    };
-
-    log::debug!("{}", desc);
-    for (nsn, prop) in ordered {
-        log::debug!("{}: {}", nsn, prop);
-    }
-}
-
 /// A calendar used by the [`cache`](crate::cache) module
 ///
 /// Most of its functionality is provided by the async traits it implements.
@@ -59,9 +55,49 @@ pub struct CachedCalendar {
 
     items: HashMap<Url, Item>,
 
+    /// Local versions of items that have been discarded by sync conflicts
+    #[serde(default)]
+    conflicts: ConflictJournal,
+
+    /// What the additional sources of a `MultiProvider` knew about this calendar, by source ID
+    #[serde(default)]
+    source_states: HashMap<String, SourceState>,
+
+    /// The items whose last sync has failed
+    #[serde(default)]
+    item_sync_failures: HashMap<Url, ItemSyncState>,
+
+    /// Previous versions of the items
+    #[serde(default)]
+    history: ItemHistory,
+
+    /// When the items have last been changed locally
+    #[serde(default)]
+    modifications: ModificationIndex,
+
+    /// The items that are marked for deletion, and that had no local changes when they were marked
+    #[serde(default)]
+    synced_before_deletion: HashSet<Url>,
+
     /// Marks this calendar for deletion.
     /// On the next sync, it should be both deleted on the server and removed from its local container
     deleted: bool,
+
+    /// Whether this calendar has already been synced with its remote counterpart
+    #[serde(default)]
+    synced: bool,
+
+    /// How the URLs of new items are composed
+    #[serde(default)]
+    item_url_policy: Option<ItemUrlPolicy>,
+
+    /// The token of the remote calendar when it has last been synced, see [`DavCalendar::current_token`]
+    #[serde(default)]
+    remote_token: Option<String>,
+
+    /// Whether a sync of this calendar has started and not completed
+    #[serde(skip)]
+    sync_in_progress: bool,
 }
 
 impl CachedCalendar {
@@ -75,7 +111,9 @@ impl CachedCalendar {
     async fn add_item_maybe_mocked(&mut self, item: Item) -> KFResult<SyncStatus> {
         if self.mock_behaviour.is_some() {
             if let Some(b) = self.mock_behaviour.as_ref() {
-                b.lock().await.can_add_item()?;
+                let mut b = b.lock().await;
+                b.can_add_item()?;
+                b.transfers.add_sent(mocked_transfer_size(&item));
             }
             Ok(self.add_or_update_item_force_synced(item))
         } else {
@@ -87,7 +125,9 @@ impl CachedCalendar {
     async fn update_item_maybe_mocked(&mut self, item: Item) -> KFResult<SyncStatus> {
         if self.mock_behaviour.is_some() {
             if let Some(b) = self.mock_behaviour.as_ref() {
-                b.lock().await.can_update_item()?;
+                let mut b = b.lock().await;
+                b.can_update_item()?;
+                b.transfers.add_sent(mocked_transfer_size(&item));
             }
             Ok(self.add_or_update_item_force_synced(item))
         } else {
@@ -99,10 +139,18 @@ impl CachedCalendar {
     fn regular_add_or_update_item(&mut self, item: Item) -> SyncStatus {
         let ss_clone = item.sync_status().clone();
         log::debug!("Adding or updating an item with {:?}", ss_clone);
-        self.items.insert(item.url().clone(), item);
+        self.insert_item(item);
         ss_clone
     }
 
+    /// Insert an item, keeping the version it replaces in the history
+    fn insert_item(&mut self, item: Item) {
+        self.modifications.touch(item.url());
+        if let Some(previous) = self.items.insert(item.url().clone(), item) {
+            self.history.record_owned(previous);
+        }
+    }
+
     fn regular_set_property(&mut self, prop: Property) -> SyncStatus {
         if let Some(p) = self.properties.get_mut(prop.nsn()) {
             //NOTE Should be okay since the key remains the same, thus the hash remains the same
@@ -137,7 +185,7 @@ impl CachedCalendar {
             _ => item.set_sync_status(SyncStatus::random_synced()),
         };
         let ss_clone = item.sync_status().clone();
-        self.items.insert(item.url().clone(), item);
+        self.insert_item(item);
         ss_clone
     }
 
@@ -162,57 +210,124 @@ impl CachedCalendar {
         self_desc: &str,
         other_desc: &str,
     ) -> KFResult<bool> {
-        if self.name != other.name
-            || self.url != other.url
-            || self.supported_components != other.supported_components
-            || self.color != other.color
-        {
-            log::debug!("Calendar properties mismatch");
-            return Ok(false);
+        let diff = self.observable_diff(other, self_desc, other_desc).await?;
+        if !diff.is_empty() {
+            log::debug!("{}", diff);
+        }
+        Ok(diff.is_empty())
+    }
+
+    /// Every difference between two calendars, as far as [`Self::has_same_observable_content_as`] is concerned
+    #[cfg(any(test, feature = "integration_tests"))]
+    pub async fn observable_diff(
+        &self,
+        other: &CachedCalendar,
+        self_desc: &str,
+        other_desc: &str,
+    ) -> KFResult<ContentDiff> {
+        let mut diff = ContentDiff::new(self_desc, other_desc);
+        let attribute = |difference| ContentDifference::CalendarAttribute {
+            calendar: self.url.clone(),
+            difference,
+        };
+        if self.name != other.name {
+            diff.push(attribute(FieldDifference::new(
+                "name",
+                &self.name,
+                &other.name,
+            )));
+        }
+        if self.url != other.url {
+            diff.push(attribute(FieldDifference::new(
+                "url",
+                self.url.as_str(),
+                other.url.as_str(),
+            )));
+        }
+        if self.supported_components != other.supported_components {
+            diff.push(attribute(FieldDifference::new(
+                "supported components",
+                self.supported_components,
+                other.supported_components,
+            )));
+        }
+        if self.color != other.color {
+            diff.push(attribute(FieldDifference::new(
+                "color",
+                self.color.as_ref().map(|c| c.to_hex_string()),
+                other.color.as_ref().map(|c| c.to_hex_string()),
+            )));
         }
 
         let items_l = self.get_items().await?;
         let items_r = other.get_items().await?;
-
-        if !crate::utils::keys_are_the_same(&items_l, &items_r) {
-            log::debug!("Different keys for items");
-            return Ok(false);
-        }
-        for (url_l, item_l) in items_l {
-            let item_r = items_r
-                .get(&url_l)
-                .expect("should not happen, we've just tested keys are the same");
-            if !item_l.has_same_observable_content_as(item_r) {
-                log::debug!("Different items for URL {}:", url_l);
-                log::debug!("{:#?}", item_l);
-                log::debug!("{:#?}", item_r);
-                return Ok(false);
+        let mut urls: Vec<&Url> = items_l.keys().chain(items_r.keys()).collect();
+        urls.sort();
+        urls.dedup();
+        for url in urls {
+            match (items_l.get(url), items_r.get(url)) {
+                (Some(item_l), Some(item_r)) => {
+                    for difference in item_l.observable_differences(item_r) {
+                        diff.push(ContentDifference::ItemField {
+                            calendar: self.url.clone(),
+                            url: url.clone(),
+                            difference,
+                        });
+                    }
+                }
+                (left, _) => diff.push(ContentDifference::MissingItem {
+                    calendar: self.url.clone(),
+                    url: url.clone(),
+                    missing_from: if left.is_none() {
+                        Side::Left
+                    } else {
+                        Side::Right
+                    },
+                }),
             }
         }
 
-        let props_a = <Self as CompleteCalendar>::get_properties(self).await;
-        let props_b = <Self as CompleteCalendar>::get_properties(other).await;
-
-        if !crate::utils::keys_are_the_same(props_a, props_b) {
-            log::debug!("Different keys for props");
-            print_props(self_desc, props_a);
-            print_props(other_desc, props_b);
-            return Ok(false);
-        }
-
-        for (nsn, prop_a) in props_a {
-            let prop_b = props_b
-                .get(nsn)
-                .expect("should not happen, we've just tested keys are the same");
-            if prop_a != prop_b {
-                log::debug!("Different props for nsn {}:", nsn);
-                log::debug!("{}: {:#?}", self_desc, prop_a);
-                log::debug!("{}: {:#?}", other_desc, prop_b);
-                return Ok(false);
+        let props_l = <Self as CompleteCalendar>::get_properties(self).await;
+        let props_r = <Self as CompleteCalendar>::get_properties(other).await;
+        let mut names: Vec<&NamespacedName> = props_l.keys().chain(props_r.keys()).collect();
+        names.sort();
+        names.dedup();
+        for name in names {
+            let property = |difference| ContentDifference::PropertyField {
+                calendar: self.url.clone(),
+                name: name.clone(),
+                difference,
+            };
+            match (props_l.get(name), props_r.get(name)) {
+                (Some(prop_l), Some(prop_r)) => {
+                    if prop_l.value() != prop_r.value() {
+                        diff.push(property(FieldDifference::new(
+                            "value",
+                            prop_l.value(),
+                            prop_r.value(),
+                        )));
+                    }
+                    if prop_l.sync_status() != prop_r.sync_status() {
+                        diff.push(property(FieldDifference::new(
+                            "sync status",
+                            prop_l.sync_status(),
+                            prop_r.sync_status(),
+                        )));
+                    }
+                }
+                (left, _) => diff.push(ContentDifference::MissingProperty {
+                    calendar: self.url.clone(),
+                    name: name.clone(),
+                    missing_from: if left.is_none() {
+                        Side::Left
+                    } else {
+                        Side::Right
+                    },
+                }),
             }
         }
 
-        Ok(true)
+        Ok(diff)
     }
 
     /// The non-async version of [`Self::get_item_urls`]
@@ -230,6 +345,11 @@ impl CachedCalendar {
 
     /// The non-async version of [`Self::get_items_mut`]
     pub fn get_items_mut_sync(&mut self) -> HashMap<Url, &mut Item> {
+        // The items may be about to be edited
+        for item in self.items.values() {
+            self.history.record(item);
+            self.modifications.touch(item.url());
+        }
         self.items
             .iter_mut()
             .map(|(url, item)| (url.clone(), item))
@@ -243,9 +363,40 @@ impl CachedCalendar {
 
     /// The non-async version of [`Self::get_item_by_url_mut`]
     pub fn get_item_by_url_mut_sync<'a>(&'a mut self, url: &Url) -> Option<&'a mut Item> {
+        // The item may be about to be edited
+        if let Some(item) = self.items.get(url) {
+            self.history.record(item);
+            self.modifications.touch(url);
+        }
         self.items.get_mut(url)
     }
 
+    /// Same as [`Self::get_item_by_url_mut_sync`], for several items at once. URLs of items that do not exist are ignored
+    pub fn get_items_by_urls_mut_sync(&mut self, urls: &[Url]) -> HashMap<Url, &mut Item> {
+        let urls: HashSet<&Url> = urls.iter().collect();
+        // The items may be about to be edited
+        for url in &urls {
+            if let Some(item) = self.items.get(*url) {
+                self.history.record(item);
+                self.modifications.touch(url);
+            }
+        }
+        self.items
+            .iter_mut()
+            .filter(|(url, _)| urls.contains(url))
+            .map(|(url, item)| (url.clone(), item))
+            .collect()
+    }
+
+    /// Refuse the local additions and changes that this calendar does not accept.
+    /// Items that come from a remote source (i.e. that are synced) are stored anyway, since the server has accepted them
+    fn check_supported_component(&self, item: &Item) -> KFResult<()> {
+        if let SyncStatus::Synced(_) = item.sync_status() {
+            return Ok(());
+        }
+        self.supported_components.check_item(&self.url, item)
+    }
+
     /// The non-async version of [`Self::add_item`]
     //FIXME misnomer
     pub async fn add_item_sync(&mut self, item: Item) -> KFResult<SyncStatus> {
@@ -256,6 +407,7 @@ impl CachedCalendar {
                 url: item.url().clone(),
             });
         }
+        self.check_supported_component(&item)?;
         #[cfg(not(feature = "local_calendar_mocks_remote_calendars"))]
         return Ok(self.regular_add_or_update_item(item));
 
@@ -273,6 +425,7 @@ impl CachedCalendar {
                 url: item.url().clone(),
             });
         }
+        self.check_supported_component(&item)?;
         #[cfg(not(feature = "local_calendar_mocks_remote_calendars"))]
         return Ok(self.regular_add_or_update_item(item));
 
@@ -293,7 +446,12 @@ impl CachedCalendar {
         self.deleted = true;
     }
 
-    /// The non-async version of [`Self::mark_for_deletion`]
+    /// The non-async version of [`Self::unmark_for_deletion`]
+    pub fn unmark_for_deletion_sync(&mut self) {
+        self.deleted = false;
+    }
+
+    /// The non-async version of [`Self::mark_item_for_deletion`]
     pub fn mark_item_for_deletion_sync(&mut self, item_url: &Url) -> KFResult<()> {
         match self.items.get_mut(item_url) {
             None => Err(KFError::ItemDoesNotExist {
@@ -306,10 +464,12 @@ impl CachedCalendar {
                     SyncStatus::Synced(prev_ss) => {
                         let prev_ss = prev_ss.clone();
                         item.set_sync_status(SyncStatus::LocallyDeleted(prev_ss));
+                        self.synced_before_deletion.insert(item_url.clone());
                     }
                     SyncStatus::LocallyModified(prev_ss) => {
                         let prev_ss = prev_ss.clone();
                         item.set_sync_status(SyncStatus::LocallyDeleted(prev_ss));
+                        self.synced_before_deletion.remove(item_url);
                     }
                     SyncStatus::LocallyDeleted(prev_ss) => {
                         let prev_ss = prev_ss.clone();
@@ -318,13 +478,51 @@ impl CachedCalendar {
                     SyncStatus::NotSynced => {
                         // This was never synced to the server, we can safely delete it as soon as now
                         self.items.remove(item_url);
+                        self.modifications.remove(item_url);
+                        return Ok(());
                     }
                 };
+                self.modifications.touch(item_url);
                 Ok(())
             }
         }
     }
 
+    /// The non-async version of [`Self::unmark_item_for_deletion`]
+    pub fn unmark_item_for_deletion_sync(&mut self, item_url: &Url) -> KFResult<()> {
+        let item = self
+            .items
+            .get_mut(item_url)
+            .ok_or_else(|| KFError::ItemDoesNotExist {
+                type_: None,
+                detail: "Can't unmark item for deletion".into(),
+                url: item_url.clone(),
+            })?;
+        if let SyncStatus::LocallyDeleted(tag) = item.sync_status() {
+            // Items that were synced would otherwise be uploaded for nothing (and could conflict with a newer version on the server)
+            let tag = tag.clone();
+            if self.synced_before_deletion.remove(item_url) {
+                item.relabel_sync_status(SyncStatus::Synced(tag));
+            } else {
+                item.relabel_sync_status(SyncStatus::LocallyModified(tag));
+            }
+            self.modifications.touch(item_url);
+        }
+        Ok(())
+    }
+
+    /// The items this calendar has marked for deletion, that the next sync will delete from the server
+    pub fn items_marked_for_deletion(&self) -> Vec<Url> {
+        let mut urls: Vec<Url> = self
+            .items
+            .iter()
+            .filter(|(_, item)| matches!(item.sync_status(), SyncStatus::LocallyDeleted(_)))
+            .map(|(url, _)| url.clone())
+            .collect();
+        urls.sort();
+        urls
+    }
+
     /// The non-async version of [`Self::immediately_delete_item`]
     pub fn immediately_delete_item_sync(&mut self, item_url: &Url) -> KFResult<()> {
         match self.items.remove(item_url) {
@@ -333,14 +531,155 @@ impl CachedCalendar {
                 detail: "Can't immediately delete item".into(),
                 url: item_url.clone(),
             }),
-            Some(_) => Ok(()),
+            Some(item) => {
+                self.history.record_owned(item);
+                self.modifications.remove(item_url);
+                self.synced_before_deletion.remove(item_url);
+                Ok(())
+            }
         }
     }
 
+    /// The non-async version of [`Self::reapply_conflict`]
+    pub fn reapply_conflict_sync(&mut self, item_url: &Url) -> KFResult<()> {
+        let conflict = self
+            .conflicts
+            .take(item_url)
+            .ok_or_else(|| KFError::ItemDoesNotExist {
+                type_: None,
+                detail: "No conflict has been recorded for this item".into(),
+                url: item_url.clone(),
+            })?;
+        let mut item = conflict.into_local_version();
+
+        let new_status = match self.items.get(item_url).map(|i| i.sync_status()) {
+            Some(SyncStatus::Synced(vt))
+            | Some(SyncStatus::LocallyModified(vt))
+            | Some(SyncStatus::LocallyDeleted(vt)) => SyncStatus::LocallyModified(vt.clone()),
+            // The item is not on the server (anymore), it will have to be created again
+            Some(SyncStatus::NotSynced) | None => SyncStatus::NotSynced,
+        };
+        item.relabel_sync_status(new_status);
+        self.insert_item(item);
+        Ok(())
+    }
+
     pub fn set_name<S: ToString>(&mut self, name: S) {
         self.name = name.to_string();
     }
 
+    /// How many previous versions of each item are kept (see [`CompleteCalendar::item_history`]). `0` (the default) disables the history
+    pub fn history_depth(&self) -> usize {
+        self.history.depth()
+    }
+
+    /// Change how many previous versions of each item are kept. See [`Self::history_depth`]
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history.set_depth(depth)
+    }
+
+    /// The non-async version of [`Self::get_items_modified_since`]
+    pub fn get_items_modified_since_sync(&self, since: DateTime<Utc>) -> HashMap<Url, &Item> {
+        self.modifications
+            .modified_since(since)
+            .filter_map(|url| self.items.get(url).map(|item| (url.clone(), item)))
+            .collect()
+    }
+
+    /// The non-async version of [`Self::item_history`]
+    pub fn item_history_sync(&self, url: &Url) -> Vec<&ItemVersion> {
+        self.history.versions(url, self.items.get(url))
+    }
+
+    /// Store the item that has been stored with the key `key` under its actual URL instead. Returns `false` in case this is not possible (e.g. another item already has this URL)
+    pub(crate) fn rekey_item(&mut self, key: &Url) -> bool {
+        let url = match self.items.get(key) {
+            None => return false,
+            Some(item) => item.url().clone(),
+        };
+        if &url == key || self.items.contains_key(&url) {
+            return false;
+        }
+        if let Some(item) = self.items.remove(key) {
+            self.modifications.remove(key);
+            self.modifications.touch(&url);
+            self.items.insert(url, item);
+        }
+        true
+    }
+
+    /// Bring the items and properties of this calendar back to `snapshot`, an earlier copy of it.
+    /// See [`SyncStatus::restored`] for how the sync statuses are handled
+    pub(crate) fn restore(&mut self, snapshot: &CachedCalendar) {
+        let urls: HashSet<Url> = self
+            .items
+            .keys()
+            .chain(snapshot.items.keys())
+            .cloned()
+            .collect();
+        for url in urls {
+            let old = snapshot.items.get(&url);
+            let current = self.items.get(&url);
+            let restored = match SyncStatus::restored(
+                old.map(|i| i.sync_status()),
+                current.map(|i| i.sync_status()),
+            ) {
+                None => None,
+                Some(status) => {
+                    // Items deleted since the snapshot keep their current content
+                    let mut item = old.or(current).cloned().unwrap();
+                    item.relabel_sync_status(status);
+                    Some(item)
+                }
+            };
+            match restored {
+                Some(item) => self.insert_item(item),
+                None => {
+                    if let Some(previous) = self.items.remove(&url) {
+                        self.history.record_owned(previous);
+                        self.modifications.remove(&url);
+                    }
+                }
+            }
+        }
+
+        let names: HashSet<NamespacedName> = self
+            .properties
+            .keys()
+            .chain(snapshot.properties.keys())
+            .cloned()
+            .collect();
+        for name in names {
+            let old = snapshot.properties.get(&name);
+            let current = self.properties.get(&name);
+            match SyncStatus::restored(
+                old.map(|p| p.sync_status()),
+                current.map(|p| p.sync_status()),
+            ) {
+                None => {
+                    self.properties.remove(&name);
+                }
+                Some(status) => {
+                    let mut prop = old.or(current).cloned().unwrap();
+                    prop.relabel_sync_status(status);
+                    self.properties.insert(name, prop);
+                }
+            }
+        }
+
+        self.deleted = snapshot.deleted;
+    }
+
+    /// A copy of `snapshot` (an earlier copy of a calendar that has been removed since), as if all of its content had been created locally
+    pub(crate) fn recreate(snapshot: &CachedCalendar) -> Self {
+        let mut calendar = snapshot.clone();
+        calendar.items.clear();
+        calendar.properties.clear();
+        calendar.restore(snapshot);
+        calendar.synced = false;
+        calendar
+    }
+
     pub fn get_property_by_name_sync(&self, name: &NamespacedName) -> Option<&Property> {
         self.properties.get(name)
     }
@@ -353,7 +692,8 @@ impl CachedCalendar {
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl BaseCalendar for CachedCalendar {
     fn name(&self) -> &str {
         &self.name
@@ -394,8 +734,7 @@ impl BaseCalendar for CachedCalendar {
     }
 }
 
-#[async_trait]
-impl CompleteCalendar for CachedCalendar {
+impl CompleteCalendarFactory for CachedCalendar {
     fn new(
         name: String,
         url: Url,
@@ -411,10 +750,24 @@ impl CompleteCalendar for CachedCalendar {
             mock_behaviour: None,
             items: HashMap::new(),
             properties: HashMap::new(),
+            conflicts: ConflictJournal::default(),
+            source_states: HashMap::new(),
+            item_sync_failures: HashMap::new(),
+            history: ItemHistory::default(),
+            modifications: ModificationIndex::default(),
+            synced_before_deletion: HashSet::new(),
             deleted: false,
+            synced: false,
+            item_url_policy: None,
+            remote_token: None,
+            sync_in_progress: false,
         }
     }
+}
 
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CompleteCalendar for CachedCalendar {
     async fn get_item_urls(&self) -> KFResult<HashSet<Url>> {
         Ok(self.get_item_urls_sync())
     }
@@ -431,10 +784,26 @@ impl CompleteCalendar for CachedCalendar {
         self.get_item_by_url_sync(url)
     }
 
+    async fn get_items_by_urls<'a>(&'a self, urls: &[Url]) -> Vec<Option<&'a Item>> {
+        urls.iter()
+            .map(|url| self.get_item_by_url_sync(url))
+            .collect()
+    }
+
     async fn get_item_by_url_mut<'a>(&'a mut self, url: &Url) -> Option<&'a mut Item> {
         self.get_item_by_url_mut_sync(url)
     }
 
+    /// Only the given tasks are recorded in the history, not every item of this calendar
+    async fn set_completion_for(
+        &mut self,
+        urls: &[Url],
+        new_status: CompletionStatus,
+    ) -> KFResult<Vec<Url>> {
+        let items = self.get_items_by_urls_mut_sync(urls);
+        crate::calendar::completion::set_completion_status_of(items, urls, new_status)
+    }
+
     async fn get_properties(&self) -> &HashMap<NamespacedName, Property> {
         &self.properties
     }
@@ -475,10 +844,67 @@ impl CompleteCalendar for CachedCalendar {
         self.deleted
     }
 
+    async fn unmark_for_deletion(&mut self) {
+        self.unmark_for_deletion_sync()
+    }
+
+    async fn has_been_synced(&self) -> bool {
+        // Caches written by former versions of this crate do not have the flag, but their synced items tell the same.
+        // (this does not hold for props, since props that are marked for deletion always have a version tag)
+        self.synced
+            || self
+                .items
+                .values()
+                .any(|item| item.sync_status().version_tag().is_some())
+    }
+
+    async fn mark_synced(&mut self) {
+        self.synced = true;
+    }
+
+    fn item_url_policy(&self) -> Option<&ItemUrlPolicy> {
+        self.item_url_policy.as_ref()
+    }
+
+    fn set_item_url_policy(&mut self, policy: Option<ItemUrlPolicy>) {
+        self.item_url_policy = policy;
+    }
+
+    fn remote_token(&self) -> Option<&str> {
+        self.remote_token.as_deref()
+    }
+
+    fn set_remote_token(&mut self, token: Option<String>) {
+        self.remote_token = token;
+    }
+
+    fn sync_in_progress(&self) -> bool {
+        self.sync_in_progress
+    }
+
+    fn set_sync_in_progress(&mut self, in_progress: bool) {
+        self.sync_in_progress = in_progress;
+    }
+
+    fn set_metadata(
+        &mut self,
+        name: String,
+        supported_components: SupportedComponents,
+        color: Option<Color>,
+    ) {
+        self.name = name;
+        self.supported_components = supported_components;
+        self.color = color;
+    }
+
     async fn mark_item_for_deletion(&mut self, item_url: &Url) -> KFResult<()> {
         self.mark_item_for_deletion_sync(item_url)
     }
 
+    async fn unmark_item_for_deletion(&mut self, item_url: &Url) -> KFResult<()> {
+        self.unmark_item_for_deletion_sync(item_url)
+    }
+
     async fn immediately_delete_item(&mut self, item_url: &Url) -> KFResult<()> {
         self.immediately_delete_item_sync(item_url)
     }
@@ -488,6 +914,11 @@ impl CompleteCalendar for CachedCalendar {
             .properties
             .get_mut(nsn)
             .ok_or(KFError::PropertyDoesNotExist(nsn.clone()))?;
+        if let SyncStatus::NotSynced = prop.sync_status() {
+            // This was never synced to the server, we can safely delete it as soon as now
+            self.properties.remove(nsn);
+            return Ok(());
+        }
         prop.mark_for_deletion();
         Ok(())
     }
@@ -499,34 +930,110 @@ impl CompleteCalendar for CachedCalendar {
             Err(KFError::PropertyDoesNotExist(nsn.clone()))
         }
     }
+
+    async fn record_conflict(&mut self, conflict: Conflict) {
+        self.conflicts.record(conflict)
+    }
+
+    async fn get_conflicts<'a>(&'a self) -> &'a ConflictJournal {
+        &self.conflicts
+    }
+
+    async fn reapply_conflict(&mut self, item_url: &Url) -> KFResult<()> {
+        self.reapply_conflict_sync(item_url)
+    }
+
+    async fn get_source_state(&self, source_id: &str) -> SourceState {
+        self.source_states
+            .get(source_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    async fn set_source_state(&mut self, source_id: &str, state: SourceState) {
+        self.source_states.insert(source_id.to_string(), state);
+    }
+
+    async fn item_sync_state(&self, url: &Url) -> ItemSyncState {
+        self.item_sync_failures
+            .get(url)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    async fn failing_items(&self) -> Vec<Url> {
+        let mut urls: Vec<Url> = self.item_sync_failures.keys().cloned().collect();
+        urls.sort();
+        urls
+    }
+
+    async fn record_item_sync_failure(&mut self, url: &Url, error: String) {
+        self.item_sync_failures
+            .entry(url.clone())
+            .or_default()
+            .record_failure(error);
+    }
+
+    async fn clear_item_sync_failures(&mut self, url: &Url) {
+        self.item_sync_failures.remove(url);
+    }
+
+    async fn get_items_modified_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> KFResult<HashMap<Url, &Item>> {
+        Ok(self.get_items_modified_since_sync(since))
+    }
+
+    async fn item_history(&self, url: &Url) -> Vec<&ItemVersion> {
+        self.item_history_sync(url)
+    }
 }
 
 // This class can be used to mock a remote calendar for integration tests
 
 #[cfg(feature = "local_calendar_mocks_remote_calendars")]
-use crate::{resource::Resource, traits::DavCalendar};
+use crate::{
+    resource::Resource,
+    traits::{DavCalendar, DavCalendarFactory},
+};
 
+/// The size an item would have on the wire
 #[cfg(feature = "local_calendar_mocks_remote_calendars")]
-#[async_trait]
-impl DavCalendar for CachedCalendar {
+fn mocked_transfer_size(item: &Item) -> usize {
+    crate::ical::build_from(item)
+        .map(|ical| ical.len())
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "local_calendar_mocks_remote_calendars")]
+impl DavCalendarFactory for CachedCalendar {
     fn new(
         name: String,
         resource: Resource,
         supported_components: SupportedComponents,
         color: Option<Color>,
     ) -> Self {
-        crate::traits::CompleteCalendar::new(
-            name,
-            resource.url().clone(),
-            supported_components,
-            color,
-        )
+        CompleteCalendarFactory::new(name, resource.url().clone(), supported_components, color)
     }
+}
 
+#[cfg(feature = "local_calendar_mocks_remote_calendars")]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for CachedCalendar {
     async fn get_item_version_tags(&self) -> KFResult<HashMap<Url, VersionTag>> {
         #[cfg(feature = "local_calendar_mocks_remote_calendars")]
         if let Some(b) = self.mock_behaviour.as_ref() {
-            b.lock().await.can_get_item_version_tags()?;
+            let mut b = b.lock().await;
+            b.can_get_item_version_tags()?;
+            if b.vanished_calendars.contains(&self.url) {
+                return Err(KFError::ItemDoesNotExist {
+                    type_: Some(crate::item::ItemType::Calendar),
+                    detail: "Mocked calendar has vanished".into(),
+                    url: self.url.clone(),
+                });
+            }
         }
 
         let mut result = HashMap::new();
@@ -550,13 +1057,30 @@ impl DavCalendar for CachedCalendar {
     async fn get_item_by_url(&self, url: &Url) -> KFResult<Option<Item>> {
         #[cfg(feature = "local_calendar_mocks_remote_calendars")]
         if let Some(b) = self.mock_behaviour.as_ref() {
-            b.lock().await.can_get_item_by_url()?;
+            let mut b = b.lock().await;
+            b.can_get_item_by_url()?;
+            if let Some(item) = self.items.get(url) {
+                b.transfers.add_received(mocked_transfer_size(item));
+            }
         }
 
         Ok(self.items.get(url).cloned())
     }
 
     async fn get_items_by_url(&self, urls: &[Url]) -> KFResult<Vec<Option<Item>>> {
+        #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+        if let Some(b) = self.mock_behaviour.as_ref() {
+            let mut b = b.lock().await;
+            if !b.is_suspended && b.rate_limited_batches > 0 {
+                b.rate_limited_batches -= 1;
+                return Err(KFError::RateLimited {
+                    url: self.url.clone(),
+                    status: reqwest::StatusCode::TOO_MANY_REQUESTS,
+                    retry_after: b.rate_limit_retry_after,
+                });
+            }
+        }
+
         let mut v = Vec::new();
         for url in urls {
             v.push(DavCalendar::get_item_by_url(self, url).await?);
@@ -603,4 +1127,27 @@ impl DavCalendar for CachedCalendar {
 
         self.immediately_delete_prop(nsn).await
     }
+
+    /// Mocked calendars derive their token from the versions of their items and from their properties, as a server would do for a ctag
+    async fn current_token(&self) -> KFResult<Option<String>> {
+        let mut versions: Vec<String> = self
+            .items
+            .iter()
+            .map(|(url, item)| {
+                let tag = item.sync_status().version_tag().map(VersionTag::as_str);
+                format!("{} {}", url, tag.unwrap_or_default())
+            })
+            .chain(
+                self.properties
+                    .values()
+                    .map(|prop| format!("{} {}", prop.nsn(), prop.value().as_str())),
+            )
+            .collect();
+        versions.sort();
+        Ok(Some(
+            VersionTag::from_content(&versions.join("\n"))
+                .as_str()
+                .to_string(),
+        ))
+    }
 }
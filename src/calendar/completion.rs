@@ -0,0 +1,352 @@
+//! Propagation of completion statuses between tasks and their subtasks
+//!
+//! Subtasks are linked to their parents with `RELATED-TO` properties (see [`Relationship`](crate::task::Relationship)).
+//! A task is a subtask of another one either when it has a `PARENT` relationship to it, or when its parent has a `CHILD` relationship to it.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use url::Url;
+
+use crate::error::{KFError, KFResult};
+use crate::item::Item;
+use crate::task::CompletionStatus;
+use crate::utils::sync::{SyncStatus, Syncable};
+
+/// How changing the completion status of a task affects its related tasks.
+/// See [`CompleteCalendar::set_completion_status_cascading`](crate::traits::CompleteCalendar::set_completion_status_cascading)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionCascade {
+    /// Only the task itself is changed
+    None,
+    /// Completing a task also completes its subtasks, recursively
+    CompleteChildren,
+    /// Un-completing a task also un-completes its parents, recursively (so that a completed task never has uncompleted subtasks)
+    UncompleteParents,
+    /// Both [`CompletionCascade::CompleteChildren`] and [`CompletionCascade::UncompleteParents`]
+    Both,
+}
+
+impl CompletionCascade {
+    fn completes_children(&self) -> bool {
+        matches!(self, Self::CompleteChildren | Self::Both)
+    }
+
+    fn uncompletes_parents(&self) -> bool {
+        matches!(self, Self::UncompleteParents | Self::Both)
+    }
+}
+
+/// Set the completion status of a task among `items`, and cascade it as defined by `cascade`.
+///
+/// Every changed task is marked as locally modified. Related tasks that already have the right completion status are left untouched.
+/// Returns the URLs of the changed tasks, starting with `task_url`
+pub fn set_completion_status(
+    mut items: HashMap<Url, &mut Item>,
+    task_url: &Url,
+    new_status: CompletionStatus,
+    cascade: CompletionCascade,
+) -> KFResult<Vec<Url>> {
+    let uid = match items.get(task_url) {
+        None => {
+            return Err(KFError::ItemDoesNotExist {
+                type_: None,
+                detail: "Can't set the completion status".into(),
+                url: task_url.clone(),
+            })
+        }
+        Some(Item::Task(task)) => task.uid().to_string(),
+        Some(item) => {
+            return Err(KFError::ItemDoesNotExist {
+                type_: Some(crate::item::ItemType::Task),
+                detail: format!("Can't set the completion status of a {:?}", item.type_()),
+                url: task_url.clone(),
+            })
+        }
+    };
+
+    let related = if new_status.is_completed() && cascade.completes_children() {
+        TaskTree::new(&items).descendants_of(&uid)
+    } else if !new_status.is_completed() && cascade.uncompletes_parents() {
+        TaskTree::new(&items).ancestors_of(&uid)
+    } else {
+        Vec::new()
+    };
+
+    let mut changed = vec![task_url.clone()];
+    if let Some(Item::Task(task)) = items.get_mut(task_url) {
+        task.set_completion_status(new_status.clone());
+    }
+    for url in related {
+        if let Some(Item::Task(task)) = items.get_mut(&url) {
+            if task.completed() != new_status.is_completed() {
+                task.set_completion_status(new_status.clone());
+                changed.push(url);
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Set the completion status of every task of `urls` among `items` at once, e.g. for a "mark all as done" feature.
+///
+/// Tasks that are already completed (or uncompleted) are left untouched, whatever their completion dates. The other ones are marked as locally modified, with the same "last modified" date.
+/// Nothing is changed in case some URLs are not tasks of `items`.
+/// Returns the URLs of the changed tasks, in the order of `urls`
+pub fn set_completion_status_of(
+    mut items: HashMap<Url, &mut Item>,
+    urls: &[Url],
+    new_status: CompletionStatus,
+) -> KFResult<Vec<Url>> {
+    for url in urls {
+        match items.get(url) {
+            Some(Item::Task(_)) => (),
+            Some(item) => {
+                return Err(KFError::ItemDoesNotExist {
+                    type_: Some(crate::item::ItemType::Task),
+                    detail: format!("Can't set the completion status of a {:?}", item.type_()),
+                    url: url.clone(),
+                })
+            }
+            None => {
+                return Err(KFError::ItemDoesNotExist {
+                    type_: None,
+                    detail: "Can't set the completion status".into(),
+                    url: url.clone(),
+                })
+            }
+        }
+    }
+
+    let now = crate::clock::now();
+    let mut changed = Vec::new();
+    for url in urls {
+        if let Some(Item::Task(task)) = items.get_mut(url) {
+            if task.completed() != new_status.is_completed() && !changed.contains(url) {
+                task.set_completion_status_at(new_status.clone(), now);
+                changed.push(url.clone());
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// The parent/child links between the tasks of a calendar
+struct TaskTree {
+    urls: HashMap<String, Url>,
+    children: HashMap<String, HashSet<String>>,
+    parents: HashMap<String, HashSet<String>>,
+}
+
+impl TaskTree {
+    fn new(items: &HashMap<Url, &mut Item>) -> Self {
+        let mut tree = Self {
+            urls: HashMap::new(),
+            children: HashMap::new(),
+            parents: HashMap::new(),
+        };
+
+        for (url, item) in items {
+            let task = match &**item {
+                Item::Task(task) => task,
+                _ => continue,
+            };
+            // Tasks that are about to be deleted are not part of the tree any more
+            if matches!(task.sync_status(), SyncStatus::LocallyDeleted(_)) {
+                continue;
+            }
+            tree.urls.insert(task.uid().to_string(), url.clone());
+            for rel in task.relationships_of_type("PARENT") {
+                tree.link(rel.related_to(), task.uid());
+            }
+            for rel in task.relationships_of_type("CHILD") {
+                tree.link(task.uid(), rel.related_to());
+            }
+        }
+        tree
+    }
+
+    fn link(&mut self, parent: &str, child: &str) {
+        self.children
+            .entry(parent.to_string())
+            .or_default()
+            .insert(child.to_string());
+        self.parents
+            .entry(child.to_string())
+            .or_default()
+            .insert(parent.to_string());
+    }
+
+    fn descendants_of(&self, uid: &str) -> Vec<Url> {
+        self.walk(uid, &self.children)
+    }
+
+    fn ancestors_of(&self, uid: &str) -> Vec<Url> {
+        self.walk(uid, &self.parents)
+    }
+
+    /// Breadth-first walk, that copes with cycles and with relationships to tasks that are not in this calendar
+    fn walk(&self, uid: &str, links: &HashMap<String, HashSet<String>>) -> Vec<Url> {
+        let mut visited = HashSet::new();
+        visited.insert(uid.to_string());
+        let mut queue = VecDeque::from(vec![uid.to_string()]);
+        let mut found = Vec::new();
+
+        while let Some(current) = queue.pop_front() {
+            for next in links.get(&current).into_iter().flatten() {
+                if visited.insert(next.clone()) {
+                    if let Some(url) = self.urls.get(next) {
+                        found.push(url.clone());
+                    }
+                    queue.push_back(next.clone());
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::calendar::cached_calendar::CachedCalendar;
+    use crate::calendar::SupportedComponents;
+    use crate::task::Relationship;
+    use crate::traits::{BaseCalendar, CompleteCalendar, CompleteCalendarFactory};
+    use crate::Task;
+
+    #[tokio::test]
+    async fn test_completion_cascade() {
+        let cal_url: Url = "https://some.calend.ar/cascade/".parse().unwrap();
+        let mut cal = CachedCalendar::new(
+            "Cascade".to_string(),
+            cal_url.clone(),
+            SupportedComponents::TODO,
+            None,
+        );
+
+        let mut parent = Task::new("Parent".to_string(), false, &cal_url).unwrap();
+        let mut child = Task::new("Child".to_string(), false, &cal_url).unwrap();
+        child.set_parent(parent.uid().to_string());
+        let mut grandchild = Task::new("Grandchild".to_string(), false, &cal_url).unwrap();
+        parent.add_relationship(Relationship::new(
+            grandchild.uid().to_string(),
+            Some("CHILD".to_string()),
+        ));
+        grandchild.set_parent(child.uid().to_string());
+        let other = Task::new("Other".to_string(), false, &cal_url).unwrap();
+
+        let urls: Vec<Url> = [&parent, &child, &grandchild, &other]
+            .iter()
+            .map(|t| t.url().clone())
+            .collect();
+        for task in [parent, child, grandchild, other] {
+            cal.add_item(Item::Task(task)).await.unwrap();
+        }
+
+        let completed = CompletionStatus::Completed(None);
+        let changed = cal
+            .set_completion_status_cascading(&urls[0], completed.clone(), CompletionCascade::None)
+            .await
+            .unwrap();
+        assert_eq!(changed, vec![urls[0].clone()]);
+
+        let changed = cal
+            .set_completion_status_cascading(&urls[0], completed, CompletionCascade::Both)
+            .await
+            .unwrap();
+        assert_eq!(changed.len(), 3);
+        for url in &urls[..3] {
+            assert!(cal
+                .get_item_by_url_sync(url)
+                .unwrap()
+                .unwrap_task()
+                .completed());
+        }
+        assert!(!cal
+            .get_item_by_url_sync(&urls[3])
+            .unwrap()
+            .unwrap_task()
+            .completed());
+
+        // The grandchild is a subtask of both other tasks, that are un-completed with it
+        let changed = cal
+            .set_completion_status_cascading(
+                &urls[2],
+                CompletionStatus::Uncompleted,
+                CompletionCascade::UncompleteParents,
+            )
+            .await
+            .unwrap();
+        assert_eq!(changed.len(), 3);
+        assert!(!cal
+            .get_item_by_url_sync(&urls[0])
+            .unwrap()
+            .unwrap_task()
+            .completed());
+
+        assert!(cal
+            .set_completion_status_cascading(
+                &"https://some.calend.ar/cascade/missing".parse().unwrap(),
+                CompletionStatus::Uncompleted,
+                CompletionCascade::Both,
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bulk_completion() {
+        use crate::utils::sync::VersionTag;
+
+        let cal_url: Url = "https://some.calend.ar/bulk/".parse().unwrap();
+        let mut cal = CachedCalendar::new(
+            "Bulk".to_string(),
+            cal_url.clone(),
+            SupportedComponents::TODO,
+            None,
+        );
+        let mut urls = Vec::new();
+        for &(name, completed) in &[("Synced", false), ("New", false), ("Done", true)] {
+            let mut task = Task::new(name.to_string(), completed, &cal_url).unwrap();
+            if name == "Synced" {
+                task.set_sync_status(SyncStatus::Synced(VersionTag::from("tag".to_string())));
+            }
+            urls.push(task.url().clone());
+            cal.add_item(Item::Task(task)).await.unwrap();
+        }
+
+        // Nothing is changed in case an URL is unknown
+        let unknown = cal_url.join("unknown.ics").unwrap();
+        let with_unknown = vec![urls[0].clone(), unknown];
+        assert!(cal
+            .set_completion_for(&with_unknown, CompletionStatus::Completed(None))
+            .await
+            .is_err());
+        assert!(!cal
+            .get_item_by_url(&urls[0])
+            .await
+            .unwrap()
+            .unwrap_task()
+            .completed());
+
+        let changed = cal
+            .set_completion_for(&urls, CompletionStatus::Completed(None))
+            .await
+            .unwrap();
+        assert_eq!(changed, urls[..2].to_vec());
+        let tasks: Vec<&Task> = cal
+            .get_items_by_urls(&urls)
+            .await
+            .into_iter()
+            .map(|item| item.unwrap().unwrap_task())
+            .collect();
+        assert!(tasks.iter().all(|task| task.completed()));
+        assert_eq!(tasks[0].last_modified(), tasks[1].last_modified());
+        assert!(matches!(
+            tasks[0].sync_status(),
+            SyncStatus::LocallyModified(_)
+        ));
+        assert_eq!(tasks[1].sync_status(), &SyncStatus::NotSynced);
+    }
+}
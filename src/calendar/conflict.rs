@@ -0,0 +1,186 @@
+//! A journal of the local changes that have been discarded during syncs
+//!
+//! In case an item has been modified in both sources, the remote version always wins.
+//! Rather than silently losing the local edit, the Provider stores the discarded local version here, so that it can later be inspected or re-applied.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::item::Item;
+
+/// The two versions of an item that were in conflict during a sync
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Conflict {
+    /// The URL of the item
+    url: Url,
+    /// When the conflict has been resolved (by discarding the local version)
+    detected_at: DateTime<Utc>,
+    /// The local version, that has been discarded
+    local_version: Item,
+    /// The remote version, that has been kept. This is `None` in case the item had been deleted from the server
+    remote_version: Option<Item>,
+}
+
+impl Conflict {
+    pub fn new(local_version: Item, remote_version: Option<Item>) -> Self {
+        Self {
+            url: local_version.url().clone(),
+            detected_at: crate::clock::now(),
+            local_version,
+            remote_version,
+        }
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+    pub fn detected_at(&self) -> &DateTime<Utc> {
+        &self.detected_at
+    }
+    pub fn local_version(&self) -> &Item {
+        &self.local_version
+    }
+    pub fn remote_version(&self) -> Option<&Item> {
+        self.remote_version.as_ref()
+    }
+    pub fn into_local_version(self) -> Item {
+        self.local_version
+    }
+}
+
+/// The list of conflicts of a calendar, from the oldest to the most recent.
+///
+/// Only the most recent conflict of each item is kept, so that items that keep conflicting do not make it grow
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConflictJournal {
+    conflicts: Vec<Conflict>,
+}
+
+impl ConflictJournal {
+    /// Record a conflict, that replaces the one previously recorded for the same item (if any)
+    pub fn record(&mut self, conflict: Conflict) {
+        log::debug!("Recording a conflict for item {}", conflict.url());
+        self.conflicts.retain(|c| c.url() != conflict.url());
+        self.conflicts.push(conflict);
+    }
+
+    /// Returns every recorded conflict
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Returns the most recent conflict recorded for this URL, if any
+    pub fn latest_for(&self, url: &Url) -> Option<&Conflict> {
+        self.conflicts.iter().rev().find(|c| c.url() == url)
+    }
+
+    /// Forget every conflict recorded for this URL, and return the most recent one (if any)
+    pub fn take(&mut self, url: &Url) -> Option<Conflict> {
+        let mut latest = None;
+        let mut kept = Vec::new();
+        for c in self.conflicts.drain(..) {
+            if c.url() == url {
+                latest = Some(c);
+            } else {
+                kept.push(c);
+            }
+        }
+        self.conflicts = kept;
+        latest
+    }
+
+    /// Forget every conflict
+    pub fn clear(&mut self) {
+        self.conflicts.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::calendar::cached_calendar::CachedCalendar;
+    use crate::calendar::SupportedComponents;
+    use crate::traits::{BaseCalendar, CompleteCalendar, CompleteCalendarFactory};
+    use crate::utils::sync::{SyncStatus, Syncable, VersionTag};
+    use crate::Task;
+
+    #[tokio::test]
+    async fn test_reapply_conflict() {
+        let cal_url: Url = "https://some.calend.ar/conflicts/".parse().unwrap();
+        let mut cal = CachedCalendar::new(
+            "Conflicts".to_string(),
+            cal_url.clone(),
+            SupportedComponents::TODO,
+            None,
+        );
+
+        let mut remote_task = Task::new("Remote name".to_string(), false, &cal_url).unwrap();
+        let url = remote_task.url().clone();
+        remote_task.set_sync_status(SyncStatus::Synced(VersionTag::from("v2".to_string())));
+        let mut local_task = remote_task.clone();
+        local_task.set_name("Local name".to_string());
+
+        cal.add_item(Item::Task(remote_task.clone())).await.unwrap();
+        cal.record_conflict(Conflict::new(
+            Item::Task(local_task),
+            Some(Item::Task(remote_task)),
+        ))
+        .await;
+        assert_eq!(
+            cal.get_conflicts().await.latest_for(&url).unwrap().url(),
+            &url
+        );
+
+        cal.reapply_conflict(&url).await.unwrap();
+        let item = cal.get_item_by_url(&url).await.unwrap();
+        assert_eq!(item.name(), "Local name");
+        assert_eq!(
+            item.sync_status(),
+            &SyncStatus::LocallyModified(VersionTag::from("v2".to_string()))
+        );
+        assert!(cal.get_conflicts().await.is_empty());
+
+        // There is nothing left to re-apply
+        assert!(cal.reapply_conflict(&url).await.is_err());
+    }
+
+    #[test]
+    fn test_journal_keeps_latest_conflicts() {
+        let cal_url: Url = "https://some.calend.ar/conflicts/".parse().unwrap();
+        let task = Task::new("Task".to_string(), false, &cal_url).unwrap();
+        let other = Task::new("Other".to_string(), false, &cal_url).unwrap();
+        let renamed = |name: &str| {
+            let mut task = task.clone();
+            task.set_name(name.to_string());
+            Item::Task(task)
+        };
+
+        let mut journal = ConflictJournal::default();
+        for name in &["First", "Second", "Third"] {
+            journal.record(Conflict::new(renamed(name), None));
+        }
+        journal.record(Conflict::new(Item::Task(other.clone()), None));
+        assert_eq!(journal.conflicts().len(), 2);
+        assert_eq!(
+            journal
+                .latest_for(task.url())
+                .unwrap()
+                .local_version()
+                .name(),
+            "Third"
+        );
+
+        assert_eq!(
+            journal.take(task.url()).unwrap().local_version().name(),
+            "Third"
+        );
+        assert!(journal.take(task.url()).is_none());
+        assert_eq!(journal.conflicts()[0].url(), other.url());
+    }
+}
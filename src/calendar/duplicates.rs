@@ -0,0 +1,247 @@
+//! Detection and merge of duplicate items
+//!
+//! Duplicates usually come from server hiccups (e.g. an upload that succeeded although the client got an error, and that has been retried) or from items whose URLs have been rewritten.
+//! They either share the same UID, or they are tasks that look the same (same name and same due date) although they have different UIDs.
+
+use std::collections::{BTreeMap, HashMap};
+
+use url::Url;
+
+use crate::error::{KFError, KFResult};
+use crate::ical::DateMaybeTime;
+use crate::item::Item;
+use crate::task::Relationship;
+use crate::utils::sync::SyncStatus;
+
+/// Why items are considered to be duplicates
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DuplicateReason {
+    /// They have the same UID, although they are at different URLs
+    SameUid(String),
+    /// They are tasks with the same name (ignoring case and surrounding whitespace) and the same due date (or none)
+    SameNameAndDue {
+        name: String,
+        due: Option<DateMaybeTime>,
+    },
+}
+
+/// A set of items that are probably duplicates of each other
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub reason: DuplicateReason,
+    /// The URLs of the items, sorted
+    pub urls: Vec<Url>,
+}
+
+/// Group the items among `items` that are probably duplicates of each other (items marked for deletion are ignored).
+///
+/// Groups of tasks with the same name and due date that only repeat a group of items with the same UID are not returned.
+/// Groups are sorted by their first URL
+pub fn find_duplicates<'a, I: IntoIterator<Item = &'a Item>>(items: I) -> Vec<DuplicateGroup> {
+    let mut by_uid: BTreeMap<&str, Vec<Url>> = BTreeMap::new();
+    let mut by_name_and_due: HashMap<(String, Option<DateMaybeTime>), Vec<Url>> = HashMap::new();
+    for item in items {
+        if matches!(item.sync_status(), SyncStatus::LocallyDeleted(_)) {
+            continue;
+        }
+        by_uid
+            .entry(item.uid())
+            .or_default()
+            .push(item.url().clone());
+        if let Item::Task(task) = item {
+            let key = (task.name().trim().to_lowercase(), task.due_at());
+            by_name_and_due
+                .entry(key)
+                .or_default()
+                .push(task.url().clone());
+        }
+    }
+
+    let mut groups: Vec<DuplicateGroup> = by_uid
+        .into_iter()
+        .filter(|(_, urls)| urls.len() > 1)
+        .map(|(uid, urls)| DuplicateGroup {
+            reason: DuplicateReason::SameUid(uid.to_string()),
+            urls,
+        })
+        .collect();
+    for group in &mut groups {
+        group.urls.sort();
+    }
+
+    for ((name, due), mut urls) in by_name_and_due {
+        if urls.len() < 2 {
+            continue;
+        }
+        urls.sort();
+        if groups.iter().any(|group| group.urls == urls) {
+            continue;
+        }
+        groups.push(DuplicateGroup {
+            reason: DuplicateReason::SameNameAndDue { name, due },
+            urls,
+        });
+    }
+    groups.sort_by(|a, b| a.urls.cmp(&b.urls));
+    groups
+}
+
+/// Make the tasks among `items` that are related to `drop` related to `keep` instead, before `drop` is deleted.
+///
+/// Nothing has to be rewritten when both items have the same UID. Every changed task is marked as locally modified.
+/// Returns the URLs of the changed tasks. See [`CompleteCalendar::merge_items`](crate::traits::CompleteCalendar::merge_items), which also marks `drop` for deletion
+pub fn rewrite_relationships(
+    mut items: HashMap<Url, &mut Item>,
+    keep: &Url,
+    drop: &Url,
+) -> KFResult<Vec<Url>> {
+    let uid_of = |items: &HashMap<Url, &mut Item>, url: &Url| {
+        items
+            .get(url)
+            .map(|item| item.uid().to_string())
+            .ok_or_else(|| KFError::ItemDoesNotExist {
+                type_: None,
+                detail: "Can't merge items".into(),
+                url: url.clone(),
+            })
+    };
+    if keep == drop {
+        return Err(KFError::ItemDoesNotExist {
+            type_: None,
+            detail: "Can't merge an item with itself".into(),
+            url: keep.clone(),
+        });
+    }
+    let keep_uid = uid_of(&items, keep)?;
+    let drop_uid = uid_of(&items, drop)?;
+
+    let mut changed = Vec::new();
+    if keep_uid == drop_uid {
+        return Ok(changed);
+    }
+    for (url, item) in items.iter_mut() {
+        if url == drop {
+            continue;
+        }
+        let task = match item {
+            Item::Task(task) => task,
+            _ => continue,
+        };
+        let to_rewrite: Vec<Relationship> = task
+            .relationships()
+            .iter()
+            .filter(|r| r.related_to() == drop_uid)
+            .cloned()
+            .collect();
+        for relationship in &to_rewrite {
+            task.remove_relationship(&drop_uid, relationship.reltype());
+            // The kept item must not become related to itself
+            if url != keep {
+                task.add_relationship(Relationship::new(
+                    keep_uid.clone(),
+                    relationship.explicit_reltype().map(String::from),
+                ));
+            }
+        }
+        if !to_rewrite.is_empty() {
+            changed.push(url.clone());
+        }
+    }
+    changed.sort();
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::calendar::cached_calendar::CachedCalendar;
+    use crate::calendar::SupportedComponents;
+    use crate::task::Task;
+    use crate::traits::{BaseCalendar, CompleteCalendar, CompleteCalendarFactory};
+    use crate::utils::sync::{Syncable, VersionTag};
+
+    fn synced_task(name: &str, cal_url: &Url) -> Task {
+        let mut task = Task::new(name.to_string(), false, cal_url).unwrap();
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from(name.to_string())));
+        task
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let cal_url: Url = "https://some.calend.ar/dup/".parse().unwrap();
+        let original = synced_task("Buy milk", &cal_url);
+        // The same task, uploaded twice at different URLs
+        let same_uid = Task::new_with_parameters(
+            original.name().to_string(),
+            original.uid().to_string(),
+            "https://some.calend.ar/dup/copy.ics".parse().unwrap(),
+            original.completion_status().clone(),
+            original.sync_status().clone(),
+            original.creation_date().cloned(),
+            *original.last_modified(),
+            original.ical_prod_id().to_string(),
+            Vec::new(),
+            Vec::new(),
+        );
+        let same_name = synced_task(" buy MILK", &cal_url);
+        let other = synced_task("Buy bread", &cal_url);
+        let items: Vec<Item> = vec![original, same_uid, same_name, other]
+            .into_iter()
+            .map(Item::Task)
+            .collect();
+
+        let groups = find_duplicates(&items);
+        assert_eq!(groups.len(), 2);
+        let uid_group = groups
+            .iter()
+            .find(|g| matches!(g.reason, DuplicateReason::SameUid(_)))
+            .unwrap();
+        assert_eq!(uid_group.urls.len(), 2);
+        let name_group = groups
+            .iter()
+            .find(|g| matches!(g.reason, DuplicateReason::SameNameAndDue { .. }))
+            .unwrap();
+        assert_eq!(name_group.urls.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_merge_items() {
+        let cal_url: Url = "https://some.calend.ar/dup/".parse().unwrap();
+        let mut cal = CachedCalendar::new(
+            "Duplicates".to_string(),
+            cal_url.clone(),
+            SupportedComponents::TODO,
+            None,
+        );
+        let keep = synced_task("Parent", &cal_url);
+        let drop = synced_task("Parent", &cal_url);
+        let mut child = synced_task("Child", &cal_url);
+        child.set_parent(drop.uid().to_string());
+        child.set_sync_status(SyncStatus::Synced(VersionTag::from("child".to_string())));
+        let (keep_url, drop_url, child_url) =
+            (keep.url().clone(), drop.url().clone(), child.url().clone());
+        let keep_uid = keep.uid().to_string();
+        for task in [keep, drop, child] {
+            cal.add_item(Item::Task(task)).await.unwrap();
+        }
+        assert_eq!(cal.find_duplicates().await.unwrap().len(), 1);
+
+        let changed = cal.merge_items(&keep_url, &drop_url).await.unwrap();
+        assert_eq!(changed, vec![child_url.clone()]);
+        let child = cal.get_item_by_url(&child_url).await.unwrap().unwrap_task();
+        assert_eq!(child.parent(), Some(&keep_uid));
+        assert!(matches!(
+            child.sync_status(),
+            SyncStatus::LocallyModified(_)
+        ));
+        let dropped = cal.get_item_by_url(&drop_url).await.unwrap();
+        assert!(matches!(
+            dropped.sync_status(),
+            SyncStatus::LocallyDeleted(_)
+        ));
+        assert!(cal.find_duplicates().await.unwrap().is_empty());
+
+        assert!(cal.merge_items(&keep_url, &keep_url).await.is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! The estimated effort of sets of tasks, for time-tracking applications
+//!
+//! The effort of a task is its estimated duration, or else its DURATION (see [`Task::estimated_effort`]). Tasks that define neither do not count.
+
+use chrono::Duration;
+
+use crate::item::Item;
+use crate::task::Task;
+use crate::utils::sync::{SyncStatus, Syncable};
+
+/// The sum of the estimated efforts of `tasks`, e.g. of the tasks of a project that are due this week
+pub fn total_estimated_effort<'a, I: IntoIterator<Item = &'a Task>>(tasks: I) -> Duration {
+    tasks
+        .into_iter()
+        .filter_map(Task::estimated_effort)
+        .fold(Duration::zero(), |total, effort| total + effort)
+}
+
+/// The sum of the estimated efforts of the tasks among `items` (except the ones that are marked for deletion).
+/// Completed tasks are only counted when `include_completed` is set
+pub fn calendar_estimated_effort<'a, I: IntoIterator<Item = &'a Item>>(
+    items: I,
+    include_completed: bool,
+) -> Duration {
+    total_estimated_effort(
+        items
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Task(task) => Some(task),
+                _ => None,
+            })
+            .filter(|task| !matches!(task.sync_status(), SyncStatus::LocallyDeleted(_)))
+            .filter(|task| include_completed || !task.completed()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use url::Url;
+
+    #[test]
+    fn test_estimated_effort() {
+        let cal_url: Url = "https://some.calend.ar/effort/".parse().unwrap();
+        let task =
+            |name: &str, completed: bool| Task::new(name.to_string(), completed, &cal_url).unwrap();
+        let items = vec![
+            Item::Task(task("Estimated", false).with_estimated_duration(Some(Duration::hours(2)))),
+            Item::Task(task("Scheduled", false).with_duration(Some(Duration::minutes(30)))),
+            Item::Task(
+                task("Both", false)
+                    .with_duration(Some(Duration::hours(8)))
+                    .with_estimated_duration(Some(Duration::hours(1))),
+            ),
+            Item::Task(task("Unknown", false)),
+            Item::Task(task("Done", true).with_estimated_duration(Some(Duration::hours(4)))),
+        ];
+        assert_eq!(
+            calendar_estimated_effort(&items, false),
+            Duration::minutes(210)
+        );
+        assert_eq!(
+            calendar_estimated_effort(&items, true),
+            Duration::minutes(450)
+        );
+        let unknown = items.iter().skip(3).map(Item::unwrap_task);
+        assert_eq!(total_estimated_effort(unknown), Duration::hours(4));
+    }
+}
@@ -0,0 +1,189 @@
+//! Previous versions of the items of a calendar
+//!
+//! This is disabled by default (see [`CachedCalendar::set_history_depth`](crate::calendar::cached_calendar::CachedCalendar::set_history_depth)).
+//! When enabled, a copy of an item is kept every time its content is about to be replaced, either by a sync or by a local edit.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::item::Item;
+use crate::utils::sync::SyncStatus;
+
+/// A version of an item that has been replaced
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemVersion {
+    /// When this version has been replaced (or when it was about to be locally edited)
+    replaced_at: DateTime<Utc>,
+    item: Item,
+}
+
+impl ItemVersion {
+    pub fn replaced_at(&self) -> &DateTime<Utc> {
+        &self.replaced_at
+    }
+    pub fn item(&self) -> &Item {
+        &self.item
+    }
+    pub fn into_item(self) -> Item {
+        self.item
+    }
+}
+
+/// The last versions of every item of a calendar, up to a given number of versions per item
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ItemHistory {
+    /// How many versions are kept for each item. `0` disables the history
+    depth: usize,
+    /// The versions of each item, most recent first
+    versions: HashMap<Url, VecDeque<ItemVersion>>,
+}
+
+impl ItemHistory {
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Change the number of versions kept for each item. Older versions are forgotten
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        for versions in self.versions.values_mut() {
+            versions.truncate(depth + 1);
+        }
+        if depth == 0 {
+            self.versions.clear();
+        }
+    }
+
+    /// Store a copy of an item, unless it is the same as the most recent stored version
+    pub fn record(&mut self, item: &Item) {
+        if self.should_record(item) {
+            self.push(item.clone());
+        }
+    }
+
+    /// Same as [`Self::record`], for an item that is not used anymore (e.g. because it has just been replaced). This saves a copy
+    pub fn record_owned(&mut self, item: Item) {
+        if self.should_record(&item) {
+            self.push(item);
+        }
+    }
+
+    fn should_record(&self, item: &Item) -> bool {
+        if self.depth == 0 {
+            return false;
+        }
+        match self.versions.get(item.url()).and_then(|v| v.front()) {
+            Some(latest) => !same_content(&latest.item, item),
+            None => true,
+        }
+    }
+
+    fn push(&mut self, item: Item) {
+        let versions = self.versions.entry(item.url().clone()).or_default();
+        versions.push_front(ItemVersion {
+            replaced_at: crate::clock::now(),
+            item,
+        });
+        // The most recent copy may be the current version of the item (in case it has not been edited after all), that does not count
+        versions.truncate(self.depth + 1);
+    }
+
+    /// The stored versions of an item, most recent first.
+    /// `current` (if any) is not listed, even in case it has been recorded already
+    pub fn versions<'a>(&'a self, url: &Url, current: Option<&Item>) -> Vec<&'a ItemVersion> {
+        let mut versions: Vec<&ItemVersion> = self
+            .versions
+            .get(url)
+            .map(|v| v.iter().collect())
+            .unwrap_or_default();
+        if let (Some(latest), Some(current)) = (versions.first(), current) {
+            if same_content(&latest.item, current) {
+                versions.remove(0);
+            }
+        }
+        versions.truncate(self.depth);
+        versions
+    }
+}
+
+/// Whether both items have the same content. Their sync statuses are ignored, since they are updated on every sync
+fn same_content(a: &Item, b: &Item) -> bool {
+    let content = |item: &Item| {
+        let mut item = item.clone();
+        item.relabel_sync_status(SyncStatus::NotSynced);
+        serde_json::to_value(item).ok()
+    };
+    match (content(a), content(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::calendar::cached_calendar::CachedCalendar;
+    use crate::calendar::SupportedComponents;
+    use crate::traits::{BaseCalendar, CompleteCalendar, CompleteCalendarFactory};
+    use crate::Task;
+
+    #[tokio::test]
+    async fn test_item_history() {
+        let cal_url: Url = "https://some.calend.ar/history/".parse().unwrap();
+        let mut cal = CachedCalendar::new(
+            "History".to_string(),
+            cal_url.clone(),
+            SupportedComponents::TODO,
+            None,
+        );
+        let task = Task::new("Version 1".to_string(), false, &cal_url).unwrap();
+        let url = task.url().clone();
+        cal.add_item(Item::Task(task)).await.unwrap();
+
+        // Disabled by default
+        cal.get_item_by_url_mut(&url)
+            .await
+            .unwrap()
+            .unwrap_task_mut()
+            .set_name("Version 2".to_string());
+        assert!(cal.item_history(&url).await.is_empty());
+
+        cal.set_history_depth(2);
+        for name in ["Version 3", "Version 4", "Version 5"] {
+            cal.get_item_by_url_mut(&url)
+                .await
+                .unwrap()
+                .unwrap_task_mut()
+                .set_name(name.to_string());
+        }
+        // Getting a mutable reference without changing anything does not add a version
+        cal.get_item_by_url_mut(&url).await.unwrap();
+
+        let names: Vec<&str> = cal
+            .item_history(&url)
+            .await
+            .iter()
+            .map(|v| v.item().name())
+            .collect();
+        assert_eq!(names, vec!["Version 4", "Version 3"]);
+
+        // Replaced and deleted items are kept as well
+        let mut replacement = cal.get_item_by_url(&url).await.unwrap().clone();
+        replacement
+            .unwrap_task_mut()
+            .set_name("Version 6".to_string());
+        cal.update_item(replacement).await.unwrap();
+        cal.immediately_delete_item(&url).await.unwrap();
+        let names: Vec<&str> = cal
+            .item_history(&url)
+            .await
+            .iter()
+            .map(|v| v.item().name())
+            .collect();
+        assert_eq!(names, vec!["Version 6", "Version 5"]);
+    }
+}
@@ -0,0 +1,246 @@
+//! How the URLs of new items are laid out under their calendar
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::{KFError, KFResult};
+use crate::item::Item;
+
+/// How the URLs of new items are composed from the URL of their calendar.
+///
+/// Servers differ on this: some store items right under the calendar, some in a subfolder, some require the file name to be the UID of the item.
+/// The default policy (that [`random_url`](crate::utils::random_url) follows) creates `<calendar URL><random name>` URLs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ItemUrlPolicy {
+    /// The folder (relative to the calendar, e.g. `items/2022`) where new items are stored. `None` stores them right under the calendar
+    subpath: Option<String>,
+    /// Whether file names are the UID of the items, rather than random names
+    uid_as_file_name: bool,
+    /// Whether file names end with `.ics`
+    ics_extension: bool,
+}
+
+impl ItemUrlPolicy {
+    /// Items are stored right under the calendar, with random file names
+    pub fn flat() -> Self {
+        Self::default()
+    }
+
+    /// Store new items in this folder, relative to the calendar
+    pub fn with_subpath<S: ToString>(mut self, subpath: S) -> Self {
+        let subpath = subpath.to_string();
+        let subpath = subpath.trim_matches('/');
+        self.subpath = if subpath.is_empty() {
+            None
+        } else {
+            Some(subpath.to_string())
+        };
+        self
+    }
+
+    /// Name the files after the UID of the items
+    pub fn with_uid_as_file_name(mut self) -> Self {
+        self.uid_as_file_name = true;
+        self
+    }
+
+    /// Add an `.ics` extension to the file names
+    pub fn with_ics_extension(mut self) -> Self {
+        self.ics_extension = true;
+        self
+    }
+
+    pub fn subpath(&self) -> Option<&str> {
+        self.subpath.as_deref()
+    }
+
+    pub fn uid_as_file_name(&self) -> bool {
+        self.uid_as_file_name
+    }
+
+    pub fn ics_extension(&self) -> bool {
+        self.ics_extension
+    }
+
+    /// The URL of a new item that has this UID, in the calendar at `calendar_url`.
+    ///
+    /// When the file name is the UID, the characters that are not allowed in a URL path segment are percent-encoded. Otherwise, a random name is picked (see [`crate::uid`]).
+    /// This fails in case `calendar_url` cannot be a base (e.g. `mailto:` URLs)
+    pub fn item_url(&self, calendar_url: &Url, uid: &str) -> KFResult<Url> {
+        let mut relative = String::new();
+        if let Some(subpath) = &self.subpath {
+            relative.push_str(subpath);
+            relative.push('/');
+        }
+        relative.push_str(&self.file_name(uid));
+        calendar_url
+            .join(&relative)
+            .map_err(|source| KFError::InvalidItemUrl {
+                parent: calendar_url.clone(),
+                source,
+            })
+    }
+
+    /// The policy that matches the URLs of most of these items (that belong to the calendar at `calendar_url`).
+    ///
+    /// On ties, the policy of the item with the smallest URL wins. This returns `None` in case no item is stored under `calendar_url`
+    pub fn detect<'a, I: IntoIterator<Item = &'a Item>>(
+        calendar_url: &Url,
+        items: I,
+    ) -> Option<Self> {
+        let mut items: Vec<&Item> = items.into_iter().collect();
+        items.sort_by(|a, b| a.url().cmp(b.url()));
+
+        let mut counts: HashMap<Self, usize> = HashMap::new();
+        let mut order = Vec::new();
+        for item in items {
+            if let Some(policy) = Self::of_item(calendar_url, item) {
+                let count = counts.entry(policy.clone()).or_insert(0);
+                if *count == 0 {
+                    order.push(policy);
+                }
+                *count += 1;
+            }
+        }
+        order
+            .into_iter()
+            .fold(None, |best: Option<(Self, usize)>, policy| {
+                let count = counts[&policy];
+                match best {
+                    Some((_, best_count)) if best_count >= count => best,
+                    _ => Some((policy, count)),
+                }
+            })
+            .map(|(policy, _)| policy)
+    }
+
+    /// The policy that would have created the URL of this item, if any. Since random names cannot be told from other names, every file name that is not the UID is deemed random
+    fn of_item(calendar_url: &Url, item: &Item) -> Option<Self> {
+        let relative = item.url().path().strip_prefix(calendar_url.path())?;
+        let (subpath, file_name) = match relative.rsplit_once('/') {
+            Some((subpath, file_name)) => (Some(subpath), file_name),
+            None => (None, relative),
+        };
+        let (stem, ics_extension) = match file_name.strip_suffix(".ics") {
+            Some(stem) => (stem, true),
+            None => (file_name, false),
+        };
+        if stem.is_empty() {
+            return None;
+        }
+        let policy = Self {
+            subpath: None,
+            uid_as_file_name: stem == encode_segment(item.uid()),
+            ics_extension,
+        };
+        Some(match subpath {
+            None => policy,
+            Some(subpath) => policy.with_subpath(subpath),
+        })
+    }
+
+    fn file_name(&self, uid: &str) -> String {
+        let mut name = if self.uid_as_file_name {
+            encode_segment(uid)
+        } else {
+            crate::uid::new_uid()
+        };
+        if self.ics_extension {
+            name.push_str(".ics");
+        }
+        name
+    }
+}
+
+/// Percent-encode everything but the unreserved characters of RFC 3986, so that a UID is a single, unambiguous path segment
+fn encode_segment(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::task::CompletionStatus;
+    use crate::utils::sync::SyncStatus;
+    use crate::Task;
+
+    fn task_at(url: &str, uid: &str) -> Item {
+        Item::Task(Task::new_with_parameters(
+            "Task".to_string(),
+            uid.to_string(),
+            url.parse().unwrap(),
+            CompletionStatus::Uncompleted,
+            SyncStatus::NotSynced,
+            None,
+            chrono::Utc::now(),
+            "prod_id".to_string(),
+            Vec::new(),
+            Vec::new(),
+        ))
+    }
+
+    #[test]
+    fn test_item_url() {
+        let cal: Url = "https://caldav.example.com/cals/tasks/".parse().unwrap();
+        let url = ItemUrlPolicy::flat().item_url(&cal, "abc").unwrap();
+        assert_eq!(
+            url.as_str().rsplit_once('/').unwrap().0,
+            cal.as_str().trim_end_matches('/')
+        );
+        assert!(!url.as_str().ends_with("/abc"));
+
+        let policy = ItemUrlPolicy::flat()
+            .with_subpath("/items/")
+            .with_uid_as_file_name()
+            .with_ics_extension();
+        assert_eq!(
+            policy.item_url(&cal, "a b/c@example.com").unwrap().as_str(),
+            "https://caldav.example.com/cals/tasks/items/a%20b%2Fc@example.com.ics"
+        );
+    }
+
+    #[test]
+    fn test_detect() {
+        let cal: Url = "https://caldav.example.com/cals/tasks/".parse().unwrap();
+        assert_eq!(ItemUrlPolicy::detect(&cal, &[]), None);
+
+        let items = [
+            task_at("https://caldav.example.com/cals/tasks/items/1.ics", "1"),
+            task_at("https://caldav.example.com/cals/tasks/items/2.ics", "2"),
+            task_at("https://caldav.example.com/cals/tasks/3", "3"),
+            task_at("https://caldav.example.com/cals/tasks/random", "4"),
+            // Not in this calendar
+            task_at("https://caldav.example.com/cals/other/5", "5"),
+        ];
+        assert_eq!(
+            ItemUrlPolicy::detect(&cal, &items),
+            Some(
+                ItemUrlPolicy::flat()
+                    .with_subpath("items")
+                    .with_uid_as_file_name()
+                    .with_ics_extension()
+            )
+        );
+        assert_eq!(
+            ItemUrlPolicy::detect(&cal, &items[2..]),
+            Some(ItemUrlPolicy::flat().with_uid_as_file_name())
+        );
+        assert_eq!(
+            ItemUrlPolicy::detect(&cal, &items[3..]),
+            Some(ItemUrlPolicy::flat())
+        );
+        assert_eq!(ItemUrlPolicy::detect(&cal, &items[4..]), None);
+    }
+}
@@ -1,14 +1,28 @@
 //! Various objects that implement Calendar-related traits
 
 pub mod cached_calendar;
+pub mod completion;
+pub mod conflict;
+pub mod duplicates;
+pub mod effort;
+pub mod history;
+pub mod item_url_policy;
+pub mod modification_index;
 pub mod remote_calendar;
+pub mod snapshot;
+pub mod sort_order;
+pub mod subscribed_calendar;
 
 use std::convert::TryFrom;
 
 use serde::{Deserialize, Serialize};
+use url::Url;
 
 use bitflags::bitflags;
 
+use crate::error::{KFError, KFResult};
+use crate::item::{Item, ItemType};
+
 #[derive(thiserror::Error, Debug)]
 pub enum SupportedComponentsError {
     #[error(
@@ -28,6 +42,29 @@ bitflags! {
 }
 
 impl SupportedComponents {
+    /// Whether a calendar with these components accepts items of this type.
+    /// An empty set accepts everything, since servers accept every component type when they do not tell which ones they support (RFC 4791, section 5.2.3)
+    pub fn accepts(&self, type_: ItemType) -> bool {
+        let component = match type_ {
+            ItemType::Event => Self::EVENT,
+            ItemType::Task => Self::TODO,
+            ItemType::Calendar => return false,
+        };
+        self.is_empty() || self.contains(component)
+    }
+
+    /// Fail with [`KFError::UnsupportedComponent`] in case the calendar at `calendar` does not accept this item
+    pub(crate) fn check_item(&self, calendar: &Url, item: &Item) -> KFResult<()> {
+        if self.accepts(item.type_()) {
+            return Ok(());
+        }
+        Err(KFError::UnsupportedComponent {
+            calendar: Box::new(calendar.clone()),
+            url: Box::new(item.url().clone()),
+            type_: item.type_(),
+        })
+    }
+
     pub fn to_xml_string(&self) -> String {
         format!(
             r#"
@@ -82,6 +119,25 @@ impl TryFrom<minidom::Element> for SupportedComponents {
     }
 }
 
+/// Normalize a calendar name, as defined on a server, so that it can be displayed on a single line.
+///
+/// Surrounding whitespace is removed, and control characters (e.g. line breaks) are replaced with spaces.
+/// Calendars keep their names unchanged (see [`BaseCalendar::display_name`](crate::traits::BaseCalendar::display_name)), so that they are never modified when they are sent back to a server
+pub fn normalize_display_name(raw: &str) -> String {
+    let mut name = String::with_capacity(raw.len());
+    for word in raw.split(|c: char| c.is_control()) {
+        let word = word.trim();
+        if word.is_empty() {
+            continue;
+        }
+        if !name.is_empty() {
+            name.push(' ');
+        }
+        name.push_str(word);
+    }
+    name
+}
+
 /// Flags to tell which events should be retrieved
 pub enum SearchFilter {
     /// Return all items
@@ -99,3 +155,56 @@ impl Default for SearchFilter {
         SearchFilter::All
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_display_name() {
+        assert_eq!(
+            normalize_display_name("  Tâches & courses "),
+            "Tâches & courses"
+        );
+        assert_eq!(normalize_display_name("Work\r\n\tstuff"), "Work stuff");
+        assert_eq!(
+            normalize_display_name("日本語  のカレンダー"),
+            "日本語  のカレンダー"
+        );
+        assert_eq!(normalize_display_name("\n"), "");
+    }
+
+    #[tokio::test]
+    async fn test_supported_components() {
+        use crate::calendar::cached_calendar::CachedCalendar;
+        use crate::task::Task;
+        use crate::traits::{BaseCalendar, CompleteCalendar, CompleteCalendarFactory};
+        use crate::utils::sync::{SyncStatus, Syncable};
+
+        assert!(SupportedComponents::TODO.accepts(ItemType::Task));
+        assert!(!SupportedComponents::TODO.accepts(ItemType::Event));
+        assert!(SupportedComponents::empty().accepts(ItemType::Event));
+
+        let url: Url = "https://some.calend.ar/events/".parse().unwrap();
+        let mut cal = CachedCalendar::new(
+            "Events".to_string(),
+            url.clone(),
+            SupportedComponents::EVENT,
+            None,
+        );
+        let task = Task::new("Not an event".to_string(), false, &url).unwrap();
+        assert!(matches!(
+            cal.add_item(Item::Task(task.clone())).await,
+            Err(KFError::UnsupportedComponent {
+                type_: ItemType::Task,
+                ..
+            })
+        ));
+
+        // The server has accepted this one
+        let mut downloaded = task;
+        downloaded.set_sync_status(SyncStatus::Synced("etag".to_string().into()));
+        cal.add_item(Item::Task(downloaded)).await.unwrap();
+        assert_eq!(cal.get_items().await.unwrap().len(), 1);
+    }
+}
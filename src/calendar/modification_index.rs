@@ -0,0 +1,109 @@
+//! When the items of a calendar have last been changed locally
+//!
+//! This is what [`CompleteCalendar::get_items_modified_since`](crate::traits::CompleteCalendar::get_items_modified_since) relies on.
+//! The dates are the ones of the local changes (including the changes made by a sync), not the `LAST-MODIFIED` dates of the items, which may have been set by other devices a long time before they are downloaded.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The last local modification date of every item of a calendar, indexed by date
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(
+    from = "HashMap<Url, DateTime<Utc>>",
+    into = "HashMap<Url, DateTime<Utc>>"
+)]
+pub struct ModificationIndex {
+    dates: HashMap<Url, DateTime<Utc>>,
+    by_date: BTreeMap<DateTime<Utc>, HashSet<Url>>,
+}
+
+impl ModificationIndex {
+    /// Record that an item has been modified now
+    pub fn touch(&mut self, url: &Url) {
+        self.touch_at(url, crate::clock::now())
+    }
+
+    /// Record that an item has been modified at `date`
+    pub fn touch_at(&mut self, url: &Url, date: DateTime<Utc>) {
+        self.remove(url);
+        self.by_date.entry(date).or_default().insert(url.clone());
+        self.dates.insert(url.clone(), date);
+    }
+
+    /// Forget about an item, e.g. because it has been deleted
+    pub fn remove(&mut self, url: &Url) {
+        if let Some(previous) = self.dates.remove(url) {
+            if let Some(urls) = self.by_date.get_mut(&previous) {
+                urls.remove(url);
+                if urls.is_empty() {
+                    self.by_date.remove(&previous);
+                }
+            }
+        }
+    }
+
+    /// When an item has last been modified, if known
+    pub fn modified_at(&self, url: &Url) -> Option<&DateTime<Utc>> {
+        self.dates.get(url)
+    }
+
+    /// The items that have been modified at `since` or later
+    pub fn modified_since(&self, since: DateTime<Utc>) -> impl Iterator<Item = &Url> {
+        self.by_date
+            .range(since..)
+            .flat_map(|(_, urls)| urls.iter())
+    }
+}
+
+impl From<HashMap<Url, DateTime<Utc>>> for ModificationIndex {
+    fn from(dates: HashMap<Url, DateTime<Utc>>) -> Self {
+        let mut by_date: BTreeMap<DateTime<Utc>, HashSet<Url>> = BTreeMap::new();
+        for (url, date) in &dates {
+            by_date.entry(*date).or_default().insert(url.clone());
+        }
+        Self { dates, by_date }
+    }
+}
+
+impl From<ModificationIndex> for HashMap<Url, DateTime<Utc>> {
+    fn from(index: ModificationIndex) -> Self {
+        index.dates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_modified_since() {
+        let a: Url = "https://some.calend.ar/cal/a.ics".parse().unwrap();
+        let b: Url = "https://some.calend.ar/cal/b.ics".parse().unwrap();
+        let early = Utc.ymd(2022, 1, 1).and_hms(10, 0, 0);
+        let late = Utc.ymd(2022, 1, 2).and_hms(10, 0, 0);
+
+        let mut index = ModificationIndex::default();
+        index.touch_at(&a, early);
+        index.touch_at(&b, early);
+        assert_eq!(index.modified_since(late).count(), 0);
+        assert_eq!(index.modified_since(early).count(), 2);
+
+        index.touch_at(&a, late);
+        assert_eq!(index.modified_since(late).collect::<Vec<_>>(), vec![&a]);
+        assert_eq!(index.modified_at(&a), Some(&late));
+
+        index.remove(&a);
+        assert_eq!(index.modified_since(early).collect::<Vec<_>>(), vec![&b]);
+
+        // Only the dates are stored, the index is rebuilt when loading them
+        let json = serde_json::to_string(&index).unwrap();
+        let loaded: ModificationIndex = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.modified_since(early).collect::<Vec<_>>(), vec![&b]);
+        assert_eq!(loaded.modified_since(late).count(), 0);
+    }
+}
@@ -0,0 +1,125 @@
+//! What has changed in a calendar since a given point in time, e.g. so that a UI only refreshes the rows that a sync has changed
+//!
+//! Take a [`SnapshotToken`] with [`CachedCalendar::snapshot_token`] before a sync, and get what has changed since with [`CachedCalendar::diff_since`].
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::calendar::cached_calendar::CachedCalendar;
+
+/// The state of a calendar at a point in time. This only contains the URLs of its items, not the items themselves, so that it is cheap to take
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotToken {
+    taken_at: DateTime<Utc>,
+    urls: HashSet<Url>,
+}
+
+impl SnapshotToken {
+    pub(crate) fn new(taken_at: DateTime<Utc>, urls: HashSet<Url>) -> Self {
+        Self { taken_at, urls }
+    }
+
+    /// When this snapshot has been taken
+    pub fn taken_at(&self) -> &DateTime<Utc> {
+        &self.taken_at
+    }
+}
+
+/// The items that have changed in a calendar since a [`SnapshotToken`] has been taken. URLs are sorted
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarDiff {
+    /// Items that did not exist when the snapshot was taken
+    pub added: Vec<Url>,
+    /// Items that may have changed since the snapshot was taken.
+    /// This includes the items that have been mutably accessed (e.g. with [`get_item_by_url_mut`](crate::traits::CompleteCalendar::get_item_by_url_mut)), even if they have actually not been modified
+    pub updated: Vec<Url>,
+    /// Items that have been removed since the snapshot was taken
+    pub removed: Vec<Url>,
+}
+
+impl CalendarDiff {
+    /// Whether nothing has changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl CachedCalendar {
+    /// Take a snapshot of this calendar, to later get what has changed since (see [`Self::diff_since`])
+    pub fn snapshot_token(&self) -> SnapshotToken {
+        SnapshotToken::new(crate::clock::now(), self.get_item_urls_sync())
+    }
+
+    /// The items that have been added, updated or removed since `token` has been taken
+    pub fn diff_since(&self, token: &SnapshotToken) -> CalendarDiff {
+        let current = self.get_item_urls_sync();
+        let mut added: Vec<Url> = current.difference(&token.urls).cloned().collect();
+        let mut removed: Vec<Url> = token.urls.difference(&current).cloned().collect();
+        let mut updated: Vec<Url> = self
+            .get_items_modified_since_sync(token.taken_at)
+            .into_keys()
+            .filter(|url| token.urls.contains(url))
+            .collect();
+        added.sort();
+        updated.sort();
+        removed.sort();
+        CalendarDiff {
+            added,
+            updated,
+            removed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::calendar::SupportedComponents;
+    use crate::item::Item;
+    use crate::task::Task;
+    use crate::traits::{BaseCalendar, CompleteCalendar, CompleteCalendarFactory};
+
+    #[tokio::test]
+    async fn test_diff_since() {
+        let cal_url: Url = "https://some.calend.ar/snapshot/".parse().unwrap();
+        let mut cal = CachedCalendar::new(
+            "Snapshot".to_string(),
+            cal_url.clone(),
+            SupportedComponents::TODO,
+            None,
+        );
+        let mut urls = Vec::new();
+        for name in ["Kept", "Updated", "Removed"] {
+            let task = Task::new(name.to_string(), false, &cal_url).unwrap();
+            urls.push(task.url().clone());
+            cal.add_item(Item::Task(task)).await.unwrap();
+        }
+
+        let token = cal.snapshot_token();
+        assert!(cal.diff_since(&token).is_empty());
+
+        // Make sure the changes happen after the snapshot
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        cal.get_item_by_url_mut(&urls[1])
+            .await
+            .unwrap()
+            .unwrap_task_mut()
+            .set_name("Renamed".to_string());
+        cal.immediately_delete_item(&urls[2]).await.unwrap();
+        let added = Task::new("Added".to_string(), false, &cal_url).unwrap();
+        let added_url = added.url().clone();
+        cal.add_item(Item::Task(added)).await.unwrap();
+
+        let diff = cal.diff_since(&token);
+        assert_eq!(diff.added, vec![added_url]);
+        assert_eq!(diff.updated, vec![urls[1].clone()]);
+        assert_eq!(diff.removed, vec![urls[2].clone()]);
+
+        let json = serde_json::to_string(&token).unwrap();
+        assert_eq!(serde_json::from_str::<SnapshotToken>(&json).unwrap(), token);
+    }
+}
@@ -0,0 +1,239 @@
+//! Manual ordering of tasks, as stored in their `X-APPLE-SORT-ORDER` extension
+//!
+//! This is the extension that Apple Reminders and Nextcloud Tasks use to sync drag-and-drop orderings across devices.
+//! Tasks that do not define it are sorted as these clients do, i.e. by their creation date (in seconds since 2001-01-01, the Apple epoch).
+//! Tasks with the same sort order are sorted by UID, so that every device shows them in the same order.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use url::Url;
+
+use crate::error::{KFError, KFResult};
+use crate::item::Item;
+use crate::task::Task;
+use crate::utils::sync::{SyncStatus, Syncable};
+
+/// Seconds between the Unix epoch and the Apple epoch (2001-01-01)
+const APPLE_EPOCH: i64 = 978_307_200;
+
+/// The gap left between two tasks whenever sort orders have to be renumbered, so that the next moves only change the moved task
+const RENUMBERING_GAP: i64 = 1 << 10;
+
+/// The sort order of a task, whether it is defined or not
+pub fn effective_sort_order(task: &Task) -> i64 {
+    task.sort_order().unwrap_or_else(|| {
+        let created = task.creation_date().unwrap_or_else(|| task.last_modified());
+        created.timestamp() - APPLE_EPOCH
+    })
+}
+
+/// Compare two tasks by their sort orders (then by their UIDs)
+pub fn compare_tasks(a: &Task, b: &Task) -> Ordering {
+    effective_sort_order(a)
+        .cmp(&effective_sort_order(b))
+        .then_with(|| a.uid().cmp(b.uid()))
+}
+
+/// The tasks among `items` (except the ones that are marked for deletion), sorted by their sort orders
+pub fn sorted_tasks<'a, I: IntoIterator<Item = &'a Item>>(items: I) -> Vec<&'a Task> {
+    let mut tasks: Vec<&Task> = items
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::Task(task) => Some(task),
+            _ => None,
+        })
+        .filter(|task| !matches!(task.sync_status(), SyncStatus::LocallyDeleted(_)))
+        .collect();
+    tasks.sort_by(|a, b| compare_tasks(a, b));
+    tasks
+}
+
+/// Move a task among `items`, right after the task at `after` (or first in case `after` is `None`).
+///
+/// Tasks are only ordered among their siblings (the tasks with the same parent), so `after` must be a sibling of the moved task.
+/// The moved task usually is the only one whose sort order changes. The sort orders of its siblings are only renumbered when there is no room left between its new neighbours.
+///
+/// Every changed task is marked as locally modified. Returns the URLs of the changed tasks
+pub fn move_task(
+    mut items: HashMap<Url, &mut Item>,
+    task_url: &Url,
+    after: Option<&Url>,
+) -> KFResult<Vec<Url>> {
+    let not_a_task = |url: &Url, detail: &str| KFError::ItemDoesNotExist {
+        type_: Some(crate::item::ItemType::Task),
+        detail: detail.into(),
+        url: url.clone(),
+    };
+    let parent = match items.get(task_url) {
+        Some(Item::Task(task)) => task.parent().cloned(),
+        _ => return Err(not_a_task(task_url, "Can't move task")),
+    };
+
+    // The siblings of the moved task, in their current order
+    let siblings: Vec<(Url, i64)> = sorted_tasks(items.values().map(|item| &**item))
+        .into_iter()
+        .filter(|task| task.url() != task_url && task.parent() == parent.as_ref())
+        .map(|task| (task.url().clone(), effective_sort_order(task)))
+        .collect();
+    let position = match after {
+        None => 0,
+        Some(after) => match siblings.iter().position(|(url, _)| url == after) {
+            Some(index) => index + 1,
+            None => return Err(not_a_task(after, "Can't move a task after a non-sibling")),
+        },
+    };
+
+    let previous = position.checked_sub(1).map(|i| siblings[i].1);
+    let next = siblings.get(position).map(|(_, order)| *order);
+    let new_order = match (previous, next) {
+        (None, None) => Some(0),
+        (Some(previous), None) => previous.checked_add(RENUMBERING_GAP),
+        (None, Some(next)) => next.checked_sub(RENUMBERING_GAP),
+        (Some(previous), Some(next)) if next - previous >= 2 => {
+            Some(previous + (next - previous) / 2)
+        }
+        _ => None,
+    };
+
+    let new_orders: Vec<(Url, i64)> = match new_order {
+        Some(order) => vec![(task_url.clone(), order)],
+        None => {
+            // No room left: renumber every sibling, starting from the current first one
+            let mut ordered: Vec<Url> = siblings.iter().map(|(url, _)| url.clone()).collect();
+            ordered.insert(position, task_url.clone());
+            let start = siblings
+                .first()
+                .map(|(_, order)| *order)
+                .unwrap_or_default();
+            ordered
+                .into_iter()
+                .enumerate()
+                .map(|(i, url)| (url, start.saturating_add(i as i64 * RENUMBERING_GAP)))
+                .collect()
+        }
+    };
+
+    let mut changed = Vec::new();
+    for (url, order) in new_orders {
+        if let Some(Item::Task(task)) = items.get_mut(&url) {
+            if task.sort_order() != Some(order) {
+                task.set_sort_order(Some(order));
+                changed.push(url);
+            }
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::utils::sync::VersionTag;
+
+    fn task(name: &str, order: Option<i64>, cal_url: &Url) -> Item {
+        let mut task = Task::new(name.to_string(), false, cal_url).unwrap();
+        task.set_sort_order(order);
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from(name.to_string())));
+        Item::Task(task)
+    }
+
+    fn names(items: &HashMap<Url, Item>) -> Vec<String> {
+        sorted_tasks(items.values())
+            .iter()
+            .map(|t| t.name().to_string())
+            .collect()
+    }
+
+    fn items_of(tasks: Vec<Item>) -> HashMap<Url, Item> {
+        tasks
+            .into_iter()
+            .map(|item| (item.url().clone(), item))
+            .collect()
+    }
+
+    fn as_mut(items: &mut HashMap<Url, Item>) -> HashMap<Url, &mut Item> {
+        items
+            .iter_mut()
+            .map(|(url, item)| (url.clone(), item))
+            .collect()
+    }
+
+    fn url_of(items: &HashMap<Url, Item>, name: &str) -> Url {
+        items
+            .values()
+            .find(|item| item.name() == name)
+            .unwrap()
+            .url()
+            .clone()
+    }
+
+    #[test]
+    fn test_move_task() {
+        let cal_url: Url = "https://some.calend.ar/sorted/".parse().unwrap();
+        let mut items = items_of(vec![
+            task("a", Some(10), &cal_url),
+            task("b", Some(11), &cal_url),
+            task("c", Some(20), &cal_url),
+        ]);
+        let (a, b, c) = (
+            url_of(&items, "a"),
+            url_of(&items, "b"),
+            url_of(&items, "c"),
+        );
+        assert_eq!(names(&items), vec!["a", "b", "c"]);
+
+        // There is room between b and c: only a changes
+        let changed = move_task(as_mut(&mut items), &a, Some(&b)).unwrap();
+        assert_eq!(changed, vec![a.clone()]);
+        assert_eq!(names(&items), vec!["b", "a", "c"]);
+        assert!(matches!(
+            items[&a].sync_status(),
+            SyncStatus::LocallyModified(_)
+        ));
+        assert!(matches!(items[&c].sync_status(), SyncStatus::Synced(_)));
+
+        let changed = move_task(as_mut(&mut items), &c, None).unwrap();
+        assert_eq!(changed, vec![c.clone()]);
+        assert_eq!(names(&items), vec!["c", "b", "a"]);
+
+        let unknown: Url = "https://some.calend.ar/sorted/unknown.ics".parse().unwrap();
+        assert!(move_task(as_mut(&mut items), &a, Some(&unknown)).is_err());
+    }
+
+    #[test]
+    fn test_move_task_renumbering() {
+        let cal_url: Url = "https://some.calend.ar/sorted/".parse().unwrap();
+        let mut items = items_of(vec![
+            task("x", Some(1), &cal_url),
+            task("y", Some(2), &cal_url),
+            task("z", Some(3), &cal_url),
+        ]);
+        let (x, y, z) = (
+            url_of(&items, "x"),
+            url_of(&items, "y"),
+            url_of(&items, "z"),
+        );
+
+        // There is no room between x and y: the following siblings are renumbered
+        let changed = move_task(as_mut(&mut items), &z, Some(&x)).unwrap();
+        assert_eq!(changed, vec![z.clone(), y.clone()]);
+        assert_eq!(names(&items), vec!["x", "z", "y"]);
+        assert_eq!(items[&x].unwrap_task().sort_order(), Some(1));
+
+        // ...which leaves room for the next moves
+        let changed = move_task(as_mut(&mut items), &y, Some(&x)).unwrap();
+        assert_eq!(changed, vec![y]);
+        assert_eq!(names(&items), vec!["x", "y", "z"]);
+    }
+
+    #[test]
+    fn test_default_sort_order() {
+        let cal_url: Url = "https://some.calend.ar/sorted/".parse().unwrap();
+        let item = task("implicit", None, &cal_url);
+        let task = item.unwrap_task();
+        let created = task.creation_date().unwrap().timestamp();
+        assert_eq!(effective_sort_order(task), created - APPLE_EPOCH);
+    }
+}
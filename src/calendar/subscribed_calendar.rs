@@ -0,0 +1,435 @@
+//! Read-only calendars published as iCal files (e.g. `webcal://` subscriptions to holidays or team calendars)
+//!
+//! A [`Subscriptions`] source can be synced into a local cache like any other remote source (see [`SubscriptionProvider`](crate::SubscriptionProvider)).
+//! Every `VTODO` of a published file becomes an item of its calendar. Since this crate does not support events yet, other components are ignored.
+//!
+//! Subscriptions are read-only: local changes to their items cannot be uploaded, and the sync reports them as errors.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use http::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Method, StatusCode};
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::calendar::SupportedComponents;
+use crate::error::{HttpStatusConstraint, KFError, KFResult};
+use crate::item::{Item, ItemType};
+use crate::resource::{RequestIds, Resource, TransferCounter};
+use crate::traits::{BaseCalendar, CalDavSource, DavCalendar, DavCalendarFactory};
+use crate::utils::prop::Property;
+use crate::utils::runtime::Instant;
+use crate::utils::sync::{SyncStatus, VersionTag};
+use crate::utils::NamespacedName;
+
+/// A calendar published as a single iCal file, that is periodically downloaded
+#[derive(Debug)]
+pub struct SubscribedCalendar {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+    /// The file is not downloaded again (nor even checked) before this delay has elapsed
+    refresh_interval: Duration,
+
+    /// The last downloaded version of the file
+    feed: Mutex<Option<Feed>>,
+}
+
+#[derive(Debug)]
+struct Feed {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    fetched_at: Instant,
+    items: HashMap<Url, Item>,
+}
+
+impl SubscribedCalendar {
+    /// The delay before which the published file is not checked again. It is checked at every sync by default
+    pub fn set_refresh_interval(&mut self, interval: Duration) {
+        self.refresh_interval = interval;
+    }
+
+    /// The URL of the item with the given UID. Items are not files of their own, they are told apart by the fragment of the URL of the calendar
+    fn item_url(&self, uid: &str) -> Url {
+        let mut url = self.resource.url().clone();
+        url.set_fragment(Some(uid));
+        url
+    }
+
+    /// Download the published file, unless it has not changed since the last download
+    async fn refresh(&self) -> KFResult<()> {
+        let mut feed = self.feed.lock().await;
+        if let Some(f) = &*feed {
+            if f.fetched_at.elapsed() < self.refresh_interval {
+                return Ok(());
+            }
+        }
+
+        let url = self.resource.url().clone();
+        let response = self
+            .resource
+            .send(Method::GET, url.clone(), |mut request| {
+                if let Some(f) = &*feed {
+                    if let Some(etag) = &f.etag {
+                        request = request.header(IF_NONE_MATCH, etag.as_str());
+                    }
+                    if let Some(last_modified) = &f.last_modified {
+                        request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+                    }
+                }
+                request
+            })
+            .await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(f) = feed.as_mut() {
+                log::debug!("Subscription {} has not changed", url);
+                f.fetched_at = Instant::now();
+                return Ok(());
+            }
+        }
+        if !response.status().is_success() {
+            return Err(KFError::UnexpectedHTTPStatusCode {
+                expected: HttpStatusConstraint::Success,
+                got: response.status(),
+            });
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let text = response
+            .text()
+            .await
+            .map_err(|source| KFError::HttpRequestError {
+                url: url.clone(),
+                method: Method::GET,
+                source,
+            })?;
+        self.resource.transfers().add_received(text.len());
+
+        let mut items = HashMap::new();
+        for ical in crate::ical::split_components(&text, "VTODO") {
+            let item_url = match uid_of(&ical) {
+                None => {
+                    log::warn!("Item without UID in subscription {}, ignoring it", url);
+                    continue;
+                }
+                Some(uid) => self.item_url(uid),
+            };
+            let version_tag = VersionTag::from_content(&ical);
+            match crate::ical::parse(&ical, item_url.clone(), SyncStatus::Synced(version_tag)) {
+                Err(err) => {
+                    log::warn!(
+                        "Invalid item in subscription {} ({}), ignoring it",
+                        url,
+                        err
+                    )
+                }
+                Ok(item) => {
+                    items.insert(item_url, item);
+                }
+            }
+        }
+        log::debug!("Subscription {} contains {} items", url, items.len());
+
+        *feed = Some(Feed {
+            etag,
+            last_modified,
+            fetched_at: Instant::now(),
+            items,
+        });
+        Ok(())
+    }
+
+    fn read_only(&self, operation: &str) -> KFError {
+        KFError::UnsupportedBySource {
+            operation: format!("{} (subscription {} is read-only)", operation, self.url()),
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for SubscribedCalendar {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn url(&self) -> &Url {
+        self.resource.url()
+    }
+    fn supported_components(&self) -> SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> KFResult<SyncStatus> {
+        Err(self.read_only(&format!("adding item {}", item.url())))
+    }
+
+    async fn update_item(&mut self, item: Item) -> KFResult<SyncStatus> {
+        Err(self.read_only(&format!("updating item {}", item.url())))
+    }
+
+    async fn get_properties_by_name(
+        &self,
+        names: &[NamespacedName],
+    ) -> KFResult<Vec<Option<Property>>> {
+        // Published files have no WebDAV properties
+        Ok(names.iter().map(|_| None).collect())
+    }
+
+    async fn set_property(&mut self, prop: Property) -> KFResult<SyncStatus> {
+        Err(self.read_only(&format!("setting property {}", prop.nsn())))
+    }
+}
+
+impl DavCalendarFactory for SubscribedCalendar {
+    fn new(
+        name: String,
+        resource: Resource,
+        supported_components: SupportedComponents,
+        color: Option<Color>,
+    ) -> Self {
+        Self {
+            name,
+            resource,
+            supported_components,
+            color,
+            refresh_interval: Duration::ZERO,
+            feed: Mutex::new(None),
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for SubscribedCalendar {
+    async fn get_item_version_tags(&self) -> KFResult<HashMap<Url, VersionTag>> {
+        self.refresh().await?;
+        let feed = self.feed.lock().await;
+        Ok(feed
+            .iter()
+            .flat_map(|f| f.items.iter())
+            .filter_map(|(url, item)| match item.sync_status() {
+                SyncStatus::Synced(vt) => Some((url.clone(), vt.clone())),
+                _ => None,
+            })
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> KFResult<Option<Item>> {
+        self.refresh().await?;
+        let feed = self.feed.lock().await;
+        Ok(feed.as_ref().and_then(|f| f.items.get(url)).cloned())
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> KFResult<Vec<Option<Item>>> {
+        self.refresh().await?;
+        let feed = self.feed.lock().await;
+        Ok(urls
+            .iter()
+            .map(|url| feed.as_ref().and_then(|f| f.items.get(url)).cloned())
+            .collect())
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> KFResult<()> {
+        Err(self.read_only(&format!("deleting item {}", item_url)))
+    }
+
+    async fn get_properties(&self) -> KFResult<Vec<Property>> {
+        Ok(Vec::new())
+    }
+
+    async fn get_property(&self, _nsn: &NamespacedName) -> KFResult<Option<Property>> {
+        Ok(None)
+    }
+
+    async fn delete_property(&mut self, nsn: &NamespacedName) -> KFResult<()> {
+        Err(self.read_only(&format!("deleting property {}", nsn)))
+    }
+}
+
+/// The UID of the (single) component of an iCal file
+fn uid_of(ical: &str) -> Option<&str> {
+    ical.lines()
+        .find_map(|line| line.strip_prefix("UID:"))
+        .map(str::trim_end)
+        .filter(|uid| !uid.is_empty())
+}
+
+/// A source made of the calendars the user has subscribed to
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    calendars: HashMap<Url, Arc<Mutex<SubscribedCalendar>>>,
+    transfers: TransferCounter,
+    request_ids: RequestIds,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to the iCal file published at `url`. `webcal://` URLs are fetched over HTTPS.
+    ///
+    /// Use [`Resource::new`] with credentials in case the file is not public
+    pub fn subscribe(
+        &mut self,
+        url: Url,
+        name: String,
+        color: Option<Color>,
+    ) -> KFResult<Arc<Mutex<SubscribedCalendar>>> {
+        let url = match url.scheme() {
+            "webcal" | "webcals" => {
+                let https = format!("https{}", &url.as_str()[url.scheme().len()..]);
+                Url::parse(&https).map_err(|source| KFError::InvalidHref {
+                    base: url.clone(),
+                    href: https,
+                    source,
+                })?
+            }
+            _ => url,
+        };
+        self.subscribe_resource(
+            Resource::new(url, String::new(), String::new()),
+            name,
+            color,
+        )
+    }
+
+    /// Subscribe to the iCal file published at the URL of `resource`, that is reached with its credentials and settings
+    pub fn subscribe_resource(
+        &mut self,
+        resource: Resource,
+        name: String,
+        color: Option<Color>,
+    ) -> KFResult<Arc<Mutex<SubscribedCalendar>>> {
+        let url = resource.url().clone();
+        if self.calendars.contains_key(&url) {
+            return Err(KFError::ItemAlreadyExists {
+                type_: ItemType::Calendar,
+                detail: "Already subscribed".into(),
+                url,
+            });
+        }
+        // Every subscription accounts for what it downloads in the counter of this source, and numbers its requests with the other ones
+        let resource = resource
+            .with_transfer_counter(self.transfers.clone())
+            .with_request_ids(self.request_ids.clone());
+        let calendar = SubscribedCalendar::new(name, resource, SupportedComponents::TODO, color);
+        let arc = Arc::new(Mutex::new(calendar));
+        self.calendars.insert(url, arc.clone());
+        Ok(arc)
+    }
+
+    /// Stop following a subscription. Returns it, if it existed
+    pub fn unsubscribe(&mut self, url: &Url) -> Option<Arc<Mutex<SubscribedCalendar>>> {
+        self.calendars.remove(url)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<SubscribedCalendar> for Subscriptions {
+    async fn get_calendars(&self) -> KFResult<HashMap<Url, Arc<Mutex<SubscribedCalendar>>>> {
+        Ok(self.calendars.clone())
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<SubscribedCalendar>>> {
+        self.calendars.get(url).cloned()
+    }
+
+    async fn create_calendar(
+        &mut self,
+        url: Url,
+        _name: String,
+        _supported_components: SupportedComponents,
+        _color: Option<Color>,
+    ) -> KFResult<Arc<Mutex<SubscribedCalendar>>> {
+        Err(KFError::UnsupportedBySource {
+            operation: format!("creating calendar {} (use Subscriptions::subscribe)", url),
+        })
+    }
+
+    /// Deleting a subscribed calendar only unsubscribes from it
+    async fn delete_calendar(
+        &mut self,
+        url: &Url,
+    ) -> KFResult<Option<Arc<Mutex<SubscribedCalendar>>>> {
+        match self.unsubscribe(url) {
+            Some(cal) => Ok(Some(cal)),
+            None => Err(KFError::ItemDoesNotExist {
+                type_: Some(ItemType::Calendar),
+                detail: "Not subscribed".into(),
+                url: url.clone(),
+            }),
+        }
+    }
+
+    async fn remove_calendar_local_only(
+        &mut self,
+        url: &Url,
+    ) -> KFResult<Option<Arc<Mutex<SubscribedCalendar>>>> {
+        Err(KFError::UnsupportedBySource {
+            operation: format!("removing calendar {} locally only", url),
+        })
+    }
+
+    async fn checkpoint_calendar(&self, _calendar: &SubscribedCalendar) -> KFResult<()> {
+        // Nothing is persisted
+        Ok(())
+    }
+
+    fn transfers(&self) -> Option<TransferCounter> {
+        Some(self.transfers.clone())
+    }
+
+    fn request_ids(&self) -> Option<RequestIds> {
+        Some(self.request_ids.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let mut subscriptions = Subscriptions::new();
+        let url: Url = "webcal://example.com/holidays.ics".parse().unwrap();
+        let cal = subscriptions
+            .subscribe(url, "Holidays".to_string(), None)
+            .unwrap();
+        let https_url: Url = "https://example.com/holidays.ics".parse().unwrap();
+        assert_eq!(cal.lock().await.url(), &https_url);
+        assert!(subscriptions.get_calendar(&https_url).await.is_some());
+        assert!(subscriptions
+            .subscribe(https_url.clone(), "Again".to_string(), None)
+            .is_err());
+
+        let item_url = cal.lock().await.item_url("some uid@example.com");
+        assert_eq!(item_url.fragment(), Some("some%20uid@example.com"));
+        assert_eq!(
+            uid_of("BEGIN:VTODO\r\nUID:abc\r\nEND:VTODO\r\n"),
+            Some("abc")
+        );
+        assert!(cal.lock().await.delete_item(&item_url).await.is_err());
+
+        assert!(subscriptions.delete_calendar(&https_url).await.is_ok());
+        assert!(subscriptions.get_calendar(&https_url).await.is_none());
+    }
+}
@@ -0,0 +1,143 @@
+//! The features a CalDAV server advertises
+//!
+//! Servers list the WebDAV extensions they support in the `DAV` header, and the methods they allow in the `Allow` header of their replies to `OPTIONS` requests.
+
+use std::collections::HashSet;
+
+use reqwest::header::{HeaderMap, ALLOW};
+use url::Url;
+
+use crate::error::{KFError, KFResult};
+
+/// What a server advertised in its reply to an `OPTIONS` request. See [`Client::capabilities`](crate::client::Client::capabilities)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerCapabilities {
+    /// The compliance classes and extensions listed in the `DAV` headers (e.g. `1`, `2`, `calendar-access`)
+    dav_tokens: HashSet<String>,
+    /// The methods listed in the `Allow` headers, upper-cased. `None` in case the server did not send any
+    allowed_methods: Option<HashSet<String>>,
+}
+
+impl ServerCapabilities {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let dav_tokens = split_header_values(headers, "DAV").into_iter().collect();
+
+        let allowed_methods = if headers.contains_key(ALLOW) {
+            Some(
+                split_header_values(headers, ALLOW.as_str())
+                    .into_iter()
+                    .map(|m| m.to_ascii_uppercase())
+                    .collect(),
+            )
+        } else {
+            None
+        };
+
+        Self {
+            dav_tokens,
+            allowed_methods,
+        }
+    }
+
+    /// Whether the `DAV` header contains this token (e.g. `"calendar-access"`)
+    pub fn has_dav_token(&self, token: &str) -> bool {
+        self.dav_tokens.contains(token)
+    }
+
+    /// Whether the server allows this method.
+    ///
+    /// This is `true` when the server did not advertise its allowed methods at all, since it may support it anyway
+    pub fn allows(&self, method: &str) -> bool {
+        match &self.allowed_methods {
+            None => true,
+            Some(methods) => methods.contains(&method.to_ascii_uppercase()),
+        }
+    }
+
+    /// Webdav-sync, i.e. `sync-collection` reports (RFC 6578)
+    pub fn sync_collection(&self) -> bool {
+        self.has_dav_token("sync-collection")
+    }
+
+    /// Extended MKCOL (RFC 5689)
+    pub fn extended_mkcol(&self) -> bool {
+        self.has_dav_token("extended-mkcol")
+    }
+
+    /// CalDAV itself (RFC 4791)
+    pub fn calendar_access(&self) -> bool {
+        self.has_dav_token("calendar-access")
+    }
+
+    /// Calendar sharing, as specified by Apple's CalendarServer
+    pub fn calendarserver_sharing(&self) -> bool {
+        self.has_dav_token("calendarserver-sharing")
+    }
+
+    /// WebDAV compliance class 2, i.e. `LOCK` and `UNLOCK` (RFC 4918)
+    pub fn locking(&self) -> bool {
+        self.has_dav_token("2")
+    }
+
+    /// Fail fast with [`KFError::UnsupportedByServer`] in case the server does not allow this method
+    pub fn require_method(&self, method: &str, url: &Url) -> KFResult<()> {
+        if self.allows(method) {
+            Ok(())
+        } else {
+            Err(KFError::UnsupportedByServer {
+                feature: method.to_string(),
+                url: url.clone(),
+            })
+        }
+    }
+}
+
+/// Every comma-separated value of every header with this name
+fn split_header_values(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_capabilities_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append("DAV", HeaderValue::from_static("1, 2, 3, calendar-access"));
+        headers.append(
+            "DAV",
+            HeaderValue::from_static("extended-mkcol,calendarserver-sharing"),
+        );
+        headers.append(
+            ALLOW,
+            HeaderValue::from_static("OPTIONS, GET, PUT, propfind"),
+        );
+
+        let caps = ServerCapabilities::from_headers(&headers);
+        assert!(caps.calendar_access());
+        assert!(caps.extended_mkcol());
+        assert!(caps.calendarserver_sharing());
+        assert!(caps.locking());
+        assert!(!caps.sync_collection());
+        assert!(caps.allows("PROPFIND"));
+        assert!(!caps.allows("MKCALENDAR"));
+
+        let url: Url = "https://some.server/".parse().unwrap();
+        assert!(caps.require_method("MKCALENDAR", &url).is_err());
+
+        // Servers that do not list their methods may support anything
+        let caps = ServerCapabilities::from_headers(&HeaderMap::new());
+        assert!(caps.allows("MKCALENDAR"));
+        assert!(!caps.calendar_access());
+    }
+}
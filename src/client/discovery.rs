@@ -0,0 +1,117 @@
+//! What a [`Client`](crate::client::Client) has discovered about a server, so that it can be persisted across sessions
+//!
+//! Discovering the calendars of an account takes three PROPFIND requests, the last one returning every collection of the calendar home set (which can be huge for accounts with hundreds of calendars).
+//! A [`DiscoveryCache`] lets a new client skip them: it only checks that the calendar home set has not changed, by comparing its version (see [`DiscoveryCache::home_set_version`]).
+
+use csscolorparser::Color;
+use minidom::Element;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::calendar::SupportedComponents;
+use crate::dav;
+use crate::utils::xml::find_elem;
+use crate::utils::NamespacedName;
+
+/// What a client has discovered about a server. See [`Client::discovery_cache`](crate::client::Client::discovery_cache) and [`Client::with_discovery_cache`](crate::client::Client::with_discovery_cache).
+///
+/// It can be stored anywhere, e.g. in the metadata of a [`Cache`](crate::cache::Cache) (see [`Cache::set_meta`](crate::cache::Cache::set_meta))
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveryCache {
+    /// The URL of the server the client has been created with
+    pub server_url: Url,
+    pub principal: Url,
+    pub calendar_home_set: Url,
+    /// The version of the calendar home set when its calendars were discovered (its `getctag`, `sync-token` or `getetag`).
+    /// `None` in case the server does not provide any, in which case the calendars are always discovered again
+    pub home_set_version: Option<String>,
+    pub calendars: Vec<DiscoveredCalendar>,
+}
+
+/// A calendar of a [`DiscoveryCache`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveredCalendar {
+    pub url: Url,
+    pub name: String,
+    pub supported_components: SupportedComponents,
+    pub color: Option<Color>,
+}
+
+/// The properties that tell whether a collection has changed, in order of preference
+pub(crate) fn version_props() -> Vec<NamespacedName> {
+    vec![dav::getctag(), dav::sync_token(), dav::getetag()]
+}
+
+/// The version of a collection, from a `response` element of a PROPFIND reply that asked for [`version_props`]
+pub(crate) fn version_of(response: &Element) -> Option<String> {
+    version_props().iter().find_map(|nsn| {
+        find_elem(response, &nsn.name)
+            .map(|el| el.text().trim().to_string())
+            .filter(|version| !version.is_empty())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_of() {
+        let response: Element =
+            r#"<d:response xmlns:d="DAV:" xmlns:cs="http://calendarserver.org/ns/">
+                <d:href>/calendars/me/</d:href>
+                <d:propstat>
+                    <d:prop><d:getetag>"etag"</d:getetag><cs:getctag>ctag-42</cs:getctag></d:prop>
+                    <d:status>HTTP/1.1 200 OK</d:status>
+                </d:propstat>
+                <d:propstat>
+                    <d:prop><d:sync-token/></d:prop>
+                    <d:status>HTTP/1.1 404 Not Found</d:status>
+                </d:propstat>
+            </d:response>"#
+                .parse()
+                .unwrap();
+        assert_eq!(version_of(&response), Some("ctag-42".to_string()));
+
+        let without_version: Element =
+            r#"<d:response xmlns:d="DAV:"><d:href>/calendars/me/</d:href></d:response>"#
+                .parse()
+                .unwrap();
+        assert_eq!(version_of(&without_version), None);
+    }
+
+    #[tokio::test]
+    async fn test_discovery_cache_round_trip() {
+        use crate::client::Client;
+
+        let server: Url = "https://caldav.example.com/dav/".parse().unwrap();
+        let cache = DiscoveryCache {
+            server_url: server.clone(),
+            principal: server.join("principals/me/").unwrap(),
+            calendar_home_set: server.join("calendars/me/").unwrap(),
+            home_set_version: Some("ctag-42".to_string()),
+            calendars: vec![DiscoveredCalendar {
+                url: server.join("calendars/me/tasks/").unwrap(),
+                name: "Tasks".to_string(),
+                supported_components: SupportedComponents::TODO,
+                color: Some(csscolorparser::parse("#ff8000").unwrap()),
+            }],
+        };
+        let json = serde_json::to_string(&cache).unwrap();
+        let cache: DiscoveryCache = serde_json::from_str(&json).unwrap();
+
+        let client = Client::new(server.as_str(), "me", "secret")
+            .unwrap()
+            .with_discovery_cache(cache.clone());
+        assert_eq!(client.discovery_cache().await, None);
+        client.restore_discovery(&mut *client.cached_replies.lock().await);
+        assert_eq!(client.discovery_cache().await, Some(cache.clone()));
+
+        // A cache of another server is ignored
+        let other = Client::new("https://other.example.com/", "me", "secret")
+            .unwrap()
+            .with_discovery_cache(cache);
+        other.restore_discovery(&mut *other.cached_replies.lock().await);
+        assert_eq!(other.discovery_cache().await, None);
+    }
+}
@@ -0,0 +1,111 @@
+//! The source of the current time
+//!
+//! Timestamps set by this crate (e.g. when a task is modified, or the `DTSTAMP` of iCal files) are read from the [`Clock`] set in [`config::CLOCK`](crate::config::CLOCK).
+//! Tests can replace it by a [`FixedClock`] to get deterministic outputs. [`set_thread_clock`] does so for the current thread only, so that tests that run in parallel are not affected.
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::utils::lock_ignoring_poison;
+
+/// Something that tells the current time
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The default clock, that reads the system time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when it is told to
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *lock_ignoring_poison(&self.now) = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = lock_ignoring_poison(&self.now);
+        *now = *now + duration;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *lock_ignoring_poison(&self.now)
+    }
+}
+
+thread_local! {
+    static THREAD_CLOCK: RefCell<Option<Arc<dyn Clock>>> = RefCell::new(None);
+}
+
+/// Use `clock` instead of the one set in [`config::CLOCK`](crate::config::CLOCK), on the current thread only, until the returned guard is dropped
+pub fn set_thread_clock(clock: Arc<dyn Clock>) -> ThreadClockGuard {
+    let previous = THREAD_CLOCK.with(|current| current.borrow_mut().replace(clock));
+    ThreadClockGuard {
+        previous,
+        _not_send: PhantomData,
+    }
+}
+
+/// Restores the clock of the current thread when dropped, see [`set_thread_clock`]
+#[must_use = "the clock is restored as soon as the guard is dropped"]
+pub struct ThreadClockGuard {
+    previous: Option<Arc<dyn Clock>>,
+    // The guard must be dropped on the thread it has been created on
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ThreadClockGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        THREAD_CLOCK.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// The current time, according to the clock of the current thread (see [`set_thread_clock`]), or else to the clock set in [`config::CLOCK`](crate::config::CLOCK)
+pub fn now() -> DateTime<Utc> {
+    match THREAD_CLOCK.with(|current| current.borrow().clone()) {
+        Some(clock) => clock.now(),
+        None => lock_ignoring_poison(&crate::config::CLOCK).now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_thread_clock() {
+        let past = Utc.ymd(2021, 4, 2).and_hms(8, 15, 57);
+        {
+            let _guard = set_thread_clock(Arc::new(FixedClock::new(past)));
+            assert_eq!(now(), past);
+            // Other threads keep the global clock
+            assert_ne!(std::thread::spawn(now).join().unwrap(), past);
+        }
+        assert_ne!(now(), past);
+    }
+}
@@ -3,6 +3,10 @@
 use once_cell::sync::Lazy;
 use std::sync::{Arc, Mutex};
 
+use crate::clock::{Clock, SystemClock};
+use crate::ical::TimestampPrecision;
+use crate::uid::{RandomUidGenerator, UidGenerator};
+
 /// Part of the ProdID string that describes the organization (example of a ProdID string: `-//ABC Corporation//My Product//EN`).
 /// Feel free to override it when initing this library.
 pub static ORG_NAME: Lazy<Arc<Mutex<String>>> =
@@ -12,3 +16,18 @@ pub static ORG_NAME: Lazy<Arc<Mutex<String>>> =
 /// Feel free to override it when initing this library.
 pub static PRODUCT_NAME: Lazy<Arc<Mutex<String>>> =
     Lazy::new(|| Arc::new(Mutex::new("KitchenFridge".to_string())));
+
+/// The clock used for every timestamp this crate generates.
+/// Feel free to override it. Tests had better use [`set_thread_clock`](crate::clock::set_thread_clock), that does not affect the tests that run in parallel.
+pub static CLOCK: Lazy<Arc<Mutex<Arc<dyn Clock>>>> =
+    Lazy::new(|| Arc::new(Mutex::new(Arc::new(SystemClock))));
+
+/// The generator used for the UIDs of new tasks and the URLs of new items.
+/// Feel free to override it, e.g. with a [`SeededUidGenerator`](crate::uid::SeededUidGenerator) in tests.
+pub static UID_GENERATOR: Lazy<Arc<Mutex<Arc<dyn UidGenerator>>>> =
+    Lazy::new(|| Arc::new(Mutex::new(Arc::new(RandomUidGenerator))));
+
+/// How precise the timestamps (`DTSTAMP`, `CREATED`, `LAST-MODIFIED`, `COMPLETED`) of the iCal files this crate generates are.
+/// They are in whole seconds by default; some servers only compare them at the minute.
+pub static TIMESTAMP_PRECISION: Lazy<Arc<Mutex<TimestampPrecision>>> =
+    Lazy::new(|| Arc::new(Mutex::new(TimestampPrecision::default())));
@@ -0,0 +1,163 @@
+//! Where the credentials sent to a server come from
+//!
+//! By default, a [`Resource`](crate::resource::Resource) sends the username and password it has been created with.
+//! Applications can instead give it a [`CredentialProvider`], that is asked for the credentials whenever a request is sent.
+//! This way, secrets can be kept in a dedicated storage (e.g. the keychain of the OS) rather than in memory for the whole life of the client, and expiring tokens can be renewed.
+//!
+//! With the `keyring` feature, `KeyringCredentials` reads the password from the keychain of the OS.
+
+use std::fmt;
+
+use async_trait::async_trait;
+use http::HeaderValue;
+
+#[cfg(feature = "keyring")]
+mod keychain;
+#[cfg(feature = "keyring")]
+pub use keychain::KeyringCredentials;
+
+/// The error type returned by credential providers
+pub type CredentialError = Box<dyn std::error::Error + Send + Sync>;
+
+/// What is sent to authenticate a request
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// HTTP Basic authentication
+    Basic { username: String, password: String },
+    /// A bearer token (e.g. an OAuth access token)
+    Bearer { token: String },
+}
+
+impl Credentials {
+    /// The value of the `Authorization` header for these credentials
+    pub fn authorization(&self) -> Result<HeaderValue, CredentialError> {
+        let value = match self {
+            Self::Basic { username, password } => format!(
+                "Basic {}",
+                base64::encode(format!("{}:{}", username, password))
+            ),
+            Self::Bearer { token } => format!("Bearer {}", token),
+        };
+        let mut value = HeaderValue::from_str(&value)?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+            Self::Bearer { .. } => f
+                .debug_struct("Bearer")
+                .field("token", &"<redacted>")
+                .finish(),
+        }
+    }
+}
+
+/// Provides the credentials of the requests sent to a server.
+///
+/// It is asked for the credentials before every request, so implementations that are slow to provide them (e.g. that read them from a keychain) should cache them
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait CredentialProvider: Send + Sync {
+    /// The user these credentials belong to. This is only used in logs and messages, and is never secret
+    fn username(&self) -> &str;
+
+    /// The credentials to send with the next request
+    async fn credentials(&self) -> Result<Credentials, CredentialError>;
+
+    /// Called when the server has refused the credentials (i.e. replied `401 Unauthorized`), e.g. to renew an expired token.
+    ///
+    /// In case this returns `true`, the request is sent once again, with the new [`Self::credentials`].
+    /// The default implementation returns `false`, i.e. the refused request fails
+    async fn refresh(&self) -> Result<bool, CredentialError> {
+        Ok(false)
+    }
+}
+
+/// Credentials that never change, e.g. a username and a password given by the user
+pub struct StaticCredentials {
+    username: String,
+    credentials: Credentials,
+}
+
+impl StaticCredentials {
+    /// HTTP Basic credentials
+    pub fn basic<S: ToString, T: ToString>(username: S, password: T) -> Self {
+        let username = username.to_string();
+        Self {
+            credentials: Credentials::Basic {
+                username: username.clone(),
+                password: password.to_string(),
+            },
+            username,
+        }
+    }
+
+    /// A bearer token, for the user `username`
+    pub fn bearer<S: ToString, T: ToString>(username: S, token: T) -> Self {
+        Self {
+            username: username.to_string(),
+            credentials: Credentials::Bearer {
+                token: token.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CredentialProvider for StaticCredentials {
+    fn username(&self) -> &str {
+        &self.username
+    }
+
+    async fn credentials(&self) -> Result<Credentials, CredentialError> {
+        Ok(self.credentials.clone())
+    }
+}
+
+impl fmt::Debug for StaticCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticCredentials")
+            .field("credentials", &self.credentials)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorization() {
+        let basic = Credentials::Basic {
+            username: "Aladdin".to_string(),
+            password: "open sesame".to_string(),
+        };
+        // The example of RFC 7617
+        assert_eq!(
+            basic.authorization().unwrap(),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert!(basic.authorization().unwrap().is_sensitive());
+        let bearer = Credentials::Bearer {
+            token: "mF_9.B5f-4.1JqM".to_string(),
+        };
+        assert_eq!(bearer.authorization().unwrap(), "Bearer mF_9.B5f-4.1JqM");
+        assert!(Credentials::Bearer {
+            token: "new\nline".to_string()
+        }
+        .authorization()
+        .is_err());
+
+        let shown = format!("{:?}", StaticCredentials::basic("Aladdin", "open sesame"));
+        assert!(!shown.contains("sesame"), "{}", shown);
+    }
+}
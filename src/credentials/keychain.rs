@@ -0,0 +1,119 @@
+//! Credentials stored in the keychain of the OS (this requires the `keyring` feature)
+
+use std::fmt;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use super::{CredentialError, CredentialProvider, Credentials};
+
+/// HTTP Basic credentials whose password is stored in the keychain of the OS (the Keychain on macOS, the Credential Manager on Windows, the Secret Service on Linux).
+///
+/// The password is read from the keychain when the first request is sent, then kept in memory.
+/// In case the server refuses it, it is read again: the request is only sent once more if it has been changed in the meantime
+pub struct KeyringCredentials {
+    service: String,
+    username: String,
+    password: Mutex<Option<String>>,
+}
+
+impl KeyringCredentials {
+    /// The password of `username`, that is stored in the keychain for `service` (e.g. the name of the application)
+    pub fn new<S: ToString, T: ToString>(service: S, username: T) -> Self {
+        Self {
+            service: service.to_string(),
+            username: username.to_string(),
+            password: Mutex::new(None),
+        }
+    }
+
+    /// Store the password in the keychain, e.g. once the user has typed it
+    pub async fn set_password(&self, password: &str) -> Result<(), CredentialError> {
+        let entry = self.entry()?;
+        let stored = password.to_string();
+        tokio::task::spawn_blocking(move || entry.set_password(&stored)).await??;
+        *self.password.lock().await = Some(password.to_string());
+        Ok(())
+    }
+
+    /// Remove the password from the keychain
+    pub async fn delete_password(&self) -> Result<(), CredentialError> {
+        let entry = self.entry()?;
+        tokio::task::spawn_blocking(move || entry.delete_credential()).await??;
+        *self.password.lock().await = None;
+        Ok(())
+    }
+
+    fn entry(&self) -> Result<keyring::Entry, CredentialError> {
+        Ok(keyring::Entry::new(&self.service, &self.username)?)
+    }
+
+    /// Accessing the keychain may block, e.g. while the OS asks the user to unlock it
+    async fn read_password(&self) -> Result<String, CredentialError> {
+        let entry = self.entry()?;
+        Ok(tokio::task::spawn_blocking(move || entry.get_password()).await??)
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for KeyringCredentials {
+    fn username(&self) -> &str {
+        &self.username
+    }
+
+    async fn credentials(&self) -> Result<Credentials, CredentialError> {
+        let mut password = self.password.lock().await;
+        let password = match &mut *password {
+            Some(password) => password.clone(),
+            None => password.insert(self.read_password().await?).clone(),
+        };
+        Ok(Credentials::Basic {
+            username: self.username.clone(),
+            password,
+        })
+    }
+
+    async fn refresh(&self) -> Result<bool, CredentialError> {
+        let mut password = self.password.lock().await;
+        let fresh = self.read_password().await?;
+        let changed = password.as_ref() != Some(&fresh);
+        *password = Some(fresh);
+        Ok(changed)
+    }
+}
+
+impl fmt::Debug for KeyringCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyringCredentials")
+            .field("service", &self.service)
+            .field("username", &self.username)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keyring_credentials() {
+        // Mock entries do not persist across `Entry` instances, so a stored password is only seen through the one the provider keeps in memory
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+
+        let credentials = KeyringCredentials::new("kitchen-fridge tests", "alice");
+        assert_eq!(credentials.username(), "alice");
+        assert!(credentials.credentials().await.is_err());
+        assert!(credentials.refresh().await.is_err());
+
+        credentials.set_password("open sesame").await.unwrap();
+        assert_eq!(
+            credentials.credentials().await.unwrap(),
+            Credentials::Basic {
+                username: "alice".to_string(),
+                password: "open sesame".to_string(),
+            }
+        );
+        let shown = format!("{:?}", credentials);
+        assert!(!shown.contains("sesame"), "{}", shown);
+    }
+}
@@ -0,0 +1,494 @@
+//! A low-level WebDAV and CalDAV client: builders for the bodies of the requests (e.g. CalDAV `REPORT` requests, see [RFC 4791, section 7](https://datatracker.ietf.org/doc/html/rfc4791#section-7)),
+//! functions that send them (see [`request`]), and typed replies (see [`Multistatus`]).
+//!
+//! These are the requests kitchen-fridge sends to discover calendars, and to list and download items. They can also be used to send custom requests, e.g.
+//! ```rust
+//! use kitchen_fridge::dav::{self, CalendarQuery};
+//!
+//! let body = CalendarQuery::todos()
+//!     .time_range(Some(chrono::Utc::now()), None)
+//!     .props([dav::getetag(), dav::calendar_data()])
+//!     .to_xml()
+//!     .unwrap();
+//! ```
+//!
+//! Requests are sent to a [`Resource`](crate::resource::Resource), e.g. one derived from a [`Client`](crate::client::Client) (see [`Client::resource`](crate::client::Client::resource)),
+//! so that they use the same credentials and HTTP settings as the rest of the crate:
+//! ```rust,no_run
+//! # async fn example(client: &kitchen_fridge::client::Client) -> kitchen_fridge::error::KFResult<()> {
+//! use kitchen_fridge::dav;
+//!
+//! let calendar = client.resource().join("/calendars/me/tasks/")?;
+//! let reply = dav::propfind(&calendar, &[dav::getctag()], 0).await?;
+//! for response in &reply.responses {
+//!     println!("{}: {:?}", response.href, response.prop(&dav::getctag()).map(|ctag| ctag.text()));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use chrono::{DateTime, Utc};
+use url::Url;
+
+use crate::error::KFResult;
+use crate::utils::prop::Property;
+use crate::utils::xml::escape_text;
+use crate::utils::{NamespacedName, Namespaces};
+
+mod multistatus;
+pub use multistatus::{Multistatus, Propstat, Response};
+mod requests;
+pub(crate) use requests::method;
+pub use requests::{mkcalendar, propfind, proppatch, report, request};
+
+/// The XML namespace of CalDAV elements
+pub const CALDAV_NS: &str = "urn:ietf:params:xml:ns:caldav";
+
+/// The `DAV:getetag` property, i.e. the version tag of an item
+pub fn getetag() -> NamespacedName {
+    NamespacedName::new("DAV:", "getetag")
+}
+
+/// The `getctag` property of the CalendarServer extensions, that changes whenever the content of a collection changes
+pub fn getctag() -> NamespacedName {
+    NamespacedName::new("http://calendarserver.org/ns/", "getctag")
+}
+
+/// The `DAV:sync-token` property of a collection ([RFC 6578](https://datatracker.ietf.org/doc/html/rfc6578#section-4))
+pub fn sync_token() -> NamespacedName {
+    NamespacedName::new("DAV:", "sync-token")
+}
+
+/// The CalDAV `calendar-data` property, i.e. the iCal content of an item
+pub fn calendar_data() -> NamespacedName {
+    NamespacedName::new(CALDAV_NS, "calendar-data")
+}
+
+/// A `calendar-query` REPORT, that lists the items of a calendar that contain a given kind of component
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CalendarQuery {
+    component: String,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    uncompleted_only: bool,
+    props: Vec<NamespacedName>,
+}
+
+impl CalendarQuery {
+    /// Query the items that contain a component (e.g. `VTODO`). By default, only their [`getetag`] is requested
+    pub fn component<S: ToString>(component: S) -> Self {
+        Self {
+            component: component.to_string(),
+            start: None,
+            end: None,
+            uncompleted_only: false,
+            props: vec![getetag()],
+        }
+    }
+
+    /// Query the tasks
+    pub fn todos() -> Self {
+        Self::component("VTODO")
+    }
+
+    /// Query the events
+    pub fn events() -> Self {
+        Self::component("VEVENT")
+    }
+
+    /// Only query the components that overlap this time range. `None` leaves a bound open
+    pub fn time_range(mut self, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Self {
+        self.start = start;
+        self.end = end;
+        self
+    }
+
+    /// Only query the components that have no `COMPLETED` property, i.e. the tasks that have not been completed.
+    ///
+    /// CalDAV filters cannot tell apart the tasks that have a `STATUS:COMPLETED` but no `COMPLETED` date, so these are still returned
+    pub fn uncompleted_only(mut self) -> Self {
+        self.uncompleted_only = true;
+        self
+    }
+
+    /// Request these properties rather than the default ones
+    pub fn props<I: IntoIterator<Item = NamespacedName>>(mut self, props: I) -> Self {
+        self.props = props.into_iter().collect();
+        self
+    }
+
+    /// The body of the REPORT request
+    ///
+    /// This will look something like:
+    ///
+    /// <d:calendar-query xmlns:d="DAV:" xmlns:z="urn:ietf:params:xml:ns:caldav">
+    ///     <d:prop>
+    ///         <d:getetag/>
+    ///     </d:prop>
+    ///     <z:filter>
+    ///         <z:comp-filter name="VCALENDAR">
+    ///             <z:comp-filter name="VTODO"/>
+    ///         </z:comp-filter>
+    ///     </z:filter>
+    /// </d:calendar-query>
+    pub fn to_xml(&self) -> KFResult<String> {
+        let mut namespaces = Namespaces::new();
+        let c = namespaces.add(CALDAV_NS)?;
+        let d = namespaces.dav_sym();
+        let prop = prop_block(&self.props, &mut namespaces)?;
+
+        let mut filters = Vec::new();
+        if self.start.is_some() || self.end.is_some() {
+            let mut bounds = String::new();
+            if let Some(start) = &self.start {
+                bounds.push_str(&format!(r#" start="{}""#, format_time(start)));
+            }
+            if let Some(end) = &self.end {
+                bounds.push_str(&format!(r#" end="{}""#, format_time(end)));
+            }
+            filters.push(format!("<{}:time-range{}/>", c, bounds));
+        }
+        if self.uncompleted_only {
+            filters.push(format!(
+                "<{c}:prop-filter name=\"COMPLETED\"><{c}:is-not-defined/></{c}:prop-filter>",
+                c = c
+            ));
+        }
+        let component_filter = if filters.is_empty() {
+            format!(r#"<{}:comp-filter name="{}"/>"#, c, escape(&self.component))
+        } else {
+            format!(
+                "<{}:comp-filter name=\"{}\">\n                {}\n            </{}:comp-filter>",
+                c,
+                escape(&self.component),
+                filters.join("\n                "),
+                c
+            )
+        };
+
+        Ok(format!(
+            r#"
+<{d}:calendar-query{decl}>
+{prop}    <{c}:filter>
+        <{c}:comp-filter name="VCALENDAR">
+            {component_filter}
+        </{c}:comp-filter>
+    </{c}:filter>
+</{d}:calendar-query>
+"#,
+            d = d,
+            c = c,
+            decl = namespaces.decl(),
+            prop = prop,
+            component_filter = component_filter,
+        ))
+    }
+}
+
+/// A `calendar-multiget` REPORT, that fetches some items of a calendar. By default, their [`calendar_data`] is requested
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CalendarMultiget {
+    urls: Vec<Url>,
+    base: Option<Url>,
+    props: Vec<NamespacedName>,
+}
+
+impl CalendarMultiget {
+    pub fn new<'a, I: IntoIterator<Item = &'a Url>>(urls: I) -> Self {
+        Self {
+            urls: urls.into_iter().cloned().collect(),
+            base: None,
+            props: vec![calendar_data()],
+        }
+    }
+
+    /// The URL the request is sent to. Items on the same server are referred to by their paths, other ones (that some servers host on other domains) by their full URLs.
+    /// Without a base, every item is referred to by its path
+    pub fn base(mut self, base: &Url) -> Self {
+        self.base = Some(base.clone());
+        self
+    }
+
+    /// Request these properties rather than the default ones
+    pub fn props<I: IntoIterator<Item = NamespacedName>>(mut self, props: I) -> Self {
+        self.props = props.into_iter().collect();
+        self
+    }
+
+    /// The body of the REPORT request
+    ///
+    /// This will look something like:
+    ///
+    /// <z:calendar-multiget xmlns:d="DAV:" xmlns:z="urn:ietf:params:xml:ns:caldav">
+    ///     <d:prop>
+    ///         <z:calendar-data/>
+    ///     </d:prop>
+    ///     <d:href>/calendars/tasks/1.ics</d:href>
+    /// </z:calendar-multiget>
+    pub fn to_xml(&self) -> KFResult<String> {
+        let mut namespaces = Namespaces::new();
+        let c = namespaces.add(CALDAV_NS)?;
+        let d = namespaces.dav_sym();
+        let prop = prop_block(&self.props, &mut namespaces)?;
+
+        let mut hrefs = String::new();
+        for url in &self.urls {
+            let href = match &self.base {
+                Some(base) if base.origin() != url.origin() => url.as_str(),
+                _ => url.path(),
+            };
+            hrefs.push_str(&format!("    <{}:href>{}</{}:href>\n", d, escape(href), d));
+        }
+
+        Ok(format!(
+            r#"
+<{c}:calendar-multiget{decl}>
+{prop}{hrefs}</{c}:calendar-multiget>
+"#,
+            c = c,
+            decl = namespaces.decl(),
+            prop = prop,
+            hrefs = hrefs,
+        ))
+    }
+}
+
+/// Body of a PROPFIND call that queries the given properties
+///
+/// This will look something like:
+///
+/// <d:propfind xmlns:d="DAV:">
+///     <d:prop>
+///         <d:allprop/>
+///     </d:prop>
+/// </d:propfind>
+pub fn propfind_body(props: &[NamespacedName]) -> KFResult<String> {
+    let mut namespaces = Namespaces::new();
+    for p in props {
+        namespaces.add(&p.xmlns)?;
+    }
+
+    let prop_names = {
+        let mut s = String::new();
+        for p in props {
+            s.push('<');
+            s.push_str(p.with_symbolized_prefix(&namespaces)?.as_str());
+            s.push('/');
+            s.push('>');
+            s.push('\n');
+        }
+        s
+    };
+
+    let d = namespaces.dav_sym();
+
+    Ok(format!(
+        r#"
+<{}:propfind{}>
+    <{}:prop>
+{}
+    </{}:prop>
+</{}:propfind>
+"#,
+        d,
+        namespaces.decl(),
+        d,
+        prop_names,
+        d,
+        d,
+    ))
+}
+
+/// Body of a PROPPATCH call that sets and removes the given properties in a single request
+///
+/// This will look something like:
+///
+/// <d:propertyupdate xmlns:d="DAV:" xmlns:z="http://apple.com/ns/ical/">
+///     <d:set>
+///         <d:prop><z:calendar-color>#FF8000</z:calendar-color></d:prop>
+///     </d:set>
+///     <d:remove>
+///         <d:prop><d:displayname/></d:prop>
+///     </d:remove>
+/// </d:propertyupdate>
+pub fn proppatch_body(set: &[Property], remove: &[NamespacedName]) -> KFResult<String> {
+    let mut namespaces = Namespaces::new();
+    for nsn in set.iter().map(|p| p.nsn()).chain(remove.iter()) {
+        namespaces.add(&nsn.xmlns)?;
+    }
+    let d = namespaces.dav_sym();
+
+    let mut blocks = String::new();
+    if !set.is_empty() {
+        blocks.push_str(&format!("    <{}:set>\n        <{}:prop>\n", d, d));
+        for p in set {
+            let symbolized = p.nsn().with_symbolized_prefix(&namespaces)?;
+            blocks.push_str(&format!(
+                "            <{}>{}</{}>\n",
+                symbolized,
+                escape_text(p.value()),
+                symbolized
+            ));
+        }
+        blocks.push_str(&format!("        </{}:prop>\n    </{}:set>\n", d, d));
+    }
+    if !remove.is_empty() {
+        blocks.push_str(&format!("    <{}:remove>\n        <{}:prop>\n", d, d));
+        for nsn in remove {
+            blocks.push_str(&format!(
+                "            <{}/>\n",
+                nsn.with_symbolized_prefix(&namespaces)?
+            ));
+        }
+        blocks.push_str(&format!("        </{}:prop>\n    </{}:remove>\n", d, d));
+    }
+
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="utf-8" ?>
+<{}:propertyupdate{}>
+{}</{}:propertyupdate>
+"#,
+        d,
+        namespaces.decl(),
+        blocks,
+        d,
+    ))
+}
+
+/// The `prop` element that requests these properties
+fn prop_block(props: &[NamespacedName], namespaces: &mut Namespaces) -> KFResult<String> {
+    for p in props {
+        namespaces.add(&p.xmlns)?;
+    }
+    let d = namespaces.dav_sym();
+    let mut s = format!("    <{}:prop>\n", d);
+    for p in props {
+        s.push_str(&format!(
+            "        <{}/>\n",
+            p.with_symbolized_prefix(namespaces)?
+        ));
+    }
+    s.push_str(&format!("    </{}:prop>\n", d));
+    Ok(s)
+}
+
+/// The UTC date-time format of the `time-range` attributes
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use minidom::Element;
+
+    use crate::error::KFError;
+    use crate::utils::xml::{find_elem, find_elems};
+
+    #[test]
+    fn test_calendar_query() {
+        let body = CalendarQuery::todos().to_xml().unwrap();
+        let root: Element = body.parse().unwrap();
+        assert_eq!(root.name(), "calendar-query");
+        assert_eq!(root.ns(), "DAV:");
+        let prop = find_elem(&root, "prop").unwrap();
+        assert_eq!(
+            prop.children().map(|p| p.name()).collect::<Vec<_>>(),
+            ["getetag"]
+        );
+        let filters = find_elems(&root, "comp-filter");
+        assert_eq!(filters[0].attr("name"), Some("VCALENDAR"));
+        let todo = filters[0].children().next().unwrap();
+        assert_eq!(todo.attr("name"), Some("VTODO"));
+        assert_eq!(todo.ns(), CALDAV_NS);
+        assert!(find_elem(&root, "time-range").is_none());
+
+        let start = Utc.ymd(2022, 3, 4).and_hms(5, 6, 7);
+        let body = CalendarQuery::events()
+            .time_range(Some(start), None)
+            .props([getetag(), calendar_data()])
+            .to_xml()
+            .unwrap();
+        let root: Element = body.parse().unwrap();
+        let prop = find_elem(&root, "prop").unwrap();
+        assert_eq!(
+            prop.children()
+                .map(|p| (p.ns(), p.name().to_string()))
+                .collect::<Vec<_>>(),
+            [
+                ("DAV:".to_string(), "getetag".to_string()),
+                (CALDAV_NS.to_string(), "calendar-data".to_string())
+            ]
+        );
+        let range = find_elem(&root, "time-range").unwrap();
+        assert_eq!(range.attr("start"), Some("20220304T050607Z"));
+        assert_eq!(range.attr("end"), None);
+        assert!(find_elem(&root, "prop-filter").is_none());
+
+        let body = CalendarQuery::todos().uncompleted_only().to_xml().unwrap();
+        let root: Element = body.parse().unwrap();
+        let prop_filter = find_elem(&root, "prop-filter").unwrap();
+        assert_eq!(prop_filter.attr("name"), Some("COMPLETED"));
+        assert!(prop_filter.has_child("is-not-defined", CALDAV_NS));
+        let todo = find_elems(&root, "comp-filter")[0]
+            .children()
+            .next()
+            .unwrap();
+        assert!(todo.has_child("prop-filter", CALDAV_NS));
+    }
+
+    #[test]
+    fn test_calendar_multiget() {
+        let urls: Vec<Url> = vec![
+            "https://some.calend.ar/tasks/1.ics".parse().unwrap(),
+            "https://some.calend.ar/tasks/a&b.ics".parse().unwrap(),
+        ];
+        let body = CalendarMultiget::new(&urls).to_xml().unwrap();
+        let root: Element = body.parse().unwrap();
+        assert_eq!(root.name(), "calendar-multiget");
+        assert_eq!(root.ns(), CALDAV_NS);
+        assert!(find_elem(&root, "calendar-data").is_some());
+        let hrefs: Vec<String> = find_elems(&root, "href").iter().map(|h| h.text()).collect();
+        assert_eq!(hrefs, ["/tasks/1.ics", "/tasks/a&b.ics"]);
+
+        // Some servers store items on other hosts
+        let elsewhere: Url = "https://p42.calend.ar/tasks/2.ics".parse().unwrap();
+        let body = CalendarMultiget::new(&[urls[0].clone(), elsewhere])
+            .base(&"https://some.calend.ar/tasks/".parse().unwrap())
+            .to_xml()
+            .unwrap();
+        let root: Element = body.parse().unwrap();
+        let hrefs: Vec<String> = find_elems(&root, "href").iter().map(|h| h.text()).collect();
+        assert_eq!(hrefs, ["/tasks/1.ics", "https://p42.calend.ar/tasks/2.ics"]);
+    }
+
+    #[test]
+    fn test_too_many_namespaces() {
+        let few: Vec<NamespacedName> = (0..10)
+            .map(|i| NamespacedName::new(format!("urn:ns:{}", i), "prop"))
+            .collect();
+        let body = propfind_body(&few).unwrap();
+        assert!(body.parse::<Element>().is_ok());
+
+        let many: Vec<NamespacedName> = (0..100)
+            .map(|i| NamespacedName::new(format!("urn:ns:{}", i), "prop"))
+            .collect();
+        assert!(matches!(
+            propfind_body(&many),
+            Err(KFError::OutOfNamespaceSymbols { .. })
+        ));
+        assert!(matches!(
+            proppatch_body(&[], &many),
+            Err(KFError::OutOfNamespaceSymbols { .. })
+        ));
+    }
+}
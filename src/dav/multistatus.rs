@@ -0,0 +1,184 @@
+//! Typed replies of WebDAV requests: the `207 Multi-Status` bodies of PROPFIND, REPORT and PROPPATCH requests ([RFC 4918, section 13](https://datatracker.ietf.org/doc/html/rfc4918#section-13))
+
+use minidom::Element;
+use reqwest::StatusCode;
+
+use crate::error::{KFError, KFResult};
+use crate::utils::xml::{find_elem, find_elems};
+use crate::utils::NamespacedName;
+
+/// A `DAV:multistatus` reply, i.e. the status of several resources (or of several properties of a resource)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Multistatus {
+    pub responses: Vec<Response>,
+}
+
+/// A `DAV:response` of a [`Multistatus`], about a single resource
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    /// The resource this is about, as written by the server (see [`Resource::join`](crate::resource::Resource::join) to resolve it)
+    pub href: String,
+    /// The status of the whole resource. Servers usually only set it when there are no properties, e.g. for a `404 Not Found` resource
+    pub status: Option<StatusCode>,
+    pub propstats: Vec<Propstat>,
+}
+
+/// A `DAV:propstat` of a [`Response`], i.e. some properties that share the same status
+#[derive(Clone, Debug, PartialEq)]
+pub struct Propstat {
+    pub status: StatusCode,
+    /// The property elements (e.g. `<d:getetag>"1234"</d:getetag>`). These are empty for properties that have not been found
+    pub props: Vec<Element>,
+}
+
+impl Multistatus {
+    /// Parse the body of a `207 Multi-Status` reply
+    pub fn parse(text: &str) -> KFResult<Self> {
+        let root: Element = text.parse().map_err(|source| KFError::DOMParseError {
+            text: text.to_string(),
+            source,
+        })?;
+        Self::from_element(&root)
+    }
+
+    /// The reply, from its root `multistatus` element
+    pub fn from_element(root: &Element) -> KFResult<Self> {
+        let responses = find_elems(root, "response")
+            .into_iter()
+            .map(Response::from_element)
+            .collect::<KFResult<_>>()?;
+        Ok(Self { responses })
+    }
+}
+
+impl Response {
+    /// Parse a `response` element
+    pub fn from_element(response: &Element) -> KFResult<Self> {
+        let href = find_elem(response, "href")
+            .map(|href| href.text().trim().to_string())
+            .ok_or_else(|| KFError::MissingDOMElement {
+                text: response.text(),
+                el: "href".to_string(),
+            })?;
+        // Only the `status` child of `response` itself, not the ones of its propstats
+        let status = response
+            .children()
+            .find(|child| child.name() == "status")
+            .and_then(|status| parse_status_line(&status.text()));
+        let propstats = response
+            .children()
+            .filter(|child| child.name() == "propstat")
+            .map(Propstat::from_element)
+            .collect::<KFResult<_>>()?;
+        Ok(Self {
+            href,
+            status,
+            propstats,
+        })
+    }
+
+    /// The value of a property, in case the server has returned it successfully
+    pub fn prop(&self, name: &NamespacedName) -> Option<&Element> {
+        self.propstats
+            .iter()
+            .filter(|propstat| propstat.status.is_success())
+            .find_map(|propstat| propstat.prop(name))
+    }
+
+    /// The status of a property, in case the server mentions it
+    pub fn prop_status(&self, name: &NamespacedName) -> Option<StatusCode> {
+        self.propstats
+            .iter()
+            .find(|propstat| propstat.prop(name).is_some())
+            .map(|propstat| propstat.status)
+    }
+}
+
+impl Propstat {
+    /// Parse a `propstat` element
+    pub fn from_element(propstat: &Element) -> KFResult<Self> {
+        let status = find_elem(propstat, "status")
+            .and_then(|status| parse_status_line(&status.text()))
+            .ok_or_else(|| KFError::MissingDOMElement {
+                text: propstat.text(),
+                el: "status".to_string(),
+            })?;
+        let props = find_elem(propstat, "prop")
+            .map(|prop| prop.children().cloned().collect())
+            .unwrap_or_default();
+        Ok(Self { status, props })
+    }
+
+    /// The element of a property of this propstat
+    pub fn prop(&self, name: &NamespacedName) -> Option<&Element> {
+        self.props
+            .iter()
+            .find(|prop| prop.name() == name.name && prop.ns() == name.xmlns)
+    }
+}
+
+/// The code of a status line, e.g. `HTTP/1.1 424 Failed Dependency`
+fn parse_status_line(line: &str) -> Option<StatusCode> {
+    line.split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .and_then(|code| StatusCode::from_u16(code).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_multistatus() {
+        let reply = r#"<?xml version="1.0" encoding="utf-8" ?>
+            <d:multistatus xmlns:d="DAV:" xmlns:cs="http://calendarserver.org/ns/">
+                <d:response>
+                    <d:href>/calendars/me/tasks/</d:href>
+                    <d:propstat>
+                        <d:prop><d:displayname>Tasks</d:displayname><cs:getctag>42</cs:getctag></d:prop>
+                        <d:status>HTTP/1.1 200 OK</d:status>
+                    </d:propstat>
+                    <d:propstat>
+                        <d:prop><d:sync-token/></d:prop>
+                        <d:status>HTTP/1.1 404 Not Found</d:status>
+                    </d:propstat>
+                </d:response>
+                <d:response>
+                    <d:href>/calendars/me/gone/</d:href>
+                    <d:status>HTTP/1.1 404 Not Found</d:status>
+                </d:response>
+            </d:multistatus>"#;
+        let multistatus = Multistatus::parse(reply).unwrap();
+        assert_eq!(multistatus.responses.len(), 2);
+
+        let tasks = &multistatus.responses[0];
+        assert_eq!(tasks.href, "/calendars/me/tasks/");
+        assert_eq!(tasks.status, None);
+        let displayname = NamespacedName::new("DAV:", "displayname");
+        assert_eq!(tasks.prop(&displayname).unwrap().text(), "Tasks");
+        assert_eq!(
+            tasks
+                .prop(&NamespacedName::new(
+                    "http://calendarserver.org/ns/",
+                    "getctag"
+                ))
+                .unwrap()
+                .text(),
+            "42"
+        );
+        let sync_token = NamespacedName::new("DAV:", "sync-token");
+        assert!(tasks.prop(&sync_token).is_none());
+        assert_eq!(tasks.prop_status(&sync_token), Some(StatusCode::NOT_FOUND));
+        assert_eq!(tasks.prop_status(&displayname), Some(StatusCode::OK));
+
+        let gone = &multistatus.responses[1];
+        assert_eq!(gone.status, Some(StatusCode::NOT_FOUND));
+        assert!(gone.propstats.is_empty());
+
+        assert!(Multistatus::parse(
+            "<d:multistatus xmlns:d=\"DAV:\"><d:response/></d:multistatus>"
+        )
+        .is_err());
+    }
+}
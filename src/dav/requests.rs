@@ -0,0 +1,244 @@
+//! Authenticated WebDAV and CalDAV requests, for applications that need calls this crate does not make by itself (e.g. vendor extensions)
+
+use std::time::Duration;
+
+use csscolorparser::Color;
+use http::{
+    header::{CONTENT_TYPE, RETRY_AFTER},
+    HeaderMap, Method,
+};
+use reqwest::StatusCode;
+
+use super::{propfind_body, proppatch_body, Multistatus};
+use crate::calendar::SupportedComponents;
+use crate::error::{HttpStatusConstraint, KFError, KFResult};
+use crate::resource::Resource;
+use crate::utils::prop::Property;
+use crate::utils::xml::{decode_body, escape_text};
+use crate::utils::{NamespacedName, Namespaces};
+
+/// A WebDAV method (e.g. `PROPFIND`) this crate uses, that is not among the constants of [`Method`]
+pub(crate) fn method(name: &str) -> Method {
+    Method::from_bytes(name.as_bytes()).expect("invalid method name")
+}
+
+/// Send a request with an XML body to a resource (with its credentials, headers and timeout, following the redirects of the server), and return the body of the reply.
+///
+/// WebDAV methods that are not among the constants of [`Method`] are built with [`Method::from_bytes`] (e.g. `Method::from_bytes(b"PROPFIND")`).
+///
+/// Replies that are not successful are errors. In case the server asks to slow down, this fails with [`KFError::RateLimited`], that tells when the request can be retried
+pub async fn request(
+    resource: &Resource,
+    method: Method,
+    body: String,
+    depth: u32,
+) -> KFResult<String> {
+    let url = resource.url();
+
+    resource.transfers().add_sent(body.len());
+    let res = resource
+        .send(method.clone(), url.clone(), |request| {
+            request
+                .header("Depth", depth)
+                .header(CONTENT_TYPE, "application/xml")
+                .body(body)
+        })
+        .await?;
+
+    if is_rate_limiting(res.status()) {
+        return Err(KFError::RateLimited {
+            url: url.clone(),
+            status: res.status(),
+            retry_after: retry_after(res.headers()),
+        });
+    }
+    if !res.status().is_success() {
+        return Err(KFError::UnexpectedHTTPStatusCode {
+            expected: HttpStatusConstraint::Success,
+            got: res.status(),
+        });
+    }
+
+    let content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .map(str::to_string);
+    let bytes = res
+        .bytes()
+        .await
+        .map_err(|source| KFError::HttpRequestError {
+            url: url.clone(),
+            method,
+            source,
+        })?;
+    resource.transfers().add_received(bytes.len());
+    Ok(decode_body(&bytes, content_type.as_deref()))
+}
+
+/// Query properties of a resource (with `depth` 0) or of its children as well (with `depth` 1)
+pub async fn propfind(
+    resource: &Resource,
+    props: &[NamespacedName],
+    depth: u32,
+) -> KFResult<Multistatus> {
+    let text = request(resource, method("PROPFIND"), propfind_body(props)?, depth).await?;
+    Multistatus::parse(&text)
+}
+
+/// Send a REPORT request, whose body can be built with a [`CalendarQuery`](super::CalendarQuery) or a [`CalendarMultiget`](super::CalendarMultiget)
+pub async fn report(resource: &Resource, body: String, depth: u32) -> KFResult<Multistatus> {
+    let text = request(resource, method("REPORT"), body, depth).await?;
+    Multistatus::parse(&text)
+}
+
+/// Set and remove properties of a resource in a single request. The reply tells the status of every property
+pub async fn proppatch(
+    resource: &Resource,
+    set: &[Property],
+    remove: &[NamespacedName],
+) -> KFResult<Multistatus> {
+    let text = request(
+        resource,
+        method("PROPPATCH"),
+        proppatch_body(set, remove)?,
+        0,
+    )
+    .await?;
+    Multistatus::parse(&text)
+}
+
+/// Create a calendar at the URL of `resource`, with some initial properties
+pub async fn mkcalendar(
+    resource: &Resource,
+    name: String,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+    properties: Vec<Property>,
+) -> KFResult<()> {
+    let body = mkcalendar_body(name, supported_components, color, properties)?;
+    resource.transfers().add_sent(body.len());
+
+    let method = Method::from_bytes(b"MKCALENDAR").unwrap();
+    let response = resource
+        .send(method, resource.url().clone(), |request| {
+            request.header(CONTENT_TYPE, "application/xml").body(body)
+        })
+        .await?;
+
+    let status = response.status();
+    if status != StatusCode::CREATED {
+        return Err(KFError::UnexpectedHTTPStatusCode {
+            expected: HttpStatusConstraint::Specific(vec![StatusCode::CREATED]),
+            got: status,
+        });
+    }
+    Ok(())
+}
+
+/// Whether a server replies with this status to ask clients to slow down
+fn is_rate_limiting(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE
+}
+
+/// The delay of a `Retry-After` header, that is either a number of seconds or an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means "now"
+    Some(
+        (date.with_timezone(&chrono::Utc) - crate::clock::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Body of a MKCALENDAR call
+fn mkcalendar_body(
+    name: String,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+    properties: Vec<Property>,
+) -> KFResult<String> {
+    let color_property = match color {
+        None => "".to_string(),
+        Some(color) => format!(
+            "<D:calendar-color xmlns:D=\"http://apple.com/ns/ical/\">{}FF</D:calendar-color>",
+            color.to_hex_string().to_ascii_uppercase()
+        ),
+    };
+
+    let mut namespaces = Namespaces::new();
+
+    for p in &properties {
+        namespaces.add(p.xmlns())?;
+    }
+
+    let other_props: String = {
+        let mut s = String::new();
+        for p in properties {
+            // <{}:{}>{}</{}:{}>\n
+
+            let symbolized = p.nsn().with_symbolized_prefix(&namespaces)?;
+            s.push('<');
+            s.push_str(symbolized.as_str());
+            s.push('>');
+            s.push_str(&escape_text(p.value()));
+            s.push('<');
+            s.push('/');
+            s.push_str(symbolized.as_str());
+            s.push('>');
+            s.push('\n');
+        }
+        s
+    };
+
+    // This is taken from https://tools.ietf.org/html/rfc4791#page-24
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="utf-8" ?>
+        <B:mkcalendar xmlns:B="urn:ietf:params:xml:ns:caldav">
+            <A:set{}>
+                <A:prop>
+                    <A:displayname>{}</A:displayname>
+                    {}
+                    {}
+                    {}
+                </A:prop>
+            </A:set>
+        </B:mkcalendar>
+        "#,
+        namespaces.decl(),
+        escape_text(&name),
+        color_property,
+        supported_components.to_xml_string(),
+        other_props
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_after() {
+        let with_header = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, value.parse().unwrap());
+            headers
+        };
+        assert_eq!(
+            retry_after(&with_header("120")),
+            Some(Duration::from_secs(120))
+        );
+        // A date that has passed already
+        assert_eq!(
+            retry_after(&with_header("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&with_header("soon")), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+}
@@ -1,4 +1,5 @@
 use std::error::Error;
+use std::path::PathBuf;
 
 use reqwest::StatusCode;
 use url::Url;
@@ -7,6 +8,7 @@ use crate::{
     calendar::remote_calendar::RemoteCalendarError,
     ical::IcalParseError,
     item::ItemType,
+    provider::sync_progress::SyncIssue,
     utils::{prop::Property, NamespacedName},
 };
 
@@ -42,11 +44,26 @@ impl HttpStatusConstraint {
 /// Errors common to the Kitchen Fridge library
 #[derive(thiserror::Error, Debug)]
 pub enum KFError {
+    /// The folder of a [`Cache`](crate::cache::Cache) is used by another process, that is syncing or saving it (see [`cache::lock`](crate::cache::lock))
+    #[error("Cache folder {folder:?} is locked by {holder}")]
+    CacheLocked { folder: PathBuf, holder: String },
+
     #[error(
         "Calendar at URL {0} didn't appear in the client cache after being created on the server"
     )]
     CalendarDidNotSyncAfterCreation(Url),
 
+    /// The [`CredentialProvider`](crate::credentials::CredentialProvider) failed to provide or refresh the credentials
+    #[error("Unable to get the credentials for {url}: {source}")]
+    CredentialError {
+        url: Url,
+        source: crate::credentials::CredentialError,
+    },
+
+    /// The server redirected a request that modifies it. This is not followed, since the request may not be meant for the new location
+    #[error("{} {} has been redirected ({}) to {:?}", .0.method, .0.url, .0.status, .0.location)]
+    DestructiveRedirect(Box<RedirectInfo>),
+
     #[error("Error parsing '{text}': {source}")]
     DOMParseError {
         /// The text being parsed
@@ -65,12 +82,50 @@ pub enum KFError {
     #[error("Error parsing ical data: {0}")]
     IcalParseError(#[from] IcalParseError),
 
+    /// A calendar time zone is not a valid `VTIMEZONE`
+    #[error("Invalid calendar time zone: {detail}")]
+    InvalidCalendarTimezone { detail: String },
+
+    /// This is not the name of an iCal extension property (i.e. `X-` followed by letters, digits and dashes)
+    #[error("Invalid extension property name {name:?}")]
+    InvalidExtensionPropertyName { name: String },
+
+    /// An href (e.g. from a server reply) cannot be resolved against the URL it relates to
+    #[error("Unable to resolve href {href} against {base}: {source}")]
+    InvalidHref {
+        base: Url,
+        href: String,
+        source: url::ParseError,
+    },
+
+    /// The URL of a new item cannot be derived from the URL of its calendar
+    #[error("Unable to build an item URL under {parent}: {source}")]
+    InvalidItemUrl {
+        parent: Url,
+        source: url::ParseError,
+    },
+
+    /// A redirect cannot be followed (e.g. it has no location, it goes from HTTPS to HTTP, or there are too many of them)
+    #[error("Unable to follow the redirect of {url}: {detail}")]
+    InvalidRedirect { url: Url, detail: String },
+
+    #[error("Invalid network configuration: {0}")]
+    InvalidNetworkConfig(#[source] reqwest::Error),
+
+    /// A property cannot be sent to a server, e.g. because its value cannot be written in XML
+    #[error("Invalid property {nsn}: {detail}")]
+    InvalidProperty { nsn: NamespacedName, detail: String },
+
     #[error("Invalid property URL: {bad_url}; from {source}")]
     InvalidPropertyUrl {
         source: url::ParseError,
         bad_url: String,
     },
 
+    /// A task cannot be built, e.g. from an inconsistent [`TaskDto`](crate::task::dto::TaskDto)
+    #[error("Invalid task {url}: {detail}")]
+    InvalidTask { url: Url, detail: String },
+
     #[error("{detail}; an IO error occurred: {source}")]
     IoError {
         detail: String,
@@ -106,20 +161,95 @@ pub enum KFError {
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
     MockError(#[from] crate::mock_behaviour::MockError),
 
+    /// Every symbol available for XML namespace prefixes is already in use
+    #[error("Unable to add XML namespace {xmlns}: ran out of namespace symbols")]
+    OutOfNamespaceSymbols { xmlns: String },
+
+    /// The [`PayloadTransformer`](crate::ical::PayloadTransformer) failed to encode or decode an item
+    #[error("Unable to transform the payload of {url}: {source}")]
+    PayloadTransformError {
+        url: Url,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
     #[error("Property already exists: {0}")]
     PropertyAlreadyExists(Property),
 
     #[error("Property does not exists: {0}")]
     PropertyDoesNotExist(NamespacedName),
 
+    /// The server asks to slow down (HTTP 429 or 503). The request can be retried after `retry_after`, in case the server told when
+    #[error("The server rate-limited the request to {url} ({status})")]
+    RateLimited {
+        url: Url,
+        status: StatusCode,
+        retry_after: Option<std::time::Duration>,
+    },
+
+    /// The resource is locked (e.g. by another client), the request can be retried later
+    #[error("{url} is locked by another client")]
+    ResourceLocked { url: Url },
+
     #[error("Remote calendar error: {0}")]
-    RemoteCalendarError(#[from] RemoteCalendarError),
+    RemoteCalendarError(#[source] Box<RemoteCalendarError>),
+
+    /// A sync has been stopped, e.g. by its [`DestructiveChangeGuard`](crate::provider::hooks::DestructiveChangeGuard)
+    #[error("The sync has been aborted: {reason}")]
+    SyncAborted { reason: String },
+
+    /// A strict sync has stopped syncing a calendar, because its data was not in the state the sync expected (see [`Provider::set_strict`](crate::provider::Provider::set_strict))
+    #[error("Strict sync stopped: {issue}")]
+    SyncInconsistency { issue: Box<SyncIssue> },
+
+    /// An XML name uses a namespace that has not been declared
+    #[error("XML namespace {0} has not been declared")]
+    UndeclaredNamespace(String),
 
     #[error("Unexpected HTTP status code {got:?} but expected {expected:?}")]
     UnexpectedHTTPStatusCode {
         expected: HttpStatusConstraint,
         got: StatusCode,
     },
+
+    /// The server does not support a feature (e.g. a WebDAV method) that is required for this operation
+    #[error("The server at {url} does not support {feature}")]
+    UnsupportedByServer { feature: String, url: Url },
+
+    /// This kind of source (e.g. a remote source) does not support this operation
+    #[error("This source does not support {operation}")]
+    UnsupportedBySource { operation: String },
+
+    /// The calendar does not accept this kind of items (see [`BaseCalendar::supported_components`](crate::traits::BaseCalendar::supported_components))
+    #[error("Calendar {calendar} does not accept {type_:?} items (item {url})")]
+    UnsupportedComponent {
+        calendar: Box<Url>,
+        url: Box<Url>,
+        type_: ItemType,
+    },
+
+    /// This crate does not support this kind of items (yet)
+    #[error("{0:?} items are not supported")]
+    UnsupportedItemType(ItemType),
+
+    /// A sync state export has been written by a newer version of this crate (see [`SyncStateExport`](crate::provider::export::SyncStateExport))
+    #[error("Unsupported sync state export format {version}")]
+    UnsupportedSyncStateFormat { version: u32 },
 }
 
 pub type KFResult<T> = Result<T, KFError>;
+
+impl From<RemoteCalendarError> for KFError {
+    fn from(err: RemoteCalendarError) -> Self {
+        Self::RemoteCalendarError(Box::new(err))
+    }
+}
+
+/// A redirect that has not been followed, see [`KFError::DestructiveRedirect`]
+#[derive(Debug)]
+pub struct RedirectInfo {
+    pub method: http::Method,
+    pub url: Url,
+    pub status: StatusCode,
+    /// The `Location` header of the reply, if any
+    pub location: Option<String>,
+}
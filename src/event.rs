@@ -13,6 +13,9 @@ pub struct Event {
     uid: String,
     name: String,
     sync_status: SyncStatus,
+    /// The revision sequence number (SEQUENCE property)
+    #[serde(default)]
+    sequence: u32,
 }
 
 impl Event {
@@ -44,10 +47,19 @@ impl Event {
         unimplemented!()
     }
 
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
     pub fn sync_status(&self) -> &SyncStatus {
         &self.sync_status
     }
     pub fn set_sync_status(&mut self, new_status: SyncStatus) {
+        self.sync_status.check_transition(&new_status, &self.uid);
+        self.sync_status = new_status;
+    }
+    /// See [`Task::relabel_sync_status`](crate::task::Task::relabel_sync_status)
+    pub(crate) fn relabel_sync_status(&mut self, new_status: SyncStatus) {
         self.sync_status = new_status;
     }
 
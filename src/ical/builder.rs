@@ -1,28 +1,34 @@
 //! A module to build ICal files
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Timelike, Utc};
 use ical::property::Property as IcalProperty;
 use ics::components::Parameter as IcsParameter;
 use ics::components::Property as IcsProperty;
 use ics::properties::RelatedTo;
-use ics::properties::{Completed, Created, LastModified, PercentComplete, Status, Summary};
+use ics::properties::{
+    Completed, Created, LastModified, PercentComplete, Sequence, Status, Summary,
+};
 use ics::{ICalendar, ToDo};
 
-use crate::item::Item;
+use crate::config::TIMESTAMP_PRECISION;
+use crate::error::{KFError, KFResult};
+use crate::item::{Item, ItemType};
 use crate::task::CompletionStatus;
+use crate::utils::lock_ignoring_poison;
 use crate::Task;
 
 /// Create an iCal item from a `crate::item::Item`
-pub fn build_from(item: &Item) -> String {
+pub fn build_from(item: &Item) -> KFResult<String> {
     match item {
-        Item::Task(t) => build_from_task(t),
-        _ => unimplemented!(),
+        Item::Task(t) => Ok(build_from_task(t)),
+        Item::Event(_) => Err(KFError::UnsupportedItemType(ItemType::Event)),
     }
 }
 
 pub fn build_from_task(task: &Task) -> String {
     let s_last_modified = format_date_time(task.last_modified());
 
+    // The DTSTAMP of an item in a calendar store is the date it was last revised (RFC5545 3.8.7.2), whose value comes from the `Clock`
     let mut todo = ToDo::new(task.uid(), s_last_modified.clone());
 
     if let Some(dt) = task.creation_date() {
@@ -31,8 +37,16 @@ pub fn build_from_task(task: &Task) -> String {
 
     todo.push(LastModified::new(s_last_modified));
     todo.push(Summary::new(task.name()));
+    // SEQUENCE defaults to 0
+    if task.sequence() > 0 {
+        todo.push(Sequence::new(task.sequence().to_string()));
+    }
     for rel in task.relationships() {
-        todo.push(RelatedTo::new(rel.to_string()));
+        let mut related_to = RelatedTo::new(rel.related_to());
+        if let Some(reltype) = rel.explicit_reltype() {
+            related_to.add(IcsParameter::new("RELTYPE", reltype));
+        }
+        todo.push(related_to);
     }
 
     match task.completion_status() {
@@ -60,8 +74,27 @@ pub fn build_from_task(task: &Task) -> String {
     calendar.to_string()
 }
 
+/// How precise the timestamps of the generated iCal files are, see [`TIMESTAMP_PRECISION`](crate::config::TIMESTAMP_PRECISION)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TimestampPrecision {
+    /// Whole seconds (iCal timestamps cannot be more precise)
+    #[default]
+    Seconds,
+    /// Whole minutes, i.e. seconds are always zero
+    Minutes,
+}
+
+/// A UTC DATE-TIME value (RFC5545 3.3.5, "form #2"), e.g. `19980119T070000Z`
 fn format_date_time(dt: &DateTime<Utc>) -> String {
-    dt.format("%Y%m%dT%H%M%S").to_string()
+    format_date_time_with(dt, *lock_ignoring_poison(&TIMESTAMP_PRECISION))
+}
+
+fn format_date_time_with(dt: &DateTime<Utc>, precision: TimestampPrecision) -> String {
+    let dt = match precision {
+        TimestampPrecision::Seconds => *dt,
+        TimestampPrecision::Minutes => dt.with_second(0).unwrap_or(*dt),
+    };
+    dt.format("%Y%m%dT%H%M%SZ").to_string()
 }
 
 fn ical_to_ics_property(prop: IcalProperty) -> IcsProperty<'static> {
@@ -81,7 +114,13 @@ fn ical_to_ics_property(prop: IcalProperty) -> IcsProperty<'static> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
+
+    use chrono::TimeZone;
+
+    use crate::clock::{set_thread_clock, FixedClock};
     use crate::config::{ORG_NAME, PRODUCT_NAME};
+    use crate::utils::lock_ignoring_poison;
     use crate::Task;
 
     #[test]
@@ -103,8 +142,8 @@ mod tests {
             STATUS:COMPLETED\r\n\
             END:VTODO\r\n\
             END:VCALENDAR\r\n",
-            ORG_NAME.lock().unwrap(),
-            PRODUCT_NAME.lock().unwrap(),
+            lock_ignoring_poison(&ORG_NAME),
+            lock_ignoring_poison(&PRODUCT_NAME),
             uid,
             s_now,
             s_now,
@@ -132,8 +171,8 @@ mod tests {
             STATUS:NEEDS-ACTION\r\n\
             END:VTODO\r\n\
             END:VCALENDAR\r\n",
-            ORG_NAME.lock().unwrap(),
-            PRODUCT_NAME.lock().unwrap(),
+            lock_ignoring_poison(&ORG_NAME),
+            lock_ignoring_poison(&PRODUCT_NAME),
             uid,
             s_now,
             s_now,
@@ -145,19 +184,47 @@ mod tests {
 
     fn build_task(completed: bool) -> (String, String, String) {
         let cal_url = "http://my.calend.ar/id".parse().unwrap();
-        let now = Utc::now();
+        let now = Utc.ymd(2021, 4, 2).and_hms(8, 15, 57);
+        let _clock = set_thread_clock(Arc::new(FixedClock::new(now)));
         let s_now = format_date_time(&now);
+        assert_eq!(s_now, "20210402T081557Z");
+
+        let task = Item::Task(
+            Task::new(
+                String::from("This is a task with ÜTF-8 characters"),
+                completed,
+                &cal_url,
+            )
+            .unwrap(),
+        );
 
-        let task = Item::Task(Task::new(
-            String::from("This is a task with ÜTF-8 characters"),
-            completed,
-            &cal_url,
-        ));
-
-        let ical = build_from(&task);
+        let ical = build_from(&task).unwrap();
         (s_now, task.uid().to_string(), ical)
     }
 
+    #[test]
+    fn test_timestamps_round_trip() {
+        let now = Utc.ymd(2021, 4, 2).and_hms_milli(8, 15, 57, 640);
+        let formatted = format_date_time(&now);
+        assert_eq!(formatted, "20210402T081557Z");
+        // Sub-second parts are dropped
+        let parsed = crate::ical::parse_date_or_date_time(&formatted).unwrap();
+        assert_eq!(parsed, Utc.ymd(2021, 4, 2).and_hms(8, 15, 57));
+        // This is the same form as UTC DATE-TIME values of task fields
+        let prop = crate::ical::DateMaybeTime::Utc(parsed).to_property("DTSTAMP");
+        assert_eq!(prop.value.as_deref(), Some(formatted.as_str()));
+        // The parser still accepts the previous (floating) form, as UTC
+        assert_eq!(
+            crate::ical::parse_date_or_date_time("20210402T081557"),
+            Some(parsed)
+        );
+
+        assert_eq!(
+            format_date_time_with(&now, TimestampPrecision::Minutes),
+            "20210402T081500Z"
+        );
+    }
+
     #[test]
     #[ignore]
     fn test_ical_from_event() {
@@ -0,0 +1,257 @@
+//! iCal DATE and DATE-TIME values, as defined in [RFC 5545](https://datatracker.ietf.org/doc/html/rfc5545#section-3.3.5)
+//!
+//! A DATE-TIME can be written in three forms: in UTC (`19980119T070000Z`), with a time zone (`TZID=America/New_York:19980119T020000`), or floating (`19980118T230000`), i.e. at the same local time whatever the time zone of the user.
+//! A DATE (`VALUE=DATE:19980118`) is a whole day, e.g. for all-day events or tasks that are due on a given day.
+//! These must not be confused: a task due on a given day is not due at midnight UTC, and a floating time happens at different instants depending on the time zone of the user.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use ical::property::Property;
+use serde::{Deserialize, Serialize};
+
+const DATE_FORMAT: &str = "%Y%m%d";
+const LOCAL_DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+const UTC_DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// An iCal DATE or DATE-TIME value
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DateMaybeTime {
+    /// A whole day (`VALUE=DATE`)
+    Date(NaiveDate),
+    /// A local time, without any time zone
+    Floating(NaiveDateTime),
+    /// An instant in UTC
+    Utc(DateTime<Utc>),
+    /// A local time in the time zone identified by `tzid` (which is defined in a `VTIMEZONE` of the iCal file, usually with an IANA name)
+    Zoned {
+        date_time: NaiveDateTime,
+        tzid: String,
+    },
+}
+
+impl DateMaybeTime {
+    /// Parse the value of a property, according to its `VALUE` and `TZID` parameters
+    pub fn from_property(prop: &Property) -> Option<Self> {
+        let value = prop.value.as_deref()?.trim();
+        let is_date = param(prop, "VALUE").is_some_and(|v| v.eq_ignore_ascii_case("DATE"))
+            || (value.len() == 8 && !value.contains('T'));
+        if is_date {
+            return NaiveDate::parse_from_str(value, DATE_FORMAT)
+                .ok()
+                .map(Self::Date);
+        }
+        if let Some(utc) = value.strip_suffix('Z') {
+            return NaiveDateTime::parse_from_str(utc, LOCAL_DATE_TIME_FORMAT)
+                .ok()
+                .map(|dt| Self::Utc(DateTime::from_utc(dt, Utc)));
+        }
+        let date_time = NaiveDateTime::parse_from_str(value, LOCAL_DATE_TIME_FORMAT).ok()?;
+        match param(prop, "TZID") {
+            Some(tzid) => Some(Self::Zoned {
+                date_time,
+                tzid: tzid.to_string(),
+            }),
+            None => Some(Self::Floating(date_time)),
+        }
+    }
+
+    /// Build a property with this value, and the `VALUE` and `TZID` parameters it needs
+    pub fn to_property(&self, name: &str) -> Property {
+        let (params, value) = match self {
+            Self::Date(date) => (
+                Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]),
+                date.format(DATE_FORMAT).to_string(),
+            ),
+            Self::Floating(dt) => (None, dt.format(LOCAL_DATE_TIME_FORMAT).to_string()),
+            Self::Utc(dt) => (None, dt.format(UTC_DATE_TIME_FORMAT).to_string()),
+            Self::Zoned { date_time, tzid } => (
+                Some(vec![("TZID".to_string(), vec![tzid.clone()])]),
+                date_time.format(LOCAL_DATE_TIME_FORMAT).to_string(),
+            ),
+        };
+        Property {
+            name: name.to_string(),
+            params,
+            value: Some(value),
+        }
+    }
+
+    /// Whether this is a whole day rather than a time
+    pub fn is_all_day(&self) -> bool {
+        matches!(self, Self::Date(_))
+    }
+
+    /// The day of this value, as it is written in the iCal file (i.e. in UTC for UTC times)
+    pub fn naive_date(&self) -> NaiveDate {
+        match self {
+            Self::Date(date) => *date,
+            Self::Floating(dt) | Self::Zoned { date_time: dt, .. } => dt.date(),
+            Self::Utc(dt) => dt.naive_utc().date(),
+        }
+    }
+
+    /// The instant this value starts at, for a user in the time zone `tz`.
+    ///
+    /// Days start at midnight in `tz`, floating times are local times in `tz`.
+    /// Time zone identifiers are not resolved, since this crate does not embed any time zone database: zoned times are considered to be in UTC if their TZID says so, and in `tz` otherwise (which is the time zone they have usually been created in).
+    ///
+    /// Returns `None` for local times that do not exist in `tz`, e.g. because of a daylight saving time transition
+    pub fn start_in<Tz: TimeZone>(&self, tz: &Tz) -> Option<DateTime<Utc>> {
+        let local = match self {
+            Self::Utc(dt) => return Some(*dt),
+            Self::Date(date) => date.and_hms(0, 0, 0),
+            Self::Floating(dt) => *dt,
+            Self::Zoned { date_time, tzid } if is_utc_tzid(tzid) => {
+                return Some(DateTime::from_utc(*date_time, Utc))
+            }
+            Self::Zoned { date_time, .. } => *date_time,
+        };
+        tz.from_local_datetime(&local)
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc))
+    }
+
+    /// The instant this value ends at, for a user in the time zone `tz` (see [`Self::start_in`]).
+    ///
+    /// Days end at the next midnight, times are instants and end when they start
+    pub fn end_in<Tz: TimeZone>(&self, tz: &Tz) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Date(date) => Self::Date(date.succ_opt()?).start_in(tz),
+            _ => self.start_in(tz),
+        }
+    }
+
+    /// Whether this value is (or, for days, starts) strictly before `instant`, for a user in the time zone `tz`
+    pub fn is_before<Tz: TimeZone>(&self, instant: DateTime<Utc>, tz: &Tz) -> bool {
+        self.start_in(tz).is_some_and(|start| start < instant)
+    }
+
+    /// Whether this value overlaps `[start, end)`, for a user in the time zone `tz`.
+    /// A day overlaps every range that intersects it, a time must be in the range
+    pub fn overlaps<Tz: TimeZone>(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        tz: &Tz,
+    ) -> bool {
+        match (self.start_in(tz), self.end_in(tz)) {
+            (Some(s), Some(e)) if s == e => start <= s && s < end,
+            (Some(s), Some(e)) => s < end && start < e,
+            _ => false,
+        }
+    }
+}
+
+impl From<DateTime<Utc>> for DateMaybeTime {
+    fn from(dt: DateTime<Utc>) -> Self {
+        Self::Utc(dt)
+    }
+}
+
+impl From<NaiveDate> for DateMaybeTime {
+    fn from(date: NaiveDate) -> Self {
+        Self::Date(date)
+    }
+}
+
+/// The first value of a parameter of a property
+fn param<'a>(prop: &'a Property, name: &str) -> Option<&'a str> {
+    prop.params
+        .as_ref()?
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+}
+
+fn is_utc_tzid(tzid: &str) -> bool {
+    let tzid = tzid.trim_start_matches('/');
+    ["UTC", "Etc/UTC", "GMT", "Etc/GMT", "Z"]
+        .iter()
+        .any(|utc| tzid.eq_ignore_ascii_case(utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::FixedOffset;
+
+    fn prop(params: Option<Vec<(&str, &str)>>, value: &str) -> Property {
+        Property {
+            name: "DUE".to_string(),
+            params: params.map(|params| {
+                params
+                    .into_iter()
+                    .map(|(n, v)| (n.to_string(), vec![v.to_string()]))
+                    .collect()
+            }),
+            value: Some(value.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cases = vec![
+            (prop(Some(vec![("VALUE", "DATE")]), "20220301"), true),
+            (prop(None, "20220301T093000"), false),
+            (prop(None, "20220301T093000Z"), false),
+            (
+                prop(Some(vec![("TZID", "Europe/Paris")]), "20220301T093000"),
+                false,
+            ),
+        ];
+        for (p, all_day) in cases {
+            let value = DateMaybeTime::from_property(&p).unwrap();
+            assert_eq!(value.is_all_day(), all_day);
+            let built = value.to_property("DUE");
+            assert_eq!(built.value, p.value);
+            assert_eq!(built.params, p.params);
+        }
+
+        // Some clients omit VALUE=DATE
+        assert_eq!(
+            DateMaybeTime::from_property(&prop(None, "20220301")),
+            Some(DateMaybeTime::Date(NaiveDate::from_ymd(2022, 3, 1)))
+        );
+        assert_eq!(DateMaybeTime::from_property(&prop(None, "garbage")), None);
+    }
+
+    #[test]
+    fn test_queries_in_time_zone() {
+        let paris = FixedOffset::east(3600);
+        let day = DateMaybeTime::Date(NaiveDate::from_ymd(2022, 3, 1));
+        let floating = DateMaybeTime::Floating(NaiveDate::from_ymd(2022, 3, 1).and_hms(9, 30, 0));
+        let utc = DateMaybeTime::Utc(Utc.ymd(2022, 3, 1).and_hms(9, 30, 0));
+        let zoned_utc = DateMaybeTime::Zoned {
+            date_time: NaiveDate::from_ymd(2022, 3, 1).and_hms(9, 30, 0),
+            tzid: "Etc/UTC".to_string(),
+        };
+
+        assert_eq!(
+            day.start_in(&paris),
+            Some(Utc.ymd(2022, 2, 28).and_hms(23, 0, 0))
+        );
+        assert_eq!(
+            day.end_in(&paris),
+            Some(Utc.ymd(2022, 3, 1).and_hms(23, 0, 0))
+        );
+        assert_eq!(
+            floating.start_in(&paris),
+            Some(Utc.ymd(2022, 3, 1).and_hms(8, 30, 0))
+        );
+        assert_eq!(
+            floating.start_in(&Utc),
+            Some(Utc.ymd(2022, 3, 1).and_hms(9, 30, 0))
+        );
+        assert_eq!(utc.start_in(&paris), utc.start_in(&Utc));
+        assert_eq!(zoned_utc.start_in(&paris), utc.start_in(&Utc));
+
+        // The whole day overlaps a range that only covers its evening
+        let evening = Utc.ymd(2022, 3, 1).and_hms(20, 0, 0);
+        let night = Utc.ymd(2022, 3, 2).and_hms(2, 0, 0);
+        assert!(day.overlaps(evening, night, &paris));
+        assert!(!floating.overlaps(evening, night, &paris));
+        assert!(day.is_before(evening, &paris));
+        assert!(!day.is_before(Utc.ymd(2022, 2, 28).and_hms(23, 0, 0), &paris));
+    }
+}
@@ -0,0 +1,156 @@
+//! iCal DURATION values, as defined in [RFC 5545](https://datatracker.ietf.org/doc/html/rfc5545#section-3.3.6)
+//!
+//! A duration is written in weeks (`P2W`), or in days and times (`P1DT2H30M`, `PT15M`), and can be negative (`-PT5M`).
+//! Durations in days are nominal (a day is not always 24 hours long, e.g. when daylight saving time starts), but they are represented as 24 hours here.
+
+use chrono::Duration;
+
+/// Parse a DURATION value. This is lenient about the case of the letters, and about weeks being combined with other units
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim().to_ascii_uppercase();
+    let (negative, rest) = match value.as_bytes().first()? {
+        b'-' => (true, &value[1..]),
+        b'+' => (false, &value[1..]),
+        _ => (false, &value[..]),
+    };
+    let rest = rest.strip_prefix('P')?;
+
+    let mut total = Duration::zero();
+    let mut in_time = false;
+    let mut has_component = false;
+    let mut number = String::new();
+    for c in rest.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if c == 'T' {
+            if in_time || !number.is_empty() {
+                return None;
+            }
+            in_time = true;
+            continue;
+        }
+        let n: i64 = number.parse().ok()?;
+        number.clear();
+        let component = match (in_time, c) {
+            (false, 'W') => Duration::weeks(n),
+            (false, 'D') => Duration::days(n),
+            (true, 'H') => Duration::hours(n),
+            (true, 'M') => Duration::minutes(n),
+            (true, 'S') => Duration::seconds(n),
+            _ => return None,
+        };
+        total = total.checked_add(&component)?;
+        has_component = true;
+    }
+    if !number.is_empty() || !has_component || (in_time && rest.ends_with('T')) {
+        return None;
+    }
+    Some(if negative { -total } else { total })
+}
+
+/// Write a DURATION value, e.g. `PT1H30M`. Sub-second precision is dropped
+pub fn format_duration(duration: Duration) -> String {
+    let sign = if duration < Duration::zero() { "-" } else { "" };
+    let seconds = duration.num_seconds().abs();
+    if seconds == 0 {
+        return "PT0S".to_string();
+    }
+    if seconds % (7 * 86400) == 0 {
+        return format!("{}P{}W", sign, seconds / (7 * 86400));
+    }
+    let (days, hours, minutes, seconds) = (
+        seconds / 86400,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+    );
+    let mut formatted = format!("{}P", sign);
+    if days > 0 {
+        formatted += &format!("{}D", days);
+    }
+    if hours > 0 || minutes > 0 || seconds > 0 {
+        formatted.push('T');
+        for &(value, unit) in [(hours, 'H'), (minutes, 'M'), (seconds, 'S')].iter() {
+            if value > 0 {
+                formatted += &format!("{}{}", value, unit);
+            }
+        }
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_durations() {
+        let valid = vec![
+            (
+                "P15DT5H0M20S",
+                Duration::seconds(15 * 86400 + 5 * 3600 + 20),
+            ),
+            ("P7W", Duration::weeks(7)),
+            ("PT1H30M", Duration::minutes(90)),
+            ("-PT5M", Duration::minutes(-5)),
+            ("+P1D", Duration::days(1)),
+            ("pt45m", Duration::minutes(45)),
+            ("PT0S", Duration::zero()),
+        ];
+        for (value, expected) in valid {
+            assert_eq!(parse_duration(value), Some(expected), "{}", value);
+            assert_eq!(
+                parse_duration(&format_duration(expected)),
+                Some(expected),
+                "{}",
+                value
+            );
+        }
+        assert_eq!(format_duration(Duration::minutes(90)), "PT1H30M");
+        assert_eq!(format_duration(Duration::days(14)), "P2W");
+        assert_eq!(format_duration(Duration::hours(-26)), "-P1DT2H");
+
+        for invalid in vec![
+            "", "P", "PT", "1H", "P1H", "PT1D", "P1DT", "PT1H2", "PTT1H", "P-1D",
+        ] {
+            assert_eq!(parse_duration(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_task_durations() {
+        use crate::ical::{build_from, parse, DateMaybeTime};
+        use crate::item::Item;
+        use crate::utils::sync::SyncStatus;
+
+        let ical = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//Other client//EN\r
+BEGIN:VTODO\r
+UID:effort\r
+DTSTAMP:20220301T120000Z\r
+SUMMARY:Write the report\r
+DTSTART:20220302T090000Z\r
+DUE:20220302T120000Z\r
+ESTIMATED-DURATION:PT2H\r
+END:VTODO\r
+END:VCALENDAR\r
+";
+        let url: url::Url = "https://some.calend.ar/effort/task.ics".parse().unwrap();
+        let item = parse(ical, url, SyncStatus::NotSynced).unwrap();
+        let mut task = item.unwrap_task().clone();
+        assert_eq!(task.estimated_duration(), Some(Duration::hours(2)));
+        assert_eq!(task.duration(), None);
+
+        task.set_estimated_duration(Some(Duration::minutes(150)));
+        task.set_duration(Some(Duration::hours(3)));
+        assert_eq!(task.due_at(), None::<DateMaybeTime>);
+        assert_eq!(task.estimated_effort(), Some(Duration::minutes(150)));
+        let built = build_from(&Item::Task(task)).unwrap();
+        assert!(built.contains("\r\nDURATION:PT3H\r\n"));
+        assert!(built.contains("\r\nESTIMATED-DURATION:PT2H30M\r\n"));
+        assert!(!built.contains("X-ESTIMATED-DURATION"));
+    }
+}
@@ -2,19 +2,32 @@
 //!
 //! It is a wrapper around different Rust third-party libraries, since I haven't find any complete library that is able to parse _and_ generate iCal files
 
+mod date;
+pub use date::DateMaybeTime;
+pub mod duration;
 mod parser;
 pub use parser::parse;
+pub(crate) use parser::parse_date_or_date_time;
+pub(crate) use parser::split_components;
 pub use parser::IcalParseError;
 mod builder;
 pub use builder::build_from;
+pub use builder::TimestampPrecision;
+mod timezone;
+pub use timezone::{CalendarTimezone, ResolvedTimezone};
+mod transform;
+pub use transform::PayloadTransformer;
+mod validate;
+pub use validate::{fix_up, validate, ValidationIssue};
 
 use crate::config::{ORG_NAME, PRODUCT_NAME};
+use crate::utils::lock_ignoring_poison;
 
 pub fn default_prod_id() -> String {
     format!(
         "-//{}//{}//EN",
-        ORG_NAME.lock().unwrap(),
-        PRODUCT_NAME.lock().unwrap()
+        *lock_ignoring_poison(&ORG_NAME),
+        *lock_ignoring_poison(&PRODUCT_NAME)
     )
 }
 
@@ -33,7 +46,7 @@ mod tests {
         let item_id = "http://item.id".parse().unwrap();
         let sync_status = SyncStatus::NotSynced;
         let deserialized = parse(&ical_with_unknown_fields, item_id, sync_status).unwrap();
-        let serialized = build_from(&deserialized);
+        let serialized = build_from(&deserialized).unwrap();
         assert_same_fields(&ical_with_unknown_fields, &serialized);
     }
 
@@ -79,6 +79,7 @@ pub fn parse(
             let mut creation_date = None;
             let mut extra_parameters = Vec::new();
             let mut relationships = Vec::new();
+            let mut sequence = 0;
 
             for prop in &todo.properties {
                 match prop.name.as_str() {
@@ -119,7 +120,7 @@ pub fn parse(
                                     .find(|p| p.0 == "RELTYPE")
                                     .map(|p| p.1.clone())
                             })
-                            .unwrap_or(vec!["PARENT".to_string()]);
+                            .unwrap_or_default();
 
                         if reltypes.len() > 1 {
                             log::warn!("Multiple RELTYPE parameter values: {:?}", reltypes);
@@ -131,9 +132,16 @@ pub fn parse(
                                 .ok_or(IcalParseError::PropertyHasNoValue {
                                     prop_name: "RELATED-TO".into(),
                                 })?,
-                            reltypes[0].clone(),
+                            reltypes.into_iter().next(),
                         ));
                     }
+                    "SEQUENCE" => {
+                        // The property can be specified once, but is not mandatory (it defaults to 0)
+                        match prop.value.as_deref().map(|v| v.trim().parse::<u32>()) {
+                            Some(Ok(value)) => sequence = value,
+                            _ => log::warn!("Invalid SEQUENCE value {:?}, ignoring it", prop.value),
+                        }
+                    }
                     "STATUS" => {
                         // Possible values:
                         //   "NEEDS-ACTION" ;Indicates to-do needs action.
@@ -172,18 +180,21 @@ pub fn parse(
                 true => CompletionStatus::Completed(completion_date),
             };
 
-            Item::Task(Task::new_with_parameters(
-                name,
-                uid,
-                item_url,
-                completion_status,
-                sync_status,
-                creation_date,
-                last_modified,
-                ical_prod_id,
-                relationships,
-                extra_parameters,
-            ))
+            Item::Task(
+                Task::new_with_parameters(
+                    name,
+                    uid,
+                    item_url,
+                    completion_status,
+                    sync_status,
+                    creation_date,
+                    last_modified,
+                    ical_prod_id,
+                    relationships,
+                    extra_parameters,
+                )
+                .with_sequence(sequence),
+            )
         }
     };
 
@@ -195,11 +206,94 @@ pub fn parse(
     Ok(item)
 }
 
+/// Split an iCal file that contains several components (e.g. a published calendar) into as many iCal files, that contain one `component` (e.g. `VTODO`) each.
+///
+/// Every resulting file keeps the calendar-level properties and the time zones of the original file. Other kinds of components are dropped
+pub(crate) fn split_components(content: &str, component: &str) -> Vec<String> {
+    let begin = format!("BEGIN:{}", component);
+    let end = format!("END:{}", component);
+
+    let mut header = Vec::new();
+    let mut components = Vec::new();
+    // The lines of the top-level component being read, and whether it must be kept
+    let mut current: Option<(Vec<&str>, bool)> = None;
+    let mut depth = 0;
+    for line in content.lines() {
+        let trimmed = line.trim_end();
+        if trimmed.eq_ignore_ascii_case("BEGIN:VCALENDAR")
+            || trimmed.eq_ignore_ascii_case("END:VCALENDAR")
+        {
+            continue;
+        }
+        match &mut current {
+            None => {
+                if trimmed.to_ascii_uppercase().starts_with("BEGIN:") {
+                    let keep = trimmed.eq_ignore_ascii_case(&begin);
+                    let is_timezone = trimmed.eq_ignore_ascii_case("BEGIN:VTIMEZONE");
+                    current = Some((vec![line], keep || is_timezone));
+                    depth = 1;
+                } else if !trimmed.is_empty() {
+                    header.push(line);
+                }
+            }
+            Some((lines, _)) => {
+                lines.push(line);
+                let upper = trimmed.to_ascii_uppercase();
+                if upper.starts_with("BEGIN:") {
+                    depth += 1;
+                } else if upper.starts_with("END:") {
+                    depth -= 1;
+                }
+                if depth == 0 {
+                    if let Some((lines, keep)) = current.take() {
+                        if !keep {
+                            log::debug!("Ignoring a {} component", lines[0].trim_end());
+                        } else if lines[0].trim_end().eq_ignore_ascii_case(&begin) {
+                            components.push(lines);
+                        } else {
+                            // Time zones are shared by every component
+                            header.extend(lines);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if current.is_some() {
+        log::warn!("Ignoring an unterminated component");
+    }
+    debug_assert!(components
+        .iter()
+        .all(|c| c.last().map(|l| l.trim_end().eq_ignore_ascii_case(&end)) == Some(true)));
+
+    components
+        .into_iter()
+        .map(|lines| {
+            let mut file = String::from("BEGIN:VCALENDAR\r\n");
+            for line in header.iter().chain(lines.iter()) {
+                file.push_str(line);
+                file.push_str("\r\n");
+            }
+            file.push_str("END:VCALENDAR\r\n");
+            file
+        })
+        .collect()
+}
+
 fn parse_date_time(dt: &str) -> Result<DateTime<Utc>, chrono::format::ParseError> {
     Utc.datetime_from_str(dt, "%Y%m%dT%H%M%SZ")
         .or_else(|_err| Utc.datetime_from_str(dt, "%Y%m%dT%H%M%S"))
 }
 
+/// Parse an iCal DATE or DATE-TIME value. DATE values are considered to be at midnight UTC
+pub(crate) fn parse_date_or_date_time(value: &str) -> Option<DateTime<Utc>> {
+    parse_date_time(value).ok().or_else(|| {
+        chrono::NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .map(|date| DateTime::from_utc(date.and_hms(0, 0, 0), Utc))
+    })
+}
+
 fn parse_date_time_from_property(value: &Option<String>) -> Option<DateTime<Utc>> {
     value.as_ref().and_then(|s| {
         parse_date_time(s)
@@ -326,6 +420,24 @@ DTSTAMP:20210321T001600
 SUMMARY:Buy a gift for Mom
 END:VTODO
 END:VCALENDAR
+"#;
+
+    const EXAMPLE_ICAL_WITH_RELATIONSHIPS: &str = r#"BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//Nextcloud Tasks v0.13.6
+BEGIN:VTODO
+UID:0633de27-8c32-42be-bcb8-63bc879c6185
+CREATED:20210321T001600
+LAST-MODIFIED:20210321T001600
+DTSTAMP:20210321T001600
+SUMMARY:Buy a gift for Mom
+RELATED-TO:parent-uid
+RELATED-TO;RELTYPE=PARENT:other-parent-uid
+RELATED-TO;RELTYPE=CHILD:child-uid
+RELATED-TO;RELTYPE=sibling:sibling-uid
+SEQUENCE:3
+END:VTODO
+END:VCALENDAR
 "#;
 
     use super::*;
@@ -403,4 +515,162 @@ END:VCALENDAR
         let item = parse(EXAMPLE_MULTIPLE_ICAL, item_url.clone(), sync_status.clone());
         assert!(item.is_err());
     }
+
+    #[test]
+    fn test_split_components() {
+        let feed = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Some feed\r\n\
+            BEGIN:VTIMEZONE\r\nTZID:Europe/Paris\r\nEND:VTIMEZONE\r\n\
+            BEGIN:VEVENT\r\nUID:event\r\nEND:VEVENT\r\n\
+            BEGIN:VTODO\r\nUID:first\r\nDTSTAMP:20210321T001600\r\nSUMMARY:First\r\n\
+            BEGIN:VALARM\r\nACTION:DISPLAY\r\nEND:VALARM\r\nEND:VTODO\r\n\
+            BEGIN:VTODO\r\nUID:second\r\nDTSTAMP:20210321T001600\r\nSUMMARY:Second\r\nEND:VTODO\r\n\
+            END:VCALENDAR\r\n";
+        let split = split_components(feed, "VTODO");
+        assert_eq!(split.len(), 2);
+        assert!(split[0].contains("TZID:Europe/Paris"));
+        assert!(split[0].contains("END:VALARM"));
+        assert!(!split[0].contains("UID:event"));
+
+        let item_url: Url = "http://some.id/feed.ics#second".parse().unwrap();
+        let item = parse(&split[1], item_url, SyncStatus::NotSynced).unwrap();
+        assert_eq!(item.uid(), "second");
+        assert_eq!(item.unwrap_task().name(), "Second");
+    }
+
+    #[test]
+    fn test_relationships_round_trip() {
+        let item_url: Url = "http://some.id/for/testing".parse().unwrap();
+        let item = parse(
+            EXAMPLE_ICAL_WITH_RELATIONSHIPS,
+            item_url.clone(),
+            SyncStatus::NotSynced,
+        )
+        .unwrap();
+        let task = item.unwrap_task();
+
+        assert_eq!(task.parent().map(|s| s.as_str()), Some("parent-uid"));
+        let parents: Vec<&str> = task
+            .relationships_of_type("PARENT")
+            .map(|r| r.related_to())
+            .collect();
+        assert_eq!(parents, vec!["parent-uid", "other-parent-uid"]);
+        let siblings: Vec<&str> = task
+            .relationships_of_type("SIBLING")
+            .map(|r| r.related_to())
+            .collect();
+        assert_eq!(siblings, vec!["sibling-uid"]);
+        assert_eq!(task.sequence(), 3);
+
+        // RELTYPE parameters are kept verbatim, and absent ones stay absent
+        let expected_lines: Vec<&str> = EXAMPLE_ICAL_WITH_RELATIONSHIPS
+            .lines()
+            .filter(|l| l.starts_with("RELATED-TO"))
+            .collect();
+        let displayed: Vec<String> = task.relationships().iter().map(|r| r.to_string()).collect();
+        assert_eq!(displayed, expected_lines);
+
+        let built = crate::ical::build_from(&item).unwrap();
+        let rebuilt = parse(&built, item_url, SyncStatus::NotSynced).unwrap();
+        assert_eq!(rebuilt.unwrap_task().relationships(), task.relationships());
+        assert_eq!(rebuilt.sequence(), 3);
+        let built_lines: Vec<&str> = built
+            .split("\r\n")
+            .filter(|l| l.starts_with("RELATED-TO"))
+            .collect();
+        assert_eq!(built_lines, expected_lines);
+    }
+
+    #[test]
+    fn test_x_properties_round_trip() {
+        let cal_url: Url = "http://some.id/cal/".parse().unwrap();
+        let mut task = Task::new("Task".to_string(), false, &cal_url).unwrap();
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("tag"))));
+
+        let value = "a; b, c\\d\nsecond line";
+        task.set_x_property("X-MyApp-Note", Some(value)).unwrap();
+        assert!(matches!(task.sync_status(), SyncStatus::LocallyModified(_)));
+        assert_eq!(task.get_x_property("x-myapp-note").as_deref(), Some(value));
+        assert!(task.set_x_property("SUMMARY", Some("Hijacked")).is_err());
+        assert!(task.set_x_property("X-", Some("Empty")).is_err());
+
+        let item = Item::Task(task);
+        let built = crate::ical::build_from(&item).unwrap();
+        let rebuilt = parse(&built, item.url().clone(), SyncStatus::NotSynced).unwrap();
+        let mut rebuilt = rebuilt.unwrap_task().clone();
+        assert_eq!(
+            rebuilt.get_x_property("X-MYAPP-NOTE").as_deref(),
+            Some(value)
+        );
+
+        rebuilt.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("tag"))));
+        rebuilt.set_x_property("X-MYAPP-NOTE", Some(value)).unwrap();
+        assert!(matches!(rebuilt.sync_status(), SyncStatus::Synced(_)));
+        rebuilt.set_x_property("X-MYAPP-NOTE", None).unwrap();
+        assert_eq!(rebuilt.get_x_property("X-MYAPP-NOTE"), None);
+        assert!(matches!(
+            rebuilt.sync_status(),
+            SyncStatus::LocallyModified(_)
+        ));
+    }
+
+    #[test]
+    fn test_link_round_trip() {
+        let ical = EXAMPLE_ICAL.replace(
+            "SUMMARY:Do not forget to do this\n",
+            "SUMMARY:Do not forget to do this\nURL;VALUE=URI:https://tracker.example/issues/42\n",
+        );
+        let item_url: Url = "http://some.id/cal/task.ics".parse().unwrap();
+        let item = parse(&ical, item_url, SyncStatus::NotSynced).unwrap();
+        let mut task = item.unwrap_task().clone();
+        let issue: Url = "https://tracker.example/issues/42".parse().unwrap();
+        assert_eq!(task.link(), Some(issue.clone()));
+
+        let built = crate::ical::build_from(&Item::Task(task.clone())).unwrap();
+        assert!(built.contains("\r\nURL;VALUE=URI:https://tracker.example/issues/42\r\n"));
+        let original = task.clone();
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("tag"))));
+        task.set_link(Some(&issue));
+        assert!(matches!(task.sync_status(), SyncStatus::Synced(_)));
+
+        let other_issue: Url = "https://tracker.example/issues/43".parse().unwrap();
+        task.set_link(Some(&other_issue));
+        assert!(matches!(task.sync_status(), SyncStatus::LocallyModified(_)));
+        assert_eq!(task.link(), Some(other_issue));
+        task.set_sync_status(SyncStatus::NotSynced);
+        assert!(!task.has_same_observable_content_as(&original));
+        task.set_link(None);
+        assert_eq!(task.link(), None);
+
+        let new_task = Task::new(
+            "New".to_string(),
+            false,
+            &"http://some.id/cal/".parse().unwrap(),
+        )
+        .unwrap()
+        .with_link(Some(&issue));
+        let built = crate::ical::build_from(&Item::Task(new_task)).unwrap();
+        assert!(built.contains("\r\nURL:https://tracker.example/issues/42\r\n"));
+    }
+
+    #[test]
+    fn test_relationship_changes_mark_task_modified() {
+        let cal_url: Url = "http://some.id/cal/".parse().unwrap();
+        let mut task = Task::new("Task".to_string(), false, &cal_url).unwrap();
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("tag"))));
+
+        let sibling = Relationship::new("sibling-uid".to_string(), Some("SIBLING".to_string()));
+        assert!(task.add_relationship(sibling.clone()));
+        assert!(matches!(task.sync_status(), SyncStatus::LocallyModified(_)));
+        assert_eq!(task.sequence(), 1);
+
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("tag"))));
+        assert!(!task.add_relationship(sibling));
+        assert!(!task.remove_relationship("sibling-uid", "CHILD"));
+        assert!(matches!(task.sync_status(), SyncStatus::Synced(_)));
+
+        assert!(task.remove_relationship("sibling-uid", "sibling"));
+        assert!(matches!(task.sync_status(), SyncStatus::LocallyModified(_)));
+        assert_eq!(task.sequence(), 2);
+        assert_eq!(task.relationships_of_type("SIBLING").count(), 0);
+    }
 }
@@ -0,0 +1,345 @@
+//! The time zone of a calendar, as defined by the `CALDAV:calendar-timezone` property ([RFC 4791](https://datatracker.ietf.org/doc/html/rfc4791#section-5.2.2))
+//!
+//! Servers use it to interpret floating times and days, e.g. in time-range queries.
+//! Its value is an iCal file that contains a single `VTIMEZONE`.
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use ical::parser::ical::component::IcalTimeZone;
+
+use crate::error::{KFError, KFResult};
+
+/// The time zone of a calendar.
+///
+/// Its [`TZID`](Self::tzid) usually is an IANA name, that is resolved with the `chrono-tz` database (see [`Self::resolve`]).
+/// Time zones that are not known to this database can still be used in case they have a single UTC offset
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CalendarTimezone {
+    tzid: String,
+    offsets: Vec<FixedOffset>,
+    ical: String,
+}
+
+impl CalendarTimezone {
+    /// Parse the value of a `CALDAV:calendar-timezone` property
+    pub fn parse(ical: &str) -> KFResult<Self> {
+        let invalid = |detail: &str| KFError::InvalidCalendarTimezone {
+            detail: detail.to_string(),
+        };
+        let calendar = ical::IcalParser::new(ical.as_bytes())
+            .next()
+            .ok_or_else(|| invalid("no VCALENDAR"))?
+            .map_err(|err| invalid(&err.to_string()))?;
+        let vtimezone = match calendar.timezones.as_slice() {
+            [vtimezone] => vtimezone,
+            _ => return Err(invalid("a single VTIMEZONE is expected")),
+        };
+        let tzid = timezone_property(vtimezone, "TZID")
+            .ok_or_else(|| invalid("missing TZID"))?
+            .to_string();
+
+        let mut offsets = Vec::new();
+        for transition in &vtimezone.transitions {
+            let offset = transition
+                .properties
+                .iter()
+                .find(|p| p.name.eq_ignore_ascii_case("TZOFFSETTO"))
+                .and_then(|p| p.value.as_deref())
+                .ok_or_else(|| invalid("missing TZOFFSETTO"))?;
+            let offset = parse_utc_offset(offset)
+                .ok_or_else(|| invalid(&format!("invalid UTC offset {:?}", offset)))?;
+            if !offsets.contains(&offset) {
+                offsets.push(offset);
+            }
+        }
+        if offsets.is_empty() {
+            return Err(invalid("no STANDARD nor DAYLIGHT observance"));
+        }
+
+        Ok(Self {
+            tzid,
+            offsets,
+            ical: ical.to_string(),
+        })
+    }
+
+    /// A time zone that is always at the same UTC offset
+    pub fn from_fixed_offset(tzid: &str, offset: FixedOffset) -> Self {
+        let offset_str = format_utc_offset(offset);
+        let ical = format!(
+            "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             PRODID:{}\r\n\
+             BEGIN:VTIMEZONE\r\n\
+             TZID:{}\r\n\
+             BEGIN:STANDARD\r\n\
+             DTSTART:19700101T000000\r\n\
+             TZOFFSETFROM:{}\r\n\
+             TZOFFSETTO:{}\r\n\
+             END:STANDARD\r\n\
+             END:VTIMEZONE\r\n\
+             END:VCALENDAR\r\n",
+            super::default_prod_id(),
+            tzid,
+            offset_str,
+            offset_str,
+        );
+        Self {
+            tzid: tzid.to_string(),
+            offsets: vec![offset],
+            ical,
+        }
+    }
+
+    /// The identifier of this time zone (its `TZID`), usually an IANA name such as `Europe/Paris`
+    pub fn tzid(&self) -> &str {
+        &self.tzid
+    }
+
+    /// The UTC offsets this time zone can be at (e.g. one for standard time, and one for daylight saving time)
+    pub fn offsets(&self) -> &[FixedOffset] {
+        &self.offsets
+    }
+
+    /// This time zone, in case it is always at the same UTC offset.
+    /// `None` for time zones with daylight saving time (see [`Self::resolve`] for these)
+    pub fn fixed_offset(&self) -> Option<FixedOffset> {
+        match self.offsets.as_slice() {
+            [offset] => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The iCal content of this time zone, i.e. the value of the `CALDAV:calendar-timezone` property
+    pub fn as_ical(&self) -> &str {
+        &self.ical
+    }
+
+    /// The IANA time zone named by the [`TZID`](Self::tzid) of this time zone.
+    ///
+    /// Some clients prefix the IANA name with a vendor path (e.g. `/mozilla.org/20050126_1/Europe/Paris`), which is ignored
+    pub fn tz(&self) -> Option<Tz> {
+        let tzid = self.tzid.trim_start_matches('/');
+        std::iter::successors(Some(tzid), |rest| {
+            rest.split_once('/').map(|(_, rest)| rest)
+        })
+        .find_map(|name| name.parse::<Tz>().ok())
+    }
+
+    /// This time zone as a [`chrono::TimeZone`]: its IANA time zone (see [`Self::tz`]), or else its single UTC offset (see [`Self::fixed_offset`]).
+    /// `None` for unknown time zones with daylight saving time
+    pub fn resolve(&self) -> Option<ResolvedTimezone> {
+        self.tz()
+            .map(ResolvedTimezone::Iana)
+            .or_else(|| self.fixed_offset().map(ResolvedTimezone::Fixed))
+    }
+}
+
+/// A [`CalendarTimezone`] that can be used to interpret floating times, see [`CalendarTimezone::resolve`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResolvedTimezone {
+    Iana(Tz),
+    Fixed(FixedOffset),
+}
+
+impl ResolvedTimezone {
+    /// The instant a floating time refers to in this time zone.
+    ///
+    /// Times that happen twice (when clocks are set back) refer to the first one, and times that are skipped (when clocks are set forward) return `None`
+    pub fn from_local_datetime(&self, local: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        match self {
+            Self::Iana(tz) => tz
+                .from_local_datetime(local)
+                .earliest()
+                .map(|dt| dt.with_timezone(&dt.offset().fix())),
+            Self::Fixed(offset) => offset.from_local_datetime(local).earliest(),
+        }
+    }
+}
+
+fn timezone_property<'a>(vtimezone: &'a IcalTimeZone, name: &str) -> Option<&'a str> {
+    vtimezone
+        .properties
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+        .and_then(|p| p.value.as_deref())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+}
+
+/// Parse a UTC-OFFSET value, e.g. `+0100`, `-0530` or `+013045`
+fn parse_utc_offset(s: &str) -> Option<FixedOffset> {
+    let s = s.trim();
+    let (sign, digits) = match s.split_at(s.find(|c: char| c.is_ascii_digit())?) {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if !(digits.len() == 4 || digits.len() == 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| digits.get(range)?.parse::<i32>().ok();
+    let hours = field(0..2)?;
+    let minutes = field(2..4)?;
+    let seconds = if digits.len() == 6 { field(4..6)? } else { 0 };
+    if minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60 + seconds))
+}
+
+fn format_utc_offset(offset: FixedOffset) -> String {
+    let total = offset.local_minus_utc();
+    let sign = if total < 0 { '-' } else { '+' };
+    let total = total.abs();
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if seconds == 0 {
+        format!("{}{:02}{:02}", sign, hours, minutes)
+    } else {
+        format!("{}{:02}{:02}{:02}", sign, hours, minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARIS: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//Example Corp.//CalDAV Client//EN\r
+BEGIN:VTIMEZONE\r
+TZID:Europe/Paris\r
+BEGIN:DAYLIGHT\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+TZNAME:CEST\r
+DTSTART:19700329T020000\r
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+TZNAME:CET\r
+DTSTART:19701025T030000\r
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r
+END:STANDARD\r
+END:VTIMEZONE\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn test_parse_calendar_timezone() {
+        let paris = CalendarTimezone::parse(PARIS).unwrap();
+        assert_eq!(paris.tzid(), "Europe/Paris");
+        assert_eq!(
+            paris.offsets(),
+            &[FixedOffset::east(7200), FixedOffset::east(3600)]
+        );
+        assert_eq!(paris.fixed_offset(), None);
+        assert_eq!(paris.as_ical(), PARIS);
+
+        let kolkata = CalendarTimezone::from_fixed_offset("Asia/Kolkata", FixedOffset::east(19800));
+        let parsed = CalendarTimezone::parse(kolkata.as_ical()).unwrap();
+        assert_eq!(parsed, kolkata);
+        assert_eq!(parsed.fixed_offset(), Some(FixedOffset::east(19800)));
+        assert!(kolkata.as_ical().contains("TZOFFSETTO:+0530\r\n"));
+
+        assert_eq!(parse_utc_offset("-0500"), Some(FixedOffset::west(18000)));
+        assert_eq!(parse_utc_offset("+013045"), Some(FixedOffset::east(5445)));
+        assert_eq!(parse_utc_offset("0100"), None);
+        assert_eq!(parse_utc_offset("+0160"), None);
+
+        assert!(CalendarTimezone::parse("BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").is_err());
+        assert!(CalendarTimezone::parse("garbage").is_err());
+    }
+
+    #[test]
+    fn test_resolve_calendar_timezone() {
+        let paris = CalendarTimezone::parse(PARIS).unwrap();
+        assert_eq!(paris.tz(), Some(chrono_tz::Europe::Paris));
+        let resolved = paris.resolve().unwrap();
+        assert_eq!(resolved, ResolvedTimezone::Iana(chrono_tz::Europe::Paris));
+        let summer = NaiveDateTime::parse_from_str("2021-07-01 12:00", "%Y-%m-%d %H:%M").unwrap();
+        let winter = NaiveDateTime::parse_from_str("2021-12-01 12:00", "%Y-%m-%d %H:%M").unwrap();
+        assert_eq!(
+            resolved.from_local_datetime(&summer).unwrap().to_rfc3339(),
+            "2021-07-01T12:00:00+02:00"
+        );
+        assert_eq!(
+            resolved.from_local_datetime(&winter).unwrap().to_rfc3339(),
+            "2021-12-01T12:00:00+01:00"
+        );
+        // Skipped when clocks are set forward
+        let skipped = NaiveDateTime::parse_from_str("2021-03-28 02:30", "%Y-%m-%d %H:%M").unwrap();
+        assert_eq!(resolved.from_local_datetime(&skipped), None);
+
+        let prefixed = CalendarTimezone::from_fixed_offset(
+            "/mozilla.org/20050126_1/Asia/Kolkata",
+            FixedOffset::east(19800),
+        );
+        assert_eq!(prefixed.tz(), Some(chrono_tz::Asia::Kolkata));
+
+        // Unknown names fall back to their UTC offset, if there is a single one
+        let custom = CalendarTimezone::from_fixed_offset("My office", FixedOffset::west(18000));
+        assert_eq!(custom.tz(), None);
+        assert_eq!(
+            custom.resolve(),
+            Some(ResolvedTimezone::Fixed(FixedOffset::west(18000)))
+        );
+        assert_eq!(
+            custom
+                .resolve()
+                .unwrap()
+                .from_local_datetime(&winter)
+                .unwrap()
+                .to_rfc3339(),
+            "2021-12-01T12:00:00-05:00"
+        );
+        let unknown_dst =
+            CalendarTimezone::parse(&PARIS.replace("Europe/Paris", "Custom")).unwrap();
+        assert_eq!(unknown_dst.resolve(), None);
+    }
+
+    #[tokio::test]
+    async fn test_calendar_timezone_property() {
+        use crate::calendar::cached_calendar::CachedCalendar;
+        use crate::calendar::SupportedComponents;
+        use crate::traits::{CompleteCalendar, CompleteCalendarFactory};
+        use crate::utils::prop::PROP_CALENDAR_TIMEZONE;
+        use crate::utils::sync::{SyncStatus, Syncable};
+
+        let mut cal = CachedCalendar::new(
+            "Timezone".to_string(),
+            "https://some.calend.ar/tz/".parse().unwrap(),
+            SupportedComponents::TODO,
+            None,
+        );
+        assert_eq!(cal.calendar_timezone().await, None);
+
+        cal.set_calendar_timezone(Some(CalendarTimezone::parse(PARIS).unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(
+            cal.calendar_timezone().await.unwrap().tzid(),
+            "Europe/Paris"
+        );
+        let prop = cal
+            .get_property_by_name_mut(&PROP_CALENDAR_TIMEZONE)
+            .await
+            .unwrap();
+        assert_eq!(prop.sync_status(), &SyncStatus::NotSynced);
+        prop.mark_synced_to_self();
+
+        let utc = CalendarTimezone::from_fixed_offset("UTC", FixedOffset::east(0));
+        cal.set_calendar_timezone(Some(utc.clone())).await.unwrap();
+        assert_eq!(cal.calendar_timezone().await, Some(utc));
+        let prop = cal
+            .get_property_by_name(&PROP_CALENDAR_TIMEZONE)
+            .await
+            .unwrap();
+        assert!(matches!(prop.sync_status(), SyncStatus::LocallyModified(_)));
+
+        cal.set_calendar_timezone(None).await.unwrap();
+        assert_eq!(cal.calendar_timezone().await, None);
+    }
+}
@@ -0,0 +1,25 @@
+//! Hooks to transform the iCal content of items on their way to and from the server
+
+use url::Url;
+
+/// Transforms the iCal content of items when they are uploaded to and downloaded from a remote calendar.
+///
+/// This makes it possible to implement end-to-end encryption on an untrusted CalDAV server: [`encode`](Self::encode) encrypts the items right before they are sent (e.g. into the `X-` property of a placeholder `VTODO`), and [`decode`](Self::decode) decrypts them right after they are received.
+/// Local calendars only ever see the decoded content, so that they can still be queried.
+///
+/// It is set with [`Client::with_payload_transformer`](crate::Client::with_payload_transformer)
+pub trait PayloadTransformer: Send + Sync {
+    /// Transform the iCal content of the item at `url`, before it is uploaded
+    fn encode(
+        &self,
+        url: &Url,
+        ical: String,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Transform back the content of the item at `url`, after it has been downloaded. This must return valid iCal content
+    fn decode(
+        &self,
+        url: &Url,
+        payload: String,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>>;
+}
@@ -0,0 +1,345 @@
+//! Checks that an item can be written as a valid iCal file ([RFC 5545](https://datatracker.ietf.org/doc/html/rfc5545)), before it is uploaded
+//!
+//! Most fields of a [`Task`] are valid by construction, but its extra properties are kept as they are (see [`Task::extra_parameters`]),
+//! so that items coming from other clients (or built with a [`TaskDto`](crate::task::dto::TaskDto)) may contradict the fields this crate writes itself.
+
+use std::fmt::{Display, Formatter};
+
+use ical::property::Property;
+
+use crate::item::Item;
+use crate::task::Task;
+
+/// The properties that [`build_from`](super::build_from) writes from the fields of a task, and that must not be duplicated by its extra properties
+const GENERATED_PROPERTIES: &[&str] = &[
+    "UID",
+    "DTSTAMP",
+    "CREATED",
+    "LAST-MODIFIED",
+    "SUMMARY",
+    "SEQUENCE",
+    "RELATED-TO",
+    "STATUS",
+    "COMPLETED",
+];
+
+/// The properties that a VTODO can contain at most once (RFC 5545 3.6.2), apart from the generated ones
+const SINGLE_PROPERTIES: &[&str] = &[
+    "CLASS",
+    "DESCRIPTION",
+    "DTSTART",
+    "DUE",
+    "DURATION",
+    "GEO",
+    "LOCATION",
+    "ORGANIZER",
+    "PERCENT-COMPLETE",
+    "PRIORITY",
+    "RECURRENCE-ID",
+    "URL",
+];
+
+/// Something that would make the iCal file of an item invalid, or inconsistent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// The UID is empty
+    MissingUid,
+    /// This property contains control characters, that cannot be written in an iCal file
+    IllegalCharacters { property: String },
+    /// This is not a valid property name (only letters, digits and dashes are allowed)
+    InvalidPropertyName { property: String },
+    /// This property appears several times, or it duplicates a property that is written from the fields of the task
+    DuplicateProperty { property: String },
+    /// These properties cannot be used together (e.g. `DUE` and `DURATION`)
+    MutuallyExclusive { first: String, second: String },
+    /// A `DURATION` is set without any `DTSTART`
+    DurationWithoutStart,
+    /// The `DUE` date is before the `DTSTART` date
+    DueBeforeStart,
+    /// The `PERCENT-COMPLETE` property contradicts the completion status of the task
+    InconsistentCompletion,
+    /// This kind of item cannot be written as an iCal file yet
+    UnsupportedItemType,
+}
+
+impl ValidationIssue {
+    /// Whether [`fix_up`] is able to fix this issue
+    pub fn is_fixable(&self) -> bool {
+        match self {
+            Self::MissingUid | Self::DueBeforeStart | Self::UnsupportedItemType => false,
+            Self::IllegalCharacters { property } => property != "UID",
+            Self::InvalidPropertyName { .. }
+            | Self::DuplicateProperty { .. }
+            | Self::MutuallyExclusive { .. }
+            | Self::DurationWithoutStart
+            | Self::InconsistentCompletion => true,
+        }
+    }
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingUid => write!(f, "missing UID"),
+            Self::IllegalCharacters { property } => {
+                write!(f, "illegal characters in {}", property)
+            }
+            Self::InvalidPropertyName { property } => {
+                write!(f, "invalid property name {:?}", property)
+            }
+            Self::DuplicateProperty { property } => write!(f, "duplicate {} property", property),
+            Self::MutuallyExclusive { first, second } => {
+                write!(f, "{} and {} cannot be used together", first, second)
+            }
+            Self::DurationWithoutStart => write!(f, "DURATION without DTSTART"),
+            Self::DueBeforeStart => write!(f, "DUE is before DTSTART"),
+            Self::InconsistentCompletion => {
+                write!(f, "PERCENT-COMPLETE contradicts the completion status")
+            }
+            Self::UnsupportedItemType => write!(f, "unsupported item type"),
+        }
+    }
+}
+
+/// The issues that would make the iCal file of this item invalid (see [`ValidationIssue`]). An empty list means the item can be uploaded as it is
+pub fn validate(item: &Item) -> Vec<ValidationIssue> {
+    match item {
+        Item::Task(task) => validate_task(task),
+        Item::Event(_) => vec![ValidationIssue::UnsupportedItemType],
+    }
+}
+
+/// Fix what [`validate`] reports, as far as possible, and return the issues that remain (see [`ValidationIssue::is_fixable`]).
+///
+/// Offending extra properties are removed (only the first one is kept for duplicates), and illegal characters are removed from the name.
+/// This neither changes the "last modified" field nor the sync status of the item, since it is meant to be called on copies of items that are about to be uploaded
+pub fn fix_up(item: &mut Item) -> Vec<ValidationIssue> {
+    let task = match item {
+        Item::Task(task) => task,
+        Item::Event(_) => return validate(item),
+    };
+    if validate_task(task).iter().any(ValidationIssue::is_fixable) {
+        let name = strip_illegal_characters(task.name());
+        let mut extra_parameters: Vec<Property> = Vec::new();
+        for prop in task.extra_parameters() {
+            let name = prop.name.to_ascii_uppercase();
+            let is_valid = is_valid_name(&prop.name)
+                && !GENERATED_PROPERTIES.contains(&name.as_str())
+                && !(SINGLE_PROPERTIES.contains(&name.as_str())
+                    && extra_parameters
+                        .iter()
+                        .any(|p| p.name.eq_ignore_ascii_case(&name)));
+            if !is_valid {
+                continue;
+            }
+            let mut prop = prop.clone();
+            prop.value = prop.value.as_deref().map(strip_illegal_characters);
+            extra_parameters.push(prop);
+        }
+        let has = |props: &[Property], name: &str| {
+            props.iter().any(|p| p.name.eq_ignore_ascii_case(name))
+        };
+        if has(&extra_parameters, "DURATION")
+            && (has(&extra_parameters, "DUE") || !has(&extra_parameters, "DTSTART"))
+        {
+            extra_parameters.retain(|p| !p.name.eq_ignore_ascii_case("DURATION"));
+        }
+        if inconsistent_completion(task.completed(), &extra_parameters) {
+            extra_parameters.retain(|p| !p.name.eq_ignore_ascii_case("PERCENT-COMPLETE"));
+        }
+        task.overwrite_for_upload(name, extra_parameters);
+    }
+    validate_task(task)
+}
+
+fn validate_task(task: &Task) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    if task.uid().trim().is_empty() {
+        issues.push(ValidationIssue::MissingUid);
+    }
+    for (property, value) in [("UID", task.uid()), ("SUMMARY", task.name())] {
+        if value.chars().any(is_illegal) {
+            issues.push(ValidationIssue::IllegalCharacters {
+                property: property.to_string(),
+            });
+        }
+    }
+
+    let extras = task.extra_parameters();
+    for (index, prop) in extras.iter().enumerate() {
+        let name = prop.name.to_ascii_uppercase();
+        if !is_valid_name(&prop.name) {
+            issues.push(ValidationIssue::InvalidPropertyName {
+                property: prop.name.clone(),
+            });
+            continue;
+        }
+        let is_duplicate = GENERATED_PROPERTIES.contains(&name.as_str())
+            || (SINGLE_PROPERTIES.contains(&name.as_str())
+                && extras[..index]
+                    .iter()
+                    .any(|p| p.name.eq_ignore_ascii_case(&name)));
+        if is_duplicate {
+            issues.push(ValidationIssue::DuplicateProperty {
+                property: name.clone(),
+            });
+        }
+        if prop
+            .value
+            .as_deref()
+            .unwrap_or_default()
+            .chars()
+            .any(is_illegal)
+        {
+            issues.push(ValidationIssue::IllegalCharacters { property: name });
+        }
+    }
+
+    let has = |name: &str| extras.iter().any(|p| p.name.eq_ignore_ascii_case(name));
+    if has("DURATION") {
+        if has("DUE") {
+            issues.push(ValidationIssue::MutuallyExclusive {
+                first: "DUE".to_string(),
+                second: "DURATION".to_string(),
+            });
+        } else if !has("DTSTART") {
+            issues.push(ValidationIssue::DurationWithoutStart);
+        }
+    }
+    if let (Some(start), Some(due)) = (task.start_at(), task.due_at()) {
+        // RFC 5545 3.8.2.3: "the value MUST be a date/time equal to or after the DTSTART value"
+        if let (Some(start), Some(due)) = (start.start_in(&chrono::Utc), due.start_in(&chrono::Utc))
+        {
+            if due < start {
+                issues.push(ValidationIssue::DueBeforeStart);
+            }
+        }
+    }
+    if inconsistent_completion(task.completed(), extras) {
+        issues.push(ValidationIssue::InconsistentCompletion);
+    }
+    issues
+}
+
+/// Completed tasks are written with `PERCENT-COMPLETE:100`, which must not be contradicted (nor duplicated) by an extra property.
+/// Uncompleted tasks (that have `STATUS:NEEDS-ACTION`) cannot be 100% complete
+fn inconsistent_completion(completed: bool, extras: &[Property]) -> bool {
+    extras
+        .iter()
+        .filter(|p| p.name.eq_ignore_ascii_case("PERCENT-COMPLETE"))
+        .any(|p| completed || p.value.as_deref().map(str::trim) == Some("100"))
+}
+
+/// `iana-token` and `x-name` (RFC 5545 3.1): letters, digits and dashes
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Control characters are not allowed in values (RFC 5545 3.3.11), apart from horizontal tabs. Line breaks must be escaped
+fn is_illegal(c: char) -> bool {
+    c.is_control() && c != '\t'
+}
+
+/// Line breaks become spaces, other illegal characters are dropped
+fn strip_illegal_characters(value: &str) -> String {
+    value
+        .chars()
+        .filter_map(|c| match c {
+            '\n' => Some(' '),
+            c if is_illegal(c) => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use url::Url;
+
+    use crate::ical::DateMaybeTime;
+
+    fn property(name: &str, value: &str) -> Property {
+        Property {
+            name: name.to_string(),
+            params: None,
+            value: Some(value.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_validate_and_fix_up() {
+        let cal_url: Url = "https://some.calend.ar/validate/".parse().unwrap();
+        let task = Task::new("Buy milk".to_string(), true, &cal_url).unwrap();
+        assert_eq!(validate(&Item::Task(task.clone())), vec![]);
+
+        let mut invalid = task.clone();
+        invalid.overwrite_for_upload(
+            "Buy\r\nmilk".to_string(),
+            vec![
+                property("PERCENT-COMPLETE", "40"),
+                property("SUMMARY", "Another name"),
+                property("PRIORITY", "1"),
+                property("PRIORITY", "2"),
+                property("DURATION", "PT1H"),
+                property("X-BAD NAME", "value"),
+                property("X-NOTE", "bell\u{7}"),
+            ],
+        );
+        let mut item = Item::Task(invalid);
+        let issues = validate(&item);
+        for expected in [
+            ValidationIssue::IllegalCharacters {
+                property: "SUMMARY".to_string(),
+            },
+            ValidationIssue::InconsistentCompletion,
+            ValidationIssue::DuplicateProperty {
+                property: "SUMMARY".to_string(),
+            },
+            ValidationIssue::DuplicateProperty {
+                property: "PRIORITY".to_string(),
+            },
+            ValidationIssue::DurationWithoutStart,
+            ValidationIssue::InvalidPropertyName {
+                property: "X-BAD NAME".to_string(),
+            },
+            ValidationIssue::IllegalCharacters {
+                property: "X-NOTE".to_string(),
+            },
+        ] {
+            assert!(issues.contains(&expected), "{:?} in {:?}", expected, issues);
+        }
+
+        assert_eq!(fix_up(&mut item), vec![]);
+        let fixed = item.unwrap_task();
+        assert_eq!(fixed.name(), "Buy milk");
+        let extras: Vec<(&str, Option<&str>)> = fixed
+            .extra_parameters()
+            .iter()
+            .map(|p| (p.name.as_str(), p.value.as_deref()))
+            .collect();
+        assert_eq!(
+            extras,
+            vec![("PRIORITY", Some("1")), ("X-NOTE", Some("bell"))]
+        );
+        assert_eq!(fixed.last_modified(), task.last_modified());
+
+        let mut late_start = Task::new("Late".to_string(), false, &cal_url).unwrap();
+        late_start.set_due(Some(DateMaybeTime::Date(chrono::NaiveDate::from_ymd(
+            2022, 3, 1,
+        ))));
+        late_start.overwrite_for_upload(
+            "Late".to_string(),
+            [
+                late_start.extra_parameters(),
+                &[property("DTSTART", "20220302")],
+            ]
+            .concat(),
+        );
+        let mut item = Item::Task(late_start);
+        assert_eq!(validate(&item), vec![ValidationIssue::DueBeforeStart]);
+        assert!(!ValidationIssue::DueBeforeStart.is_fixable());
+        assert_eq!(fix_up(&mut item), vec![ValidationIssue::DueBeforeStart]);
+    }
+}
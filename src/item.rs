@@ -5,6 +5,8 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+#[cfg(any(test, feature = "integration_tests"))]
+use crate::utils::diff::FieldDifference;
 use crate::utils::sync::{SyncStatus, Syncable};
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
@@ -40,6 +42,7 @@ impl Item {
     synthetise_common_getter!(last_modified, &DateTime<Utc>);
     synthetise_common_getter!(sync_status, &SyncStatus);
     synthetise_common_getter!(ical_prod_id, &str);
+    synthetise_common_getter!(sequence, u32);
 
     pub fn set_sync_status(&mut self, new_status: SyncStatus) {
         match self {
@@ -48,6 +51,23 @@ impl Item {
         }
     }
 
+    /// Set the sync status, without checking that it is a legal transition from the current one (see [`Task::relabel_sync_status`])
+    pub(crate) fn relabel_sync_status(&mut self, new_status: SyncStatus) {
+        match self {
+            Item::Event(e) => e.relabel_sync_status(new_status),
+            Item::Task(t) => t.relabel_sync_status(new_status),
+        }
+    }
+
+    /// A counter that increases whenever this item is locally modified, see [`Task::generation`](crate::task::Task::generation)
+    pub fn generation(&self) -> u64 {
+        match self {
+            // Events cannot be modified locally (yet)
+            Item::Event(_) => 0,
+            Item::Task(t) => t.generation(),
+        }
+    }
+
     pub fn is_event(&self) -> bool {
         matches!(self, Item::Event(_))
     }
@@ -80,10 +100,22 @@ impl Item {
 
     #[cfg(any(test, feature = "integration_tests"))]
     pub fn has_same_observable_content_as(&self, other: &Item) -> bool {
+        self.observable_differences(other).is_empty()
+    }
+
+    /// The fields that differ between two items, as far as [`Self::has_same_observable_content_as`] is concerned
+    #[cfg(any(test, feature = "integration_tests"))]
+    pub fn observable_differences(&self, other: &Item) -> Vec<FieldDifference> {
         match (self, other) {
-            (Item::Event(s), Item::Event(o)) => s.has_same_observable_content_as(o),
-            (Item::Task(s), Item::Task(o)) => s.has_same_observable_content_as(o),
-            _ => false,
+            (Item::Event(s), Item::Event(o)) => {
+                if s.has_same_observable_content_as(o) {
+                    Vec::new()
+                } else {
+                    vec![FieldDifference::new("event", s, o)]
+                }
+            }
+            (Item::Task(s), Item::Task(o)) => s.observable_differences(o),
+            _ => vec![FieldDifference::new("type", self.type_(), other.type_())],
         }
     }
 
@@ -17,6 +17,7 @@
 //! It also handles synchronisation between the local cache and the server, and robustly recovers from any network error (so that it never corrupts the local or remote source).
 //!
 //! Note that many methods are defined in common traits (see [`crate::traits`]).
+//! The most common types and traits can be imported at once from the [`prelude`].
 //!
 //! ## Examples
 //!
@@ -42,24 +43,40 @@ pub mod task;
 pub use task::Task;
 pub mod event;
 pub use event::Event;
+pub mod accounts;
 pub mod mock_behaviour;
 pub mod provider;
 
 pub mod client;
 pub use client::Client;
 pub mod cache;
+pub mod dav;
 pub use cache::Cache;
 pub mod ical;
 
+pub mod clock;
 pub mod config;
+pub mod credentials;
+pub mod prelude;
 pub mod resource;
+pub mod uid;
 pub mod utils;
 
 /// Unless you want another kind of Provider to write integration tests, you'll probably want this kind of Provider. \
 /// See alse the [`Provider` documentation](crate::provider::Provider)
+///
+/// Applications that would rather not spell out these types can erase them with [`Provider::into_dyn`](crate::provider::Provider::into_dyn)
 pub type CalDavProvider = provider::Provider<
     cache::Cache,
     calendar::cached_calendar::CachedCalendar,
     Client,
     calendar::remote_calendar::RemoteCalendar,
 >;
+
+/// A Provider that syncs calendars published as iCal files (see [`calendar::subscribed_calendar`]) into a local cache
+pub type SubscriptionProvider = provider::Provider<
+    cache::Cache,
+    calendar::cached_calendar::CachedCalendar,
+    calendar::subscribed_calendar::Subscriptions,
+    calendar::subscribed_calendar::SubscribedCalendar,
+>;
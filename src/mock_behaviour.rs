@@ -1,6 +1,12 @@
 //! This module provides ways to tweak mocked calendars, so that they can return errors on some tests
 #![cfg(feature = "local_calendar_mocks_remote_calendars")]
 
+use std::time::Duration;
+
+use url::Url;
+
+use crate::resource::TransferCounter;
+
 /// Errors related to mocking
 #[derive(thiserror::Error, Debug)]
 pub enum MockError {
@@ -35,6 +41,17 @@ pub struct MockBehaviour {
     pub get_properties_behaviour: (u32, u32),
     pub get_property_behaviour: (u32, u32),
     pub delete_property_behaviour: (u32, u32),
+
+    /// The data that a server would have sent and received (i.e. the iCal size of the items that have been uploaded or downloaded)
+    pub transfers: TransferCounter,
+
+    /// Calendars that behave as if they had just been deleted from the server: listing their items fails with a "calendar not found" error
+    pub vanished_calendars: Vec<Url>,
+
+    /// How many batch downloads (see `DavCalendar::get_items_by_url`) will be rate-limited by the mocked server before it serves them again
+    pub rate_limited_batches: u32,
+    /// The `Retry-After` the mocked server sends along with its rate-limiting responses
+    pub rate_limit_retry_after: Option<Duration>,
 }
 
 impl MockBehaviour {
@@ -58,6 +75,10 @@ impl MockBehaviour {
             get_properties_behaviour: (0, n_fails),
             get_property_behaviour: (0, n_fails),
             delete_property_behaviour: (0, n_fails),
+            transfers: TransferCounter::default(),
+            vanished_calendars: Vec::new(),
+            rate_limited_batches: 0,
+            rate_limit_retry_after: None,
         }
     }
 
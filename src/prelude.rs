@@ -0,0 +1,54 @@
+//! The types and traits most applications need, in a single import
+//!
+//! ```
+//! use kitchen_fridge::prelude::*;
+//! ```
+//!
+//! Everything re-exported here is a stable part of the API: it will only be removed or renamed in a major version.
+//! Items that are not reachable from this module (or from the public modules of this crate) are implementation details.
+
+pub use crate::cache::Cache;
+pub use crate::calendar::cached_calendar::CachedCalendar;
+pub use crate::calendar::remote_calendar::RemoteCalendar;
+pub use crate::calendar::SupportedComponents;
+pub use crate::client::Client;
+pub use crate::error::{KFError, KFResult};
+pub use crate::item::{Item, ItemType};
+pub use crate::provider::sync_progress::SyncResult;
+pub use crate::provider::Provider;
+pub use crate::task::{CompletionStatus, Task};
+pub use crate::traits::{
+    BaseCalendar, CalDavSource, CompleteCalendar, CompleteCalendarFactory, DavCalendar,
+    DavCalendarFactory,
+};
+pub use crate::utils::sync::{SyncStatus, Syncable, VersionTag};
+pub use crate::{CalDavProvider, SubscriptionProvider};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// This fails to build in case an item is removed from the prelude, or changes its kind (e.g. a trait that becomes a struct)
+    #[test]
+    fn test_prelude() {
+        fn is_source<S: CalDavSource<C>, C: BaseCalendar>() {}
+        fn is_dav_calendar<C: DavCalendar + BaseCalendar + DavCalendarFactory>() {}
+        fn is_complete_calendar<C: CompleteCalendar + CompleteCalendarFactory>() {}
+        fn is_syncable<S: Syncable>() {}
+
+        is_source::<Cache, CachedCalendar>();
+        is_source::<Client, RemoteCalendar>();
+        is_dav_calendar::<RemoteCalendar>();
+        is_complete_calendar::<CachedCalendar>();
+        is_syncable::<Task>();
+
+        let _: Option<CalDavProvider> = None;
+        let _: Option<SubscriptionProvider> = None;
+        let _: Option<Provider<Cache, CachedCalendar, Client, RemoteCalendar>> = None;
+        let _: Option<(Task, ItemType, CompletionStatus, SupportedComponents)> = None;
+        let _: Option<(SyncStatus, VersionTag, SyncResult)> = None;
+        let _: KFResult<()> = Err(KFError::SyncAborted {
+            reason: String::new(),
+        });
+    }
+}
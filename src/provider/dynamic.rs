@@ -0,0 +1,225 @@
+//! A provider whose sources and calendars are type-erased
+//!
+//! A [`Provider`] has four type parameters, which are cumbersome to spell out in the structs of an application, and which are fixed at compile time.
+//! A [`DynProvider`] wraps any provider behind trait objects, so that the backends (e.g. a [`Cache`](crate::cache::Cache) or a mocked source) can be chosen at runtime.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+use url::Url;
+
+use super::sync_progress::{FeedbackSender, Skipped, SyncResult};
+use super::Provider;
+use crate::error::KFResult;
+use crate::traits::{CalDavSource, CompleteCalendar, DavCalendar};
+
+/// A local calendar, whatever its actual type
+pub type DynLocalCalendar = dyn CompleteCalendar + Send + Sync;
+
+/// A remote calendar, whatever its actual type
+pub type DynRemoteCalendar = dyn DavCalendar + Send + Sync;
+
+/// The parts of a [`Provider`] that do not depend on its type parameters
+///
+/// Like the ones of [`Provider::sync`], the futures of these functions are not `Send`
+#[async_trait(?Send)]
+trait ErasedProvider: Send + Sync {
+    async fn sync(&mut self) -> bool;
+    async fn sync_with_result(&mut self, feedback_sender: Option<FeedbackSender>) -> SyncResult;
+    async fn retry_skipped(&mut self) -> SyncResult;
+    async fn push(&mut self) -> SyncResult;
+    async fn pull(&mut self) -> SyncResult;
+    fn skipped(&self) -> &[Skipped];
+    async fn local_calendars(&self) -> KFResult<HashMap<Url, Arc<Mutex<DynLocalCalendar>>>>;
+    async fn local_calendar(&self, url: &Url) -> Option<Arc<Mutex<DynLocalCalendar>>>;
+    async fn remote_calendars(&self) -> KFResult<HashMap<Url, Arc<Mutex<DynRemoteCalendar>>>>;
+    fn local(&self) -> &dyn Any;
+    fn local_mut(&mut self) -> &mut dyn Any;
+    fn remote(&self) -> &dyn Any;
+}
+
+#[async_trait(?Send)]
+impl<L, T, R, U> ErasedProvider for Provider<L, T, R, U>
+where
+    L: CalDavSource<T> + Send + Sync + 'static,
+    T: CompleteCalendar + Sync + Send + 'static,
+    R: CalDavSource<U> + Send + Sync + 'static,
+    U: DavCalendar + Sync + Send + 'static,
+{
+    async fn sync(&mut self) -> bool {
+        Provider::sync(self).await
+    }
+
+    async fn sync_with_result(&mut self, feedback_sender: Option<FeedbackSender>) -> SyncResult {
+        Provider::sync_with_result(self, feedback_sender).await
+    }
+
+    async fn retry_skipped(&mut self) -> SyncResult {
+        Provider::retry_skipped(self).await
+    }
+
+    async fn push(&mut self) -> SyncResult {
+        Provider::push(self).await
+    }
+
+    async fn pull(&mut self) -> SyncResult {
+        Provider::pull(self).await
+    }
+
+    fn skipped(&self) -> &[Skipped] {
+        Provider::skipped(self)
+    }
+
+    async fn local_calendars(&self) -> KFResult<HashMap<Url, Arc<Mutex<DynLocalCalendar>>>> {
+        let cals = self.local.get_calendars().await?;
+        Ok(cals
+            .into_iter()
+            .map(|(url, cal)| (url, cal as Arc<Mutex<DynLocalCalendar>>))
+            .collect())
+    }
+
+    async fn local_calendar(&self, url: &Url) -> Option<Arc<Mutex<DynLocalCalendar>>> {
+        let cal = self.local.get_calendar(url).await?;
+        Some(cal as Arc<Mutex<DynLocalCalendar>>)
+    }
+
+    async fn remote_calendars(&self) -> KFResult<HashMap<Url, Arc<Mutex<DynRemoteCalendar>>>> {
+        let cals = self.remote.get_calendars().await?;
+        Ok(cals
+            .into_iter()
+            .map(|(url, cal)| (url, cal as Arc<Mutex<DynRemoteCalendar>>))
+            .collect())
+    }
+
+    fn local(&self) -> &dyn Any {
+        &self.local
+    }
+
+    fn local_mut(&mut self) -> &mut dyn Any {
+        &mut self.local
+    }
+
+    fn remote(&self) -> &dyn Any {
+        &self.remote
+    }
+}
+
+/// A [`Provider`], whatever the types of its sources and calendars
+///
+/// It is created from a provider that has been fully configured (e.g. with [`Provider::set_conflict_strategy`]), see [`Provider::into_dyn`].
+/// The calendars are returned as trait objects. The sources themselves can be accessed by downcasting them to their actual types, e.g. to save a [`Cache`](crate::cache::Cache) to its folder.
+pub struct DynProvider {
+    inner: Box<dyn ErasedProvider>,
+}
+
+impl DynProvider {
+    /// Erase the types of a provider
+    pub fn new<L, T, R, U>(provider: Provider<L, T, R, U>) -> Self
+    where
+        L: CalDavSource<T> + Send + Sync + 'static,
+        T: CompleteCalendar + Sync + Send + 'static,
+        R: CalDavSource<U> + Send + Sync + 'static,
+        U: DavCalendar + Sync + Send + 'static,
+    {
+        Self {
+            inner: Box::new(provider),
+        }
+    }
+
+    /// See [`Provider::sync`]
+    pub async fn sync(&mut self) -> bool {
+        self.inner.sync().await
+    }
+
+    /// See [`Provider::sync_with_result`]
+    pub async fn sync_with_result(
+        &mut self,
+        feedback_sender: Option<FeedbackSender>,
+    ) -> SyncResult {
+        self.inner.sync_with_result(feedback_sender).await
+    }
+
+    /// See [`Provider::retry_skipped`]
+    pub async fn retry_skipped(&mut self) -> SyncResult {
+        self.inner.retry_skipped().await
+    }
+
+    /// See [`Provider::push`]
+    pub async fn push(&mut self) -> SyncResult {
+        self.inner.push().await
+    }
+
+    /// See [`Provider::pull`]
+    pub async fn pull(&mut self) -> SyncResult {
+        self.inner.pull().await
+    }
+
+    /// See [`Provider::skipped`]
+    pub fn skipped(&self) -> &[Skipped] {
+        self.inner.skipped()
+    }
+
+    /// The calendars of the local source
+    pub async fn local_calendars(&self) -> KFResult<HashMap<Url, Arc<Mutex<DynLocalCalendar>>>> {
+        self.inner.local_calendars().await
+    }
+
+    /// A calendar of the local source
+    pub async fn local_calendar(&self, url: &Url) -> Option<Arc<Mutex<DynLocalCalendar>>> {
+        self.inner.local_calendar(url).await
+    }
+
+    /// The calendars of the remote source. See [`Provider::remote`] about why they should rarely be used
+    pub async fn remote_calendars(&self) -> KFResult<HashMap<Url, Arc<Mutex<DynRemoteCalendar>>>> {
+        self.inner.remote_calendars().await
+    }
+
+    /// The local source, in case it is a `L`
+    pub fn local<L: 'static>(&self) -> Option<&L> {
+        self.inner.local().downcast_ref()
+    }
+
+    /// The local source, in case it is a `L`
+    pub fn local_mut<L: 'static>(&mut self) -> Option<&mut L> {
+        self.inner.local_mut().downcast_mut()
+    }
+
+    /// The remote source, in case it is a `R`
+    pub fn remote<R: 'static>(&self) -> Option<&R> {
+        self.inner.remote().downcast_ref()
+    }
+}
+
+impl std::fmt::Debug for DynProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DynProvider")
+    }
+}
+
+impl<L, T, R, U> Provider<L, T, R, U>
+where
+    L: CalDavSource<T> + Send + Sync + 'static,
+    T: CompleteCalendar + Sync + Send + 'static,
+    R: CalDavSource<U> + Send + Sync + 'static,
+    U: DavCalendar + Sync + Send + 'static,
+{
+    /// Erase the types of this provider, see [`DynProvider`]
+    pub fn into_dyn(self) -> DynProvider {
+        DynProvider::new(self)
+    }
+}
+
+impl<L, T, R, U> From<Provider<L, T, R, U>> for DynProvider
+where
+    L: CalDavSource<T> + Send + Sync + 'static,
+    T: CompleteCalendar + Sync + Send + 'static,
+    R: CalDavSource<U> + Send + Sync + 'static,
+    U: DavCalendar + Sync + Send + 'static,
+{
+    fn from(provider: Provider<L, T, R, U>) -> Self {
+        Self::new(provider)
+    }
+}
@@ -0,0 +1,123 @@
+//! The sync state of a local source, in a portable form, to move it to another machine or another kind of source
+//!
+//! Copying the items of a calendar is not enough to keep syncing them: a source that does not know their version tags would download every item again,
+//! and would upload the items it has never seen being synced as new items, i.e. duplicates.
+//! A [`SyncStateExport`] holds what a sync needs besides the items themselves. See [`Provider::export_sync_state`](crate::provider::Provider::export_sync_state)
+//! and [`Provider::import_sync_state`](crate::provider::Provider::import_sync_state)
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::{KFError, KFResult};
+use crate::ical;
+use crate::item::Item;
+use crate::task::dto::SyncState;
+use crate::traits::CompleteCalendar;
+use crate::utils::stable_hash;
+use crate::utils::sync::{SyncStatus, VersionTag};
+
+/// The version of the format written by this crate. It is increased whenever an older crate would misread newer exports
+pub const SYNC_STATE_FORMAT: u32 = 1;
+
+/// The sync state of every calendar of a local source, see the [module documentation](self).
+///
+/// Its fields (and their serialized names) are stable, so that exports can be imported by other versions of this crate
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncStateExport {
+    /// See [`SYNC_STATE_FORMAT`]
+    pub format: u32,
+    pub calendars: Vec<CalendarSyncState>,
+}
+
+/// The sync state of a calendar of a [`SyncStateExport`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarSyncState {
+    pub url: Url,
+    /// The token of the remote calendar when it was last synced (see [`CompleteCalendar::remote_token`])
+    pub remote_token: Option<String>,
+    /// The items that have been synced at least once. Items that have never been synced are not exported: they are new, whatever the source
+    pub items: Vec<ItemSyncStateExport>,
+}
+
+/// The sync state of an item of a [`CalendarSyncState`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemSyncStateExport {
+    pub url: Url,
+    /// Never [`SyncState::NotSynced`]
+    pub sync_state: SyncState,
+    /// The version tag (etag) of the item when it was last synced
+    pub version_tag: String,
+    /// A hash of the item when it was exported, to tell whether it has been modified before it is imported
+    pub content_hash: String,
+}
+
+impl CalendarSyncState {
+    pub(crate) async fn export<T: CompleteCalendar>(cal: &T) -> KFResult<Self> {
+        let mut exported = Vec::new();
+        for item in cal.get_items().await?.values() {
+            let (sync_state, tag) = match item.sync_status() {
+                SyncStatus::NotSynced => continue,
+                SyncStatus::Synced(tag) => (SyncState::Synced, tag),
+                SyncStatus::LocallyModified(tag) => (SyncState::LocallyModified, tag),
+                SyncStatus::LocallyDeleted(tag) => (SyncState::LocallyDeleted, tag),
+            };
+            exported.push(ItemSyncStateExport {
+                url: item.url().clone(),
+                sync_state,
+                version_tag: tag.as_str().to_string(),
+                content_hash: content_hash(item)?,
+            });
+        }
+        exported.sort_by(|a, b| a.url.cmp(&b.url));
+        Ok(Self {
+            url: cal.url().clone(),
+            remote_token: cal.remote_token().map(String::from),
+            items: exported,
+        })
+    }
+
+    /// Restore the sync state of the items of `cal` this export knows about. Returns how many items have been updated
+    pub(crate) async fn import<T: CompleteCalendar>(&self, cal: &mut T) -> KFResult<usize> {
+        cal.set_remote_token(self.remote_token.clone());
+        cal.mark_synced().await;
+
+        let mut items = cal.get_items_mut().await?;
+        let mut restored = 0;
+        for exported in &self.items {
+            let item = match items.get_mut(&exported.url) {
+                None => continue,
+                Some(item) => item,
+            };
+            let tag = VersionTag::from(exported.version_tag.clone());
+            let status = match exported.sync_state {
+                SyncState::NotSynced => continue,
+                // Items modified since they have been exported are local changes, that the next sync pushes
+                SyncState::Synced if content_hash(item)? == exported.content_hash => {
+                    SyncStatus::Synced(tag)
+                }
+                SyncState::Synced | SyncState::LocallyModified => SyncStatus::LocallyModified(tag),
+                SyncState::LocallyDeleted => SyncStatus::LocallyDeleted(tag),
+            };
+            if item.sync_status() != &status {
+                item.relabel_sync_status(status);
+                restored += 1;
+            }
+        }
+        Ok(restored)
+    }
+}
+
+impl SyncStateExport {
+    pub(crate) fn check_format(&self) -> KFResult<()> {
+        if self.format > SYNC_STATE_FORMAT {
+            return Err(KFError::UnsupportedSyncStateFormat {
+                version: self.format,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn content_hash(item: &Item) -> KFResult<String> {
+    Ok(format!("{:016x}", stable_hash(&ical::build_from(item)?)))
+}
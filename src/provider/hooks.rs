@@ -0,0 +1,137 @@
+//! Hooks that are called by the syncs: [`ItemHooks`] transform items on their way to and from the remote source, and a [`DestructiveChangeGuard`] can veto the changes that lose data
+
+use std::fmt;
+
+use async_trait::async_trait;
+use url::Url;
+
+use crate::item::Item;
+use crate::utils::NamespacedName;
+
+/// Transforms items during a sync, e.g. to strip private fields before they are uploaded to a shared server, or to enrich downloaded items.
+///
+/// Unlike a [`PayloadTransformer`](crate::ical::PayloadTransformer), which works on the raw iCal content of a [`Client`](crate::Client), these hooks work on parsed items, whatever the sources of the [`Provider`](super::Provider).
+/// They are set with [`Provider::set_item_hooks`](super::Provider::set_item_hooks). Both functions do nothing by default
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait ItemHooks: Send + Sync {
+    /// Called on a copy of a local item, right before it is uploaded to the remote source.
+    /// The local item is not changed, so that fields removed here are kept locally
+    async fn before_upload(&self, _item: &mut Item) {}
+
+    /// Called on an item that has just been downloaded from the remote source, before it is stored locally (and before it is merged with a conflicting local version).
+    /// The item is stored as synced, whatever this changes: these changes are only pushed to the remote source if the item is modified locally later
+    async fn after_download(&self, _item: &mut Item) {}
+}
+
+impl std::fmt::Debug for dyn ItemHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ItemHooks")
+    }
+}
+
+/// A change that a sync is about to apply, and that loses data. See [`DestructiveChangeGuard`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlannedChange {
+    /// Items that have been deleted from the server are about to be deleted locally
+    DeleteLocalItems { calendar: Url, urls: Vec<Url> },
+    /// Items that have been deleted locally are about to be deleted from the server
+    DeleteRemoteItems { calendar: Url, urls: Vec<Url> },
+    /// Properties of a calendar that have been deleted locally are about to be deleted from the server
+    DeleteRemoteProps {
+        calendar: Url,
+        names: Vec<NamespacedName>,
+    },
+    /// Items that have been modified on both ends are about to be replaced by their remote versions.
+    /// Their local versions are still kept in the conflict journal (see [`CompleteCalendar::get_conflicts`](crate::traits::CompleteCalendar::get_conflicts))
+    OverwriteLocalChanges { calendar: Url, urls: Vec<Url> },
+    /// A calendar that has been deleted from the server is about to be deleted locally, with its items
+    DeleteLocalCalendar { url: Url, name: String },
+    /// A calendar that has been deleted locally is about to be deleted from the server, with its items
+    DeleteRemoteCalendar { url: Url, name: String },
+}
+
+impl PlannedChange {
+    /// The items this change is about. This is empty for the changes of whole calendars
+    pub fn item_urls(&self) -> &[Url] {
+        match self {
+            Self::DeleteLocalItems { urls, .. }
+            | Self::DeleteRemoteItems { urls, .. }
+            | Self::OverwriteLocalChanges { urls, .. } => urls,
+            Self::DeleteRemoteProps { .. }
+            | Self::DeleteLocalCalendar { .. }
+            | Self::DeleteRemoteCalendar { .. } => &[],
+        }
+    }
+}
+
+impl fmt::Display for PlannedChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DeleteLocalItems { calendar, urls } => write!(
+                f,
+                "deleting {} items locally from calendar {}",
+                urls.len(),
+                calendar
+            ),
+            Self::DeleteRemoteItems { calendar, urls } => write!(
+                f,
+                "deleting {} items from calendar {} on the server",
+                urls.len(),
+                calendar
+            ),
+            Self::DeleteRemoteProps { calendar, names } => write!(
+                f,
+                "deleting {} properties from calendar {} on the server",
+                names.len(),
+                calendar
+            ),
+            Self::OverwriteLocalChanges { calendar, urls } => write!(
+                f,
+                "overwriting the local changes of {} items of calendar {}",
+                urls.len(),
+                calendar
+            ),
+            Self::DeleteLocalCalendar { url, name } => {
+                write!(f, "deleting calendar {} ({}) locally", name, url)
+            }
+            Self::DeleteRemoteCalendar { url, name } => {
+                write!(f, "deleting calendar {} ({}) from the server", name, url)
+            }
+        }
+    }
+}
+
+/// What a sync does with a [`PlannedChange`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// The change is applied
+    Allow,
+    /// The change is not applied, and the rest of the sync goes on. The next sync will plan it (and ask for it) again
+    Skip,
+    /// The sync stops right away. Changes that have already been applied are kept
+    Abort,
+}
+
+/// Called before a sync applies a change that loses data (see [`PlannedChange`]), e.g. to ask the user for a confirmation before deleting hundreds of items.
+///
+/// Changes of items and properties are grouped by calendar and by kind, so that the guard is called at most four times per calendar.
+/// It is set with [`Provider::set_destructive_change_guard`](super::Provider::set_destructive_change_guard). Closures can be used as guards
+pub trait DestructiveChangeGuard: Send + Sync {
+    fn on_destructive_change(&self, change: &PlannedChange) -> Decision;
+}
+
+impl<F> DestructiveChangeGuard for F
+where
+    F: Fn(&PlannedChange) -> Decision + Send + Sync,
+{
+    fn on_destructive_change(&self, change: &PlannedChange) -> Decision {
+        self(change)
+    }
+}
+
+impl std::fmt::Debug for dyn DestructiveChangeGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DestructiveChangeGuard")
+    }
+}
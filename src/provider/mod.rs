@@ -4,23 +4,39 @@
 
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter, Write};
+use std::future::Future;
 use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
+use std::time::Duration;
 
 use itertools::Itertools;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedMutexGuard};
 use url::Url;
 
-use crate::error::KFResult;
+use crate::calendar::conflict::Conflict;
+use crate::calendar::item_url_policy::ItemUrlPolicy;
+use crate::error::{KFError, KFResult};
+use crate::ical;
+use crate::item::{Item, ItemType};
+use crate::resource::canonical_url;
+use crate::task::CompletionStatus;
 use crate::traits::CompleteCalendar;
 use crate::traits::{BaseCalendar, CalDavSource, DavCalendar};
 use crate::utils::prop::Property;
-use crate::utils::sync::{SyncStatus, Syncable};
+use crate::utils::runtime::sleep;
+use crate::utils::sync::{SyncStatus, Syncable, VersionTag};
 use crate::utils::NamespacedName;
 
+pub mod dynamic;
+pub mod export;
+pub mod hooks;
+pub mod multi;
 pub mod sync_progress;
+use export::{CalendarSyncState, SyncStateExport};
+use hooks::{Decision, DestructiveChangeGuard, ItemHooks, PlannedChange};
 use sync_progress::SyncProgress;
-use sync_progress::{FeedbackSender, SyncEvent};
+use sync_progress::{FeedbackSender, Skipped, SyncEvent, SyncIssue, SyncResult};
 
 /// How many items will be batched in a single HTTP request when downloading from the server
 #[cfg(not(test))]
@@ -29,8 +45,24 @@ const DOWNLOAD_BATCH_SIZE: usize = 30;
 #[cfg(test)]
 const DOWNLOAD_BATCH_SIZE: usize = 3;
 
+/// How many downloaded items trigger a checkpoint of the local calendar, by default
+const DEFAULT_CHECKPOINT_INTERVAL: usize = 300;
+
+/// How many times we try to lock a remote calendar that is locked by another client
+const LOCK_ATTEMPTS: u32 = 3;
+/// How long we wait before trying again to lock a remote calendar that is locked by another client
+const LOCK_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// How many times we try to download a batch of items that the server has rate-limited
+const RATE_LIMIT_ATTEMPTS: u32 = 5;
+/// How long we wait before the first retry of a rate-limited download, when the server does not tell (this doubles at every attempt)
+const RATE_LIMIT_DEFAULT_DELAY: Duration = Duration::from_secs(1);
+/// The longest we are willing to wait before retrying a rate-limited download, whatever the server asks
+const RATE_LIMIT_MAX_DELAY: Duration = Duration::from_secs(120);
+
 // I am too lazy to actually make `fetch_and_apply` generic over an async closure.
 // Let's work around by passing an enum, so that `fetch_and_apply` will know what to do
+#[derive(Clone, Copy)]
 enum BatchDownloadType {
     RemoteAdditions,
     RemoteChanges,
@@ -45,6 +77,114 @@ impl Display for BatchDownloadType {
     }
 }
 
+/// A batch of items that has been downloaded, and that is waiting to be applied locally
+struct DownloadedBatch {
+    urls: Vec<Url>,
+    result: KFResult<Vec<Option<Item>>>,
+    /// How long the download has waited because the remote source was rate-limited, for each retry.
+    /// These are reported once the batch is applied, since the `SyncProgress` may be in use in the meantime
+    rate_limit_delays: Vec<Duration>,
+}
+
+/// How a sync handles items that have been modified both locally and remotely since the last sync
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// The remote version replaces the local one (which is then recorded in the conflict journal, see [`CompleteCalendar::get_conflicts`])
+    #[default]
+    RemoteWins,
+    /// The local changes are merged into the remote version when both versions have changed different fields of a task
+    /// (e.g. it has been renamed on the server, and locally completed). Otherwise, the remote version wins
+    Merge,
+}
+
+/// Whether (and how) the items are checked with [`ical::validate`](crate::ical::validate) before they are uploaded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValidationPolicy {
+    /// Items are uploaded as they are
+    #[default]
+    Off,
+    /// Invalid items are uploaded anyway, but their issues are logged as warnings
+    Warn,
+    /// Invalid items are not uploaded, and they are reported as [`Skipped`] by the sync
+    Reject,
+    /// Invalid items are fixed (see [`ical::fix_up`](crate::ical::fix_up)) before they are uploaded. The local items are not changed.
+    /// Items whose issues cannot be fixed are not uploaded, and they are reported as [`Skipped`] by the sync
+    FixUp,
+}
+
+/// Which changes a sync applies
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncDirection {
+    /// Local changes are pushed to the remote, and remote changes are applied locally
+    #[default]
+    Both,
+    /// Only local changes are pushed to the remote (see [`Provider::push`]).
+    /// Local items that have changed on the remote as well are left untouched, until a sync in the other direction resolves the conflict
+    Push,
+    /// Only remote changes are applied locally (see [`Provider::pull`]). Local changes are kept, and pushed by a later sync
+    Pull,
+}
+
+impl SyncDirection {
+    fn pushes(&self) -> bool {
+        matches!(self, Self::Both | Self::Push)
+    }
+
+    fn pulls(&self) -> bool {
+        matches!(self, Self::Both | Self::Pull)
+    }
+}
+
+/// Which calendars a sync is allowed to create, when a calendar only exists on one side
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CalendarCreationPolicy {
+    /// Calendars that only exist on one side are created on the other side
+    #[default]
+    CreateBoth,
+    /// Remote calendars are created locally, but calendars are never created on the remote (e.g. on shared servers where creating collections is forbidden).
+    /// Local calendars that do not exist on the remote are not synced
+    LocalOnly,
+    /// Calendars are never created. Calendars that only exist on one side are not synced
+    NeverCreate,
+}
+
+impl CalendarCreationPolicy {
+    fn creates_local_calendars(&self) -> bool {
+        matches!(self, Self::CreateBoth | Self::LocalOnly)
+    }
+
+    fn creates_remote_calendars(&self) -> bool {
+        matches!(self, Self::CreateBoth)
+    }
+}
+
+/// Tells whether a local calendar should be deleted, now that it has been deleted from the server. It is given the URL and the name of the calendar
+pub type CalendarDeletionConfirmation = Arc<dyn Fn(&Url, &str) -> bool + Send + Sync>;
+
+/// How a sync handles local calendars that have been synced before, but that do not exist on the server anymore
+#[derive(Clone, Default)]
+pub enum RemoteCalendarDeletionPolicy {
+    /// The local calendar is deleted as well
+    #[default]
+    DeleteLocally,
+    /// The callback decides whether the local calendar is deleted.
+    /// If it is not, it is kept locally but not synced, and the callback will be called again on the next sync
+    Confirm(CalendarDeletionConfirmation),
+    /// The calendar is created again on the server, from its local copy.
+    /// With this policy, syncs do not record which calendars have been synced (see [`CompleteCalendar::has_been_synced`])
+    Recreate,
+}
+
+impl std::fmt::Debug for RemoteCalendarDeletionPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DeleteLocally => f.write_str("DeleteLocally"),
+            Self::Confirm(_) => f.write_str("Confirm(<callback>)"),
+            Self::Recreate => f.write_str("Recreate"),
+        }
+    }
+}
+
 struct ItemChanges {
     local_item_dels: HashSet<Url>,
     remote_item_dels: HashSet<Url>,
@@ -52,6 +192,10 @@ struct ItemChanges {
     remote_item_changes: HashSet<Url>,
     local_item_additions: HashSet<Url>,
     remote_item_additions: HashSet<Url>,
+    /// Local versions of the items whose local modifications will be discarded in favour of the remote version
+    conflicting_local_versions: HashMap<Url, Item>,
+    /// Whether the local versions that are kept when resolving conflicts are pushed by this sync
+    push_kept_local_versions: bool,
 }
 
 struct PropChanges {
@@ -62,6 +206,41 @@ struct PropChanges {
     local_prop_additions: HashSet<Property>,
     remote_prop_additions: HashSet<Property>,
 }
+impl ItemChanges {
+    /// Forget about the changes that a sync in `direction` must not apply
+    fn restrict_to(&mut self, direction: SyncDirection) {
+        if !direction.pulls() {
+            self.remote_item_dels.clear();
+            self.remote_item_changes.clear();
+            self.remote_item_additions.clear();
+            // Conflicting items are remote changes or deletions, they are left untouched
+            self.conflicting_local_versions.clear();
+        }
+        if !direction.pushes() {
+            self.local_item_dels.clear();
+            self.local_item_changes.clear();
+            self.local_item_additions.clear();
+            self.push_kept_local_versions = false;
+        }
+    }
+}
+
+impl PropChanges {
+    /// Forget about the changes that a sync in `direction` must not apply
+    fn restrict_to(&mut self, direction: SyncDirection) {
+        if !direction.pulls() {
+            self.remote_prop_dels.clear();
+            self.remote_prop_changes.clear();
+            self.remote_prop_additions.clear();
+        }
+        if !direction.pushes() {
+            self.local_prop_dels.clear();
+            self.local_prop_changes.clear();
+            self.local_prop_additions.clear();
+        }
+    }
+}
+
 impl std::fmt::Debug for PropChanges {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str("local_prop_dels:")?;
@@ -109,6 +288,19 @@ where
     remote: R,
     /// The local cache
     local: L,
+    /// Whether remote calendars should be locked while they are synced
+    lock_remote_calendars: bool,
+    conflict_strategy: ConflictStrategy,
+    calendar_creation_policy: CalendarCreationPolicy,
+    remote_calendar_deletion_policy: RemoteCalendarDeletionPolicy,
+    checkpoint_interval: Option<usize>,
+    max_download_bytes: Option<u64>,
+    item_hooks: Option<Arc<dyn ItemHooks>>,
+    validation_policy: ValidationPolicy,
+    destructive_change_guard: Option<Arc<dyn DestructiveChangeGuard>>,
+    strict: bool,
+    /// What the last sync has skipped
+    skipped: Vec<Skipped>,
 
     phantom_t: PhantomData<T>,
     phantom_u: PhantomData<U>,
@@ -129,6 +321,17 @@ where
         Self {
             remote,
             local,
+            lock_remote_calendars: false,
+            conflict_strategy: ConflictStrategy::default(),
+            calendar_creation_policy: CalendarCreationPolicy::default(),
+            remote_calendar_deletion_policy: RemoteCalendarDeletionPolicy::default(),
+            checkpoint_interval: Some(DEFAULT_CHECKPOINT_INTERVAL),
+            max_download_bytes: None,
+            item_hooks: None,
+            destructive_change_guard: None,
+            validation_policy: ValidationPolicy::default(),
+            strict: false,
+            skipped: Vec::new(),
             phantom_t: PhantomData,
             phantom_u: PhantomData,
         }
@@ -151,17 +354,182 @@ where
         &self.remote
     }
 
+    /// Whether every remote calendar should be locked (see [`DavCalendar::lock`]) while it is synced, so that other clients do not modify it at the same time.
+    ///
+    /// This is disabled by default. In case a calendar is locked by another client, the sync of this calendar is retried a few times, then skipped.
+    pub fn set_lock_remote_calendars(&mut self, lock: bool) {
+        self.lock_remote_calendars = lock;
+    }
+
+    /// How items that have been modified on both ends since the last sync are handled. This is [`ConflictStrategy::RemoteWins`] by default
+    pub fn set_conflict_strategy(&mut self, strategy: ConflictStrategy) {
+        self.conflict_strategy = strategy;
+    }
+
+    /// Which calendars a sync may create, when a calendar only exists on one side. This is [`CalendarCreationPolicy::CreateBoth`] by default
+    pub fn set_calendar_creation_policy(&mut self, policy: CalendarCreationPolicy) {
+        self.calendar_creation_policy = policy;
+    }
+
+    /// How local calendars that have been deleted from the server are handled. This is [`RemoteCalendarDeletionPolicy::DeleteLocally`] by default
+    pub fn set_remote_calendar_deletion_policy(&mut self, policy: RemoteCalendarDeletionPolicy) {
+        self.remote_calendar_deletion_policy = policy;
+    }
+
+    /// While a sync downloads new items (e.g. during the first sync of a large calendar), the local calendar is persisted every `items` downloaded items
+    /// (see [`CalDavSource::checkpoint_calendar`]), so that an interrupted sync does not download them again. `None` disables these checkpoints.
+    ///
+    /// This is every 300 items by default
+    pub fn set_checkpoint_interval(&mut self, items: Option<usize>) {
+        self.checkpoint_interval = items;
+    }
+
+    /// Stop downloading items once a sync has received `bytes` from the remote source (e.g. to bound what a background sync pulls over a metered connection).
+    ///
+    /// This is checked between batches of downloads, so that a sync may receive slightly more than this.
+    /// Such a sync is still successful, but it is not [complete](SyncResult::is_complete): the remaining items will be downloaded by the next syncs.
+    /// There is no limit by default
+    pub fn set_max_download_bytes(&mut self, bytes: Option<u64>) {
+        self.max_download_bytes = bytes;
+    }
+
+    /// Transform the items that are uploaded and downloaded by the syncs (see [`ItemHooks`]). There are no hooks by default
+    pub fn set_item_hooks(&mut self, hooks: Option<Arc<dyn ItemHooks>>) {
+        self.item_hooks = hooks;
+    }
+
+    /// Whether the items are checked before they are uploaded, once the hooks have been called. This is [`ValidationPolicy::Off`] by default
+    pub fn set_validation_policy(&mut self, policy: ValidationPolicy) {
+        self.validation_policy = policy;
+    }
+
+    /// Consult `guard` before the syncs delete items, properties or calendars, or overwrite local changes (see [`DestructiveChangeGuard`]). There is no guard by default
+    pub fn set_destructive_change_guard(&mut self, guard: Option<Arc<dyn DestructiveChangeGuard>>) {
+        self.destructive_change_guard = guard;
+    }
+
+    /// Stop syncing a calendar as soon as its data is not in the state the sync expects (e.g. an item that is listed but missing, a URL that is used by unrelated items in both sources, or an item that vanishes from the server during the sync),
+    /// instead of working around it to converge anyway. The calendar is then skipped with a [`KFError::SyncInconsistency`] error. This is disabled by default.
+    ///
+    /// In any case, these are reported in [`SyncResult::issues`]
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Remove a calendar from the local source only, e.g. to stop syncing a huge calendar that should be kept on the server.
+    ///
+    /// The next syncs will neither delete it from the server nor download it again, until [`Self::stop_ignoring_calendar`] is called.
+    /// Local changes to this calendar that have not been synced yet are lost. See [`CalDavSource::remove_calendar_local_only`]
+    pub async fn remove_calendar_local_only(
+        &mut self,
+        url: &Url,
+    ) -> KFResult<Option<Arc<Mutex<T>>>> {
+        self.local.remove_calendar_local_only(url).await
+    }
+
+    /// Sync again a calendar that has been removed by [`Self::remove_calendar_local_only`]. Returns whether it was ignored
+    pub fn stop_ignoring_calendar(&mut self, url: &Url) -> bool {
+        self.local.stop_ignoring_calendar(url)
+    }
+
+    /// The sync state of every local calendar (the version tags of their items and the tokens of the remote calendars), e.g. to move the local source to another machine.
+    ///
+    /// The items themselves are not exported. Once they have been copied to another source, [`Self::import_sync_state`] lets it sync them
+    /// without downloading them again nor uploading them as new items. See [`export`]
+    pub async fn export_sync_state(&self) -> KFResult<SyncStateExport> {
+        let mut calendars = Vec::new();
+        for cal in self.local.get_calendars().await?.values() {
+            calendars.push(CalendarSyncState::export(&*cal.lock().await).await?);
+        }
+        calendars.sort_by(|a, b| a.url.cmp(&b.url));
+        Ok(SyncStateExport {
+            format: export::SYNC_STATE_FORMAT,
+            calendars,
+        })
+    }
+
+    /// Restore a sync state that has been exported by [`Self::export_sync_state`], once the items have been copied to the local source.
+    ///
+    /// Local items that were synced when the state was exported get their sync status back (items that have been modified since then become local changes),
+    /// and local calendars their remote tokens. Calendars and items the export does not know are left untouched, and the local calendars are persisted.
+    /// Returns how many items have been updated
+    pub async fn import_sync_state(&mut self, state: &SyncStateExport) -> KFResult<usize> {
+        state.check_format()?;
+        let mut restored = 0;
+        for exported in &state.calendars {
+            let cal = match self.local.get_calendar(&exported.url).await {
+                None => continue,
+                Some(cal) => cal,
+            };
+            let mut cal = cal.lock().await;
+            restored += exported.import(&mut *cal).await?;
+            self.local.checkpoint_calendar(&cal).await?;
+        }
+        Ok(restored)
+    }
+
+    /// Lists the local tasks that [`Self::purge_completed`] would mark for deletion, without changing anything
+    pub async fn completed_tasks_to_purge(&self, older_than: Duration) -> KFResult<Vec<Url>> {
+        let mut to_purge = Vec::new();
+        let cutoff = match Self::purge_cutoff(older_than) {
+            None => return Ok(to_purge),
+            Some(cutoff) => cutoff,
+        };
+        for cal in self.local.get_calendars().await?.values() {
+            let cal = cal.lock().await;
+            for (url, item) in cal.get_items().await? {
+                if let Item::Task(task) = item {
+                    let completed_at = match task.completion_status() {
+                        CompletionStatus::Uncompleted => continue,
+                        CompletionStatus::Completed(Some(date)) => date,
+                        // Fall back to the last time this task was modified, which is probably when it has been completed
+                        CompletionStatus::Completed(None) => task.last_modified(),
+                    };
+                    let already_deleted =
+                        matches!(task.sync_status(), SyncStatus::LocallyDeleted(_));
+                    if *completed_at < cutoff && !already_deleted {
+                        to_purge.push(url);
+                    }
+                }
+            }
+        }
+        Ok(to_purge)
+    }
+
+    /// Marks the local tasks that have been completed for more than `older_than` for deletion.
+    /// The next sync will then delete them from the server, like any other local deletion.
+    ///
+    /// Returns the URLs of the purged tasks. See [`Self::completed_tasks_to_purge`] for a dry run
+    pub async fn purge_completed(&mut self, older_than: Duration) -> KFResult<Vec<Url>> {
+        let to_purge = self.completed_tasks_to_purge(older_than).await?;
+        for cal in self.local.get_calendars().await?.values() {
+            let mut cal = cal.lock().await;
+            let cal_items = cal.get_item_urls().await?;
+            for url in to_purge.iter().filter(|url| cal_items.contains(*url)) {
+                cal.mark_item_for_deletion(url).await?;
+            }
+        }
+        Ok(to_purge)
+    }
+
+    /// `None` for durations that are longer than any task lifespan
+    fn purge_cutoff(older_than: Duration) -> Option<chrono::DateTime<chrono::Utc>> {
+        let older_than = chrono::Duration::from_std(older_than).ok()?;
+        crate::clock::now().checked_sub_signed(older_than)
+    }
+
     /// Performs a synchronisation between `local` and `remote`, and provide feeedback to the user about the progress.
     ///
     /// This bidirectional sync applies additions/deletions made on a source to the other source.
-    /// In case of conflicts (the same item has been modified on both ends since the last sync, `remote` always wins).
+    /// In case of conflicts (the same item has been modified on both ends since the last sync, `remote` wins, unless the local version has a higher SEQUENCE number).
     ///
     /// It returns whether the sync was totally successful (details about errors are logged using the `log::*` macros).
     /// In case errors happened, the sync might have been partially executed but your data will never be correupted (either locally nor in the server).
     /// Simply run this function again, it will re-start a sync, picking up where it failed.
     pub async fn sync_with_feedback(&mut self, feedback_sender: FeedbackSender) -> bool {
         let mut progress = SyncProgress::new_with_feedback_channel(feedback_sender);
-        self.run_sync(&mut progress).await
+        self.run_sync(&mut progress, None, SyncDirection::Both)
+            .await
     }
 
     /// Performs a synchronisation between `local` and `remote`, without giving any feedback.
@@ -169,47 +537,197 @@ where
     /// See [`Self::sync_with_feedback`]
     pub async fn sync(&mut self) -> bool {
         let mut progress = SyncProgress::new();
-        self.run_sync(&mut progress).await
+        self.run_sync(&mut progress, None, SyncDirection::Both)
+            .await
+    }
+
+    /// Performs a synchronisation between `local` and `remote` (see [`Self::sync_with_feedback`]), and returns details about the issues that happened
+    pub async fn sync_with_result(
+        &mut self,
+        feedback_sender: Option<FeedbackSender>,
+    ) -> SyncResult {
+        let mut progress = match feedback_sender {
+            Some(sender) => SyncProgress::new_with_feedback_channel(sender),
+            None => SyncProgress::new(),
+        };
+        self.run_sync(&mut progress, None, SyncDirection::Both)
+            .await;
+        progress.result()
+    }
+
+    /// Only push the local changes (additions, changes and deletions of items and properties, new and deleted calendars) to `remote`.
+    /// Nothing is changed in `local`, apart from the sync statuses of what has been pushed.
+    ///
+    /// Items that have been modified on both ends are not pushed, so that the remote version is not overwritten: they are handled by the next [`Self::sync`] or [`Self::pull`]
+    pub async fn push(&mut self) -> SyncResult {
+        let mut progress = SyncProgress::new();
+        self.run_sync(&mut progress, None, SyncDirection::Push)
+            .await;
+        progress.result()
+    }
+
+    /// Only apply the remote changes to `local` (including new and deleted calendars). Nothing is changed in `remote`.
+    ///
+    /// Local changes are kept, and pushed by the next [`Self::sync`] or [`Self::push`]. Items that have been modified on both ends are handled according to the [`ConflictStrategy`], like during a sync
+    pub async fn pull(&mut self) -> SyncResult {
+        let mut progress = SyncProgress::new();
+        self.run_sync(&mut progress, None, SyncDirection::Pull)
+            .await;
+        progress.result()
+    }
+
+    /// The calendars and items that the last sync has not been able to sync (see [`SyncResult::skipped`])
+    pub fn skipped(&self) -> &[Skipped] {
+        &self.skipped
+    }
+
+    /// Sync again the calendars that the last sync has skipped, or whose items it has partly skipped, e.g. after a temporary network failure.
+    /// Other calendars are left untouched, so that this is much faster than a full sync.
+    ///
+    /// Skipped items are re-attempted by syncing their whole calendar again, which only transfers what still differs.
+    /// What is skipped again is returned, and can be retried by calling this function again
+    pub async fn retry_skipped(&mut self) -> SyncResult {
+        let calendars: HashSet<Url> = self
+            .skipped
+            .iter()
+            .map(|skipped| skipped.calendar().clone())
+            .collect();
+        let mut progress = SyncProgress::new();
+        self.run_sync(&mut progress, Some(&calendars), SyncDirection::Both)
+            .await;
+        progress.result()
     }
 
-    async fn run_sync(&mut self, progress: &mut SyncProgress) -> bool {
-        if let Err(err) = self.run_sync_inner(progress).await {
+    /// Sync every calendar, or only the calendars of `only`
+    async fn run_sync(
+        &mut self,
+        progress: &mut SyncProgress,
+        only: Option<&HashSet<Url>>,
+        direction: SyncDirection,
+    ) -> bool {
+        let result = match self.local.sync_started() {
+            Err(err) => Err(err),
+            Ok(()) => {
+                let result = self.sync_calendars(progress, only, direction).await;
+                self.local.sync_finished();
+                result
+            }
+        };
+        if let Err(err) = result {
+            if let KFError::CacheLocked { .. } = err {
+                progress.local_source_locked();
+            }
             progress.error(&format!("Sync terminated because of an error: {}", err));
         }
-        progress.feedback(SyncEvent::Finished {
+        self.skipped = progress.skipped().to_vec();
+        let event = SyncEvent::Finished {
             success: progress.is_success(),
-        });
+            summary: progress.summary().clone(),
+        };
+        progress.feedback(event);
         progress.is_success()
     }
 
     async fn run_sync_inner(&mut self, progress: &mut SyncProgress) -> KFResult<()> {
-        progress.info("Starting a sync.");
-        progress.feedback(SyncEvent::Started);
+        self.sync_calendars(progress, None, SyncDirection::Both)
+            .await
+    }
+
+    async fn sync_calendars(
+        &mut self,
+        progress: &mut SyncProgress,
+        only: Option<&HashSet<Url>>,
+        direction: SyncDirection,
+    ) -> KFResult<()> {
+        progress.info(&format!("Starting a sync ({:?}).", direction));
+        progress.feedback(SyncEvent::Started {
+            sync_id: progress.sync_id().to_string(),
+        });
+        if let Some(counter) = self.remote.transfers() {
+            progress.track_transfers(counter);
+        }
+        if let Some(ids) = self.remote.request_ids() {
+            progress.track_request_ids(ids);
+        }
+        progress.set_max_download_bytes(self.max_download_bytes);
+        progress.set_item_hooks(self.item_hooks.clone());
+        progress.set_validation_policy(self.validation_policy);
+        progress.set_destructive_change_guard(self.destructive_change_guard.clone());
+        progress.set_strict(self.strict);
+        if progress.has_feedback_channel() {
+            self.plan_sync(progress, only).await?;
+        }
 
         let mut handled_calendars = HashSet::new();
+        // Calendars that have been deleted from the server while they were being synced
+        let mut vanished_calendars = HashSet::new();
+        let ignored_calendars = self.local.ignored_calendars();
+        let excluded = |url: &Url| only.is_some_and(|only| !only.contains(url));
 
         // Sync every remote calendar
         let cals_remote = self.remote.get_calendars().await?;
-        for (cal_url, cal_remote) in cals_remote {
+        let local_cal_urls = canonical_forms(self.local.get_calendars().await?.into_keys());
+        for (remote_url, cal_remote) in cals_remote {
+            let cal_url = local_form(&remote_url, &local_cal_urls);
+            if excluded(&cal_url) {
+                continue;
+            }
+            if ignored_calendars.contains(&cal_url) {
+                // This is what the user asked for, this does not make the sync fail
+                progress.debug(&format!(
+                    "Calendar {} has been removed locally only, skipping it",
+                    cal_url
+                ));
+                continue;
+            }
+            if !direction.pulls() && self.local.get_calendar(&cal_url).await.is_none() {
+                progress.debug(&format!(
+                    "Calendar {} only exists on the remote, it is not pushed",
+                    cal_url
+                ));
+                continue;
+            }
             let counterpart = match self
                 .get_or_insert_local_counterpart_calendar(&cal_url, cal_remote.clone())
                 .await
             {
                 Err(err) => {
-                    progress.warn(&format!("Unable to get or insert local counterpart calendar for {} ({}). Skipping this time", cal_url, err));
+                    progress.skip(Skipped::Calendar {
+                        url: cal_url.clone(),
+                        reason: format!("unable to get or insert its local counterpart ({})", err),
+                    });
+                    continue;
+                }
+                Ok(None) => {
+                    // This is what the user asked for, this does not make the sync fail
+                    log::warn!("Remote calendar {} does not exist locally, and the calendar creation policy forbids creating it. Skipping it", cal_url);
                     continue;
                 }
-                Ok(arc) => arc,
+                Ok(Some(arc)) => arc,
             };
 
-            if let Err(err) = self
-                .sync_calendar_pair(counterpart, cal_remote, progress)
-                .await
-            {
-                progress.warn(&format!(
-                    "Unable to sync calendar {}: {}, skipping this time.",
-                    cal_url, err
-                ));
+            let result = self
+                .sync_calendar_pair(counterpart, cal_remote, progress, direction)
+                .await;
+            progress.calendar_finished();
+            self.local.sync_progressed();
+            if let Err(err) = result {
+                if let KFError::SyncAborted { .. } = err {
+                    return Err(err);
+                }
+                if is_calendar_not_found(&err, &remote_url) {
+                    // This is reconciled like any calendar that is missing from the server, see below
+                    progress.info(&format!(
+                        "Calendar {} has been deleted from the server during the sync",
+                        cal_url
+                    ));
+                    vanished_calendars.insert(cal_url);
+                    continue;
+                }
+                progress.skip(Skipped::Calendar {
+                    url: cal_url.clone(),
+                    reason: format!("unable to sync it ({})", err),
+                });
                 continue;
             }
             handled_calendars.insert(cal_url);
@@ -218,7 +736,7 @@ where
         // Sync every local calendar that would not be in the remote yet
         let cals_local = self.local.get_calendars().await?;
         for (cal_url, cal_local) in cals_local {
-            if handled_calendars.contains(&cal_url) {
+            if handled_calendars.contains(&cal_url) || excluded(&cal_url) {
                 continue;
             }
 
@@ -227,25 +745,96 @@ where
                 continue;
             }
 
+            let vanished = vanished_calendars.contains(&cal_url);
+            if cal_local.lock().await.has_been_synced().await
+                && (vanished || self.remote.get_calendar(&cal_url).await.is_none())
+            {
+                if !direction.pulls() {
+                    progress.debug(&format!(
+                        "Calendar {} has been deleted from the server, this is not pulled",
+                        cal_url
+                    ));
+                    continue;
+                }
+                let name = cal_local.lock().await.name().to_string();
+                let mut delete = match &self.remote_calendar_deletion_policy {
+                    RemoteCalendarDeletionPolicy::DeleteLocally => Some(true),
+                    RemoteCalendarDeletionPolicy::Confirm(confirm) => {
+                        Some(confirm(&cal_url, &name))
+                    }
+                    RemoteCalendarDeletionPolicy::Recreate => None,
+                };
+                if delete == Some(true) {
+                    let change = PlannedChange::DeleteLocalCalendar {
+                        url: cal_url.clone(),
+                        name,
+                    };
+                    delete = Some(allowed(progress, &change)?);
+                }
+                match delete {
+                    Some(true) => {
+                        progress.info(&format!(
+                            "Calendar {} has been deleted from the server, deleting it locally",
+                            cal_url
+                        ));
+                        self.local_mut().delete_calendar(&cal_url).await?;
+                        continue;
+                    }
+                    Some(false) => {
+                        log::warn!("Calendar {} has been deleted from the server, but its local deletion has not been confirmed. Skipping it", cal_url);
+                        continue;
+                    }
+                    None => (),
+                }
+            }
+            if vanished {
+                // The remote source may still list it until it discovers its calendars again
+                progress.skip(Skipped::Calendar {
+                    url: cal_url.clone(),
+                    reason: "it has been deleted from the server during the sync, the next sync will create it again".to_string(),
+                });
+                continue;
+            }
+            if !direction.pushes() {
+                progress.debug(&format!(
+                    "Calendar {} only exists locally, it is not pulled",
+                    cal_url
+                ));
+                continue;
+            }
+
             let counterpart = match self
                 .get_or_insert_remote_counterpart_calendar(&cal_url, cal_local.clone())
                 .await
             {
                 Err(err) => {
-                    progress.warn(&format!("Unable to get or insert remote counterpart calendar for {} ({}). Skipping this time", cal_url, err));
+                    progress.skip(Skipped::Calendar {
+                        url: cal_url.clone(),
+                        reason: format!("unable to get or insert its remote counterpart ({})", err),
+                    });
+                    continue;
+                }
+                Ok(None) => {
+                    // This is what the user asked for, this does not make the sync fail
+                    log::warn!("Local calendar {} does not exist on the remote, and the calendar creation policy forbids creating it. Skipping it", cal_url);
                     continue;
                 }
-                Ok(arc) => arc,
+                Ok(Some(arc)) => arc,
             };
 
-            if let Err(err) = self
-                .sync_calendar_pair(cal_local, counterpart, progress)
-                .await
-            {
-                progress.warn(&format!(
-                    "Unable to sync calendar {}: {}, skipping this time.",
-                    cal_url, err
-                ));
+            let result = self
+                .sync_calendar_pair(cal_local, counterpart, progress, direction)
+                .await;
+            progress.calendar_finished();
+            self.local.sync_progressed();
+            if let Err(err) = result {
+                if let KFError::SyncAborted { .. } = err {
+                    return Err(err);
+                }
+                progress.skip(Skipped::Calendar {
+                    url: cal_url.clone(),
+                    reason: format!("unable to sync it ({})", err),
+                });
                 continue;
             }
         }
@@ -255,19 +844,128 @@ where
         Ok(())
     }
 
+    /// Estimate how many items every calendar will handle, so that the sync can report its overall progress.
+    /// This lists the items of every remote calendar one more time, so this is only done when someone listens to the progress
+    async fn plan_sync(
+        &self,
+        progress: &mut SyncProgress,
+        only: Option<&HashSet<Url>>,
+    ) -> KFResult<()> {
+        let excluded = |url: &Url| only.is_some_and(|only| !only.contains(url));
+        let ignored_calendars = self.local.ignored_calendars();
+        let mut estimates: HashMap<Url, usize> = HashMap::new();
+        for (cal_url, cal_remote) in self.remote.get_calendars().await? {
+            if excluded(&cal_url) || ignored_calendars.contains(&cal_url) {
+                continue;
+            }
+            // Items that can't be listed now will fail the sync of this calendar later anyway
+            let remote_items = match cal_remote.lock().await.get_item_version_tags().await {
+                Ok(tags) => tags.len(),
+                Err(_) => 0,
+            };
+            estimates.insert(cal_url, remote_items);
+        }
+        for (cal_url, cal_local) in self.local.get_calendars().await? {
+            if excluded(&cal_url) || ignored_calendars.contains(&cal_url) {
+                continue;
+            }
+            let cal_local = cal_local.lock().await;
+            let pending = match cal_local.get_items().await {
+                Ok(items) => items
+                    .values()
+                    .filter(|item| !matches!(item.sync_status(), SyncStatus::Synced(_)))
+                    .count(),
+                Err(_) => 0,
+            };
+            *estimates.entry(cal_url).or_default() += pending;
+        }
+        for (cal_url, estimate) in &estimates {
+            progress.plan_calendar(cal_url, *estimate);
+        }
+        progress.debug(&format!(
+            "About {} items to sync in {} calendars",
+            estimates.values().sum::<usize>(),
+            estimates.len()
+        ));
+        Ok(())
+    }
+
     async fn get_or_insert_local_counterpart_calendar(
         &mut self,
         cal_url: &Url,
         needle: Arc<Mutex<U>>,
-    ) -> KFResult<Arc<Mutex<T>>> {
-        get_or_insert_counterpart_calendar("local", &mut self.local, cal_url, needle).await
+    ) -> KFResult<Option<Arc<Mutex<T>>>> {
+        let create = self.calendar_creation_policy.creates_local_calendars();
+        // A new local calendar gets the properties of the server, as if they had been synced already
+        let props = async {
+            match needle.lock().await.get_properties().await {
+                Err(err) => {
+                    log::warn!(
+                        "Unable to get the properties of remote calendar {}: {}. They will be downloaded by the property sync",
+                        cal_url,
+                        err
+                    );
+                    Vec::new()
+                }
+                Ok(props) => props
+                    .into_iter()
+                    .map(|mut prop| {
+                        prop.mark_synced_to_self();
+                        prop
+                    })
+                    .collect(),
+            }
+        };
+        get_or_insert_counterpart_calendar(
+            "local",
+            &mut self.local,
+            cal_url,
+            needle.clone(),
+            props,
+            create,
+        )
+        .await
+        .map(|cal| cal.map(|(cal, _)| cal))
     }
     async fn get_or_insert_remote_counterpart_calendar(
         &mut self,
         cal_url: &Url,
         needle: Arc<Mutex<T>>,
-    ) -> KFResult<Arc<Mutex<U>>> {
-        get_or_insert_counterpart_calendar("remote", &mut self.remote, cal_url, needle).await
+    ) -> KFResult<Option<Arc<Mutex<U>>>> {
+        let create = self.calendar_creation_policy.creates_remote_calendars();
+        let props = async {
+            needle
+                .lock()
+                .await
+                .get_properties()
+                .await
+                .values()
+                .filter(|prop| !matches!(prop.sync_status(), SyncStatus::LocallyDeleted(_)))
+                .cloned()
+                .collect()
+        };
+        let (cal, copied) = match get_or_insert_counterpart_calendar(
+            "remote",
+            &mut self.remote,
+            cal_url,
+            needle.clone(),
+            props,
+            create,
+        )
+        .await?
+        {
+            None => return Ok(None),
+            Some(found) => found,
+        };
+
+        // The properties that have been copied to the server are now in sync
+        let mut local = needle.lock().await;
+        for name in copied {
+            if let Some(prop) = local.get_property_by_name_mut(&name).await {
+                prop.mark_synced_to_self();
+            }
+        }
+        Ok(Some(cal))
     }
 
     async fn sync_calendar_pair(
@@ -275,21 +973,31 @@ where
         cal_local: Arc<Mutex<T>>,
         cal_remote: Arc<Mutex<U>>,
         progress: &mut SyncProgress,
+        direction: SyncDirection,
     ) -> KFResult<()> {
         let mut cal_remote = cal_remote.lock().await;
-        let mut cal_local = cal_local.lock().await;
+        let mut cal_local = LockedCalendar::lock(cal_local).await;
+        Self::update_calendar_metadata(&mut *cal_local, &*cal_remote, progress);
         let cal_name = cal_local.name().to_string();
 
         progress.info(&format!("Syncing calendar {}", cal_name));
-        progress.reset_counter();
-        progress.feedback(SyncEvent::ItemsInProgress {
-            calendar_name: cal_name.clone(),
-            items_done_already: 0,
-            details: "started".to_string(),
-        });
+        progress.calendar_started(cal_local.url());
+        let event = progress.items_in_progress(&cal_name, "started".to_string());
+        progress.feedback(event);
 
         // Step 0 - if the local calendar is marked for deletion, remove it from the remote and the local providers
         if cal_local.marked_for_deletion().await {
+            if !direction.pushes() {
+                // The next push will delete it
+                return Ok(());
+            }
+            let change = PlannedChange::DeleteRemoteCalendar {
+                url: cal_local.url().clone(),
+                name: cal_name,
+            };
+            if !allowed(progress, &change)? {
+                return Ok(());
+            }
             self.remote
                 .delete_calendar(cal_local.url())
                 .await
@@ -301,41 +1009,348 @@ where
             return Ok(());
         }
 
-        // Step 1 - find the differences
-        progress.debug("Finding the differences to sync...");
-
-        // - Step 1.1 - find the differences in items
-        let item_changes =
-            Self::calculate_item_changes(&cal_local, &cal_remote, progress, cal_name.clone())
-                .await?;
-
-        // - Step 1.2 - find the differences in properties
-        let prop_changes =
-            Self::calculate_prop_changes(&cal_local, &cal_remote, progress, cal_name.clone())
-                .await?;
-
-        log::debug!("Prop changes: {:?}", prop_changes);
-
-        // Step 2 - commit changes to tasks
-        Self::commit_item_changes(
+        if self.lock_remote_calendars {
+            Self::lock_remote_calendar(&mut cal_remote, progress).await?;
+        }
+        let first_sync = !cal_local.has_been_synced().await;
+        // This is asked for before the changes are listed, so that the remote changes made during the sync are seen by the next token check
+        let remote_token = match cal_remote.current_token().await {
+            Ok(token) => token,
+            Err(err) => {
+                progress.debug(&format!(
+                    "Unable to get the token of calendar {}: {}",
+                    cal_remote.url(),
+                    err
+                ));
+                None
+            }
+        };
+        let checkpoint = Checkpoint::new(&self.local, self.checkpoint_interval);
+        let skipped_before = progress.skipped().len();
+        // This stays set in case this future is dropped before the changes are applied, so that the half-synced calendar is not saved
+        cal_local.set_sync_in_progress(true);
+        let result = Self::sync_calendar_contents(
             &mut cal_local,
             &mut cal_remote,
             progress,
-            cal_name.clone(),
-            item_changes,
+            cal_name,
+            self.conflict_strategy,
+            direction,
+            checkpoint,
         )
-        .await?;
+        .await;
+        // Even a failed sync leaves every item in a consistent state
+        cal_local.set_sync_in_progress(false);
+        Self::update_item_sync_states(&mut *cal_local, &progress.skipped()[skipped_before..]).await;
+        let tracks_deletions = !matches!(
+            self.remote_calendar_deletion_policy,
+            RemoteCalendarDeletionPolicy::Recreate
+        );
+        if result.is_ok() && first_sync {
+            Self::detect_item_url_policy(&mut *cal_local, progress).await;
+        }
+        if result.is_ok() && tracks_deletions {
+            cal_local.mark_synced().await;
+        }
+        let pulled_everything = direction.pulls()
+            && progress.skipped().len() == skipped_before
+            && !progress.metrics().download_limit_reached;
+        if result.is_ok() && pulled_everything {
+            cal_local.set_remote_token(remote_token);
+        }
+        if self.lock_remote_calendars {
+            if let Err(err) = cal_remote.unlock().await {
+                progress.warn(&format!(
+                    "Unable to unlock remote calendar {}: {}",
+                    cal_remote.url(),
+                    err
+                ));
+            }
+        }
+        result
+    }
 
-        // Step 3 - commit changes to props
-        Self::commit_prop_changes(
-            &mut cal_local,
-            &mut cal_remote,
-            progress,
-            cal_name.clone(),
-            prop_changes,
-        )
-        .await?;
+    /// Record the items that this sync has failed to upload or download, and forget the past failures of the items that are now synced
+    async fn update_item_sync_states(cal_local: &mut T, skipped: &[Skipped]) {
+        let mut failed = HashSet::new();
+        for skipped in skipped {
+            if let Skipped::Items {
+                calendar,
+                urls,
+                reason,
+            } = skipped
+            {
+                if calendar != cal_local.url() {
+                    continue;
+                }
+                for url in urls {
+                    cal_local
+                        .record_item_sync_failure(url, reason.clone())
+                        .await;
+                    failed.insert(url.clone());
+                }
+            }
+        }
+        for url in cal_local.failing_items().await {
+            if failed.contains(&url) {
+                continue;
+            }
+            let synced = match cal_local.get_item_by_url(&url).await {
+                // It has been deleted, or it has never been downloaded
+                None => true,
+                Some(item) => matches!(item.sync_status(), SyncStatus::Synced(_)),
+            };
+            if synced {
+                cal_local.clear_item_sync_failures(&url).await;
+            }
+        }
+    }
+
+    /// Metadata (name, color, supported components) is only read when a local calendar is created. Update it in case it has changed on the server since then
+    fn update_calendar_metadata(cal_local: &mut T, cal_remote: &U, progress: &mut SyncProgress) {
+        let unchanged = cal_local.name() == cal_remote.name()
+            && cal_local.color() == cal_remote.color()
+            && cal_local.supported_components() == cal_remote.supported_components();
+        if unchanged {
+            return;
+        }
+        progress.debug(&format!(
+            "Updating the metadata of calendar {} (now named {})",
+            cal_local.url(),
+            cal_remote.name()
+        ));
+        cal_local.set_metadata(
+            cal_remote.name().to_string(),
+            cal_remote.supported_components(),
+            cal_remote.color().cloned(),
+        );
+    }
+
+    /// Unless it has been set already, guess how the URLs of new items should be composed from the items that are on the server
+    async fn detect_item_url_policy(cal_local: &mut T, progress: &mut SyncProgress) {
+        if cal_local.item_url_policy().is_some() {
+            return;
+        }
+        let items = match cal_local.get_items().await {
+            Ok(items) => items,
+            Err(err) => {
+                log::warn!(
+                    "Unable to detect the item URL policy of {}: {}",
+                    cal_local.url(),
+                    err
+                );
+                return;
+            }
+        };
+        let detected = ItemUrlPolicy::detect(
+            cal_local.url(),
+            items
+                .values()
+                .copied()
+                .filter(|item| matches!(item.sync_status(), SyncStatus::Synced(_))),
+        );
+        if let Some(policy) = detected {
+            progress.debug(&format!(
+                "Detected item URL policy {:?} for calendar {}",
+                policy,
+                cal_local.url()
+            ));
+            cal_local.set_item_url_policy(Some(policy));
+        }
+    }
+
+    /// Lock a remote calendar, waiting for other clients to release their own locks
+    async fn lock_remote_calendar(cal_remote: &mut U, progress: &mut SyncProgress) -> KFResult<()> {
+        let mut attempt = 1;
+        loop {
+            match cal_remote.lock().await {
+                Err(KFError::ResourceLocked { url }) if attempt < LOCK_ATTEMPTS => {
+                    progress.info(&format!(
+                        "Calendar {} is locked by another client, retrying in {:?}",
+                        url, LOCK_RETRY_DELAY
+                    ));
+                    sleep(LOCK_RETRY_DELAY).await;
+                    attempt += 1;
+                }
+                other => return other,
+            }
+        }
+    }
+
+    async fn sync_calendar_contents(
+        cal_local: &mut LockedCalendar<T>,
+        cal_remote: &mut U,
+        progress: &mut SyncProgress,
+        cal_name: String,
+        conflict_strategy: ConflictStrategy,
+        direction: SyncDirection,
+        checkpoint: Checkpoint<'_, L>,
+    ) -> KFResult<()> {
+        // Step 1 - find the differences
+        progress.debug("Finding the differences to sync...");
+
+        // - Step 1.1 - find the differences in items
+        let mut item_changes =
+            Self::calculate_item_changes(cal_local, cal_remote, progress, cal_name.clone()).await?;
+
+        // - Step 1.2 - find the differences in properties
+        let mut prop_changes =
+            Self::calculate_prop_changes(cal_local, cal_remote, progress, cal_name.clone()).await?;
+
+        // - Step 1.3 - only keep the changes of the requested direction
+        item_changes.restrict_to(direction);
+        prop_changes.restrict_to(direction);
+
+        // - Step 1.4 - do not push the local changes that have been reverted since the last sync
+        Self::coalesce_local_changes(cal_local, &mut item_changes, &mut prop_changes, progress)
+            .await;
+
+        // - Step 1.5 - let the application veto the changes that lose data
+        Self::guard_destructive_item_changes(cal_local, &mut item_changes, progress).await?;
+
+        // - Step 1.6 - strict syncs do not change anything in case something is off
+        progress.check_strict()?;
+
+        log::debug!("Prop changes: {:?}", prop_changes);
+
+        // Step 2 - commit changes to tasks
+        Self::commit_item_changes(
+            cal_local,
+            cal_remote,
+            progress,
+            cal_name.clone(),
+            item_changes,
+            conflict_strategy,
+            checkpoint,
+        )
+        .await?;
+        progress.check_strict()?;
 
+        // Step 3 - commit changes to props
+        Self::commit_prop_changes(
+            cal_local,
+            cal_remote,
+            progress,
+            cal_name.clone(),
+            prop_changes,
+        )
+        .await?;
+        progress.check_strict()?;
+
+        Ok(())
+    }
+
+    /// Forget about the local changes that would not change anything on the remote source (e.g. a task that has been renamed, then renamed back), and mark them as synced instead
+    async fn coalesce_local_changes(
+        cal_local: &mut T,
+        item_changes: &mut ItemChanges,
+        prop_changes: &mut PropChanges,
+        progress: &mut SyncProgress,
+    ) {
+        let mut reverted_items = Vec::new();
+        for url in &item_changes.local_item_changes {
+            if let Some(Item::Task(task)) = cal_local.get_item_by_url(url).await {
+                if task.local_changes_reverted() {
+                    reverted_items.push(url.clone());
+                }
+            }
+        }
+        for url in reverted_items {
+            if let Some(Item::Task(task)) = cal_local.get_item_by_url_mut(&url).await {
+                task.discard_reverted_changes();
+            }
+            progress.debug(&format!(
+                "*   {} has been changed back, it is not pushed",
+                url
+            ));
+            item_changes.local_item_changes.remove(&url);
+        }
+
+        let mut reverted_props = Vec::new();
+        for nsn in &prop_changes.local_prop_changes {
+            if let Some(prop) = cal_local.get_property_by_name_mut(nsn).await {
+                if let SyncStatus::LocallyModified(tag) = prop.sync_status() {
+                    // The version tag of a property is its value
+                    if tag.as_str() == prop.value().as_str() {
+                        prop.mark_synced_to_self();
+                        reverted_props.push(nsn.clone());
+                    }
+                }
+            }
+        }
+        for nsn in reverted_props {
+            progress.debug(&format!(
+                "*   {} has been changed back, it is not pushed",
+                nsn
+            ));
+            prop_changes.local_prop_changes.remove(&nsn);
+        }
+    }
+
+    /// Forget about the destructive item changes that the [`DestructiveChangeGuard`] of this sync skips.
+    /// Fails in case it aborts the sync
+    async fn guard_destructive_item_changes(
+        cal_local: &T,
+        changes: &mut ItemChanges,
+        progress: &mut SyncProgress,
+    ) -> KFResult<()> {
+        let mut local_deletions = Vec::new();
+        for url in &changes.remote_item_dels {
+            // Items that have been deleted on both ends are no loss
+            if let Some(SyncStatus::LocallyDeleted(_)) = cal_local
+                .get_item_by_url(url)
+                .await
+                .map(|i| i.sync_status())
+            {
+                continue;
+            }
+            local_deletions.push(url.clone());
+        }
+        let overwritten = changes
+            .conflicting_local_versions
+            .keys()
+            .filter(|url| changes.remote_item_changes.contains(*url))
+            .cloned()
+            .sorted()
+            .collect();
+        let remote_deletions = changes.local_item_dels.iter().cloned().sorted().collect();
+        local_deletions.sort();
+
+        let calendar = cal_local.url().clone();
+        for change in [
+            PlannedChange::DeleteLocalItems {
+                calendar: calendar.clone(),
+                urls: local_deletions,
+            },
+            PlannedChange::DeleteRemoteItems {
+                calendar: calendar.clone(),
+                urls: remote_deletions,
+            },
+            PlannedChange::OverwriteLocalChanges {
+                calendar: calendar.clone(),
+                urls: overwritten,
+            },
+        ] {
+            if change.item_urls().is_empty() || allowed(progress, &change)? {
+                continue;
+            }
+            for url in change.item_urls() {
+                match change {
+                    PlannedChange::DeleteLocalItems { .. } => {
+                        changes.remote_item_dels.remove(url);
+                        changes.conflicting_local_versions.remove(url);
+                    }
+                    PlannedChange::DeleteRemoteItems { .. } => {
+                        changes.local_item_dels.remove(url);
+                    }
+                    PlannedChange::OverwriteLocalChanges { .. } => {
+                        changes.remote_item_changes.remove(url);
+                        changes.conflicting_local_versions.remove(url);
+                    }
+                    _ => (),
+                }
+            }
+        }
         Ok(())
     }
 
@@ -352,13 +1367,18 @@ where
         let mut remote_item_changes = HashSet::new();
         let mut local_item_additions = HashSet::new();
         let mut remote_item_additions = HashSet::new();
+        let mut conflicting_local_versions = HashMap::new();
 
-        let remote_items = cal_remote.get_item_version_tags().await?;
-        progress.feedback(SyncEvent::ItemsInProgress {
-            calendar_name: cal_name.clone(),
-            items_done_already: 0,
-            details: format!("{} remote items", remote_items.len()),
-        });
+        let local_urls = canonical_forms(cal_local.get_item_urls().await?);
+        let remote_items: HashMap<Url, VersionTag> = cal_remote
+            .get_item_version_tags()
+            .await?
+            .into_iter()
+            .map(|(url, tag)| (local_form(&url, &local_urls), tag))
+            .collect();
+        let event =
+            progress.items_in_progress(&cal_name, format!("{} remote items", remote_items.len()));
+        progress.feedback(event);
 
         let mut local_items_to_handle = cal_local.get_item_urls().await?;
         for (url, remote_tag) in remote_items {
@@ -371,15 +1391,25 @@ where
                 }
                 Some(local_item) => {
                     if !local_items_to_handle.remove(&url) {
-                        progress.error(&format!(
-                            "Inconsistent state: missing task {} from the local tasks",
-                            url
-                        ));
+                        progress.issue(SyncIssue::Inconsistency {
+                            calendar: cal_local.url().clone(),
+                            url: url.clone(),
+                            property: None,
+                            expected: "listed among the local items".to_string(),
+                            found: "missing from the list".to_string(),
+                        });
                     }
 
                     match local_item.sync_status() {
                         SyncStatus::NotSynced => {
-                            progress.error(&format!("URL reuse between remote and local sources ({}). Ignoring this item in the sync", url));
+                            // URL reuse between remote and local sources. This item is ignored in the sync
+                            progress.issue(SyncIssue::Inconsistency {
+                                calendar: cal_local.url().clone(),
+                                url: url.clone(),
+                                property: None,
+                                expected: "a synced local item".to_string(),
+                                found: "a local item that has never been synced".to_string(),
+                            });
                             continue;
                         }
                         SyncStatus::Synced(local_tag) => {
@@ -396,8 +1426,10 @@ where
                                 local_item_changes.insert(url);
                             } else {
                                 progress.info(&format!("Conflict: task {} has been modified in both sources. Using the remote version.", url));
+                                progress.record_conflict(&url);
                                 progress
                                     .debug(&format!("*   {} is considered a remote change", url));
+                                conflicting_local_versions.insert(url.clone(), local_item.clone());
                                 remote_item_changes.insert(url);
                             }
                         }
@@ -408,6 +1440,7 @@ where
                                 local_item_dels.insert(url);
                             } else {
                                 progress.info(&format!("Conflict: task {} has been locally deleted and remotely modified. Reverting to the remote version.", url));
+                                progress.record_conflict(&url);
                                 progress
                                     .debug(&format!("*   {} is a considered a remote change", url));
                                 remote_item_changes.insert(url);
@@ -423,10 +1456,13 @@ where
             progress.trace(&format!("##### Considering local item {}...", url));
             let local_item = match cal_local.get_item_by_url(&url).await {
                 None => {
-                    progress.error(&format!(
-                        "Inconsistent state: missing task {} from the local tasks",
-                        url
-                    ));
+                    progress.issue(SyncIssue::Inconsistency {
+                        calendar: cal_local.url().clone(),
+                        url: url.clone(),
+                        property: None,
+                        expected: "a local item".to_string(),
+                        found: "listed, but missing".to_string(),
+                    });
                     continue;
                 }
                 Some(item) => item,
@@ -451,6 +1487,8 @@ where
                 }
                 SyncStatus::LocallyModified(_) => {
                     progress.info(&format!("Conflict: item {} has been deleted from the server and locally modified. Deleting the local copy", url));
+                    progress.record_conflict(&url);
+                    conflicting_local_versions.insert(url.clone(), local_item.clone());
                     remote_item_dels.insert(url);
                 }
             }
@@ -463,6 +1501,8 @@ where
             remote_item_changes,
             local_item_additions,
             remote_item_additions,
+            conflicting_local_versions,
+            push_kept_local_versions: true,
         })
     }
 
@@ -506,17 +1546,27 @@ where
                 Some(local_prop) => {
                     debug_assert_eq!(remote_prop.nsn(), local_prop.nsn());
                     if local_props_to_handle.remove(remote_prop.nsn()).is_none() {
-                        progress.error(&format!(
-                            "Inconsistent state: missing prop {} from the local props",
-                            remote_prop
-                        ));
+                        progress.issue(SyncIssue::Inconsistency {
+                            calendar: cal_local.url().clone(),
+                            url: cal_local.url().clone(),
+                            property: Some(remote_prop.nsn().clone()),
+                            expected: "listed among the local props".to_string(),
+                            found: "missing from the list".to_string(),
+                        });
                     }
 
                     let prop_name: NamespacedName = local_prop.clone().into();
 
                     match local_prop.sync_status() {
                         SyncStatus::NotSynced => {
-                            progress.error(&format!("Property reuse between remote and local sources ({}). Ignoring this item in the sync", prop_name));
+                            // Property reuse between remote and local sources. This prop is ignored in the sync
+                            progress.issue(SyncIssue::Inconsistency {
+                                calendar: cal_local.url().clone(),
+                                url: cal_local.url().clone(),
+                                property: Some(prop_name),
+                                expected: "a synced local prop".to_string(),
+                                found: "a local prop that has never been synced".to_string(),
+                            });
                             continue;
                         }
                         SyncStatus::Synced(local_tag) => {
@@ -602,41 +1652,54 @@ where
 
     /// Based on the delta between local and remote, make whatever changes are necessary to bring the two sources into sync
     async fn commit_item_changes(
-        cal_local: &mut T,
+        cal_local: &mut LockedCalendar<T>,
         cal_remote: &mut U,
         progress: &mut SyncProgress,
         cal_name: String,
         item_changes: ItemChanges,
+        conflict_strategy: ConflictStrategy,
+        checkpoint: Checkpoint<'_, L>,
     ) -> KFResult<()> {
         let ItemChanges {
             local_item_dels,
             remote_item_dels,
-            local_item_changes,
+            mut local_item_changes,
             remote_item_changes,
             local_item_additions,
             remote_item_additions,
+            mut conflicting_local_versions,
+            push_kept_local_versions,
         } = item_changes;
+        progress.set_items_total(
+            local_item_dels.len()
+                + remote_item_dels.len()
+                + local_item_changes.len()
+                + remote_item_changes.len()
+                + local_item_additions.len()
+                + remote_item_additions.len(),
+        );
         progress.trace("Committing changes to tasks...");
+        let mut names = Self::item_names(cal_local, &local_item_dels).await;
         for url_del in local_item_dels {
             progress.debug(&format!(
                 "> Pushing local deletion {} to the server",
                 url_del
             ));
             progress.increment_counter(1);
-            progress.feedback(SyncEvent::ItemsInProgress {
-                calendar_name: cal_name.clone(),
-                items_done_already: progress.counter(),
-                details: Self::item_name(cal_local, &url_del).await,
-            });
+            let name = names.remove(&url_del).unwrap_or_default();
+            let event = progress.items_in_progress(&cal_name, name);
+            progress.feedback(event);
 
             match cal_remote.delete_item(&url_del).await {
                 Err(err) => {
-                    progress.warn(&format!(
-                        "Unable to delete remote item {}: {}",
-                        url_del, err
-                    ));
+                    progress.skip(Skipped::Items {
+                        calendar: cal_local.url().clone(),
+                        urls: vec![url_del.clone()],
+                        reason: format!("unable to delete it from the server ({})", err),
+                    });
                 }
                 Ok(()) => {
+                    progress.summary_mut().local_deletions += 1;
                     // Change the local copy from "marked to deletion" to "actually deleted"
                     if let Err(err) = cal_local.immediately_delete_item(&url_del).await {
                         progress.error(&format!(
@@ -648,16 +1711,25 @@ where
             }
         }
 
+        let mut names = Self::item_names(cal_local, &remote_item_dels).await;
         for url_del in remote_item_dels {
             progress.debug(&format!("> Applying remote deletion {} locally", url_del));
             progress.increment_counter(1);
-            progress.feedback(SyncEvent::ItemsInProgress {
-                calendar_name: cal_name.clone(),
-                items_done_already: progress.counter(),
-                details: Self::item_name(cal_local, &url_del).await,
-            });
-            if let Err(err) = cal_local.immediately_delete_item(&url_del).await {
-                progress.warn(&format!("Unable to delete local item {}: {}", url_del, err));
+            let name = names.remove(&url_del).unwrap_or_default();
+            let event = progress.items_in_progress(&cal_name, name);
+            progress.feedback(event);
+            if let Some(local_version) = conflicting_local_versions.remove(&url_del) {
+                cal_local
+                    .record_conflict(Conflict::new(local_version, None))
+                    .await;
+            }
+            match cal_local.immediately_delete_item(&url_del).await {
+                Err(err) => progress.skip(Skipped::Items {
+                    calendar: cal_local.url().clone(),
+                    urls: vec![url_del.clone()],
+                    reason: format!("unable to delete it locally ({})", err),
+                }),
+                Ok(()) => progress.summary_mut().remote_deletions += 1,
             }
         }
 
@@ -667,78 +1739,144 @@ where
             &mut *cal_remote,
             progress,
             &cal_name,
+            checkpoint,
         )
         .await;
 
-        Self::apply_remote_item_changes(
+        let kept_local_versions = Self::apply_remote_item_changes(
             remote_item_changes,
+            &mut conflicting_local_versions,
+            conflict_strategy,
             &mut *cal_local,
             &mut *cal_remote,
             progress,
             &cal_name,
         )
         .await;
+        if !push_kept_local_versions {
+            // These will be uploaded by the next push
+            return Ok(());
+        }
+        // These will be uploaded as well
+        let kept = kept_local_versions
+            .iter()
+            .filter(|url| !local_item_changes.contains(*url))
+            .count();
+        if let Some(total) = progress.items_total() {
+            progress.set_items_total(total + kept);
+        }
+        local_item_changes.extend(kept_local_versions);
 
+        let mut names = Self::item_names(cal_local, &local_item_additions).await;
         for url_add in local_item_additions {
             progress.debug(&format!(
                 "> Pushing local addition {} to the server",
                 url_add
             ));
             progress.increment_counter(1);
-            progress.feedback(SyncEvent::ItemsInProgress {
-                calendar_name: cal_name.clone(),
-                items_done_already: progress.counter(),
-                details: Self::item_name(cal_local, &url_add).await,
-            });
-            match cal_local.get_item_by_url_mut(&url_add).await {
+            let name = names.remove(&url_add).unwrap_or_default();
+            let event = progress.items_in_progress(&cal_name, name);
+            progress.feedback(event);
+            let (upload, generation) = match cal_local.get_item_by_url(&url_add).await {
                 None => {
-                    progress.error(&format!("Inconsistency: created item {} has been marked for upload but is locally missing", url_add));
+                    progress.issue(SyncIssue::Inconsistency {
+                        calendar: cal_local.url().clone(),
+                        url: url_add.clone(),
+                        property: None,
+                        expected: "a local addition to upload".to_string(),
+                        found: "missing locally".to_string(),
+                    });
                     continue;
                 }
-                Some(item) => {
-                    match cal_remote.add_item(item.clone()).await {
-                        Err(err) => progress.error(&format!(
-                            "Unable to add item {} to remote calendar: {}",
-                            url_add, err
-                        )),
-                        Ok(new_ss) => {
-                            // Update local sync status
-                            item.set_sync_status(new_ss);
-                        }
+                Some(item) => match Self::item_to_upload(item, progress).await {
+                    Ok(upload) => (upload, item.generation()),
+                    Err(reason) => {
+                        progress.skip(Skipped::Items {
+                            calendar: cal_local.url().clone(),
+                            urls: vec![url_add.clone()],
+                            reason,
+                        });
+                        continue;
                     }
-                }
+                },
             };
+            // The application may change the item in the meantime
+            match cal_local.unlocked(cal_remote.add_item(upload)).await {
+                Err(KFError::UnsupportedComponent {
+                    calendar,
+                    url,
+                    type_,
+                }) => progress.issue(SyncIssue::UnsupportedComponent {
+                    calendar: *calendar,
+                    url: *url,
+                    type_,
+                }),
+                Err(err) => progress.skip(Skipped::Items {
+                    calendar: cal_local.url().clone(),
+                    urls: vec![url_add.clone()],
+                    reason: format!("unable to add it to the server ({})", err),
+                }),
+                Ok(new_ss) => {
+                    Self::finalize_upload(cal_local, &url_add, generation, new_ss, progress).await;
+                    progress.summary_mut().local_additions += 1;
+                }
+            }
         }
 
+        let mut names = Self::item_names(cal_local, &local_item_changes).await;
         for url_change in local_item_changes {
             progress.debug(&format!(
                 "> Pushing local change {} to the server",
                 url_change
             ));
             progress.increment_counter(1);
-            progress.feedback(SyncEvent::ItemsInProgress {
-                calendar_name: cal_name.clone(),
-                items_done_already: progress.counter(),
-                details: Self::item_name(cal_local, &url_change).await,
-            });
-            match cal_local.get_item_by_url_mut(&url_change).await {
+            let name = names.remove(&url_change).unwrap_or_default();
+            let event = progress.items_in_progress(&cal_name, name);
+            progress.feedback(event);
+            let (upload, generation) = match cal_local.get_item_by_url(&url_change).await {
                 None => {
-                    progress.error(&format!("Inconsistency: modified item {} has been marked for upload but is locally missing", url_change));
+                    progress.issue(SyncIssue::Inconsistency {
+                        calendar: cal_local.url().clone(),
+                        url: url_change.clone(),
+                        property: None,
+                        expected: "a local change to upload".to_string(),
+                        found: "missing locally".to_string(),
+                    });
                     continue;
                 }
-                Some(item) => {
-                    match cal_remote.update_item(item.clone()).await {
-                        Err(err) => progress.error(&format!(
-                            "Unable to update item {} in remote calendar: {}",
-                            url_change, err
-                        )),
-                        Ok(new_ss) => {
-                            // Update local sync status
-                            item.set_sync_status(new_ss);
-                        }
-                    };
-                }
+                Some(item) => match Self::item_to_upload(item, progress).await {
+                    Ok(upload) => (upload, item.generation()),
+                    Err(reason) => {
+                        progress.skip(Skipped::Items {
+                            calendar: cal_local.url().clone(),
+                            urls: vec![url_change.clone()],
+                            reason,
+                        });
+                        continue;
+                    }
+                },
             };
+            match cal_local.unlocked(cal_remote.update_item(upload)).await {
+                Err(KFError::UnsupportedComponent {
+                    calendar,
+                    url,
+                    type_,
+                }) => progress.issue(SyncIssue::UnsupportedComponent {
+                    calendar: *calendar,
+                    url: *url,
+                    type_,
+                }),
+                Err(err) => progress.skip(Skipped::Items {
+                    calendar: cal_local.url().clone(),
+                    urls: vec![url_change.clone()],
+                    reason: format!("unable to update it on the server ({})", err),
+                }),
+                Ok(new_ss) => {
+                    Self::finalize_upload(cal_local, &url_change, generation, new_ss, progress)
+                        .await;
+                    progress.summary_mut().local_changes += 1;
+                }
+            }
         }
 
         Ok(())
@@ -763,37 +1901,6 @@ where
         } = prop_changes;
         progress.trace("Committing changes to props...");
 
-        for prop_del in local_prop_dels {
-            progress.debug(&format!(
-                "> Pushing local prop deletion {} to the server",
-                prop_del
-            ));
-            progress.increment_counter(1);
-            progress.feedback(SyncEvent::PropsInProgress {
-                calendar_name: cal_name.clone(),
-                props_done_already: progress.counter(),
-                details: format!("{}", prop_del),
-            });
-
-            match cal_remote.delete_property(&prop_del).await {
-                Err(err) => {
-                    progress.warn(&format!(
-                        "Unable to delete remote prop {}: {}",
-                        prop_del, err
-                    ));
-                }
-                Ok(()) => {
-                    // Change the local copy from "marked to deletion" to "actually deleted"
-                    if let Err(err) = cal_local.immediately_delete_prop(&prop_del).await {
-                        progress.error(&format!(
-                            "Unable to permanently delete local prop {}: {}",
-                            prop_del, err
-                        ));
-                    }
-                }
-            }
-        }
-
         for prop_del in remote_prop_dels {
             progress.debug(&format!("> Applying remote deletion {} locally", prop_del));
             progress.increment_counter(1);
@@ -821,186 +1928,501 @@ where
         Self::apply_remote_prop_changes(remote_prop_changes, &mut *cal_local, progress, &cal_name)
             .await;
 
-        for prop_add in local_prop_additions {
+        let mut local_prop_dels = local_prop_dels;
+        if !local_prop_dels.is_empty() {
+            let change = PlannedChange::DeleteRemoteProps {
+                calendar: cal_local.url().clone(),
+                names: local_prop_dels
+                    .iter()
+                    .cloned()
+                    .sorted_by_key(|nsn| nsn.to_string())
+                    .collect(),
+            };
+            if !allowed(progress, &change)? {
+                local_prop_dels.clear();
+            }
+        }
+
+        Self::push_local_prop_changes(
+            local_prop_dels,
+            local_prop_additions,
+            local_prop_changes,
+            &mut *cal_local,
+            &mut *cal_remote,
+            progress,
+            &cal_name,
+        )
+        .await;
+
+        Ok(())
+    }
+
+    /// Push every local prop deletion, addition and change to the server, in a single batch
+    async fn push_local_prop_changes(
+        local_prop_dels: HashSet<NamespacedName>,
+        local_prop_additions: HashSet<Property>,
+        local_prop_changes: HashSet<NamespacedName>,
+        cal_local: &mut T,
+        cal_remote: &mut U,
+        progress: &mut SyncProgress,
+        cal_name: &str,
+    ) {
+        let mut to_set = Vec::new();
+        for nsn in local_prop_additions
+            .iter()
+            .map(|p| p.nsn())
+            .chain(local_prop_changes.iter())
+        {
+            match cal_local.get_property_by_name(nsn).await {
+                None => progress.issue(SyncIssue::Inconsistency {
+                    calendar: cal_local.url().clone(),
+                    url: cal_local.url().clone(),
+                    property: Some(nsn.clone()),
+                    expected: "a local prop to upload".to_string(),
+                    found: "missing locally".to_string(),
+                }),
+                Some(local_prop) => to_set.push(local_prop.clone()),
+            }
+        }
+        let to_remove: Vec<NamespacedName> = local_prop_dels.into_iter().collect();
+        if to_set.is_empty() && to_remove.is_empty() {
+            return;
+        }
+
+        for nsn in &to_remove {
             progress.debug(&format!(
-                "> Pushing local addition {} to the server",
-                prop_add
+                "> Pushing local prop deletion {} to the server",
+                nsn
             ));
-            progress.increment_counter(1);
-            progress.feedback(SyncEvent::PropsInProgress {
-                calendar_name: cal_name.clone(),
-                props_done_already: progress.counter(),
-                details: format!("{}", prop_add),
-            });
+        }
+        for prop in &to_set {
+            progress.debug(&format!("> Pushing local prop {} to the server", prop));
+        }
+        let batch_size = to_set.len() + to_remove.len();
+        let batch_names: Vec<NamespacedName> = to_set
+            .iter()
+            .map(|p| p.nsn().clone())
+            .chain(to_remove.iter().cloned())
+            .collect();
 
-            match cal_local.get_property_by_name_mut(prop_add.nsn()).await {
-                None => {
-                    progress.error(&format!("Inconsistency: created prop {} has been marked for upload but is locally missing", prop_add));
-                    continue;
+        let outcome = match cal_remote.patch_properties(to_set, to_remove).await {
+            Err(err) => {
+                progress.warn(&format!(
+                    "Unable to push {} local prop changes to the server: {}",
+                    batch_size, err
+                ));
+                return;
+            }
+            Ok(outcome) => outcome,
+        };
+        progress.increment_counter(batch_size);
+        progress.feedback(SyncEvent::PropsInProgress {
+            calendar_name: cal_name.to_string(),
+            props_done_already: progress.counter(),
+            details: batch_names.iter().join(", "),
+        });
+
+        for (nsn, result) in outcome.removed {
+            match result {
+                Err(err) => {
+                    progress.warn(&format!("Unable to delete remote prop {}: {}", nsn, err));
                 }
-                Some(local_prop) => {
-                    match cal_remote.set_property(local_prop.clone()).await {
-                        Err(err) => progress.error(&format!(
-                            "Unable to add prop {} to remote calendar: {}",
-                            prop_add, err
-                        )),
-                        Ok(ss) => {
-                            // Update local sync status
-                            local_prop.set_sync_status(ss);
-                        }
+                Ok(()) => {
+                    // Change the local copy from "marked to deletion" to "actually deleted"
+                    if let Err(err) = cal_local.immediately_delete_prop(&nsn).await {
+                        progress.error(&format!(
+                            "Unable to permanently delete local prop {}: {}",
+                            nsn, err
+                        ));
                     }
                 }
-            };
+            }
         }
 
-        for prop_change in local_prop_changes {
-            progress.debug(&format!(
-                "> Pushing local change {} to the server",
-                prop_change
-            ));
-            progress.increment_counter(1);
-            progress.feedback(SyncEvent::PropsInProgress {
-                calendar_name: cal_name.clone(),
-                props_done_already: progress.counter(),
-                details: format!("{}", prop_change),
-            });
-            match cal_local.get_property_by_name_mut(&prop_change).await {
-                None => {
-                    progress.error(&format!("Inconsistency: modified prop {} has been marked for upload but is locally missing", prop_change));
-                    continue;
-                }
-                Some(local_prop) => {
-                    match cal_remote.set_property(local_prop.clone()).await {
-                        Err(err) => progress.error(&format!(
-                            "Unable to update prop {} in remote calendar: {}",
-                            prop_change, err
-                        )),
-                        Ok(ss) => {
-                            // Update local sync status
-                            local_prop.set_sync_status(ss);
-                        }
-                    };
+        for (nsn, result) in outcome.set {
+            match result {
+                Err(err) => progress.error(&format!(
+                    "Unable to set prop {} in remote calendar: {}",
+                    nsn, err
+                )),
+                Ok(ss) => {
+                    // Update local sync status
+                    if let Some(local_prop) = cal_local.get_property_by_name_mut(&nsn).await {
+                        local_prop.set_sync_status(ss);
+                    }
                 }
-            };
+            }
         }
+    }
 
-        Ok(())
+    /// Mark an uploaded item as synced to `new_ss`, unless it has been modified while it was being uploaded (i.e. its generation is not `generation` any more, see [`Item::generation`]).
+    /// Such an item keeps its new version, as a local change (or deletion) of the uploaded version, so that the next sync pushes it
+    async fn finalize_upload(
+        cal_local: &mut T,
+        url: &Url,
+        generation: u64,
+        new_ss: SyncStatus,
+        progress: &mut SyncProgress,
+    ) {
+        let item = match cal_local.get_item_by_url_mut(url).await {
+            None => {
+                progress.debug(&format!(
+                    "Item {} has been removed locally while it was being uploaded",
+                    url
+                ));
+                return;
+            }
+            Some(item) => item,
+        };
+        let locally_deleted = matches!(item.sync_status(), SyncStatus::LocallyDeleted(_));
+        if item.generation() == generation && !locally_deleted {
+            item.set_sync_status(new_ss);
+            return;
+        }
+        let tag = match new_ss.version_tag() {
+            Some(tag) => tag.clone(),
+            // Without the version tag of the uploaded version, the item is left as it is, and will be uploaded again
+            None => return,
+        };
+        progress.info(&format!(
+            "Item {} has been modified while it was being uploaded, its new version will be pushed by the next sync",
+            url
+        ));
+        item.relabel_sync_status(if locally_deleted {
+            SyncStatus::LocallyDeleted(tag)
+        } else {
+            SyncStatus::LocallyModified(tag)
+        });
+        progress
+            .summary_mut()
+            .modified_during_sync
+            .push(url.clone());
     }
 
-    async fn item_name(cal: &T, url: &Url) -> String {
-        cal.get_item_by_url(url)
-            .await
-            .map(|item| item.name())
-            .unwrap_or_default()
-            .to_string()
+    /// The copy of a local item that is uploaded, once the hooks have been called and it has been validated.
+    /// Returns why it must not be uploaded in case it is invalid
+    async fn item_to_upload(item: &Item, progress: &mut SyncProgress) -> Result<Item, String> {
+        let mut upload = item.clone();
+        if let Some(hooks) = progress.item_hooks() {
+            hooks.before_upload(&mut upload).await;
+        }
+        let issues = match progress.validation_policy() {
+            ValidationPolicy::Off => Vec::new(),
+            ValidationPolicy::Warn => {
+                for issue in ical::validate(&upload) {
+                    progress.warn(&format!(
+                        "Uploading invalid item {}: {}",
+                        upload.url(),
+                        issue
+                    ));
+                }
+                Vec::new()
+            }
+            ValidationPolicy::Reject => ical::validate(&upload),
+            ValidationPolicy::FixUp => ical::fix_up(&mut upload),
+        };
+        if issues.is_empty() {
+            Ok(upload)
+        } else {
+            let issues: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+            Err(format!("invalid item ({})", issues.join(", ")))
+        }
+    }
+
+    /// The names of the items of `cal` at `urls`, looked up at once. Items that do not exist are missing from the result
+    async fn item_names<'u, I>(cal: &T, urls: I) -> HashMap<Url, String>
+    where
+        I: IntoIterator<Item = &'u Url>,
+    {
+        let urls: Vec<Url> = urls.into_iter().cloned().collect();
+        let items = cal.get_items_by_urls(&urls).await;
+        urls.into_iter()
+            .zip(items)
+            .filter_map(|(url, item)| Some((url, item?.name().to_string())))
+            .collect()
     }
 
     async fn apply_remote_item_additions(
-        mut remote_additions: HashSet<Url>,
+        remote_additions: HashSet<Url>,
         cal_local: &mut T,
         cal_remote: &mut U,
         progress: &mut SyncProgress,
         cal_name: &str,
+        mut checkpoint: Checkpoint<'_, L>,
     ) {
-        for batch in remote_additions
-            .drain()
-            .chunks(DOWNLOAD_BATCH_SIZE)
-            .into_iter()
-        {
-            Self::fetch_batch_and_apply_items(
-                BatchDownloadType::RemoteAdditions,
-                batch,
-                cal_local,
-                cal_remote,
-                progress,
-                cal_name,
-            )
-            .await;
-        }
+        Self::fetch_and_apply_batches(
+            BatchDownloadType::RemoteAdditions,
+            remote_additions,
+            None,
+            cal_local,
+            cal_remote,
+            progress,
+            cal_name,
+            Some(&mut checkpoint),
+        )
+        .await;
     }
 
+    /// Returns the URLs of the conflicting items whose local version has been kept (see `apply_batch`), that must be pushed to the server
     async fn apply_remote_item_changes(
-        mut remote_changes: HashSet<Url>,
+        remote_changes: HashSet<Url>,
+        conflicting_local_versions: &mut HashMap<Url, Item>,
+        conflict_strategy: ConflictStrategy,
         cal_local: &mut T,
         cal_remote: &mut U,
         progress: &mut SyncProgress,
         cal_name: &str,
-    ) {
-        for batch in remote_changes
-            .drain()
+    ) -> HashSet<Url> {
+        Self::fetch_and_apply_batches(
+            BatchDownloadType::RemoteChanges,
+            remote_changes,
+            Some((conflicting_local_versions, conflict_strategy)),
+            cal_local,
+            cal_remote,
+            progress,
+            cal_name,
+            None,
+        )
+        .await
+    }
+
+    /// Download the items by batches, and apply them locally.
+    ///
+    /// This is pipelined: the next batch is downloaded while the current one is applied, so that the latency of the remote source is (mostly) hidden by the local work.
+    /// At most one batch is downloaded ahead, so that memory usage stays bounded
+    #[allow(clippy::too_many_arguments)]
+    async fn fetch_and_apply_batches(
+        batch_type: BatchDownloadType,
+        urls: HashSet<Url>,
+        mut conflicts: Option<(&mut HashMap<Url, Item>, ConflictStrategy)>,
+        cal_local: &mut T,
+        cal_remote: &mut U,
+        progress: &mut SyncProgress,
+        cal_name: &str,
+        mut checkpoint: Option<&mut Checkpoint<'_, L>>,
+    ) -> HashSet<Url> {
+        let mut kept_local_versions = HashSet::new();
+        let cal_remote = &*cal_remote;
+        let mut batches = urls
+            .into_iter()
             .chunks(DOWNLOAD_BATCH_SIZE)
             .into_iter()
-        {
-            Self::fetch_batch_and_apply_items(
-                BatchDownloadType::RemoteChanges,
-                batch,
+            .map(|batch| batch.collect::<Vec<Url>>())
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        let mut downloaded = match Self::next_batch(&mut batches, progress) {
+            None => return kept_local_versions,
+            Some(urls) => Self::download_batch(cal_remote, urls).await,
+        };
+        loop {
+            let next_urls = Self::next_batch(&mut batches, progress);
+            let batch_len = downloaded.urls.len();
+            progress.debug(&format!(
+                "> Applying a batch of {} {} locally",
+                batch_len, batch_type
+            ));
+            let next_download = async {
+                match next_urls {
+                    Some(urls) => Some(Self::download_batch(cal_remote, urls).await),
+                    None => None,
+                }
+            };
+            let apply = Self::apply_batch(
+                batch_type,
+                downloaded,
+                conflicts
+                    .as_mut()
+                    .map(|(versions, strategy)| (&mut **versions, *strategy)),
                 cal_local,
-                cal_remote,
                 progress,
                 cal_name,
-            )
-            .await;
+            );
+            let (next, kept) = futures_util::future::join(next_download, apply).await;
+            kept_local_versions.extend(kept);
+            if let Some(checkpoint) = checkpoint.as_mut() {
+                checkpoint.items_applied(batch_len, cal_local).await;
+            }
+            downloaded = match next {
+                None => return kept_local_versions,
+                Some(next) => next,
+            };
+        }
+    }
+
+    /// The URLs of the next batch to download, unless the download limit has been reached (in which case these items will be downloaded by the next syncs)
+    fn next_batch(
+        batches: &mut impl Iterator<Item = Vec<Url>>,
+        progress: &mut SyncProgress,
+    ) -> Option<Vec<Url>> {
+        if progress.download_limit_reached() {
+            return None;
         }
+        batches.next()
     }
 
-    async fn fetch_batch_and_apply_items<I: Iterator<Item = Url>>(
+    /// Download a batch of items, retrying in case the remote source is rate-limited.
+    /// This does not report anything to the `SyncProgress`, so that it can run while another batch is applied: see [`DownloadedBatch::rate_limit_delays`]
+    async fn download_batch(cal_remote: &U, urls: Vec<Url>) -> DownloadedBatch {
+        let mut rate_limit_delays = Vec::new();
+        let result = loop {
+            match cal_remote.get_items_by_url(&urls).await {
+                Err(KFError::RateLimited { retry_after, .. })
+                    if rate_limit_delays.len() + 1 < RATE_LIMIT_ATTEMPTS as usize =>
+                {
+                    let attempt = rate_limit_delays.len() as u32 + 1;
+                    let delay = retry_after
+                        .unwrap_or(RATE_LIMIT_DEFAULT_DELAY * 2u32.pow(attempt - 1))
+                        .min(RATE_LIMIT_MAX_DELAY);
+                    rate_limit_delays.push(delay);
+                    sleep(delay).await;
+                }
+                other => break other,
+            }
+        };
+        DownloadedBatch {
+            urls,
+            result,
+            rate_limit_delays,
+        }
+    }
+
+    /// Store a downloaded batch in the local calendar
+    async fn apply_batch(
         batch_type: BatchDownloadType,
-        remote_additions: I,
+        batch: DownloadedBatch,
+        mut conflicts: Option<(&mut HashMap<Url, Item>, ConflictStrategy)>,
         cal_local: &mut T,
-        cal_remote: &mut U,
         progress: &mut SyncProgress,
         cal_name: &str,
-    ) {
-        progress.debug(&format!("> Applying a batch of {} locally", batch_type) /* too bad Chunks does not implement ExactSizeIterator, that could provide useful debug info. See https://github.com/rust-itertools/itertools/issues/171 */);
-
-        let list_of_additions: Vec<Url> = remote_additions.collect();
-        match cal_remote.get_items_by_url(&list_of_additions).await {
+    ) -> HashSet<Url> {
+        let mut kept_local_versions = HashSet::new();
+        for delay in &batch.rate_limit_delays {
+            progress.rate_limited(cal_name, *delay);
+        }
+        let list_of_additions = batch.urls;
+        match batch.result {
             Err(err) => {
-                progress.warn(&format!(
-                    "Unable to get the batch of {} {:?}: {}. Skipping them.",
-                    batch_type, list_of_additions, err
-                ));
+                progress.skip(Skipped::Items {
+                    calendar: cal_local.url().clone(),
+                    urls: list_of_additions.clone(),
+                    reason: format!("unable to download this batch of {} ({})", batch_type, err),
+                });
             }
             Ok(items) => {
+                let fetched: HashSet<&Url> = items.iter().flatten().map(|i| i.url()).collect();
+                for url in &list_of_additions {
+                    if !fetched.contains(url) {
+                        progress.issue(SyncIssue::Inconsistency {
+                            calendar: cal_local.url().clone(),
+                            url: url.clone(),
+                            property: None,
+                            expected: format!("a remote item of the batch of {}", batch_type),
+                            found: "missing from the server".to_string(),
+                        });
+                    }
+                }
                 for item in items {
                     match item {
-                        None => {
-                            progress.error("Inconsistency: an item from the batch has vanished from the remote end");
-                            continue;
-                        }
-                        Some(new_item) => {
+                        None => continue,
+                        Some(mut new_item) => {
+                            if let Some(hooks) = progress.item_hooks() {
+                                let downloaded_status = new_item.sync_status().clone();
+                                hooks.after_download(&mut new_item).await;
+                                new_item.relabel_sync_status(downloaded_status);
+                            }
+                            let conflict = conflicts.as_mut().and_then(|(versions, strategy)| {
+                                versions
+                                    .remove(new_item.url())
+                                    .map(|local_version| (local_version, *strategy))
+                            });
+                            if let Some((local_version, conflict_strategy)) = conflict {
+                                // Both versions have changed since the last sync. The SEQUENCE numbers tell whether the remote version has been
+                                // derived from a more recent revision than the local one (or the same), in which case the remote version wins.
+                                if local_version.sequence() > new_item.sequence() {
+                                    progress.info(&format!("Conflict: item {} has a higher SEQUENCE locally ({} > {}). Keeping the local version.", new_item.url(), local_version.sequence(), new_item.sequence()));
+                                    if let Some(local_item) =
+                                        cal_local.get_item_by_url_mut(new_item.url()).await
+                                    {
+                                        // The local version will overwrite the current remote version
+                                        if let SyncStatus::Synced(remote_tag) =
+                                            new_item.sync_status()
+                                        {
+                                            local_item.set_sync_status(
+                                                SyncStatus::LocallyModified(remote_tag.clone()),
+                                            );
+                                        }
+                                    }
+                                    kept_local_versions.insert(new_item.url().clone());
+                                    continue;
+                                }
+                                let merged = match (conflict_strategy, &local_version, &new_item) {
+                                    (
+                                        ConflictStrategy::Merge,
+                                        Item::Task(local),
+                                        Item::Task(remote),
+                                    ) => remote.merge_local_changes(local),
+                                    _ => None,
+                                };
+                                if let Some(merged) = merged {
+                                    progress.info(&format!("Conflict: item {} has been modified in both sources, but not in the same fields. Merging both versions.", new_item.url()));
+                                    new_item = Item::Task(merged);
+                                    kept_local_versions.insert(new_item.url().clone());
+                                } else {
+                                    cal_local
+                                        .record_conflict(Conflict::new(
+                                            local_version,
+                                            Some(new_item.clone()),
+                                        ))
+                                        .await;
+                                }
+                            }
+                            // The item is moved into the local calendar, only its URL is kept to report errors
+                            let url = new_item.url().clone();
                             let local_update_result = match batch_type {
                                 BatchDownloadType::RemoteAdditions => {
-                                    cal_local.add_item(new_item.clone()).await
+                                    cal_local.add_item(new_item).await
                                 }
                                 BatchDownloadType::RemoteChanges => {
-                                    cal_local.update_item(new_item.clone()).await
+                                    cal_local.update_item(new_item).await
                                 }
                             };
-                            if let Err(err) = local_update_result {
-                                progress.error(&format!(
-                                    "Not able to add item {} to local calendar: {}",
-                                    new_item.url(),
-                                    err
-                                ));
+                            match local_update_result {
+                                Err(err) => progress.skip(Skipped::Items {
+                                    calendar: cal_local.url().clone(),
+                                    urls: vec![url],
+                                    reason: format!(
+                                        "unable to store it in the local calendar ({})",
+                                        err
+                                    ),
+                                }),
+                                Ok(_) => match batch_type {
+                                    BatchDownloadType::RemoteAdditions => {
+                                        progress.summary_mut().remote_additions += 1
+                                    }
+                                    BatchDownloadType::RemoteChanges => {
+                                        progress.summary_mut().remote_changes += 1
+                                    }
+                                },
                             }
                         }
                     }
                 }
 
                 // Notifying every item at the same time would not make sense. Let's notify only one of them
-                let one_item_name = match list_of_additions.first() {
-                    Some(url) => Self::item_name(cal_local, url).await,
-                    None => String::from("<unable to get the name of the first batched item>"),
-                };
+                let one_item_name = Self::item_names(cal_local, list_of_additions.first())
+                    .await
+                    .into_values()
+                    .next()
+                    .unwrap_or_else(|| {
+                        String::from("<unable to get the name of the first batched item>")
+                    });
                 progress.increment_counter(list_of_additions.len());
-                progress.feedback(SyncEvent::ItemsInProgress {
-                    calendar_name: cal_name.to_string(),
-                    items_done_already: progress.counter(),
-                    details: one_item_name,
-                });
+                let event = progress.items_in_progress(cal_name, one_item_name);
+                progress.feedback(event);
             }
         }
+        kept_local_versions
     }
 
     async fn apply_remote_prop_additions(
@@ -1093,30 +2515,256 @@ where
     }
 }
 
-async fn get_or_insert_counterpart_calendar<H, N, I>(
+/// Periodically persists a local calendar while items are downloaded into it
+struct Checkpoint<'a, L> {
+    local: &'a L,
+    interval: Option<usize>,
+    /// How many items have been applied since the last checkpoint
+    pending: usize,
+}
+
+impl<'a, L> Checkpoint<'a, L> {
+    fn new(local: &'a L, interval: Option<usize>) -> Self {
+        Self {
+            local,
+            interval,
+            pending: 0,
+        }
+    }
+
+    async fn items_applied<T>(&mut self, count: usize, cal_local: &T)
+    where
+        L: CalDavSource<T>,
+        T: CompleteCalendar + Sync + Send,
+    {
+        let interval = match self.interval {
+            None => return,
+            Some(interval) => interval,
+        };
+        self.pending += count;
+        if self.pending < interval {
+            return;
+        }
+        self.pending = 0;
+        log::debug!("Checkpointing local calendar {}", cal_local.url());
+        if let Err(err) = self.local.checkpoint_calendar(cal_local).await {
+            // This only makes an interrupted sync slower to resume
+            log::warn!("{}", err);
+        }
+    }
+}
+
+/// A local calendar that a sync keeps locked, except while it uploads items: the application can change them in the meantime (see [`Provider::finalize_upload`])
+struct LockedCalendar<T> {
+    cal: Arc<Mutex<T>>,
+    guard: Option<OwnedMutexGuard<T>>,
+}
+
+impl<T> LockedCalendar<T> {
+    async fn lock(cal: Arc<Mutex<T>>) -> Self {
+        let guard = Arc::clone(&cal).lock_owned().await;
+        Self {
+            cal,
+            guard: Some(guard),
+        }
+    }
+
+    /// Release the lock while `future` runs
+    async fn unlocked<F: Future>(&mut self, future: F) -> F::Output {
+        self.guard = None;
+        let output = future.await;
+        self.guard = Some(Arc::clone(&self.cal).lock_owned().await);
+        output
+    }
+}
+
+impl<T> Deref for LockedCalendar<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // It is only unlocked within `unlocked`, that borrows it mutably
+        self.guard.as_deref().expect("the local calendar is locked")
+    }
+}
+
+impl<T> DerefMut for LockedCalendar<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard
+            .as_deref_mut()
+            .expect("the local calendar is locked")
+    }
+}
+
+/// Local URLs, by their canonical forms (see [`canonical_url`])
+fn canonical_forms(local_urls: impl IntoIterator<Item = Url>) -> HashMap<Url, Url> {
+    local_urls
+        .into_iter()
+        .map(|url| (canonical_url(url.clone()), url))
+        .collect()
+}
+
+/// The local URL that is equivalent to `remote_url`, in case it is not stored in the same percent-encoded form (e.g. by a former version of this crate, that did not canonicalize hrefs).
+/// Such URLs keep their local form, which the server accepts as well
+fn local_form(remote_url: &Url, local_urls: &HashMap<Url, Url>) -> Url {
+    local_urls
+        .get(&canonical_url(remote_url.clone()))
+        .unwrap_or(remote_url)
+        .clone()
+}
+
+/// Whether the [`DestructiveChangeGuard`] of this sync allows `change`. Fails in case it aborts the sync
+fn allowed(progress: &mut SyncProgress, change: &PlannedChange) -> KFResult<bool> {
+    match progress.decide(change) {
+        Decision::Allow => Ok(true),
+        Decision::Skip => Ok(false),
+        Decision::Abort => Err(KFError::SyncAborted {
+            reason: format!("{} has been vetoed", change),
+        }),
+    }
+}
+
+/// Whether `err` tells that the calendar at `url` does not exist (anymore)
+fn is_calendar_not_found(err: &KFError, url: &Url) -> bool {
+    matches!(
+        err,
+        KFError::ItemDoesNotExist {
+            type_: Some(ItemType::Calendar),
+            url: missing,
+            ..
+        } if missing == url
+    )
+}
+
+/// Returns the counterpart calendar, and the names of the properties of `needle` (listed by `props`) that have been copied to it in case it has just been created.
+/// Returns `None` in case the calendar does not exist in `haystack`, and `create` is false
+async fn get_or_insert_counterpart_calendar<H, N, I, P>(
     haystack_descr: &str,
     haystack: &mut H,
     cal_url: &Url,
     needle: Arc<Mutex<N>>,
-) -> KFResult<Arc<Mutex<I>>>
+    props: P,
+    create: bool,
+) -> KFResult<Option<(Arc<Mutex<I>>, Vec<NamespacedName>)>>
 where
     H: CalDavSource<I>,
     I: BaseCalendar,
     N: BaseCalendar,
+    P: std::future::Future<Output = Vec<Property>>,
 {
-    loop {
-        if let Some(cal) = haystack.get_calendar(cal_url).await {
-            break Ok(cal);
-        }
+    if let Some(cal) = haystack.get_calendar(cal_url).await {
+        return Ok(Some((cal, Vec::new())));
+    }
+    if !create {
+        return Ok(None);
+    }
 
-        // This calendar does not exist locally yet, let's add it
-        log::debug!("Adding a {} calendar {}", haystack_descr, cal_url);
+    // This calendar does not exist locally yet, let's add it
+    log::debug!("Adding a {} calendar {}", haystack_descr, cal_url);
+    let (name, supported_comps, color) = {
         let src = needle.lock().await;
-        let name = src.name().to_string();
-        let supported_comps = src.supported_components();
-        let color = src.color();
-        haystack
-            .create_calendar(cal_url.clone(), name, supported_comps, color.cloned())
-            .await?;
+        (
+            src.name().to_string(),
+            src.supported_components(),
+            src.color().cloned(),
+        )
+    };
+    let cal = haystack
+        .create_calendar(cal_url.clone(), name, supported_comps, color)
+        .await?;
+
+    // Copy the properties as well, so that the first sync of this calendar does not have to reconcile them
+    let mut copied = Vec::new();
+    let mut new_cal = cal.lock().await;
+    for prop in props.await {
+        let nsn = prop.nsn().clone();
+        match new_cal.set_property(prop).await {
+            Err(err) => log::warn!(
+                "Unable to copy property {} to {} calendar {}: {}",
+                nsn,
+                haystack_descr,
+                cal_url,
+                err
+            ),
+            Ok(_) => copied.push(nsn),
+        }
+    }
+    drop(new_cal);
+    Ok(Some((cal, copied)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cache::Cache;
+    use crate::calendar::cached_calendar::CachedCalendar;
+    use crate::calendar::remote_calendar::RemoteCalendar;
+    use crate::calendar::SupportedComponents;
+    use crate::client::Client;
+    use crate::task::Task;
+    use crate::traits::CompleteCalendarFactory;
+    use crate::utils::sync::VersionTag;
+
+    type CacheProvider = Provider<Cache, CachedCalendar, Client, RemoteCalendar>;
+
+    #[tokio::test]
+    async fn test_changes_during_upload() {
+        let cal_url: Url = "https://some.calend.ar/uploads/".parse().unwrap();
+        let mut calendar = CachedCalendar::new(
+            "Uploads".to_string(),
+            cal_url.clone(),
+            SupportedComponents::TODO,
+            None,
+        );
+        let mut urls = Vec::new();
+        for name in &["Untouched", "Renamed", "Deleted"] {
+            let mut task = Task::new(name.to_string(), false, &cal_url).unwrap();
+            // These items are local changes of items that have already been synced
+            let old_tag = VersionTag::from("old-tag".to_string());
+            task.set_sync_status(SyncStatus::Synced(old_tag.clone()));
+            task.set_sync_status(SyncStatus::LocallyModified(old_tag));
+            urls.push(task.url().clone());
+            calendar.add_item(Item::Task(task)).await.unwrap();
+        }
+        let generations: Vec<u64> = urls
+            .iter()
+            .map(|url| calendar.get_item_by_url_sync(url).unwrap().generation())
+            .collect();
+
+        // Changes made between the moment the items are read for the upload and the moment the server answers
+        calendar
+            .get_item_by_url_mut(&urls[1])
+            .await
+            .unwrap()
+            .unwrap_task_mut()
+            .set_name("Renamed again".to_string());
+        calendar.mark_item_for_deletion(&urls[2]).await.unwrap();
+
+        let mut progress = SyncProgress::new();
+        for (url, generation) in urls.iter().zip(generations) {
+            let uploaded = SyncStatus::Synced(VersionTag::from(format!("tag-{}", url)));
+            CacheProvider::finalize_upload(&mut calendar, url, generation, uploaded, &mut progress)
+                .await;
+        }
+
+        let status = |url: &Url| {
+            calendar
+                .get_item_by_url_sync(url)
+                .unwrap()
+                .sync_status()
+                .clone()
+        };
+        assert!(matches!(status(&urls[0]), SyncStatus::Synced(_)));
+        assert!(
+            matches!(status(&urls[1]), SyncStatus::LocallyModified(tag) if tag.as_str() == format!("tag-{}", urls[1]))
+        );
+        assert!(
+            matches!(status(&urls[2]), SyncStatus::LocallyDeleted(tag) if tag.as_str() == format!("tag-{}", urls[2]))
+        );
+        assert_eq!(
+            calendar.get_item_by_url_sync(&urls[1]).unwrap().name(),
+            "Renamed again"
+        );
+        assert_eq!(progress.summary().modified_during_sync, urls[1..].to_vec());
     }
 }
@@ -0,0 +1,765 @@
+//! A provider that syncs a local source with several remote sources
+//!
+//! This is useful to mirror a CalDAV server to both a local cache and a secondary server, without maintaining two caches that would drift apart.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json_any_key::any_key_map;
+use tokio::sync::Mutex;
+use url::Url;
+
+use super::hooks::{DestructiveChangeGuard, PlannedChange};
+use super::sync_progress::{FeedbackSender, Skipped, SyncEvent, SyncProgress, SyncResult};
+use super::{allowed, Provider, RemoteCalendarDeletionPolicy, SyncDirection};
+use crate::error::{KFError, KFResult};
+use crate::item::Item;
+use crate::traits::{CalDavSource, CompleteCalendar, DavCalendar};
+use crate::utils::prop::Property;
+use crate::utils::sync::{SyncStatus, Syncable, VersionTag};
+use crate::utils::{stable_hash, NamespacedName};
+
+/// What an additional source of a [`MultiProvider`] knew about a local calendar, as of the last sync with this source.
+///
+/// The sync statuses stored in local items only refer to the primary source. This keeps track of the version tags of the other sources.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SourceState {
+    /// The version tag on this source and the content fingerprint of each item, as of their last sync
+    items: HashMap<Url, (VersionTag, u64)>,
+    /// The value of each property, as of their last sync
+    #[serde(with = "any_key_map")]
+    props: HashMap<NamespacedName, String>,
+}
+
+impl SourceState {
+    /// The version tag an item had on this source when it was last synced
+    pub fn item_version_tag(&self, url: &Url) -> Option<&VersionTag> {
+        self.items.get(url).map(|(vt, _)| vt)
+    }
+}
+
+/// The primary sync statuses of a local calendar, saved while it is synced with a secondary source
+#[derive(Default)]
+struct PrimaryStatuses {
+    items: HashMap<Url, (SyncStatus, u64)>,
+    props: HashMap<NamespacedName, (SyncStatus, String)>,
+    /// Local deletions that the secondary source never knew about. They are kept out of the calendar during the sync.
+    hidden_items: Vec<Item>,
+    hidden_props: Vec<Property>,
+}
+
+/// A data source that combines a local source with a chain of remote sources, and that is able to sync all of them.
+///
+/// The first remote source is the primary one: the sync statuses of the local items refer to it, exactly like in a regular [`Provider`].
+/// Each additional source is identified by a unique ID, and its version tags are stored in the local calendars (see [`CompleteCalendar::get_source_state`]).
+///
+/// A sync first syncs the local source with the primary source, then with every additional source in turn.
+/// Every pair sync works like [`Provider::sync`] (the remote end wins conflicts), and changes that are pulled from a source are pushed to the other ones.
+/// Note that changes pulled from an additional source only reach the sources before it in the chain at the next sync.
+pub struct MultiProvider<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    /// The local source and the primary remote source
+    primary: Provider<L, T, R, U>,
+    /// The additional remote sources, and their IDs
+    secondaries: Vec<(String, R)>,
+    /// An item that cannot be hidden from the secondary sources, to test how a failing preparation is handled
+    #[cfg(test)]
+    unhideable_item: Option<Url>,
+}
+
+impl<L, T, R, U> MultiProvider<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    /// Create a provider, that syncs `local` with `primary` only. See [`Self::add_source`] to add more sources.
+    pub fn new(primary: R, local: L) -> Self {
+        Self {
+            primary: Provider::new(primary, local),
+            secondaries: Vec::new(),
+            #[cfg(test)]
+            unhideable_item: None,
+        }
+    }
+
+    /// Add a remote source at the end of the chain.
+    ///
+    /// `source_id` must be stable across runs, since it used to find the stored sync state of this source. Adding a source with an existing ID replaces the former source.
+    pub fn add_source(&mut self, source_id: String, source: R) {
+        match self.secondaries.iter_mut().find(|(id, _)| *id == source_id) {
+            Some(existing) => existing.1 = source,
+            None => self.secondaries.push((source_id, source)),
+        }
+    }
+
+    /// Returns the local source
+    pub fn local(&self) -> &L {
+        self.primary.local()
+    }
+    /// Returns the local source
+    pub fn local_mut(&mut self) -> &mut L {
+        self.primary.local_mut()
+    }
+    /// Returns the primary remote source
+    pub fn primary(&self) -> &R {
+        self.primary.remote()
+    }
+    /// Consult `guard` before the syncs delete data from any source. See [`Provider::set_destructive_change_guard`]
+    pub fn set_destructive_change_guard(&mut self, guard: Option<Arc<dyn DestructiveChangeGuard>>) {
+        self.primary.set_destructive_change_guard(guard);
+    }
+    /// Returns the additional remote source with the given ID
+    pub fn source(&self, source_id: &str) -> Option<&R> {
+        self.secondaries
+            .iter()
+            .find(|(id, _)| id == source_id)
+            .map(|(_, source)| source)
+    }
+
+    /// Performs a synchronisation between every source, and provide feeedback to the user about the progress.
+    ///
+    /// It returns whether the sync was totally successful. See [`Provider::sync_with_feedback`]
+    pub async fn sync_with_feedback(&mut self, feedback_sender: FeedbackSender) -> bool {
+        let mut progress = SyncProgress::new_with_feedback_channel(feedback_sender);
+        self.run_sync(&mut progress).await
+    }
+
+    /// Performs a synchronisation between every source, without giving any feedback.
+    ///
+    /// See [`Self::sync_with_feedback`]
+    pub async fn sync(&mut self) -> bool {
+        let mut progress = SyncProgress::new();
+        self.run_sync(&mut progress).await
+    }
+
+    /// Performs a synchronisation between every source, and returns details about the issues that happened. See [`Provider::sync_with_result`]
+    pub async fn sync_with_result(
+        &mut self,
+        feedback_sender: Option<FeedbackSender>,
+    ) -> SyncResult {
+        let mut progress = match feedback_sender {
+            Some(sender) => SyncProgress::new_with_feedback_channel(sender),
+            None => SyncProgress::new(),
+        };
+        self.run_sync(&mut progress).await;
+        progress.result()
+    }
+
+    /// The calendars and items that the last sync has not been able to sync (see [`SyncResult::skipped`])
+    pub fn skipped(&self) -> &[Skipped] {
+        self.primary.skipped()
+    }
+
+    async fn run_sync(&mut self, progress: &mut SyncProgress) -> bool {
+        // The local source is locked for the whole chain of pair syncs, like in `Provider::run_sync`
+        let result = match self.primary.local.sync_started() {
+            Err(err) => Err(err),
+            Ok(()) => {
+                let result = self.run_sync_inner(progress).await;
+                self.primary.local.sync_finished();
+                result
+            }
+        };
+        if let Err(err) = result {
+            if let KFError::CacheLocked { .. } = err {
+                progress.local_source_locked();
+            }
+            progress.error(&format!("Sync terminated because of an error: {}", err));
+        }
+        self.primary.skipped = progress.skipped().to_vec();
+        let event = SyncEvent::Finished {
+            success: progress.is_success(),
+            summary: progress.summary().clone(),
+        };
+        progress.feedback(event);
+        progress.is_success()
+    }
+
+    async fn run_sync_inner(&mut self, progress: &mut SyncProgress) -> KFResult<()> {
+        progress.set_destructive_change_guard(self.primary.destructive_change_guard.clone());
+        // The primary sync removes the local calendars that are marked for deletion, they must be known before
+        let mut deleted_calendars = Vec::new();
+        for (cal_url, cal_local) in self.primary.local.get_calendars().await? {
+            let cal_local = cal_local.lock().await;
+            if cal_local.marked_for_deletion().await {
+                deleted_calendars.push((cal_url, cal_local.name().to_string()));
+            }
+        }
+
+        match self.primary.run_sync_inner(progress).await {
+            Err(err) => progress.error(&format!("Unable to sync with the primary source: {}", err)),
+            Ok(()) => {
+                self.propagate_calendar_deletions(deleted_calendars, progress)
+                    .await?
+            }
+        }
+
+        for index in 0..self.secondaries.len() {
+            let source_id = self.secondaries[index].0.clone();
+            progress.info(&format!("Syncing with source {}", source_id));
+            if let Err(err) = self.sync_secondary(index, progress).await {
+                if let KFError::SyncAborted { .. } = err {
+                    return Err(err);
+                }
+                progress.error(&format!(
+                    "Unable to sync with source {}: {}",
+                    source_id, err
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete from the other sources the calendars that the primary sync has deleted, if the [`DestructiveChangeGuard`] of this sync allows it
+    async fn propagate_calendar_deletions(
+        &mut self,
+        deleted_calendars: Vec<(Url, String)>,
+        progress: &mut SyncProgress,
+    ) -> KFResult<()> {
+        for (cal_url, name) in deleted_calendars {
+            // The primary sync may have skipped it
+            if self.primary.local.get_calendar(&cal_url).await.is_some() {
+                continue;
+            }
+            let mut sources = Vec::new();
+            for (source_id, source) in self.secondaries.iter_mut() {
+                if source.get_calendar(&cal_url).await.is_some() {
+                    sources.push((source_id, source));
+                }
+            }
+            if sources.is_empty() {
+                continue;
+            }
+            let change = PlannedChange::DeleteRemoteCalendar {
+                url: cal_url.clone(),
+                name,
+            };
+            if !allowed(progress, &change)? {
+                continue;
+            }
+            for (source_id, source) in sources {
+                if let Err(err) = source.delete_calendar(&cal_url).await {
+                    progress.warn(&format!(
+                        "Unable to delete calendar {} from source {}: {}",
+                        cal_url, source_id, err
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn sync_secondary(&mut self, index: usize, progress: &mut SyncProgress) -> KFResult<()> {
+        let source_id = self.secondaries[index].0.clone();
+        let vetoed = self.push_local_deletions(index, progress).await?;
+        let only: Option<HashSet<Url>> = if vetoed.is_empty() {
+            None
+        } else {
+            let mut calendars: HashSet<Url> = self
+                .primary
+                .local
+                .get_calendars()
+                .await?
+                .into_keys()
+                .chain(self.secondaries[index].1.get_calendars().await?.into_keys())
+                .collect();
+            calendars.retain(|url| !vetoed.contains(url));
+            Some(calendars)
+        };
+        let saved = self.prepare_local_view(index, progress).await?;
+
+        // Temporarily make this source the remote end of the pair sync.
+        // Whether a local calendar has been synced refers to the primary source, so calendars missing from this source are created there
+        std::mem::swap(&mut self.primary.remote, &mut self.secondaries[index].1);
+        let deletion_policy = std::mem::replace(
+            &mut self.primary.remote_calendar_deletion_policy,
+            RemoteCalendarDeletionPolicy::Recreate,
+        );
+        let result = self
+            .primary
+            .sync_calendars(progress, only.as_ref(), SyncDirection::Both)
+            .await;
+        self.primary.remote_calendar_deletion_policy = deletion_policy;
+        std::mem::swap(&mut self.primary.remote, &mut self.secondaries[index].1);
+
+        for err in self.restore_local_view(&source_id, saved).await {
+            progress.error(&format!(
+                "Unable to restore the local calendars after syncing with source {}: {}",
+                source_id, err
+            ));
+        }
+        result
+    }
+
+    /// Push to a secondary source the deletions that have been applied locally (e.g. by a previous source) since their last sync.
+    ///
+    /// Returns the calendars for which the [`DestructiveChangeGuard`] of this sync has skipped some deletions:
+    /// they must not be synced with this source, otherwise the items and props that are still there would be pulled back
+    async fn push_local_deletions(
+        &mut self,
+        index: usize,
+        progress: &mut SyncProgress,
+    ) -> KFResult<HashSet<Url>> {
+        let (source_id, source) = &mut self.secondaries[index];
+        let mut vetoed = HashSet::new();
+
+        for (cal_url, cal) in self.primary.local.get_calendars().await? {
+            let mut cal = cal.lock().await;
+            let mut state = cal.get_source_state(source_id).await;
+            let local_urls = cal.get_item_urls().await?;
+            let local_nsns: HashSet<NamespacedName> =
+                cal.get_properties().await.keys().cloned().collect();
+            let gone_items: Vec<Url> = state
+                .items
+                .keys()
+                .filter(|url| !local_urls.contains(url))
+                .cloned()
+                .collect();
+            let gone_props: Vec<NamespacedName> = state
+                .props
+                .keys()
+                .filter(|nsn| !local_nsns.contains(nsn))
+                .cloned()
+                .collect();
+            match source.get_calendar(&cal_url).await {
+                None => {
+                    for url in gone_items {
+                        state.items.remove(&url);
+                    }
+                    for nsn in gone_props {
+                        state.props.remove(&nsn);
+                    }
+                }
+                Some(remote_cal) => {
+                    let mut remote_cal = remote_cal.lock().await;
+                    let mut gone_items = gone_items;
+                    let mut gone_props = gone_props;
+                    gone_items.sort();
+                    gone_props.sort_by_key(|nsn| nsn.to_string());
+                    // Vetoed deletions are planned (and asked for) again at the next sync
+                    if !gone_items.is_empty() {
+                        let change = PlannedChange::DeleteRemoteItems {
+                            calendar: cal_url.clone(),
+                            urls: gone_items.clone(),
+                        };
+                        if !allowed(progress, &change)? {
+                            vetoed.insert(cal_url.clone());
+                            gone_items.clear();
+                        }
+                    }
+                    if !gone_props.is_empty() {
+                        let change = PlannedChange::DeleteRemoteProps {
+                            calendar: cal_url.clone(),
+                            names: gone_props.clone(),
+                        };
+                        if !allowed(progress, &change)? {
+                            vetoed.insert(cal_url.clone());
+                            gone_props.clear();
+                        }
+                    }
+                    for url in gone_items {
+                        match remote_cal.delete_item(&url).await {
+                            Ok(()) => {
+                                state.items.remove(&url);
+                            }
+                            Err(err) => progress.warn(&format!(
+                                "Unable to delete item {} from source {}: {}",
+                                url, source_id, err
+                            )),
+                        }
+                    }
+                    for nsn in gone_props {
+                        match remote_cal.delete_property(&nsn).await {
+                            Ok(()) => {
+                                state.props.remove(&nsn);
+                            }
+                            Err(err) => progress.warn(&format!(
+                                "Unable to delete prop {} from source {}: {}",
+                                nsn, source_id, err
+                            )),
+                        }
+                    }
+                }
+            }
+
+            cal.set_source_state(source_id, state).await;
+        }
+
+        Ok(vetoed)
+    }
+
+    /// Replace the sync statuses of the local items and props with the ones relative to a secondary source, and return the primary ones.
+    ///
+    /// Every local calendar is locked, and every new status is computed before anything is changed.
+    /// In case an item or a prop cannot be hidden from the secondary source, the calendars are put back as they were.
+    async fn prepare_local_view(
+        &mut self,
+        index: usize,
+        progress: &mut SyncProgress,
+    ) -> KFResult<SavedViews<T>> {
+        let source_id = self.secondaries[index].0.clone();
+        let mut calendars = Vec::new();
+        for (cal_url, cal) in self.primary.local.get_calendars().await? {
+            let guard = Arc::clone(&cal).lock_owned().await;
+            calendars.push((cal_url, cal, guard));
+        }
+
+        let mut views = Vec::new();
+        for (_, _, cal) in &calendars {
+            let state = cal.get_source_state(&source_id).await;
+            views.push(local_view(&**cal, &state).await?);
+        }
+
+        let mut failure = None;
+        let mut saved = HashMap::new();
+        for ((cal_url, cal, mut guard), (mut primary, view)) in calendars.into_iter().zip(views) {
+            if failure.is_none() {
+                if let Err(err) = self.apply_local_view(&mut *guard, &mut primary, view).await {
+                    failure = Some(err);
+                }
+            }
+            saved.insert(cal_url, (cal, primary, guard));
+        }
+
+        match failure {
+            None => Ok(saved
+                .into_iter()
+                .map(|(cal_url, (cal, primary, _))| (cal_url, (cal, primary)))
+                .collect()),
+            Some(err) => {
+                let mut errors = Vec::new();
+                for (_, (_, primary, mut guard)) in saved {
+                    put_back_local_view(&mut *guard, primary, None, &mut errors).await;
+                }
+                for rollback_err in errors {
+                    progress.error(&format!(
+                        "Unable to put back the local calendars: {}",
+                        rollback_err
+                    ));
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Relabel the items and props of a local calendar with their statuses relative to a secondary source, and hide the ones this source must not know about
+    async fn apply_local_view(
+        &mut self,
+        cal: &mut T,
+        primary: &mut PrimaryStatuses,
+        view: LocalView,
+    ) -> KFResult<()> {
+        let mut to_hide = Vec::new();
+        for (url, status) in view.items {
+            match status {
+                Some(status) => {
+                    if let Some(item) = cal.get_item_by_url_mut(&url).await {
+                        item.relabel_sync_status(status);
+                    }
+                }
+                None => to_hide.push(url),
+            }
+        }
+        to_hide.sort();
+        for url in to_hide {
+            #[cfg(test)]
+            if self.unhideable_item.as_ref() == Some(&url) {
+                return Err(KFError::UnsupportedBySource {
+                    operation: format!("Hiding item {}", url),
+                });
+            }
+            if let Some(item) = cal.get_item_by_url(&url).await.cloned() {
+                cal.immediately_delete_item(&url).await?;
+                primary.items.remove(&url);
+                primary.hidden_items.push(item);
+            }
+        }
+
+        let mut to_hide = Vec::new();
+        for (nsn, status) in view.props {
+            match status {
+                Some(status) => {
+                    if let Some(prop) = cal.get_property_by_name_mut(&nsn).await {
+                        prop.relabel_sync_status(status);
+                    }
+                }
+                None => to_hide.push(nsn),
+            }
+        }
+        for nsn in to_hide {
+            if let Some(prop) = cal.get_property_by_name(&nsn).await.cloned() {
+                cal.immediately_delete_prop(&nsn).await?;
+                primary.props.remove(&nsn);
+                primary.hidden_props.push(prop);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Store the sync statuses relative to a secondary source, and restore the primary ones.
+    ///
+    /// Items that have been changed by this pair sync are marked as locally modified, so that the next sync pushes them to the primary source.
+    /// This carries on after a failure, so that every hidden item and prop is put back. The failures are returned.
+    async fn restore_local_view(
+        &mut self,
+        source_id: &str,
+        mut saved: SavedViews<T>,
+    ) -> Vec<KFError> {
+        let mut errors = Vec::new();
+        let calendars = match self.primary.local.get_calendars().await {
+            Ok(calendars) => calendars,
+            Err(err) => {
+                errors.push(err);
+                HashMap::new()
+            }
+        };
+
+        for (cal_url, cal) in calendars {
+            let primary = saved
+                .remove(&cal_url)
+                .map(|(_, primary)| primary)
+                .unwrap_or_default();
+            let mut cal = cal.lock().await;
+            let mut state = cal.get_source_state(source_id).await;
+            put_back_local_view(&mut *cal, primary, Some(&mut state), &mut errors).await;
+            cal.set_source_state(source_id, state).await;
+        }
+        // Calendars that have been deleted by this pair sync, or that could not be listed
+        for (_, (cal, primary)) in saved {
+            put_back_local_view(&mut *cal.lock().await, primary, None, &mut errors).await;
+        }
+
+        errors
+    }
+}
+
+/// The local calendars of a [`MultiProvider`] whose sync statuses have been replaced, and their primary statuses
+type SavedViews<T> = HashMap<Url, (Arc<Mutex<T>>, PrimaryStatuses)>;
+
+/// The sync statuses that the items and props of a local calendar have relative to a secondary source.
+/// The ones without a status must be hidden from this source.
+#[derive(Default)]
+struct LocalView {
+    items: Vec<(Url, Option<SyncStatus>)>,
+    props: Vec<(NamespacedName, Option<SyncStatus>)>,
+}
+
+/// Compute the sync statuses of a local calendar relative to a secondary source, and save its primary ones. This does not change the calendar
+async fn local_view<T: CompleteCalendar>(
+    cal: &T,
+    state: &SourceState,
+) -> KFResult<(PrimaryStatuses, LocalView)> {
+    let mut primary = PrimaryStatuses::default();
+    let mut view = LocalView::default();
+
+    for (url, item) in cal.get_items().await? {
+        let fingerprint = fingerprint(item)?;
+        let view_status = match (item.sync_status(), state.items.get(&url)) {
+            (SyncStatus::LocallyDeleted(_), Some((vt, _))) => {
+                Some(SyncStatus::LocallyDeleted(vt.clone()))
+            }
+            (SyncStatus::LocallyDeleted(_), None) => None,
+            (_, Some((vt, known))) if *known == fingerprint => Some(SyncStatus::Synced(vt.clone())),
+            (_, Some((vt, _))) => Some(SyncStatus::LocallyModified(vt.clone())),
+            (_, None) => Some(SyncStatus::NotSynced),
+        };
+        primary
+            .items
+            .insert(url.clone(), (item.sync_status().clone(), fingerprint));
+        view.items.push((url, view_status));
+    }
+
+    for (nsn, prop) in cal.get_properties().await {
+        let view_status = match (prop.sync_status(), state.props.get(nsn)) {
+            (SyncStatus::LocallyDeleted(_), Some(known)) => {
+                Some(SyncStatus::LocallyDeleted(VersionTag::from(known.clone())))
+            }
+            (SyncStatus::LocallyDeleted(_), None) => None,
+            (_, Some(known)) if known == prop.value() => {
+                Some(SyncStatus::Synced(VersionTag::from(known.clone())))
+            }
+            (_, Some(known)) => Some(SyncStatus::LocallyModified(VersionTag::from(known.clone()))),
+            (_, None) => Some(SyncStatus::NotSynced),
+        };
+        primary.props.insert(
+            nsn.clone(),
+            (prop.sync_status().clone(), prop.value().clone()),
+        );
+        view.props.push((nsn.clone(), view_status));
+    }
+
+    Ok((primary, view))
+}
+
+/// Restore the primary sync statuses of a local calendar, and put back the items and props it hides.
+///
+/// When a `state` is given, the statuses relative to the secondary source are stored into it first.
+/// This carries on after a failure, and pushes it to `errors`.
+async fn put_back_local_view<T: CompleteCalendar>(
+    cal: &mut T,
+    primary: PrimaryStatuses,
+    mut state: Option<&mut SourceState>,
+    errors: &mut Vec<KFError>,
+) {
+    match cal.get_items_mut().await {
+        Err(err) => errors.push(err),
+        Ok(items) => {
+            let mut current_urls = HashSet::new();
+            for (url, item) in items {
+                let fingerprint = match fingerprint(item) {
+                    Ok(fingerprint) => Some(fingerprint),
+                    Err(err) => {
+                        errors.push(err);
+                        None
+                    }
+                };
+                if let (Some(state), SyncStatus::Synced(vt), Some(fingerprint)) =
+                    (state.as_deref_mut(), item.sync_status(), fingerprint)
+                {
+                    state.items.insert(url.clone(), (vt.clone(), fingerprint));
+                }
+                let restored = match primary.items.get(&url) {
+                    Some((status, former)) if Some(*former) == fingerprint => status.clone(),
+                    Some((status, _)) => modified_status(status),
+                    // This item has been pulled from this source
+                    None => SyncStatus::NotSynced,
+                };
+                item.relabel_sync_status(restored);
+                current_urls.insert(url);
+            }
+            // Items that have been deleted from this source
+            if let Some(state) = state.as_deref_mut() {
+                state.items.retain(|url, _| current_urls.contains(url));
+            }
+        }
+    }
+    for item in primary.hidden_items {
+        if let Err(err) = cal.add_item(item).await {
+            errors.push(err);
+        }
+    }
+
+    let nsns: Vec<NamespacedName> = cal.get_properties().await.keys().cloned().collect();
+    for nsn in &nsns {
+        let prop = match cal.get_property_by_name_mut(nsn).await {
+            None => continue,
+            Some(prop) => prop,
+        };
+        if let (Some(state), SyncStatus::Synced(_)) = (state.as_deref_mut(), prop.sync_status()) {
+            state.props.insert(nsn.clone(), prop.value().clone());
+        }
+        let restored = match primary.props.get(nsn) {
+            Some((status, former)) if former == prop.value() => status.clone(),
+            Some((status, _)) => modified_status(status),
+            None => SyncStatus::NotSynced,
+        };
+        prop.relabel_sync_status(restored);
+    }
+    if let Some(state) = state {
+        state.props.retain(|nsn, _| nsns.contains(nsn));
+    }
+    for prop in primary.hidden_props {
+        if let Err(err) = cal.add_property(prop).await {
+            errors.push(err);
+        }
+    }
+}
+
+/// The status an item or a prop should have, once its content has changed
+fn modified_status(status: &SyncStatus) -> SyncStatus {
+    match status {
+        SyncStatus::Synced(vt) => SyncStatus::LocallyModified(vt.clone()),
+        other => other.clone(),
+    }
+}
+
+/// A hash of the content of an item, that is stable across runs (see [`stable_hash`])
+fn fingerprint(item: &Item) -> KFResult<u64> {
+    Ok(stable_hash(&crate::ical::build_from(item)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use crate::cache::Cache;
+    use crate::calendar::cached_calendar::CachedCalendar;
+    use crate::calendar::remote_calendar::RemoteCalendar;
+    use crate::calendar::SupportedComponents;
+    use crate::client::Client;
+    use crate::task::Task;
+    use crate::traits::BaseCalendar;
+
+    #[tokio::test]
+    async fn test_failing_local_view_preparation() {
+        let cal_url: Url = "https://some.calend.ar/prepared/".parse().unwrap();
+        let mut local = Cache::new(&PathBuf::from("test_cache/multi_preparation/"));
+        let cal = local
+            .create_calendar(
+                cal_url.clone(),
+                "Prepared".to_string(),
+                SupportedComponents::TODO,
+                None,
+            )
+            .await
+            .unwrap();
+
+        // A synced item that the mirror knows, and two local deletions it has never seen
+        let mut statuses = HashMap::new();
+        let mut state = SourceState::default();
+        for (name, deleted) in &[("Known", false), ("Deleted", true), ("Also deleted", true)] {
+            let mut task = Task::new(name.to_string(), false, &cal_url).unwrap();
+            let primary_tag = VersionTag::from(format!("primary-{}", name));
+            task.set_sync_status(SyncStatus::Synced(primary_tag.clone()));
+            if *deleted {
+                task.set_sync_status(SyncStatus::LocallyDeleted(primary_tag));
+            } else {
+                let known = (
+                    VersionTag::from("mirror-tag".to_string()),
+                    fingerprint(&Item::Task(task.clone())).unwrap(),
+                );
+                state.items.insert(task.url().clone(), known);
+            }
+            statuses.insert(task.url().clone(), task.sync_status().clone());
+            cal.lock().await.add_item(Item::Task(task)).await.unwrap();
+        }
+        cal.lock().await.set_source_state("mirror", state).await;
+
+        let mut provider: MultiProvider<Cache, CachedCalendar, Client, RemoteCalendar> =
+            MultiProvider::new(
+                Client::new("https://some.calend.ar/", "user", "pass").unwrap(),
+                local,
+            );
+        provider.add_source(
+            "mirror".to_string(),
+            Client::new("https://mirror.calend.ar/", "user", "pass").unwrap(),
+        );
+        // The known item has been relabeled and the first deletion has been hidden by the time this one fails
+        provider.unhideable_item = statuses
+            .iter()
+            .filter(|(_, status)| matches!(status, SyncStatus::LocallyDeleted(_)))
+            .map(|(url, _)| url.clone())
+            .max();
+
+        let mut progress = SyncProgress::new();
+        assert!(provider.prepare_local_view(0, &mut progress).await.is_err());
+        let cal = cal.lock().await;
+        let items = cal.get_items().await.unwrap();
+        assert_eq!(items.len(), 3);
+        for (url, item) in items {
+            assert_eq!(item.sync_status(), &statuses[&url]);
+        }
+    }
+}
@@ -1,6 +1,21 @@
 //! Utilities to track the progression of a sync
 
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{Display, Error, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use url::Url;
+
+use crate::error::{KFError, KFResult};
+use crate::item::ItemType;
+use crate::provider::hooks::{Decision, DestructiveChangeGuard, ItemHooks, PlannedChange};
+use crate::provider::ValidationPolicy;
+use crate::resource::{RequestIds, TransferCounter};
+use crate::utils::lock_ignoring_poison;
+use crate::utils::NamespacedName;
 
 /// An event that happens during a sync
 #[derive(Clone, Debug)]
@@ -8,12 +23,21 @@ pub enum SyncEvent {
     /// Sync has not started
     NotStarted,
     /// Sync has just started but no calendar is handled yet
-    Started,
+    Started {
+        /// The ID of this sync (see [`SyncResult::sync_id`])
+        sync_id: String,
+    },
 
     /// Item sync is in progress.
     ItemsInProgress {
         calendar_name: String,
         items_done_already: usize,
+        /// How many items this calendar has to sync, once it is known (i.e. once the items of both sources have been listed)
+        items_total: Option<usize>,
+        /// The estimated remaining time to sync the items of the calendar, from the throughput observed so far
+        eta: Option<Duration>,
+        /// The progress of the whole sync, across every calendar (see [`OverallProgress`])
+        overall: Option<OverallProgress>,
         details: String,
     },
 
@@ -24,24 +48,49 @@ pub enum SyncEvent {
         details: String,
     },
 
+    /// The server has asked to slow down. The download of the items of this calendar is paused for `retry_in`, and then resumes where it stopped
+    RateLimited {
+        calendar_name: String,
+        retry_in: Duration,
+    },
+
+    /// Some events have been dropped because the listener did not keep up with them (see [`bounded_feedback_channel`])
+    Lossy { dropped: usize },
+
     /// Sync is finished
-    Finished { success: bool },
+    Finished {
+        success: bool,
+        /// What the sync has done
+        summary: SyncSummary,
+    },
 }
 
 impl Display for SyncEvent {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         match self {
             SyncEvent::NotStarted => write!(f, "Not started"),
-            SyncEvent::Started => write!(f, "Sync has started..."),
+            SyncEvent::Started { sync_id } => write!(f, "Sync {} has started...", sync_id),
             SyncEvent::ItemsInProgress {
                 calendar_name,
                 items_done_already,
+                items_total,
+                eta,
+                overall,
                 details,
-            } => write!(
-                f,
-                "(i) {} [{}/?] {}...",
-                calendar_name, items_done_already, details
-            ),
+            } => {
+                write!(f, "(i) {} [{}/", calendar_name, items_done_already)?;
+                match items_total {
+                    Some(total) => write!(f, "{}]", total)?,
+                    None => write!(f, "?]")?,
+                }
+                if let Some(overall) = overall {
+                    write!(f, " [{}% overall]", overall.percent())?;
+                }
+                if let Some(eta) = eta {
+                    write!(f, " (about {}s left)", eta.as_secs())?;
+                }
+                write!(f, " {}...", details)
+            }
             SyncEvent::PropsInProgress {
                 calendar_name,
                 props_done_already,
@@ -51,55 +100,551 @@ impl Display for SyncEvent {
                 "(p) {} [{}/?] {}...",
                 calendar_name, props_done_already, details
             ),
-            SyncEvent::Finished { success } => match success {
-                true => write!(f, "Sync successfully finished"),
-                false => write!(f, "Sync finished with errors"),
+            SyncEvent::RateLimited {
+                calendar_name,
+                retry_in,
+            } => write!(
+                f,
+                "(r) {} is rate-limited by the server, resuming in {}s",
+                calendar_name,
+                retry_in.as_secs()
+            ),
+            SyncEvent::Lossy { dropped } => write!(f, "({} events have been dropped)", dropped),
+            SyncEvent::Finished { success, summary } => match success {
+                true => write!(f, "Sync successfully finished ({})", summary),
+                false => write!(f, "Sync finished with errors ({})", summary),
             },
         }
     }
 }
 
+/// The progress of a whole sync, across every calendar, e.g. to show a single progress bar
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OverallProgress {
+    /// How many items have been handled so far
+    pub done: usize,
+    /// How many items the sync has to handle.
+    ///
+    /// This is estimated before the sync starts from the remote items and the pending local changes of every calendar, and refined once the actual changes of a calendar are known.
+    /// It may thus decrease during the sync, but never goes below `done`
+    pub total: usize,
+}
+
+impl OverallProgress {
+    /// The progress, from 0.0 to 1.0
+    pub fn fraction(&self) -> f64 {
+        match self.total {
+            0 => 1.0,
+            total => self.done as f64 / total as f64,
+        }
+    }
+
+    /// The progress, as a percentage
+    pub fn percent(&self) -> u8 {
+        (self.fraction() * 100.0).floor() as u8
+    }
+}
+
+/// How [`SyncProgress`] computes the [`OverallProgress`]
+#[derive(Debug, Default)]
+struct OverallPlan {
+    /// How many items each calendar is expected to handle
+    estimates: HashMap<Url, usize>,
+    /// The calendar that is being synced
+    current: Option<Url>,
+    /// How many items the calendars that have been synced already have handled
+    done: usize,
+}
+
 impl Default for SyncEvent {
     fn default() -> Self {
         Self::NotStarted
     }
 }
 
-/// See [`feedback_channel`]
-pub type FeedbackSender = tokio::sync::watch::Sender<SyncEvent>;
+/// A problem that happened during a sync, and that applications may want to know about (e.g. to file automatic bug reports)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyncIssue {
+    /// The local or the remote data were not in the state the sync algorithm expected
+    Inconsistency {
+        /// The calendar being synced
+        calendar: Url,
+        /// The item involved (or the calendar itself, in case this is about a calendar property)
+        url: Url,
+        /// The calendar property involved, if any
+        property: Option<NamespacedName>,
+        expected: String,
+        found: String,
+    },
+    /// A local item could not be uploaded, because the remote calendar does not accept this kind of items (see [`SupportedComponents`](crate::calendar::SupportedComponents))
+    UnsupportedComponent {
+        calendar: Url,
+        url: Url,
+        type_: ItemType,
+    },
+}
+
+impl Display for SyncIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            SyncIssue::Inconsistency {
+                calendar,
+                url,
+                property,
+                expected,
+                found,
+            } => {
+                write!(f, "Inconsistent state in calendar {}: ", calendar)?;
+                match property {
+                    Some(nsn) => write!(f, "prop {}", nsn)?,
+                    None => write!(f, "item {}", url)?,
+                }
+                write!(f, " was expected to be {}, but is {}", expected, found)
+            }
+            SyncIssue::UnsupportedComponent {
+                calendar,
+                url,
+                type_,
+            } => write!(
+                f,
+                "Item {} cannot be uploaded: calendar {} does not accept {:?} items",
+                url, calendar, type_
+            ),
+        }
+    }
+}
+
+/// Something a sync has not been able to handle this time. The next sync (or [`Provider::retry_skipped`](crate::provider::Provider::retry_skipped)) tries again
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Skipped {
+    /// A whole calendar has not been synced
+    Calendar { url: Url, reason: String },
+    /// Some items of a calendar have not been synced, while the rest of the calendar has been
+    Items {
+        calendar: Url,
+        urls: Vec<Url>,
+        reason: String,
+    },
+}
+
+impl Skipped {
+    /// The calendar that has been skipped, or that contains the skipped items
+    pub fn calendar(&self) -> &Url {
+        match self {
+            Skipped::Calendar { url, .. } => url,
+            Skipped::Items { calendar, .. } => calendar,
+        }
+    }
+
+    /// Why it has been skipped
+    pub fn reason(&self) -> &str {
+        match self {
+            Skipped::Calendar { reason, .. } | Skipped::Items { reason, .. } => reason,
+        }
+    }
+}
+
+impl Display for Skipped {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Skipped::Calendar { url, reason } => {
+                write!(f, "Calendar {} skipped this time: {}", url, reason)
+            }
+            Skipped::Items {
+                calendar,
+                urls,
+                reason,
+            } => write!(
+                f,
+                "{} item(s) of calendar {} skipped this time ({:?}): {}",
+                urls.len(),
+                calendar,
+                urls.iter().map(|url| url.as_str()).collect::<Vec<_>>(),
+                reason
+            ),
+        }
+    }
+}
+
+/// What a sync has done
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncSummary {
+    /// Local items that have been uploaded to the server
+    pub local_additions: usize,
+    /// Local changes that have been uploaded to the server
+    pub local_changes: usize,
+    /// Local deletions that have been applied on the server
+    pub local_deletions: usize,
+    /// Items of the server that have been downloaded
+    pub remote_additions: usize,
+    /// Changes of the server that have been downloaded
+    pub remote_changes: usize,
+    /// Deletions of the server that have been applied locally
+    pub remote_deletions: usize,
+    /// The items that had been modified in both sources, and whose conflict has been resolved (see [`ConflictStrategy`](crate::provider::ConflictStrategy))
+    pub conflicts: Vec<Url>,
+    /// Local items that have been modified while they were being uploaded. Their new versions are pushed by the next sync
+    pub modified_during_sync: Vec<Url>,
+}
+
+impl Display for SyncSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(
+            f,
+            "pushed {} additions, {} changes, {} deletions; pulled {} additions, {} changes, {} deletions; {} conflicts",
+            self.local_additions,
+            self.local_changes,
+            self.local_deletions,
+            self.remote_additions,
+            self.remote_changes,
+            self.remote_deletions,
+            self.conflicts.len()
+        )
+    }
+}
+
+/// Figures about the data exchanged during a sync
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncMetrics {
+    /// The size of the HTTP request bodies
+    pub bytes_sent: u64,
+    /// The size of the HTTP response bodies, once decompressed
+    pub bytes_received: u64,
+    /// Whether the sync stopped downloading items because of [`Provider::set_max_download_bytes`](crate::provider::Provider::set_max_download_bytes)
+    pub download_limit_reached: bool,
+    /// How long the sync has waited because the server rate-limited its requests
+    pub rate_limit_wait: Duration,
+}
+
+/// The outcome of a sync
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncResult {
+    sync_id: String,
+    success: bool,
+    issues: Vec<SyncIssue>,
+    skipped: Vec<Skipped>,
+    metrics: SyncMetrics,
+    summary: SyncSummary,
+    locked: bool,
+}
+
+impl SyncResult {
+    /// The unique ID of this sync.
+    ///
+    /// It prefixes the log lines of the sync, as well as the IDs of the requests it has sent (see [`RequestIds`]), so that a failed sync can be found in the logs of the application and of the server
+    pub fn sync_id(&self) -> &str {
+        &self.sync_id
+    }
+
+    /// Whether the sync was totally successful
+    pub fn is_success(&self) -> bool {
+        self.success
+    }
+
+    /// Whether the sync has not run at all, because another process was syncing or saving the local source (see [`KFError::CacheLocked`]).
+    /// It can be started again once the other process is done
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Whether the sync was successful, and did not leave any item to download for the next sync
+    pub fn is_complete(&self) -> bool {
+        self.success && !self.metrics.download_limit_reached
+    }
+
+    /// The issues that happened during the sync
+    pub fn issues(&self) -> &[SyncIssue] {
+        &self.issues
+    }
+
+    /// The calendars and items that have not been synced this time (see [`Provider::retry_skipped`](crate::provider::Provider::retry_skipped))
+    pub fn skipped(&self) -> &[Skipped] {
+        &self.skipped
+    }
+
+    pub fn metrics(&self) -> &SyncMetrics {
+        &self.metrics
+    }
+
+    /// What the sync has done
+    pub fn summary(&self) -> &SyncSummary {
+        &self.summary
+    }
+}
+
+/// Where the events of a sync are sent, see [`feedback_channel`] and [`bounded_feedback_channel`].
+///
+/// Sending an event never waits for the listener, so that a slow listener cannot stall the sync
+#[derive(Debug)]
+pub struct FeedbackSender(FeedbackSink);
+
+#[derive(Debug)]
+enum FeedbackSink {
+    Latest(tokio::sync::watch::Sender<SyncEvent>),
+    Queue(Arc<FeedbackQueue>),
+}
+
+impl FeedbackSender {
+    /// Send an event to the listener (if it still listens)
+    pub fn send(&self, event: SyncEvent) {
+        match &self.0 {
+            FeedbackSink::Latest(sender) => {
+                let _ = sender.send(event);
+            }
+            FeedbackSink::Queue(queue) => queue.push(event),
+        }
+    }
+}
+
+impl From<tokio::sync::watch::Sender<SyncEvent>> for FeedbackSender {
+    fn from(sender: tokio::sync::watch::Sender<SyncEvent>) -> Self {
+        Self(FeedbackSink::Latest(sender))
+    }
+}
+
+impl Drop for FeedbackSender {
+    fn drop(&mut self) {
+        if let FeedbackSink::Queue(queue) = &self.0 {
+            lock_ignoring_poison(&queue.state).closed = true;
+            queue.notify.notify_one();
+        }
+    }
+}
+
 /// See [`feedback_channel`]
 pub type FeedbackReceiver = tokio::sync::watch::Receiver<SyncEvent>;
 
-/// Create a feeback channel, that can be used to retrieve the current progress of a sync operation
+/// Create a feeback channel, that can be used to retrieve the current progress of a sync operation.
+///
+/// The receiver only sees the latest event: the ones it has not looked at in time are replaced by the next ones.
+/// See [`bounded_feedback_channel`] to receive every event (unless the listener is too slow)
 pub fn feedback_channel() -> (FeedbackSender, FeedbackReceiver) {
-    tokio::sync::watch::channel(SyncEvent::default())
+    let (sender, receiver) = tokio::sync::watch::channel(SyncEvent::default());
+    (sender.into(), receiver)
+}
+
+/// What a [`bounded_feedback_channel`] does with a new event once it is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedbackOverflow {
+    /// Drop the oldest events. The receiver gets a [`SyncEvent::Lossy`] event where they were
+    DropOldest,
+    /// Replace the last event with the new one when both are progress events of the same calendar (e.g. `[3/10]` by `[4/10]`).
+    /// The oldest events are dropped (as with [`Self::DropOldest`]) in case this is not enough
+    CoalesceProgress,
+}
+
+/// Create a feedback channel that queues up to `capacity` events, so that the listener can receive every event in order.
+///
+/// The sync never waits for the listener: in case it is too slow and the queue is full, events are dropped or merged as `overflow` says
+pub fn bounded_feedback_channel(
+    capacity: usize,
+    overflow: FeedbackOverflow,
+) -> (FeedbackSender, BoundedFeedbackReceiver) {
+    let queue = Arc::new(FeedbackQueue {
+        capacity: capacity.max(1),
+        overflow,
+        state: std::sync::Mutex::new(QueueState::default()),
+        notify: tokio::sync::Notify::new(),
+    });
+    (
+        FeedbackSender(FeedbackSink::Queue(queue.clone())),
+        BoundedFeedbackReceiver { queue },
+    )
+}
+
+/// See [`bounded_feedback_channel`]
+#[derive(Debug)]
+pub struct BoundedFeedbackReceiver {
+    queue: Arc<FeedbackQueue>,
+}
+
+impl BoundedFeedbackReceiver {
+    /// Wait for the next event. Returns `None` once the sender has been dropped and every event has been received
+    pub async fn recv(&mut self) -> Option<SyncEvent> {
+        loop {
+            let notified = self.queue.notify.notified();
+            {
+                let mut state = lock_ignoring_poison(&self.queue.state);
+                if let Some(event) = state.pop() {
+                    return Some(event);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// The next event, if there is one already
+    pub fn try_recv(&mut self) -> Option<SyncEvent> {
+        lock_ignoring_poison(&self.queue.state).pop()
+    }
+}
+
+#[derive(Debug)]
+struct FeedbackQueue {
+    capacity: usize,
+    overflow: FeedbackOverflow,
+    state: std::sync::Mutex<QueueState>,
+    notify: tokio::sync::Notify,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    events: VecDeque<SyncEvent>,
+    /// How many events have been dropped since the receiver last got an event
+    dropped: usize,
+    closed: bool,
+}
+
+impl FeedbackQueue {
+    fn push(&self, event: SyncEvent) {
+        {
+            let mut state = lock_ignoring_poison(&self.state);
+            if state.events.len() >= self.capacity {
+                let coalesced = self.overflow == FeedbackOverflow::CoalesceProgress
+                    && state
+                        .events
+                        .back()
+                        .is_some_and(|last| same_progress(last, &event));
+                if coalesced {
+                    state.events.pop_back();
+                } else {
+                    state.events.pop_front();
+                    state.dropped += 1;
+                }
+            }
+            state.events.push_back(event);
+        }
+        self.notify.notify_one();
+    }
+}
+
+impl QueueState {
+    fn pop(&mut self) -> Option<SyncEvent> {
+        if self.dropped > 0 {
+            let dropped = std::mem::take(&mut self.dropped);
+            return Some(SyncEvent::Lossy { dropped });
+        }
+        self.events.pop_front()
+    }
+}
+
+/// Whether `new` is a later state of the same progress as `old`, so that it can replace it
+fn same_progress(old: &SyncEvent, new: &SyncEvent) -> bool {
+    match (old, new) {
+        (
+            SyncEvent::ItemsInProgress {
+                calendar_name: old, ..
+            },
+            SyncEvent::ItemsInProgress {
+                calendar_name: new, ..
+            },
+        )
+        | (
+            SyncEvent::PropsInProgress {
+                calendar_name: old, ..
+            },
+            SyncEvent::PropsInProgress {
+                calendar_name: new, ..
+            },
+        ) => old == new,
+        _ => false,
+    }
 }
 
 /// A structure that tracks the progression and the errors that happen during a sync
 pub struct SyncProgress {
+    sync_id: String,
     n_errors: u32,
+    issues: Vec<SyncIssue>,
+    skipped: Vec<Skipped>,
+    summary: SyncSummary,
     feedback_channel: Option<FeedbackSender>,
     counter: usize,
+    /// How many items the current calendar has to sync, once known
+    items_total: Option<usize>,
+    /// When the current calendar started syncing its items
+    items_started_at: Option<DateTime<Utc>>,
+    /// The counters of the sources involved in this sync, with their `(sent, received)` values when they started being tracked
+    transfers: Vec<(TransferCounter, u64, u64)>,
+    /// The request IDs of the sources involved in this sync, that are numbered in the scope of this sync until it is dropped
+    request_ids: Vec<RequestIds>,
+    max_download_bytes: Option<u64>,
+    download_limit_reached: bool,
+    rate_limit_wait: Duration,
+    item_hooks: Option<Arc<dyn ItemHooks>>,
+    validation_policy: ValidationPolicy,
+    destructive_change_guard: Option<Arc<dyn DestructiveChangeGuard>>,
+    strict: bool,
+    /// The first issue of the current calendar, for strict syncs
+    strict_violation: Option<SyncIssue>,
+    overall: Option<OverallPlan>,
+    local_locked: bool,
 }
 impl SyncProgress {
     pub fn new() -> Self {
         Self {
+            sync_id: uuid::Uuid::new_v4().to_hyphenated().to_string(),
             n_errors: 0,
+            issues: Vec::new(),
+            skipped: Vec::new(),
+            summary: SyncSummary::default(),
             feedback_channel: None,
             counter: 0,
+            items_total: None,
+            items_started_at: None,
+            transfers: Vec::new(),
+            request_ids: Vec::new(),
+            max_download_bytes: None,
+            download_limit_reached: false,
+            rate_limit_wait: Duration::ZERO,
+            item_hooks: None,
+            validation_policy: ValidationPolicy::default(),
+            destructive_change_guard: None,
+            strict: false,
+            strict_violation: None,
+            overall: None,
+            local_locked: false,
         }
     }
     pub fn new_with_feedback_channel(channel: FeedbackSender) -> Self {
         Self {
+            sync_id: uuid::Uuid::new_v4().to_hyphenated().to_string(),
             n_errors: 0,
+            issues: Vec::new(),
+            skipped: Vec::new(),
+            summary: SyncSummary::default(),
             feedback_channel: Some(channel),
             counter: 0,
+            items_total: None,
+            items_started_at: None,
+            transfers: Vec::new(),
+            request_ids: Vec::new(),
+            max_download_bytes: None,
+            download_limit_reached: false,
+            rate_limit_wait: Duration::ZERO,
+            item_hooks: None,
+            validation_policy: ValidationPolicy::default(),
+            destructive_change_guard: None,
+            strict: false,
+            strict_violation: None,
+            overall: None,
+            local_locked: false,
         }
     }
 
-    /// Reset the user-info counter
+    /// The unique ID of this sync, see [`SyncResult::sync_id`]
+    pub fn sync_id(&self) -> &str {
+        &self.sync_id
+    }
+
+    /// Reset the user-info counter (and the estimations that are based on it)
     pub fn reset_counter(&mut self) {
         self.counter = 0;
+        self.items_total = None;
+        self.items_started_at = None;
     }
     /// Increments the user-info counter.
     pub fn increment_counter(&mut self, increment: usize) {
@@ -112,37 +657,340 @@ impl SyncProgress {
         self.counter
     }
 
+    /// Set how many items the current calendar has to sync. The remaining time is estimated from the time elapsed since the first call after [`Self::reset_counter`]
+    pub fn set_items_total(&mut self, total: usize) {
+        self.items_total = Some(total);
+        if self.items_started_at.is_none() {
+            self.items_started_at = Some(crate::clock::now());
+        }
+    }
+    /// How many items the current calendar has to sync, if known
+    pub fn items_total(&self) -> Option<usize> {
+        self.items_total
+    }
+
+    /// The estimated remaining time to sync the items of the current calendar, based on the throughput observed so far.
+    ///
+    /// This is `None` until [`Self::set_items_total`] has been called and some items have been handled
+    pub fn eta(&self) -> Option<Duration> {
+        self.eta_at(crate::clock::now())
+    }
+
+    fn eta_at(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let total = self.items_total?;
+        let started_at = self.items_started_at?;
+        if self.counter == 0 {
+            return None;
+        }
+        let elapsed = (now - started_at).to_std().ok()?;
+        let remaining = total.saturating_sub(self.counter);
+        Some(elapsed.mul_f64(remaining as f64 / self.counter as f64))
+    }
+
+    /// Whether events are sent to a feedback channel
+    pub fn has_feedback_channel(&self) -> bool {
+        self.feedback_channel.is_some()
+    }
+
+    /// Plan that the calendar at `url` will handle about `items` items during this sync (e.g. its remote items and its pending local changes).
+    /// Once a calendar has been planned, the [`OverallProgress`] of the sync is tracked
+    pub fn plan_calendar(&mut self, url: &Url, items: usize) {
+        self.overall
+            .get_or_insert_with(OverallPlan::default)
+            .estimates
+            .insert(url.clone(), items);
+    }
+
+    /// The calendar at `url` starts being synced. Its counter is reset (see [`Self::reset_counter`])
+    pub fn calendar_started(&mut self, url: &Url) {
+        self.calendar_finished();
+        self.reset_counter();
+        self.strict_violation = None;
+        if let Some(plan) = &mut self.overall {
+            plan.current = Some(url.clone());
+        }
+    }
+
+    /// The current calendar is done (or has been skipped), whatever is left of its planned items is considered as handled
+    pub fn calendar_finished(&mut self) {
+        let current_total = self.current_calendar_total();
+        if let Some(plan) = &mut self.overall {
+            if let Some(url) = plan.current.take() {
+                plan.done += current_total;
+                plan.estimates.insert(url, current_total);
+            }
+        }
+    }
+
+    /// How many items the current calendar handles: the actual number once it is known, its estimate otherwise
+    fn current_calendar_total(&self) -> usize {
+        let plan = match &self.overall {
+            None => return 0,
+            Some(plan) => plan,
+        };
+        let estimate = plan
+            .current
+            .as_ref()
+            .and_then(|url| plan.estimates.get(url))
+            .copied()
+            .unwrap_or(0);
+        self.items_total.unwrap_or(estimate)
+    }
+
+    /// The progress of the whole sync, if calendars have been planned (see [`Self::plan_calendar`])
+    pub fn overall(&self) -> Option<OverallProgress> {
+        let plan = self.overall.as_ref()?;
+        let current_total = self.current_calendar_total();
+        let current = plan.current.as_ref();
+        let others: usize = plan
+            .estimates
+            .iter()
+            .filter(|(url, _)| Some(*url) != current)
+            .map(|(_, estimate)| estimate)
+            .sum();
+        Some(OverallProgress {
+            done: plan.done + self.counter.min(current_total),
+            total: others + current_total,
+        })
+    }
+
+    /// An [`SyncEvent::ItemsInProgress`] event for the current state of the sync
+    pub fn items_in_progress(&self, calendar_name: &str, details: String) -> SyncEvent {
+        SyncEvent::ItemsInProgress {
+            calendar_name: calendar_name.to_string(),
+            items_done_already: self.counter,
+            items_total: self.items_total,
+            eta: self.eta(),
+            overall: self.overall(),
+            details,
+        }
+    }
+
     pub fn is_success(&self) -> bool {
         self.n_errors == 0
     }
 
+    /// The outcome of the sync so far
+    pub fn result(&self) -> SyncResult {
+        SyncResult {
+            sync_id: self.sync_id.clone(),
+            success: self.is_success(),
+            issues: self.issues.clone(),
+            skipped: self.skipped.clone(),
+            metrics: self.metrics(),
+            summary: self.summary.clone(),
+            locked: self.local_locked,
+        }
+    }
+
+    /// What the sync has done so far
+    pub fn summary(&self) -> &SyncSummary {
+        &self.summary
+    }
+    pub(crate) fn summary_mut(&mut self) -> &mut SyncSummary {
+        &mut self.summary
+    }
+    /// Record that the item at `url` had been modified in both sources
+    pub fn record_conflict(&mut self, url: &Url) {
+        if !self.summary.conflicts.contains(url) {
+            self.summary.conflicts.push(url.clone());
+        }
+    }
+
+    /// Account for the data exchanged with a source from now on. Tracking the same counter twice has no effect
+    pub fn track_transfers(&mut self, counter: TransferCounter) {
+        if self
+            .transfers
+            .iter()
+            .any(|(tracked, _, _)| tracked.is_shared_with(&counter))
+        {
+            return;
+        }
+        let (sent, received) = (counter.sent(), counter.received());
+        self.transfers.push((counter, sent, received));
+    }
+
+    /// Number the requests of a source in the scope of this sync, until this `SyncProgress` is dropped. Tracking the same IDs twice has no effect
+    pub fn track_request_ids(&mut self, ids: RequestIds) {
+        if self
+            .request_ids
+            .iter()
+            .any(|tracked| tracked.is_shared_with(&ids))
+        {
+            return;
+        }
+        ids.set_scope(Some(&self.sync_id));
+        self.request_ids.push(ids);
+    }
+
+    /// Stop downloading items once this amount of data has been received (see [`Self::download_limit_reached`])
+    pub fn set_max_download_bytes(&mut self, max_download_bytes: Option<u64>) {
+        self.max_download_bytes = max_download_bytes;
+    }
+
+    /// The hooks to call on the items that are uploaded and downloaded (see [`ItemHooks`])
+    pub fn set_item_hooks(&mut self, item_hooks: Option<Arc<dyn ItemHooks>>) {
+        self.item_hooks = item_hooks;
+    }
+
+    /// See [`Self::set_item_hooks`]
+    pub fn item_hooks(&self) -> Option<Arc<dyn ItemHooks>> {
+        self.item_hooks.clone()
+    }
+
+    /// How the items are checked before they are uploaded (see [`ValidationPolicy`])
+    pub fn set_validation_policy(&mut self, policy: ValidationPolicy) {
+        self.validation_policy = policy;
+    }
+
+    /// See [`Self::set_validation_policy`]
+    pub fn validation_policy(&self) -> ValidationPolicy {
+        self.validation_policy
+    }
+
+    /// Consulted before the destructive changes of this sync (see [`DestructiveChangeGuard`])
+    pub fn set_destructive_change_guard(&mut self, guard: Option<Arc<dyn DestructiveChangeGuard>>) {
+        self.destructive_change_guard = guard;
+    }
+
+    /// What to do with a destructive change, according to the guard of this sync. Changes are allowed when there is no guard
+    pub fn decide(&mut self, change: &PlannedChange) -> Decision {
+        let decision = match &self.destructive_change_guard {
+            None => return Decision::Allow,
+            Some(guard) => guard.on_destructive_change(change),
+        };
+        if decision == Decision::Skip {
+            self.info(&format!("Skipping {}, as requested", change));
+        }
+        decision
+    }
+
+    /// Whether the sync of a calendar stops at its first [`SyncIssue`] (see [`Self::check_strict`])
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// See [`Self::set_strict`]
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// For strict syncs, fails in case an issue has been found since the current calendar started syncing (see [`Self::calendar_started`])
+    pub fn check_strict(&self) -> KFResult<()> {
+        match &self.strict_violation {
+            Some(issue) => Err(KFError::SyncInconsistency {
+                issue: Box::new(issue.clone()),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The data exchanged so far with the tracked sources
+    pub fn metrics(&self) -> SyncMetrics {
+        let mut metrics = SyncMetrics {
+            download_limit_reached: self.download_limit_reached,
+            rate_limit_wait: self.rate_limit_wait,
+            ..SyncMetrics::default()
+        };
+        for (counter, sent, received) in &self.transfers {
+            metrics.bytes_sent += counter.sent() - sent;
+            metrics.bytes_received += counter.received() - received;
+        }
+        metrics
+    }
+
+    /// Whether the sync has received the maximum amount of data it is allowed to, and must not download more items
+    pub fn download_limit_reached(&mut self) -> bool {
+        if !self.download_limit_reached {
+            if let Some(max) = self.max_download_bytes {
+                let received = self.metrics().bytes_received;
+                if received >= max {
+                    self.info(&format!(
+                        "{} bytes have been received (the limit is {}). Pausing the download of items until the next sync",
+                        received, max
+                    ));
+                    self.download_limit_reached = true;
+                }
+            }
+        }
+        self.download_limit_reached
+    }
+
+    /// Record that the server has rate-limited the requests about a calendar, and that the sync waits for `delay` before resuming.
+    /// This is notified to the feedback channel
+    pub fn rate_limited(&mut self, calendar_name: &str, delay: Duration) {
+        self.info(&format!(
+            "Calendar {} is rate-limited by the server, waiting {:?}",
+            calendar_name, delay
+        ));
+        self.rate_limit_wait += delay;
+        self.feedback(SyncEvent::RateLimited {
+            calendar_name: calendar_name.to_string(),
+            retry_in: delay,
+        });
+    }
+
+    /// Log an issue as an error, and keep it for the [`SyncResult`]
+    pub fn issue(&mut self, issue: SyncIssue) {
+        self.error(&issue.to_string());
+        if self.strict && self.strict_violation.is_none() {
+            self.strict_violation = Some(issue.clone());
+        }
+        self.issues.push(issue);
+    }
+
+    /// Log something that has been skipped as a warning, and keep it for the [`SyncResult`]
+    pub fn skip(&mut self, skipped: Skipped) {
+        self.warn(&skipped.to_string());
+        self.skipped.push(skipped);
+    }
+
+    /// What has been skipped so far
+    pub fn skipped(&self) -> &[Skipped] {
+        &self.skipped
+    }
+
+    /// Record that the sync could not start, because the local source is locked by another process
+    pub fn local_source_locked(&mut self) {
+        self.local_locked = true;
+    }
+
     /// Log an error
     pub fn error(&mut self, text: &str) {
-        log::error!("{}", text);
+        log::error!("[sync {}] {}", self.sync_id, text);
         self.n_errors += 1;
     }
     /// Log a warning
     pub fn warn(&mut self, text: &str) {
-        log::warn!("{}", text);
+        log::warn!("[sync {}] {}", self.sync_id, text);
         self.n_errors += 1;
     }
     /// Log an info
     pub fn info(&mut self, text: &str) {
-        log::info!("{}", text);
+        log::info!("[sync {}] {}", self.sync_id, text);
     }
     /// Log a debug message
     pub fn debug(&mut self, text: &str) {
-        log::debug!("{}", text);
+        log::debug!("[sync {}] {}", self.sync_id, text);
     }
     /// Log a trace message
     pub fn trace(&mut self, text: &str) {
-        log::trace!("{}", text);
+        log::trace!("[sync {}] {}", self.sync_id, text);
     }
     /// Send an event as a feedback to the listener (if any).
     pub fn feedback(&mut self, event: SyncEvent) {
-        self.feedback_channel
-            .as_ref()
-            .map(|sender| sender.send(event));
+        if let Some(sender) = &self.feedback_channel {
+            sender.send(event);
+        }
+    }
+}
+
+impl Drop for SyncProgress {
+    fn drop(&mut self) {
+        // The next requests are not part of this sync anymore
+        for ids in &self.request_ids {
+            ids.set_scope(None);
+        }
     }
 }
 
@@ -151,3 +999,153 @@ impl Default for SyncProgress {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_eta() {
+        let start = Utc.ymd(2022, 1, 1).and_hms(12, 0, 0);
+        let mut progress = SyncProgress::new();
+        progress.reset_counter();
+        assert_eq!(progress.eta_at(start), None);
+
+        progress.items_total = Some(40);
+        progress.items_started_at = Some(start);
+        assert_eq!(progress.eta_at(start), None);
+
+        progress.increment_counter(10);
+        let now = start + chrono::Duration::seconds(5);
+        assert_eq!(progress.eta_at(now), Some(Duration::from_secs(15)));
+
+        progress.increment_counter(35);
+        assert_eq!(progress.eta_at(now), Some(Duration::ZERO));
+
+        let event = SyncEvent::ItemsInProgress {
+            calendar_name: "Tasks".to_string(),
+            items_done_already: 10,
+            items_total: Some(40),
+            eta: Some(Duration::from_secs(15)),
+            overall: None,
+            details: "Buy milk".to_string(),
+        };
+        assert_eq!(
+            event.to_string(),
+            "(i) Tasks [10/40] (about 15s left) Buy milk..."
+        );
+    }
+
+    #[test]
+    fn test_sync_ids() {
+        let ids = RequestIds::new();
+        let outside_of_syncs = ids.next_id();
+        let first = SyncProgress::new();
+        {
+            let mut progress = SyncProgress::new();
+            assert_ne!(progress.sync_id(), first.sync_id());
+            assert_eq!(progress.result().sync_id(), progress.sync_id());
+            progress.track_request_ids(ids.clone());
+            progress.track_request_ids(ids.clone());
+            assert_eq!(progress.request_ids.len(), 1);
+            assert_eq!(ids.next_id(), format!("{}.2", progress.sync_id()));
+        }
+        // Once the sync is over, requests are not part of it anymore
+        assert_eq!(
+            ids.next_id(),
+            format!("{}3", &outside_of_syncs[..outside_of_syncs.len() - 1])
+        );
+    }
+
+    #[test]
+    fn test_overall_progress() {
+        let cal_a: Url = "https://some.calend.ar/a/".parse().unwrap();
+        let cal_b: Url = "https://some.calend.ar/b/".parse().unwrap();
+        let mut progress = SyncProgress::new();
+        assert_eq!(progress.overall(), None);
+
+        progress.plan_calendar(&cal_a, 10);
+        progress.plan_calendar(&cal_b, 30);
+        let overall = |done, total| Some(OverallProgress { done, total });
+        assert_eq!(progress.overall(), overall(0, 40));
+
+        progress.calendar_started(&cal_a);
+        progress.increment_counter(2);
+        assert_eq!(progress.overall(), overall(2, 40));
+        // The actual changes of a calendar refine its estimate
+        progress.set_items_total(4);
+        progress.increment_counter(1);
+        assert_eq!(progress.overall(), overall(3, 34));
+        // Props are not counted in the overall progress
+        progress.increment_counter(5);
+        assert_eq!(progress.overall(), overall(4, 34));
+
+        progress.calendar_started(&cal_b);
+        assert_eq!(progress.overall(), overall(4, 34));
+        progress.increment_counter(15);
+        assert_eq!(progress.overall().unwrap().percent(), 55);
+        let event = progress.items_in_progress("B", "Buy milk".to_string());
+        assert_eq!(event.to_string(), "(i) B [15/?] [55% overall] Buy milk...");
+
+        // A calendar that fails midway is considered as done
+        progress.calendar_finished();
+        assert_eq!(progress.overall(), overall(34, 34));
+        assert_eq!(progress.overall().unwrap().fraction(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_bounded_feedback_channel() {
+        let progress = |done: usize| SyncEvent::ItemsInProgress {
+            calendar_name: "Tasks".to_string(),
+            items_done_already: done,
+            items_total: None,
+            eta: None,
+            overall: None,
+            details: String::new(),
+        };
+
+        let (sender, mut receiver) = bounded_feedback_channel(2, FeedbackOverflow::DropOldest);
+        sender.send(SyncEvent::Started {
+            sync_id: String::new(),
+        });
+        for done in 0..3 {
+            sender.send(progress(done));
+        }
+        drop(sender);
+        let mut received = Vec::new();
+        while let Some(event) = receiver.recv().await {
+            received.push(event.to_string());
+        }
+        assert_eq!(
+            received,
+            vec![
+                "(2 events have been dropped)",
+                "(i) Tasks [1/?] ...",
+                "(i) Tasks [2/?] ..."
+            ]
+        );
+
+        let (sender, mut receiver) =
+            bounded_feedback_channel(2, FeedbackOverflow::CoalesceProgress);
+        sender.send(SyncEvent::Started {
+            sync_id: String::new(),
+        });
+        for done in 0..5 {
+            sender.send(progress(done));
+        }
+        assert!(matches!(
+            receiver.try_recv(),
+            Some(SyncEvent::Started { .. })
+        ));
+        assert!(matches!(
+            receiver.try_recv(),
+            Some(SyncEvent::ItemsInProgress {
+                items_done_already: 4,
+                ..
+            })
+        ));
+        assert!(receiver.try_recv().is_none());
+    }
+}
@@ -1,13 +1,23 @@
 //! To-do tasks (iCal `VTODO` item)
 
+use std::collections::HashMap;
 use std::fmt::Display;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use ical::property::Property;
 use serde::{Deserialize, Serialize};
+use serde_json_any_key::any_key_map;
 use url::Url;
-use uuid::Uuid;
 
+pub mod dto;
+pub mod patch;
+
+use crate::calendar::item_url_policy::ItemUrlPolicy;
+use crate::error::{KFError, KFResult};
+use crate::ical::duration::{format_duration, parse_duration};
+use crate::ical::DateMaybeTime;
+#[cfg(any(test, feature = "integration_tests"))]
+use crate::utils::diff::FieldDifference;
 use crate::utils::{
     random_url,
     sync::{SyncStatus, Syncable},
@@ -31,42 +41,117 @@ impl CompletionStatus {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Relationship {
     /// The ical RELATED-TO property, see https://datatracker.ietf.org/doc/html/rfc5545#section-3.8.4.5
     ///
     /// This is the UID of a task to which this task is related.
     related_to: String,
 
-    /// The ical RELTYPE parameter as found on a RELATED-TO property.
+    /// The ical RELTYPE parameter as found on a RELATED-TO property, verbatim.
+    /// `None` when the property has no such parameter, which means `PARENT`.
     ///
     /// See https://datatracker.ietf.org/doc/html/rfc5545#section-3.2.15
-    reltype: String,
+    reltype: Option<String>,
 }
 impl Relationship {
-    pub fn new(related_to: String, reltype: String) -> Self {
+    /// The default RELTYPE, when a RELATED-TO property has none
+    pub const DEFAULT_RELTYPE: &'static str = "PARENT";
+
+    pub fn new(related_to: String, reltype: Option<String>) -> Self {
         Self {
             related_to,
             reltype,
         }
     }
+
+    /// A relationship to the parent of a task, without an explicit RELTYPE
+    pub fn parent(related_to: String) -> Self {
+        Self::new(related_to, None)
+    }
+
+    pub fn related_to(&self) -> &str {
+        &self.related_to
+    }
+
+    /// The type of this relationship (e.g. `PARENT`, `CHILD` or `SIBLING`), `PARENT` if none was explicitly set
+    pub fn reltype(&self) -> &str {
+        self.reltype.as_deref().unwrap_or(Self::DEFAULT_RELTYPE)
+    }
+
+    /// The RELTYPE parameter of this relationship, in case it was explicitly set
+    pub fn explicit_reltype(&self) -> Option<&str> {
+        self.reltype.as_deref()
+    }
+
+    /// Whether this relationship has this type. RELTYPE values are case-insensitive
+    pub fn is_of_type(&self, reltype: &str) -> bool {
+        self.reltype().eq_ignore_ascii_case(reltype)
+    }
 }
+/// Formats as the RELATED-TO line of an iCal file (e.g. `RELATED-TO;RELTYPE=CHILD:some-uid`).
+/// The RELTYPE parameter is written exactly as it was parsed, and omitted when it was absent
 impl Display for Relationship {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.reltype.as_str() {
-            "PARENT" => {}
-            x => {
-                f.write_str("RELTYPE=")?;
-                f.write_str(x)?;
-                f.write_str(":")?;
-            }
+        f.write_str("RELATED-TO")?;
+        if let Some(reltype) = &self.reltype {
+            f.write_str(";RELTYPE=")?;
+            f.write_str(reltype)?;
         }
-
+        f.write_str(":")?;
         f.write_str(self.related_to.as_str())
     }
 }
 
+/// Nextcloud Tasks: whether the subtasks of a task are collapsed (`1`) or not (`0`)
+pub const X_OC_HIDESUBTASKS: &str = "X-OC-HIDESUBTASKS";
+/// Nextcloud Tasks and Apple Reminders: the position of a task in a manually sorted list (lower values come first)
+pub const X_APPLE_SORT_ORDER: &str = "X-APPLE-SORT-ORDER";
+/// The estimated time needed to complete a task, as defined by the [tasks extensions to iCalendar](https://datatracker.ietf.org/doc/html/draft-ietf-calext-ical-tasks)
+pub const ESTIMATED_DURATION: &str = "ESTIMATED-DURATION";
+/// The estimated time needed to complete a task, as written by the clients that predate [`ESTIMATED_DURATION`]
+pub const X_ESTIMATED_DURATION: &str = "X-ESTIMATED-DURATION";
+
+/// A part of a task that can be changed independently from the others. See [`Task::local_changes`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TaskField {
+    Name,
+    CompletionStatus,
+    Relationships,
+    /// Every extra parameter with this name (e.g. `DUE` or `CATEGORIES`)
+    ExtraProperty(String),
+}
+
+/// The value of a [`TaskField`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum FieldValue {
+    Name(String),
+    CompletionStatus(CompletionStatus),
+    Relationships(Vec<Relationship>),
+    ExtraProperty(Vec<Property>),
+}
+impl PartialEq for FieldValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Name(a), Self::Name(b)) => a == b,
+            (Self::CompletionStatus(a), Self::CompletionStatus(b)) => a == b,
+            (Self::Relationships(a), Self::Relationships(b)) => a == b,
+            // ical::property::Property does not implement PartialEq
+            (Self::ExtraProperty(a), Self::ExtraProperty(b)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b).all(|(a, b)| {
+                        a.name == b.name && a.params == b.params && a.value == b.value
+                    })
+            }
+            _ => false,
+        }
+    }
+}
+
 /// A to-do task
+///
+/// Its serialization is the internal format of a [`Cache`](crate::cache::Cache), which may change in any version.
+/// Applications that store tasks in their own formats should rather use a [`TaskDto`](dto::TaskDto)
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Task {
     /// The task URL
@@ -96,6 +181,20 @@ pub struct Task {
     /// Related items, derived from the RELATED-TO property.
     relationships: Vec<Relationship>,
 
+    /// The revision sequence number (SEQUENCE property, see https://datatracker.ietf.org/doc/html/rfc5545#section-3.8.7.4)
+    ///
+    /// It is incremented whenever a synced task is locally modified, so that concurrent edits can be told apart.
+    #[serde(default)]
+    sequence: u32,
+
+    /// The fields that have been locally changed since the last sync, with the value they had at that time
+    #[serde(default, with = "any_key_map")]
+    local_changes: HashMap<TaskField, FieldValue>,
+
+    /// How many times this task has been locally modified, so that a sync can tell whether it has been modified while it was being uploaded (see [`Self::generation`])
+    #[serde(default)]
+    generation: u64,
+
     /// Extra parameters that have not been parsed from the iCal file (because they're not supported (yet) by this crate).
     /// They are needed to serialize this item into an equivalent iCal file
     extra_parameters: Vec<Property>,
@@ -104,20 +203,23 @@ pub struct Task {
 impl Task {
     /// Create a brand new Task that is not on a server yet.
     /// This will pick a new (random) task ID.
-    pub fn new(name: String, completed: bool, parent_calendar_url: &Url) -> Self {
-        let new_url = random_url(parent_calendar_url);
+    ///
+    /// This fails in case no item URL can be derived from `parent_calendar_url` (see [`random_url`])
+    pub fn new(name: String, completed: bool, parent_calendar_url: &Url) -> KFResult<Self> {
+        let new_url = random_url(parent_calendar_url)?;
         let new_sync_status = SyncStatus::NotSynced;
-        let new_uid = Uuid::new_v4().to_hyphenated().to_string();
-        let new_creation_date = Some(Utc::now());
-        let new_last_modified = Utc::now();
+        let new_uid = crate::uid::new_uid();
+        let now = crate::clock::now();
+        let new_creation_date = Some(now);
+        let new_last_modified = now;
         let new_completion_status = if completed {
-            CompletionStatus::Completed(Some(Utc::now()))
+            CompletionStatus::Completed(Some(now))
         } else {
             CompletionStatus::Uncompleted
         };
         let ical_prod_id = crate::ical::default_prod_id();
         let extra_parameters = Vec::new();
-        Self::new_with_parameters(
+        Ok(Self::new_with_parameters(
             name,
             new_uid,
             new_url,
@@ -128,7 +230,19 @@ impl Task {
             ical_prod_id,
             Vec::new(),
             extra_parameters,
-        )
+        ))
+    }
+
+    /// Create a brand new Task that is not on a server yet, whose URL follows `policy` (e.g. the [`CompleteCalendar::item_url_policy`](crate::traits::CompleteCalendar::item_url_policy) of its calendar)
+    pub fn new_with_url_policy(
+        name: String,
+        completed: bool,
+        parent_calendar_url: &Url,
+        policy: &ItemUrlPolicy,
+    ) -> KFResult<Self> {
+        let mut task = Self::new(name, completed, parent_calendar_url)?;
+        task.url = policy.item_url(parent_calendar_url, &task.uid)?;
+        Ok(task)
     }
 
     /// Create a new Task instance, that may be synced on the server already
@@ -154,6 +268,9 @@ impl Task {
             last_modified,
             ical_prod_id,
             relationships,
+            sequence: 0,
+            local_changes: HashMap::new(),
+            generation: 0,
             extra_parameters,
         }
     }
@@ -182,72 +299,486 @@ impl Task {
     pub fn completion_status(&self) -> &CompletionStatus {
         &self.completion_status
     }
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+    /// Set the sequence number that has been read from an iCal file
+    pub fn with_sequence(mut self, sequence: u32) -> Self {
+        self.sequence = sequence;
+        self
+    }
+    /// A counter that increases whenever this task is locally modified.
+    ///
+    /// A sync reads it before it uploads the task, and only marks the task as synced in case it has not changed once the upload is done.
+    /// Otherwise, the new local version is kept, and pushed by the next sync
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
     pub fn relationships(&self) -> &Vec<Relationship> {
         &self.relationships
     }
-    /// The UID of the parent of this task, if any
+    /// The relationships of a given type (e.g. `"CHILD"` or `"SIBLING"`). RELTYPE values are case-insensitive
+    pub fn relationships_of_type<'a>(
+        &'a self,
+        reltype: &'a str,
+    ) -> impl Iterator<Item = &'a Relationship> + 'a {
+        self.relationships
+            .iter()
+            .filter(move |r| r.is_of_type(reltype))
+    }
+    /// The UID of the parent of this task, if any.
+    /// In case this task has several parents, this is the first one
     pub fn parent(&self) -> Option<&String> {
         self.relationships
             .iter()
-            .find(|r| r.reltype == "PARENT")
+            .find(|r| r.is_of_type(Relationship::DEFAULT_RELTYPE))
             .map(|r| &r.related_to)
     }
+    /// Replace the (first) parent of this task, or add one.
+    /// This updates its "last modified" field
     pub fn set_parent(&mut self, parent_uid: String) {
-        match self.parent().cloned() {
-            Some(parent) => {
-                self.relationships
-                    .iter_mut()
-                    .find(|r| r.reltype == "PARENT" && r.related_to == parent)
-                    .unwrap()
-                    .related_to = parent_uid;
-            }
-            None => {
-                self.relationships
-                    .push(Relationship::new(parent_uid, "PARENT".to_string()));
-            }
+        self.mark_locally_modified(TaskField::Relationships);
+        match self
+            .relationships
+            .iter_mut()
+            .find(|r| r.is_of_type(Relationship::DEFAULT_RELTYPE))
+        {
+            Some(parent) => parent.related_to = parent_uid,
+            None => self.relationships.push(Relationship::parent(parent_uid)),
+        }
+    }
+    /// Add a relationship, unless this task already has the very same one.
+    /// Returns whether it has been added, in which case this updates its "last modified" field
+    pub fn add_relationship(&mut self, relationship: Relationship) -> bool {
+        if self.relationships.contains(&relationship) {
+            return false;
+        }
+        self.mark_locally_modified(TaskField::Relationships);
+        self.relationships.push(relationship);
+        true
+    }
+    /// Remove every relationship of this type to this UID.
+    /// Returns whether some have been removed, in which case this updates its "last modified" field
+    pub fn remove_relationship(&mut self, related_to: &str, reltype: &str) -> bool {
+        let matches = |r: &Relationship| r.related_to == related_to && r.is_of_type(reltype);
+        if !self.relationships.iter().any(matches) {
+            return false;
         }
+        self.mark_locally_modified(TaskField::Relationships);
+        self.relationships.retain(|r| !matches(r));
+        true
     }
     pub fn extra_parameters(&self) -> &[Property] {
         &self.extra_parameters
     }
+    /// The DUE date, if any (and if it can be parsed). Dates without a time are considered to be at midnight UTC, floating times are considered to be in UTC.
+    ///
+    /// See [`Self::due_in`] to interpret them in the time zone of the user
+    pub fn due(&self) -> Option<DateTime<Utc>> {
+        self.due_in(&Utc)
+    }
+    /// The DUE date, for a user in the time zone `tz` (see [`DateMaybeTime::start_in`])
+    pub fn due_in<Tz: TimeZone>(&self, tz: &Tz) -> Option<DateTime<Utc>> {
+        self.due_at().and_then(|due| due.start_in(tz))
+    }
+    /// The DUE date as it is written in the iCal file, i.e. either a day or a (UTC, floating or zoned) time
+    pub fn due_at(&self) -> Option<DateMaybeTime> {
+        self.date_property("DUE")
+    }
+    /// Set or remove (with `None`) the DUE date.
+    /// This updates its "last modified" field, unless nothing has changed
+    pub fn set_due(&mut self, due: Option<DateMaybeTime>) {
+        let properties = due.map(|due| due.to_property("DUE")).into_iter().collect();
+        self.set_extra_parameters_named("DUE", properties);
+    }
+    /// The DTSTART date as it is written in the iCal file, if any
+    pub fn start_at(&self) -> Option<DateMaybeTime> {
+        self.date_property("DTSTART")
+    }
+    /// The (first) extra parameter with this name, parsed as a date
+    fn date_property(&self, name: &str) -> Option<DateMaybeTime> {
+        self.extra_parameters
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(DateMaybeTime::from_property)
+    }
+    /// The DURATION of this task, if any (and if it can be parsed), i.e. how long after its DTSTART it is due
+    pub fn duration(&self) -> Option<Duration> {
+        self.extension_value("DURATION").and_then(parse_duration)
+    }
+    /// Set or remove (with `None`) the DURATION.
+    /// Since a task cannot have both, setting a duration removes the DUE date. RFC 5545 also requires a DTSTART for tasks that have a duration.
+    /// This updates its "last modified" field, unless nothing has changed
+    pub fn set_duration(&mut self, duration: Option<Duration>) {
+        if duration.is_some() {
+            self.set_due(None);
+        }
+        self.set_extension_value("DURATION", duration.map(format_duration));
+    }
+    /// Same as [`Self::set_duration`], for tasks that are being built
+    pub fn with_duration(mut self, duration: Option<Duration>) -> Self {
+        self.set_duration(duration);
+        self
+    }
+    /// The estimated time needed to complete this task (its [`ESTIMATED_DURATION`] or [`X_ESTIMATED_DURATION`] property), if any and if it can be parsed
+    pub fn estimated_duration(&self) -> Option<Duration> {
+        [ESTIMATED_DURATION, X_ESTIMATED_DURATION]
+            .iter()
+            .find_map(|name| self.extension_value(name).and_then(parse_duration))
+    }
+    /// Set or remove (with `None`) the estimated time needed to complete this task.
+    /// It is written to the property this task already uses, or to [`X_ESTIMATED_DURATION`], which every client keeps.
+    /// This updates its "last modified" field, unless nothing has changed
+    pub fn set_estimated_duration(&mut self, estimate: Option<Duration>) {
+        let name = match self.extension_value(ESTIMATED_DURATION) {
+            Some(_) => ESTIMATED_DURATION,
+            None => X_ESTIMATED_DURATION,
+        };
+        for other in &[ESTIMATED_DURATION, X_ESTIMATED_DURATION] {
+            if *other != name {
+                self.set_extension_value(other, None);
+            }
+        }
+        self.set_extension_value(name, estimate.map(format_duration));
+    }
+    /// Same as [`Self::set_estimated_duration`], for tasks that are being built
+    pub fn with_estimated_duration(mut self, estimate: Option<Duration>) -> Self {
+        self.set_estimated_duration(estimate);
+        self
+    }
+    /// The effort this task is expected to take: its [estimated duration](Self::estimated_duration), or else its [`DURATION`](Self::duration)
+    pub fn estimated_effort(&self) -> Option<Duration> {
+        self.estimated_duration().or_else(|| self.duration())
+    }
+    /// The URL property of this task, i.e. a link to a related web page (e.g. the issue of a tracker this task has been created from), if any and if it is valid.
+    ///
+    /// This must not be confused with [`Self::url`], which is where this task is stored
+    pub fn link(&self) -> Option<Url> {
+        self.extension_value("URL")
+            .and_then(|value| Url::parse(value.trim()).ok())
+    }
+    /// Set or remove (with `None`) the URL property, see [`Self::link`].
+    /// This updates its "last modified" field, unless nothing has changed
+    pub fn set_link(&mut self, link: Option<&Url>) {
+        self.set_extension_value("URL", link.map(|link| link.to_string()));
+    }
+    /// Same as [`Self::set_link`], for tasks that are being built
+    pub fn with_link(mut self, link: Option<&Url>) -> Self {
+        self.set_link(link);
+        self
+    }
+    /// The CATEGORIES of this task, unescaped
+    pub fn categories(&self) -> Vec<String> {
+        self.extra_parameters
+            .iter()
+            .filter(|p| p.name == "CATEGORIES")
+            .filter_map(|p| p.value.as_deref())
+            .flat_map(split_text_list)
+            .filter(|c| !c.is_empty())
+            .collect()
+    }
+    /// Whether the subtasks of this task should be hidden (the `X-OC-HIDESUBTASKS` extension of Nextcloud Tasks), if defined
+    pub fn hide_subtasks(&self) -> Option<bool> {
+        self.extension_value(X_OC_HIDESUBTASKS)
+            .map(|value| value.trim() != "0")
+    }
+    /// Set or remove (with `None`) the `X-OC-HIDESUBTASKS` extension.
+    /// This updates its "last modified" field, unless nothing has changed
+    pub fn set_hide_subtasks(&mut self, hide: Option<bool>) {
+        let value = hide.map(|hide| if hide { "1" } else { "0" }.to_string());
+        self.set_extension_value(X_OC_HIDESUBTASKS, value);
+    }
+    /// The position of this task in a manually sorted list (the `X-APPLE-SORT-ORDER` extension), if defined and valid
+    pub fn sort_order(&self) -> Option<i64> {
+        self.extension_value(X_APPLE_SORT_ORDER)
+            .and_then(|value| value.trim().parse().ok())
+    }
+    /// Set or remove (with `None`) the `X-APPLE-SORT-ORDER` extension.
+    /// This updates its "last modified" field, unless nothing has changed
+    pub fn set_sort_order(&mut self, sort_order: Option<i64>) {
+        self.set_extension_value(X_APPLE_SORT_ORDER, sort_order.map(|o| o.to_string()));
+    }
+    /// The value of an `X-` extension property (e.g. `X-MYAPP-PRIORITY`), unescaped. Names are case-insensitive.
+    ///
+    /// Applications can use these to attach their own data to tasks. They are kept as they are by the other iCal clients
+    pub fn get_x_property(&self, name: &str) -> Option<String> {
+        let name = name.to_ascii_uppercase();
+        self.extension_value(&name).map(unescape_text)
+    }
+    /// Set or remove (with `None`) an `X-` extension property. The value is escaped as an iCal TEXT, so that it can contain any character.
+    /// This updates its "last modified" field (and the sync status), unless nothing has changed
+    pub fn set_x_property(&mut self, name: &str, value: Option<&str>) -> KFResult<()> {
+        let is_valid = name.len() > 2
+            && name[..2].eq_ignore_ascii_case("X-")
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !is_valid {
+            return Err(KFError::InvalidExtensionPropertyName {
+                name: name.to_string(),
+            });
+        }
+        let name = name.to_ascii_uppercase();
+        self.set_extension_value(&name, value.map(escape_text));
+        Ok(())
+    }
+    /// The value of the (first) extra parameter with this name
+    fn extension_value(&self, name: &str) -> Option<&str> {
+        self.extra_parameters
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.value.as_deref())
+    }
+    /// Replace the extra parameter with this name by a parameter with this value, or remove it
+    fn set_extension_value(&mut self, name: &str, value: Option<String>) {
+        // Keep the parameters of the current property, in case the value is unchanged
+        let params = self
+            .extra_parameters
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.params.clone());
+        let properties = value
+            .map(|value| Property {
+                name: name.to_string(),
+                params,
+                value: Some(value),
+            })
+            .into_iter()
+            .collect();
+        self.set_extra_parameters_named(name, properties);
+    }
+    /// Replace every extra parameter that has this name.
+    /// This updates its "last modified" field, unless nothing has changed
+    pub(crate) fn set_extra_parameters_named(&mut self, name: &str, properties: Vec<Property>) {
+        let field = TaskField::ExtraProperty(name.to_string());
+        if self.field_value(&field) == FieldValue::ExtraProperty(properties.clone()) {
+            return;
+        }
+        self.mark_locally_modified(field);
+        self.extra_parameters.retain(|p| p.name != name);
+        self.extra_parameters.extend(properties);
+    }
+
+    /// The current value of a field
+    pub fn field_value(&self, field: &TaskField) -> FieldValue {
+        match field {
+            TaskField::Name => FieldValue::Name(self.name.clone()),
+            TaskField::CompletionStatus => {
+                FieldValue::CompletionStatus(self.completion_status.clone())
+            }
+            TaskField::Relationships => FieldValue::Relationships(self.relationships.clone()),
+            TaskField::ExtraProperty(name) => FieldValue::ExtraProperty(
+                self.extra_parameters
+                    .iter()
+                    .filter(|p| &p.name == name)
+                    .cloned()
+                    .collect(),
+            ),
+        }
+    }
+    /// Overwrite a field, without marking it as modified
+    fn set_field_value(&mut self, field: &TaskField, value: FieldValue) {
+        match (field, value) {
+            (_, FieldValue::Name(name)) => self.name = name,
+            (_, FieldValue::CompletionStatus(status)) => self.completion_status = status,
+            (_, FieldValue::Relationships(relationships)) => self.relationships = relationships,
+            (TaskField::ExtraProperty(name), FieldValue::ExtraProperty(properties)) => {
+                self.extra_parameters.retain(|p| &p.name != name);
+                self.extra_parameters.extend(properties);
+            }
+            (field, value) => log::error!("Cannot set field {:?} to {:?}", field, value),
+        }
+    }
+    /// Merge the local changes of `local` into this version, that has just been downloaded from the server.
+    ///
+    /// Returns `None` in case both versions have changed the same field in different ways since the last sync.
+    /// Otherwise, the merged task is a new revision of this version, that is marked as locally modified so that the next sync uploads it
+    pub fn merge_local_changes(&self, local: &Task) -> Option<Task> {
+        let remote_tag = match &self.sync_status {
+            SyncStatus::Synced(tag) => tag.clone(),
+            _ => return None,
+        };
+
+        let mut merged = self.clone();
+        for (field, synced_value) in &local.local_changes {
+            let remote_value = self.field_value(field);
+            let local_value = local.field_value(field);
+            if &remote_value != synced_value && remote_value != local_value {
+                return None;
+            }
+            merged.local_changes.insert(field.clone(), remote_value);
+            merged.set_field_value(field, local_value);
+        }
+
+        merged.sync_status = SyncStatus::LocallyModified(remote_tag);
+        merged.sequence = self.sequence.max(local.sequence) + 1;
+        merged.last_modified = crate::clock::now();
+        Some(merged)
+    }
+    /// The fields that have been locally changed since this task was last synced, with the value they had at that time.
+    ///
+    /// This is empty for tasks that have never been synced
+    pub fn local_changes(&self) -> &HashMap<TaskField, FieldValue> {
+        &self.local_changes
+    }
+
+    /// Whether this task is locally modified, but all its local changes have been reverted since the last sync (e.g. it has been renamed, then renamed back)
+    pub fn local_changes_reverted(&self) -> bool {
+        // Without local changes, there is no telling what has been changed
+        matches!(self.sync_status, SyncStatus::LocallyModified(_))
+            && !self.local_changes.is_empty()
+            && self
+                .local_changes
+                .iter()
+                .all(|(field, synced_value)| &self.field_value(field) == synced_value)
+    }
+
+    /// Mark this task as synced again in case its local changes have all been reverted (see [`Self::local_changes_reverted`]), so that it is not uploaded for nothing.
+    /// Returns whether it has been marked as synced
+    pub(crate) fn discard_reverted_changes(&mut self) -> bool {
+        let tag = match &self.sync_status {
+            SyncStatus::LocallyModified(tag) if self.local_changes_reverted() => tag.clone(),
+            _ => return false,
+        };
+        self.sync_status = SyncStatus::Synced(tag);
+        self.local_changes.clear();
+        // It has been incremented when the task was first modified after the last sync
+        self.sequence = self.sequence.saturating_sub(1);
+        true
+    }
+
+    /// Forget about the fields that have been locally changed, without changing the sync status
+    pub(crate) fn clear_local_changes(&mut self) {
+        self.local_changes.clear();
+    }
+
+    /// Change the UID of this task. This is only meant for tasks that have never been synced
+    pub(crate) fn set_uid(&mut self, uid: String) {
+        self.uid = uid;
+        self.update_last_modified();
+    }
+
+    /// Overwrite the name and the extra parameters, without changing the "last modified" field nor the sync status.
+    /// This is only meant for copies of tasks that are about to be uploaded (see [`crate::ical::fix_up`])
+    pub(crate) fn overwrite_for_upload(&mut self, name: String, extra_parameters: Vec<Property>) {
+        self.name = name;
+        self.extra_parameters = extra_parameters;
+    }
 
     #[cfg(any(test, feature = "integration_tests"))]
     pub fn has_same_observable_content_as(&self, other: &Task) -> bool {
-        self.url == other.url
-        && self.uid == other.uid
-        && self.name == other.name
+        self.observable_differences(other).is_empty()
+    }
+
+    /// The fields that differ between two tasks, as far as [`Self::has_same_observable_content_as`] is concerned
+    #[cfg(any(test, feature = "integration_tests"))]
+    pub fn observable_differences(&self, other: &Task) -> Vec<FieldDifference> {
+        let mut differences = Vec::new();
+        if self.url != other.url {
+            differences.push(FieldDifference::new(
+                "url",
+                self.url.as_str(),
+                other.url.as_str(),
+            ));
+        }
+        if self.uid != other.uid {
+            differences.push(FieldDifference::new("uid", &self.uid, &other.uid));
+        }
+        if self.name != other.name {
+            differences.push(FieldDifference::new("name", &self.name, &other.name));
+        }
+        let (link, other_link) = (self.link(), other.link());
+        if link != other_link {
+            let as_str = |link: &Option<Url>| link.as_ref().map(Url::to_string).unwrap_or_default();
+            differences.push(FieldDifference::new(
+                "link",
+                as_str(&link),
+                as_str(&other_link),
+            ));
+        }
         // sync status must be the same variant, but we ignore its embedded version tag
-        && std::mem::discriminant(&self.sync_status) == std::mem::discriminant(&other.sync_status)
+        if std::mem::discriminant(&self.sync_status) != std::mem::discriminant(&other.sync_status) {
+            differences.push(FieldDifference::new(
+                "sync status",
+                &self.sync_status,
+                &other.sync_status,
+            ));
+        }
         // completion status must be the same variant, but we ignore its embedded completion date (they are not totally mocked in integration tests)
-        && std::mem::discriminant(&self.completion_status) == std::mem::discriminant(&other.completion_status)
+        if std::mem::discriminant(&self.completion_status)
+            != std::mem::discriminant(&other.completion_status)
+        {
+            differences.push(FieldDifference::new(
+                "completion status",
+                &self.completion_status,
+                &other.completion_status,
+            ));
+        }
         // last modified dates are ignored (they are not totally mocked in integration tests)
+        differences
+    }
+
+    /// Record a local modification of a field, before it is actually changed: this updates the sync status, the "last modified" field,
+    /// the local changes and (once per sync) the sequence number
+    fn mark_locally_modified(&mut self, field: TaskField) {
+        match self.sync_status {
+            SyncStatus::Synced(_) | SyncStatus::LocallyModified(_) => {
+                if !self.local_changes.contains_key(&field) {
+                    let synced_value = self.field_value(&field);
+                    self.local_changes.insert(field, synced_value);
+                }
+            }
+            SyncStatus::NotSynced | SyncStatus::LocallyDeleted(_) => (),
+        }
+        if let SyncStatus::Synced(_) = self.sync_status {
+            self.sequence += 1;
+        }
+        self.generation += 1;
+        self.mark_modified_since_last_sync();
+        self.update_last_modified();
+    }
+
+    /// Set the sync status, without checking that it is a legal transition from the current one (see [`SyncStatus::can_become`]).
+    /// This is meant for statuses that are computed against another baseline, e.g. when restoring a snapshot
+    pub(crate) fn relabel_sync_status(&mut self, new_status: SyncStatus) {
+        if let SyncStatus::Synced(_) = new_status {
+            // This version is now the one the server knows
+            self.local_changes.clear();
+        }
+        self.sync_status = new_status;
     }
 
     fn update_last_modified(&mut self) {
-        self.last_modified = Utc::now();
+        self.last_modified = crate::clock::now();
     }
 
     /// Rename a task.
     /// This updates its "last modified" field
     pub fn set_name(&mut self, new_name: String) {
-        self.mark_modified_since_last_sync();
-        self.update_last_modified();
+        self.mark_locally_modified(TaskField::Name);
         self.name = new_name;
     }
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
     /// Rename a task, but forces a "master" SyncStatus, just like CalDAV servers are always "masters"
     pub fn mock_remote_calendar_set_name(&mut self, new_name: String) {
         self.sync_status = SyncStatus::random_synced();
+        self.sequence += 1;
         self.update_last_modified();
         self.name = new_name;
     }
 
     /// Set the completion status
     pub fn set_completion_status(&mut self, new_completion_status: CompletionStatus) {
-        self.mark_modified_since_last_sync();
-        self.update_last_modified();
+        self.mark_locally_modified(TaskField::CompletionStatus);
         self.completion_status = new_completion_status;
     }
+    /// Same as [`Self::set_completion_status`], with a given "last modified" date (e.g. the same date for tasks that are changed together)
+    pub(crate) fn set_completion_status_at(
+        &mut self,
+        new_completion_status: CompletionStatus,
+        modified_at: DateTime<Utc>,
+    ) {
+        self.set_completion_status(new_completion_status);
+        self.last_modified = modified_at;
+    }
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
     /// Set the completion status, but forces a "master" SyncStatus, just like CalDAV servers are always "masters"
     pub fn mock_remote_calendar_set_completion_status(
@@ -255,6 +786,7 @@ impl Task {
         new_completion_status: CompletionStatus,
     ) {
         self.sync_status = SyncStatus::random_synced();
+        self.sequence += 1;
         self.completion_status = new_completion_status;
     }
 }
@@ -269,6 +801,59 @@ impl Syncable for Task {
     }
 
     fn set_sync_status(&mut self, new_status: SyncStatus) {
-        self.sync_status = new_status;
+        self.sync_status.check_transition(&new_status, &self.url);
+        self.relabel_sync_status(new_status);
+    }
+}
+
+/// Escape a TEXT value, as defined by RFC5545 3.3.11
+fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Unescape a TEXT value (see [`escape_text`])
+fn unescape_text(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(escaped) => unescaped.push(escaped),
+                None => (),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Split an iCal list of TEXT values (e.g. `a,b\,c`) into unescaped values
+fn split_text_list(value: &str) -> Vec<String> {
+    let mut values = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => values.last_mut().unwrap().push('\n'),
+                Some(escaped) => values.last_mut().unwrap().push(escaped),
+                None => (),
+            },
+            ',' => values.push(String::new()),
+            c => values.last_mut().unwrap().push(c),
+        }
     }
+    values
 }
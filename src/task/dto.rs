@@ -0,0 +1,239 @@
+//! A plain representation of tasks, for applications that store them in their own formats
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Utc};
+use ical::property::Property;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::{KFError, KFResult};
+use crate::ical::DateMaybeTime;
+use crate::task::{CompletionStatus, Relationship, Task};
+use crate::utils::sync::{SyncStatus, Syncable, VersionTag};
+
+/// A [`Task`] with only public fields, e.g. to map tasks into the database of an application.
+///
+/// The serialization of a [`Task`] is the internal format of a [`Cache`](crate::cache::Cache), and it changes whenever the crate needs it to.
+/// On the contrary, the fields of this struct (and their serialized names) are stable: they will only change in a major version.
+///
+/// Converting a task into a `TaskDto` and back is lossless, except for the fields that have been locally changed since the last sync (see [`Task::local_changes`]):
+/// in case the task is concurrently modified on the server, the next sync resolves the conflict as a whole instead of merging the changed fields
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskDto {
+    pub url: Url,
+    pub uid: String,
+    pub name: String,
+    pub completed: bool,
+    /// When the task has been completed, if known. This must be `None` for uncompleted tasks
+    pub completion_date: Option<DateTime<Utc>>,
+    pub creation_date: Option<DateTime<Utc>>,
+    pub last_modified: DateTime<Utc>,
+    pub due: Option<DateMaybeTime>,
+    pub relationships: Vec<RelationshipDto>,
+    /// The revision sequence number (see [`Task::sequence`])
+    pub sequence: u32,
+    /// The PRODID of the iCal file of the task
+    pub ical_prod_id: String,
+    pub sync_state: SyncState,
+    /// The version tag (etag) of the task when it was last synced. This must be `None` for [`SyncState::NotSynced`] tasks, and set for the other ones
+    pub version_tag: Option<String>,
+    /// Every other iCal property of the task (e.g. `CATEGORIES`, `PRIORITY` or `X-` extensions), in their iCal form
+    pub extra_properties: Vec<PropertyDto>,
+}
+
+/// A [`Relationship`] of a [`TaskDto`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelationshipDto {
+    /// The UID of the related task
+    pub related_to: String,
+    /// The RELTYPE (e.g. `CHILD`), `None` for the default `PARENT` relationships
+    pub reltype: Option<String>,
+}
+
+/// An iCal property of a [`TaskDto`], e.g. `PRIORITY:1`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropertyDto {
+    pub name: String,
+    /// The parameters of the property, with their values (e.g. `("VALUE", vec!["DATE"])`)
+    pub params: Vec<(String, Vec<String>)>,
+    /// The value, as written in the iCal file (i.e. escaped)
+    pub value: Option<String>,
+}
+
+/// Whether a [`TaskDto`] has been synced, see [`SyncStatus`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncState {
+    NotSynced,
+    Synced,
+    LocallyModified,
+    LocallyDeleted,
+}
+
+impl From<&Task> for TaskDto {
+    fn from(task: &Task) -> Self {
+        let completion_date = match task.completion_status() {
+            CompletionStatus::Completed(date) => *date,
+            CompletionStatus::Uncompleted => None,
+        };
+        let (sync_state, version_tag) = match task.sync_status() {
+            SyncStatus::NotSynced => (SyncState::NotSynced, None),
+            SyncStatus::Synced(tag) => (SyncState::Synced, Some(tag)),
+            SyncStatus::LocallyModified(tag) => (SyncState::LocallyModified, Some(tag)),
+            SyncStatus::LocallyDeleted(tag) => (SyncState::LocallyDeleted, Some(tag)),
+        };
+        Self {
+            url: task.url().clone(),
+            uid: task.uid().to_string(),
+            name: task.name().to_string(),
+            completed: task.completed(),
+            completion_date,
+            creation_date: task.creation_date().cloned(),
+            last_modified: *task.last_modified(),
+            due: task.due_at(),
+            relationships: task
+                .relationships()
+                .iter()
+                .map(|r| RelationshipDto {
+                    related_to: r.related_to().to_string(),
+                    reltype: r.explicit_reltype().map(String::from),
+                })
+                .collect(),
+            sequence: task.sequence(),
+            ical_prod_id: task.ical_prod_id().to_string(),
+            sync_state,
+            version_tag: version_tag.map(|tag| tag.as_str().to_string()),
+            extra_properties: task
+                .extra_parameters()
+                .iter()
+                .filter(|p| p.name != "DUE")
+                .map(|p| PropertyDto {
+                    name: p.name.clone(),
+                    params: p.params.clone().unwrap_or_default(),
+                    value: p.value.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<Task> for TaskDto {
+    fn from(task: Task) -> Self {
+        Self::from(&task)
+    }
+}
+
+impl TryFrom<TaskDto> for Task {
+    type Error = KFError;
+
+    /// Fails in case the DTO is inconsistent (e.g. an uncompleted task with a completion date, or a synced task without version tag)
+    fn try_from(dto: TaskDto) -> KFResult<Self> {
+        let url = dto.url.clone();
+        let invalid = |detail: &str| KFError::InvalidTask {
+            url: url.clone(),
+            detail: detail.to_string(),
+        };
+        if dto.uid.is_empty() {
+            return Err(invalid("missing UID"));
+        }
+        let completion_status = match (dto.completed, dto.completion_date) {
+            (true, date) => CompletionStatus::Completed(date),
+            (false, None) => CompletionStatus::Uncompleted,
+            (false, Some(_)) => return Err(invalid("an uncompleted task has a completion date")),
+        };
+        let tag = dto.version_tag.clone().map(VersionTag::from);
+        let sync_status = match (dto.sync_state, tag) {
+            (SyncState::NotSynced, None) => SyncStatus::NotSynced,
+            (SyncState::NotSynced, Some(_)) => {
+                return Err(invalid(
+                    "a task that has never been synced has a version tag",
+                ))
+            }
+            (SyncState::Synced, Some(tag)) => SyncStatus::Synced(tag),
+            (SyncState::LocallyModified, Some(tag)) => SyncStatus::LocallyModified(tag),
+            (SyncState::LocallyDeleted, Some(tag)) => SyncStatus::LocallyDeleted(tag),
+            (_, None) => return Err(invalid("a synced task has no version tag")),
+        };
+        let mut extra_parameters = Vec::with_capacity(dto.extra_properties.len() + 1);
+        for prop in dto.extra_properties {
+            if prop.name.is_empty() || prop.name.eq_ignore_ascii_case("DUE") {
+                return Err(invalid(&format!("invalid extra property {:?}", prop.name)));
+            }
+            extra_parameters.push(Property {
+                name: prop.name,
+                params: Some(prop.params).filter(|params| !params.is_empty()),
+                value: prop.value,
+            });
+        }
+        extra_parameters.extend(dto.due.map(|due| due.to_property("DUE")));
+
+        Ok(Task {
+            url: dto.url,
+            uid: dto.uid,
+            sync_status,
+            creation_date: dto.creation_date,
+            last_modified: dto.last_modified,
+            completion_status,
+            name: dto.name,
+            ical_prod_id: dto.ical_prod_id,
+            relationships: dto
+                .relationships
+                .into_iter()
+                .map(|r| Relationship::new(r.related_to, r.reltype))
+                .collect(),
+            sequence: dto.sequence,
+            local_changes: HashMap::new(),
+            generation: 0,
+            extra_parameters,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_task_dto_round_trip() {
+        let cal_url: Url = "https://some.calend.ar/dto/".parse().unwrap();
+        let mut task = Task::new("Buy milk".to_string(), true, &cal_url).unwrap();
+        task.set_due(Some(DateMaybeTime::Utc(
+            Utc.ymd(2022, 3, 4).and_hms(12, 0, 0),
+        )));
+        task.set_x_property("X-MYAPP-COLOR", Some("red, or blue"))
+            .unwrap();
+        task.add_relationship(Relationship::new(
+            "sibling".to_string(),
+            Some("SIBLING".to_string()),
+        ));
+
+        let dto = TaskDto::from(&task);
+        assert!(dto.completed);
+        assert_eq!(dto.sync_state, SyncState::NotSynced);
+        assert_eq!(dto.extra_properties.len(), 1);
+        let json = serde_json::to_value(&dto).unwrap();
+        assert_eq!(json["sync_state"], "not_synced");
+        assert_eq!(json["extra_properties"][0]["name"], "X-MYAPP-COLOR");
+
+        let restored = Task::try_from(serde_json::from_value::<TaskDto>(json).unwrap()).unwrap();
+        assert!(restored.has_same_observable_content_as(&task));
+        assert_eq!(restored.sync_status(), task.sync_status());
+        assert_eq!(restored.due_at(), task.due_at());
+        assert_eq!(
+            restored.get_x_property("X-MYAPP-COLOR").as_deref(),
+            Some("red, or blue")
+        );
+        assert_eq!(TaskDto::from(restored), dto);
+
+        let mut synced_without_tag = dto.clone();
+        synced_without_tag.sync_state = SyncState::Synced;
+        assert!(Task::try_from(synced_without_tag).is_err());
+        let mut completion_date_mismatch = dto;
+        completion_date_mismatch.completed = false;
+        assert!(Task::try_from(completion_date_mismatch).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! Partial updates of tasks
+
+use std::collections::HashSet;
+
+use ical::property::Property;
+
+use crate::ical::DateMaybeTime;
+
+use crate::task::{CompletionStatus, Task, TaskField, X_APPLE_SORT_ORDER, X_OC_HIDESUBTASKS};
+
+/// A set of changes to apply to a task. Fields that are `None` are left untouched.
+///
+/// Applying a patch only marks the fields that actually change as locally modified (see [`Task::local_changes`]),
+/// so that a sync can tell them apart from the fields that have concurrently been modified on the server.
+/// See [`CompleteCalendar::patch_item`](crate::traits::CompleteCalendar::patch_item)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TaskPatch {
+    pub name: Option<String>,
+    pub completion_status: Option<CompletionStatus>,
+    /// The DUE date. `Some(None)` removes it
+    pub due: Option<Option<DateMaybeTime>>,
+    /// The CATEGORIES. `Some(vec![])` removes them
+    pub categories: Option<Vec<String>>,
+    /// The `X-OC-HIDESUBTASKS` extension. `Some(None)` removes it
+    pub hide_subtasks: Option<Option<bool>>,
+    /// The `X-APPLE-SORT-ORDER` extension. `Some(None)` removes it
+    pub sort_order: Option<Option<i64>>,
+}
+
+impl TaskPatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether this patch changes nothing at all
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Apply this patch, and return the fields that have changed
+    pub fn apply(&self, task: &mut Task) -> HashSet<TaskField> {
+        let mut changed = HashSet::new();
+
+        if let Some(name) = &self.name {
+            if task.name() != name {
+                task.set_name(name.clone());
+                changed.insert(TaskField::Name);
+            }
+        }
+        if let Some(completion_status) = &self.completion_status {
+            if task.completion_status() != completion_status {
+                task.set_completion_status(completion_status.clone());
+                changed.insert(TaskField::CompletionStatus);
+            }
+        }
+        if let Some(due) = &self.due {
+            let props = due.iter().map(|due| due.to_property("DUE")).collect();
+            set_extra_property(task, "DUE", props, &mut changed);
+        }
+        if let Some(categories) = &self.categories {
+            let props = if categories.is_empty() {
+                Vec::new()
+            } else {
+                let escaped: Vec<String> = categories
+                    .iter()
+                    .map(|c| c.replace('\\', "\\\\").replace(',', "\\,"))
+                    .collect();
+                vec![extra_property("CATEGORIES", escaped.join(","))]
+            };
+            set_extra_property(task, "CATEGORIES", props, &mut changed);
+        }
+        if let Some(hide) = self.hide_subtasks {
+            track_extension(task, X_OC_HIDESUBTASKS, &mut changed, |t| {
+                t.set_hide_subtasks(hide)
+            });
+        }
+        if let Some(sort_order) = self.sort_order {
+            track_extension(task, X_APPLE_SORT_ORDER, &mut changed, |t| {
+                t.set_sort_order(sort_order)
+            });
+        }
+
+        changed
+    }
+}
+
+fn extra_property(name: &str, value: String) -> Property {
+    Property {
+        name: name.to_string(),
+        params: None,
+        value: Some(value),
+    }
+}
+
+fn set_extra_property(
+    task: &mut Task,
+    name: &str,
+    properties: Vec<Property>,
+    changed: &mut HashSet<TaskField>,
+) {
+    let field = TaskField::ExtraProperty(name.to_string());
+    let before = task.field_value(&field);
+    task.set_extra_parameters_named(name, properties);
+    if task.field_value(&field) != before {
+        changed.insert(field);
+    }
+}
+
+/// Apply a change to a vendor extension of a task, and record whether it actually changed
+fn track_extension<F: FnOnce(&mut Task)>(
+    task: &mut Task,
+    name: &str,
+    changed: &mut HashSet<TaskField>,
+    change: F,
+) {
+    let field = TaskField::ExtraProperty(name.to_string());
+    let before = task.field_value(&field);
+    change(task);
+    if task.field_value(&field) != before {
+        changed.insert(field);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+    use url::Url;
+
+    use crate::task::FieldValue;
+    use crate::utils::sync::{SyncStatus, Syncable, VersionTag};
+
+    #[test]
+    fn test_patch_records_changed_fields() {
+        let cal_url: Url = "https://some.calend.ar/patch/".parse().unwrap();
+        let mut task = Task::new("Task".to_string(), false, &cal_url).unwrap();
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("tag"))));
+
+        let patch = TaskPatch {
+            name: Some("Task".to_string()),
+            due: Some(Some(Utc.ymd(2021, 4, 2).and_hms(8, 15, 57).into())),
+            categories: Some(vec!["home".to_string(), "a, b".to_string()]),
+            ..TaskPatch::default()
+        };
+        let changed = patch.apply(&mut task);
+
+        // The name has not changed
+        let expected: HashSet<TaskField> = vec![
+            TaskField::ExtraProperty("DUE".to_string()),
+            TaskField::ExtraProperty("CATEGORIES".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(changed, expected);
+        assert!(matches!(task.sync_status(), SyncStatus::LocallyModified(_)));
+        assert_eq!(
+            task.local_changes()
+                .keys()
+                .cloned()
+                .collect::<HashSet<TaskField>>(),
+            expected
+        );
+        assert_eq!(
+            task.local_changes()[&TaskField::ExtraProperty("DUE".to_string())],
+            FieldValue::ExtraProperty(Vec::new())
+        );
+        let values: Vec<Option<&str>> = task
+            .extra_parameters()
+            .iter()
+            .map(|p| p.value.as_deref())
+            .collect();
+        assert_eq!(values, vec![Some("20210402T081557Z"), Some("home,a\\, b")]);
+        assert_eq!(task.due(), Some(Utc.ymd(2021, 4, 2).and_hms(8, 15, 57)));
+        assert_eq!(task.categories(), vec!["home", "a, b"]);
+
+        // Applying it again is a no-op
+        assert!(patch.apply(&mut task).is_empty());
+        assert!(TaskPatch::new().is_empty());
+
+        // Syncing forgets about the local changes
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("tag2"))));
+        assert!(task.local_changes().is_empty());
+    }
+
+    #[test]
+    fn test_patch_all_day_due() {
+        let cal_url: Url = "https://some.calend.ar/patch/".parse().unwrap();
+        let mut task = Task::new("Task".to_string(), false, &cal_url).unwrap();
+        let day = chrono::NaiveDate::from_ymd(2021, 4, 2);
+
+        let patch = TaskPatch {
+            due: Some(Some(day.into())),
+            ..TaskPatch::default()
+        };
+        patch.apply(&mut task);
+        let due = &task.extra_parameters()[0];
+        assert_eq!(due.value.as_deref(), Some("20210402"));
+        assert_eq!(
+            due.params,
+            Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])])
+        );
+        assert_eq!(task.due_at(), Some(DateMaybeTime::Date(day)));
+
+        // The day starts at midnight in the time zone of the user
+        let tz = chrono::FixedOffset::west(5 * 3600);
+        assert_eq!(task.due_in(&tz), Some(Utc.ymd(2021, 4, 2).and_hms(5, 0, 0)));
+    }
+
+    #[test]
+    fn test_patch_vendor_extensions() {
+        let cal_url: Url = "https://some.calend.ar/patch/".parse().unwrap();
+        let mut task = Task::new("Task".to_string(), false, &cal_url).unwrap();
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("tag"))));
+        assert_eq!(task.hide_subtasks(), None);
+        assert_eq!(task.sort_order(), None);
+
+        let patch = TaskPatch {
+            hide_subtasks: Some(Some(true)),
+            sort_order: Some(Some(-42)),
+            ..TaskPatch::default()
+        };
+        assert_eq!(patch.apply(&mut task).len(), 2);
+        assert!(matches!(task.sync_status(), SyncStatus::LocallyModified(_)));
+        assert_eq!(task.hide_subtasks(), Some(true));
+        assert_eq!(task.sort_order(), Some(-42));
+        assert!(patch.apply(&mut task).is_empty());
+
+        let ical = crate::ical::build_from(&crate::Item::Task(task.clone())).unwrap();
+        assert!(ical.contains("X-OC-HIDESUBTASKS:1"));
+        assert!(ical.contains("X-APPLE-SORT-ORDER:-42"));
+        let parsed = crate::ical::parse(&ical, task.url().clone(), SyncStatus::NotSynced).unwrap();
+        let parsed = parsed.unwrap_task();
+        assert_eq!(parsed.hide_subtasks(), Some(true));
+        assert_eq!(parsed.sort_order(), Some(-42));
+
+        task.set_hide_subtasks(None);
+        assert_eq!(task.hide_subtasks(), None);
+        assert!(task
+            .extra_parameters()
+            .iter()
+            .all(|p| p.name != X_OC_HIDESUBTASKS));
+    }
+}
@@ -4,22 +4,33 @@ use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use csscolorparser::Color;
 use tokio::sync::Mutex;
 use url::Url;
 
+use crate::calendar::completion::CompletionCascade;
+use crate::calendar::conflict::{Conflict, ConflictJournal};
+use crate::calendar::duplicates::DuplicateGroup;
+use crate::calendar::history::ItemVersion;
+use crate::calendar::item_url_policy::ItemUrlPolicy;
 use crate::calendar::SupportedComponents;
-use crate::error::KFResult;
+use crate::error::{KFError, KFResult};
+use crate::ical::CalendarTimezone;
 use crate::item::Item;
-use crate::resource::Resource;
-use crate::utils::prop::Property;
-use crate::utils::sync::{SyncStatus, VersionTag};
+use crate::provider::multi::SourceState;
+use crate::resource::{RequestIds, Resource, TransferCounter};
+use crate::task::patch::TaskPatch;
+use crate::task::{CompletionStatus, Task, TaskField};
+use crate::utils::prop::{PropPatchOutcome, Property, PROP_CALENDAR_TIMEZONE};
+use crate::utils::sync::{ItemSyncState, SyncStatus, Syncable, VersionTag};
 use crate::utils::NamespacedName;
 
 /// This trait must be implemented by data sources (either local caches or remote CalDAV clients)
 ///
 /// Note that some concrete types (e.g. [`crate::cache::Cache`]) can also provide non-async versions of these functions
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait CalDavSource<T: BaseCalendar> {
     /// Returns the current calendars that this source contains
     /// This function may trigger an update (that can be a long process, or that can even fail, e.g. in case of a remote server)
@@ -41,16 +52,71 @@ pub trait CalDavSource<T: BaseCalendar> {
     ///
     /// Returns Err if the calendar is not found in the source.
     async fn delete_calendar(&mut self, url: &Url) -> KFResult<Option<Arc<Mutex<T>>>>;
+
+    /// Remove a calendar from this source only.
+    ///
+    /// Unlike [`CompleteCalendar::mark_for_deletion`], this is never propagated to the other source of a sync, that keeps this calendar.
+    /// Syncs ignore this calendar from now on (see [`CalDavSource::ignored_calendars`]), until [`CalDavSource::stop_ignoring_calendar`] is called.
+    ///
+    /// Returns the removed calendar, if it was present. Sources that cannot ignore calendars (e.g. remote sources) return [`KFError::UnsupportedBySource`]
+    async fn remove_calendar_local_only(&mut self, url: &Url) -> KFResult<Option<Arc<Mutex<T>>>>;
+
+    /// The calendars that have been removed with [`CalDavSource::remove_calendar_local_only`], and that syncs must not create again
+    fn ignored_calendars(&self) -> HashSet<Url> {
+        HashSet::new()
+    }
+
+    /// Stop ignoring a calendar removed with [`CalDavSource::remove_calendar_local_only`], so that the next sync downloads it again.
+    /// Returns whether this calendar was ignored
+    fn stop_ignoring_calendar(&mut self, _url: &Url) -> bool {
+        false
+    }
+
+    /// Persist the current state of one of its calendars, so that an interrupted sync can resume from there rather than download everything again.
+    ///
+    /// This is called periodically while a sync downloads many items (see [`Provider::set_checkpoint_interval`](crate::provider::Provider::set_checkpoint_interval)).
+    /// Sources that are not persisted have nothing to do
+    async fn checkpoint_calendar(&self, calendar: &T) -> KFResult<()>;
+
+    /// Called by a [`Provider`](crate::provider::Provider) before it syncs this source, e.g. to make sure no other process syncs or saves it at the same time.
+    /// An error prevents the sync. Sources that cannot be shared have nothing to do
+    fn sync_started(&self) -> KFResult<()> {
+        Ok(())
+    }
+
+    /// Called by a [`Provider`](crate::provider::Provider) every time it is done syncing a calendar of this source, after a successful [`Self::sync_started`].
+    /// This lets sources tell other processes that the sync is still running. Sources that cannot be shared have nothing to do
+    fn sync_progressed(&self) {}
+
+    /// Called by a [`Provider`](crate::provider::Provider) once it is done syncing this source, after a successful [`Self::sync_started`]
+    fn sync_finished(&self) {}
+
+    /// The amount of data exchanged with this source, for sources that are reached through the network
+    fn transfers(&self) -> Option<TransferCounter> {
+        None
+    }
+
+    /// How the requests to this source are numbered, for sources that are reached through the network.
+    /// During a sync, they are numbered in the scope of the sync (see [`RequestIds`])
+    fn request_ids(&self) -> Option<RequestIds> {
+        None
+    }
 }
 
 /// This trait contains functions that are common to all calendars
 ///
 /// Note that some concrete types (e.g. [`crate::calendar::cached_calendar::CachedCalendar`]) can also provide non-async versions of these functions
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait BaseCalendar {
-    /// Returns the calendar name
+    /// Returns the calendar name, exactly as the server defines it (so that it is sent back unchanged)
     fn name(&self) -> &str;
 
+    /// Returns the calendar name, normalized to be displayed (see [`normalize_display_name`](crate::calendar::normalize_display_name))
+    fn display_name(&self) -> String {
+        crate::calendar::normalize_display_name(self.name())
+    }
+
     /// Returns the calendar URL
     fn url(&self) -> &Url;
 
@@ -95,17 +161,12 @@ pub trait BaseCalendar {
 
 /// Functions availabe for calendars that are backed by a CalDAV server
 ///
+/// This trait is object-safe, so that calendars can be stored as `Box<dyn DavCalendar + Send + Sync>`. They are created with [`DavCalendarFactory`]
+///
 /// Note that some concrete types (e.g. [`crate::calendar::cached_calendar::CachedCalendar`]) can also provide non-async versions of these functions
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait DavCalendar: BaseCalendar {
-    /// Create a new calendar
-    fn new(
-        name: String,
-        resource: Resource,
-        supported_components: SupportedComponents,
-        color: Option<Color>,
-    ) -> Self;
-
     /// Get the URLs and the version tags of every item in this calendar
     async fn get_item_version_tags(&self) -> KFResult<HashMap<Url, VersionTag>>;
 
@@ -130,31 +191,78 @@ pub trait DavCalendar: BaseCalendar {
     /// See also [`CompleteCalendar::mark_prop_for_deletion`] and [`CompleteCalendar::immediately_delete_prop`].
     async fn delete_property(&mut self, nsn: &NamespacedName) -> KFResult<()>;
 
+    /// Set and remove several properties at once.
+    ///
+    /// Remote calendars do this in a single PROPPATCH request. This default implementation simply calls [`BaseCalendar::set_property`] and [`DavCalendar::delete_property`] for every property
+    async fn patch_properties(
+        &mut self,
+        set: Vec<Property>,
+        remove: Vec<NamespacedName>,
+    ) -> KFResult<PropPatchOutcome> {
+        let mut outcome = PropPatchOutcome::default();
+        for nsn in remove {
+            let result = self.delete_property(&nsn).await;
+            outcome.removed.insert(nsn, result);
+        }
+        for prop in set {
+            let nsn = prop.nsn().clone();
+            let result = self.set_property(prop).await;
+            outcome.set.insert(nsn, result);
+        }
+        Ok(outcome)
+    }
+
     /// Get the URLs of all current items in this calendar
     async fn get_item_urls(&self) -> KFResult<HashSet<Url>> {
         let items = self.get_item_version_tags().await?;
         Ok(items.keys().cloned().collect())
     }
 
-    // Note: the CalDAV protocol could also enable to do this:
-    // fn get_current_version(&self) -> CTag
+    /// Take a write lock on this calendar, so that other clients cannot modify it until [`DavCalendar::unlock`] is called.
+    ///
+    /// This fails with [`KFError::ResourceLocked`](crate::error::KFError::ResourceLocked) in case another client holds a lock already.
+    /// Sources that do not support locking can keep this default implementation, that does nothing
+    async fn lock(&mut self) -> KFResult<()> {
+        Ok(())
+    }
+
+    /// Release the lock taken by [`DavCalendar::lock`], if any
+    async fn unlock(&mut self) -> KFResult<()> {
+        Ok(())
+    }
+
+    /// The current version of the whole calendar (e.g. its `getctag` or `sync-token`), that changes whenever anything changes in it.
+    ///
+    /// Comparing it with the [`CompleteCalendar::remote_token`] of the local calendar is a cheap way to tell whether anything has changed since the last sync, without running a sync.
+    /// `None` in case the source does not provide any, which is what this default implementation says
+    async fn current_token(&self) -> KFResult<Option<String>> {
+        Ok(None)
+    }
 }
 
-/// Functions availabe for calendars we have full knowledge of
-///
-/// Usually, these are local calendars fully backed by a local folder
+/// Creation of calendars that are backed by a CalDAV server
 ///
-/// Note that some concrete types (e.g. [`crate::calendar::cached_calendar::CachedCalendar`]) can also provide non-async versions of these functions
-#[async_trait]
-pub trait CompleteCalendar: BaseCalendar {
+/// This is kept apart from [`DavCalendar`], which would not be object-safe otherwise
+pub trait DavCalendarFactory: DavCalendar + Sized {
     /// Create a new calendar
     fn new(
         name: String,
-        url: Url,
+        resource: Resource,
         supported_components: SupportedComponents,
         color: Option<Color>,
     ) -> Self;
+}
 
+/// Functions availabe for calendars we have full knowledge of
+///
+/// Usually, these are local calendars fully backed by a local folder
+///
+/// This trait is object-safe, so that calendars can be stored as `Box<dyn CompleteCalendar + Send + Sync>`. They are created with [`CompleteCalendarFactory`]
+///
+/// Note that some concrete types (e.g. [`crate::calendar::cached_calendar::CachedCalendar`]) can also provide non-async versions of these functions
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait CompleteCalendar: BaseCalendar {
     /// Get the URLs of all current items in this calendar
     async fn get_item_urls(&self) -> KFResult<HashSet<Url>>;
 
@@ -164,9 +272,27 @@ pub trait CompleteCalendar: BaseCalendar {
     /// Returns all items that this calendar contains
     async fn get_items_mut(&mut self) -> KFResult<HashMap<Url, &mut Item>>;
 
+    /// Returns the items that have been changed locally (by a local edit or by a sync) at `since` or later.
+    ///
+    /// This is meant for applications that refresh their own models after a sync: they can remember when they last did it, and only look at these items.
+    /// Items that have been mutably accessed (e.g. with [`Self::get_item_by_url_mut`]) count as changed.
+    /// Deleted items are not returned, their absence from [`Self::get_item_urls`] must be checked instead
+    async fn get_items_modified_since(&self, since: DateTime<Utc>)
+        -> KFResult<HashMap<Url, &Item>>;
+
     /// Returns a particular item
     async fn get_item_by_url<'a>(&'a self, url: &Url) -> Option<&'a Item>;
 
+    /// Returns a set of items, in the same order as `urls` (`None` for the ones that do not exist).
+    /// This looks them up at once, instead of calling [`Self::get_item_by_url`] for each of them
+    async fn get_items_by_urls<'a>(&'a self, urls: &[Url]) -> Vec<Option<&'a Item>> {
+        let mut items = Vec::with_capacity(urls.len());
+        for url in urls {
+            items.push(self.get_item_by_url(url).await);
+        }
+        items
+    }
+
     /// Returns a particular item
     async fn get_item_by_url_mut<'a>(&'a mut self, url: &Url) -> Option<&'a mut Item>;
 
@@ -191,11 +317,52 @@ pub trait CompleteCalendar: BaseCalendar {
     /// Whether this calendar is flagged to be deleted on the next sync
     async fn marked_for_deletion(&self) -> bool;
 
+    /// Cancel [`CompleteCalendar::mark_for_deletion`], so that the next sync keeps this calendar
+    async fn unmark_for_deletion(&mut self);
+
+    /// Whether this calendar has already been synced with its remote counterpart.
+    /// When such a calendar does not exist on the remote anymore, it has been deleted there
+    async fn has_been_synced(&self) -> bool;
+
+    /// Record that this calendar has been synced with its remote counterpart
+    async fn mark_synced(&mut self);
+
+    /// How the URLs of new items of this calendar should be composed. `None` in case it has not been set nor detected (yet)
+    fn item_url_policy(&self) -> Option<&ItemUrlPolicy>;
+
+    /// Change how the URLs of new items of this calendar should be composed
+    fn set_item_url_policy(&mut self, policy: Option<ItemUrlPolicy>);
+
+    /// The [token](DavCalendar::current_token) of the remote calendar when its last complete sync started, if the remote source provides one
+    fn remote_token(&self) -> Option<&str>;
+
+    /// Record the token of the remote calendar. The [`Provider`](crate::provider::Provider) does it once it has synced this calendar
+    fn set_remote_token(&mut self, token: Option<String>);
+
+    /// Whether changes are being applied to this calendar by a sync.
+    /// This is still `true` after a sync has been cancelled (i.e. its future has been dropped) while applying them, in which case the content of this calendar may only be partially synced
+    fn sync_in_progress(&self) -> bool;
+
+    /// Record that a sync starts (or stops) applying changes to this calendar. The [`Provider`](crate::provider::Provider) does it
+    fn set_sync_in_progress(&mut self, in_progress: bool);
+
+    /// Replace the name, supported components and color of this calendar, e.g. because they have been changed on the server
+    fn set_metadata(
+        &mut self,
+        name: String,
+        supported_components: SupportedComponents,
+        color: Option<Color>,
+    );
+
     /// Mark an item for deletion.
     /// This is required so that the upcoming sync will know it should also also delete this task from the server
     /// (and then call [`CompleteCalendar::immediately_delete_item`] once it has been successfully deleted on the server)
     async fn mark_item_for_deletion(&mut self, item_id: &Url) -> KFResult<()>;
 
+    /// Cancel [`CompleteCalendar::mark_item_for_deletion`], so that the next sync keeps this item. It gets back the sync status it had before being marked.
+    /// Items that have never been synced are removed as soon as they are marked for deletion, they cannot be restored
+    async fn unmark_item_for_deletion(&mut self, item_id: &Url) -> KFResult<()>;
+
     /// Immediately remove an item. See [`CompleteCalendar::mark_item_for_deletion`]
     async fn immediately_delete_item(&mut self, item_id: &Url) -> KFResult<()>;
 
@@ -206,4 +373,233 @@ pub trait CompleteCalendar: BaseCalendar {
 
     /// Immediately remove a prop. See [`CompleteCalendar::mark_prop_for_deletion`]
     async fn immediately_delete_prop(&mut self, nsn: &NamespacedName) -> KFResult<()>;
+
+    /// Store the local version of an item that a sync has discarded because of a conflict with the remote version
+    async fn record_conflict(&mut self, conflict: Conflict);
+
+    /// Returns the local versions that have been discarded by sync conflicts (only the most recent one of each item is kept)
+    async fn get_conflicts<'a>(&'a self) -> &'a ConflictJournal;
+
+    /// Restore the most recently discarded local version of an item, so that the next sync uploads it again.
+    /// Every conflict recorded for this item is then removed from the journal
+    async fn reapply_conflict(&mut self, item_url: &Url) -> KFResult<()>;
+
+    /// Returns what an additional source of a [`MultiProvider`](crate::provider::multi::MultiProvider) knew about this calendar, as of its last sync
+    async fn get_source_state(&self, source_id: &str) -> SourceState;
+
+    /// Stores what an additional source of a [`MultiProvider`](crate::provider::multi::MultiProvider) knows about this calendar
+    async fn set_source_state(&mut self, source_id: &str, state: SourceState);
+
+    /// Whether the last syncs have failed to upload or download this item (the default state in case they have not)
+    async fn item_sync_state(&self, url: &Url) -> ItemSyncState;
+
+    /// The URLs of the items whose last sync has failed
+    async fn failing_items(&self) -> Vec<Url>;
+
+    /// Record that a sync has failed to upload or download an item. The [`Provider`](crate::provider::Provider) does it
+    async fn record_item_sync_failure(&mut self, url: &Url, error: String);
+
+    /// Forget the sync failures of an item, e.g. because it has now been synced
+    async fn clear_item_sync_failures(&mut self, url: &Url);
+
+    /// The previous versions of an item (most recent first), that have been replaced by syncs or local edits, in case the calendar keeps them.
+    /// Versions of deleted items are kept as well
+    async fn item_history(&self, url: &Url) -> Vec<&ItemVersion>;
+
+    /// The time zone of this calendar (its `CALDAV:calendar-timezone` property), that the server uses to interpret floating times and days.
+    /// `None` in case it is not set (or it is invalid, or marked for deletion)
+    async fn calendar_timezone(&self) -> Option<CalendarTimezone> {
+        let prop = self.get_property_by_name(&PROP_CALENDAR_TIMEZONE).await?;
+        if let SyncStatus::LocallyDeleted(_) = prop.sync_status() {
+            return None;
+        }
+        match CalendarTimezone::parse(prop.value()) {
+            Err(err) => {
+                log::warn!("Ignoring the time zone of calendar {}: {}", self.url(), err);
+                None
+            }
+            Ok(tz) => Some(tz),
+        }
+    }
+
+    /// Set (or remove, with `None`) the time zone of this calendar. The next sync pushes it to the server
+    async fn set_calendar_timezone(&mut self, timezone: Option<CalendarTimezone>) -> KFResult<()> {
+        let nsn = PROP_CALENDAR_TIMEZONE.clone();
+        match (self.get_property_by_name_mut(&nsn).await, timezone) {
+            (Some(prop), Some(timezone)) => {
+                if let SyncStatus::LocallyDeleted(vt) = prop.sync_status() {
+                    let vt = vt.clone();
+                    prop.set_sync_status(SyncStatus::LocallyModified(vt));
+                }
+                if prop.value() != timezone.as_ical() {
+                    prop.set_value(timezone.as_ical().to_string());
+                }
+                Ok(())
+            }
+            (None, Some(timezone)) => {
+                self.add_property(Property::new_from_nsn(nsn, timezone.as_ical()))
+                    .await
+            }
+            (Some(_), None) => self.mark_prop_for_deletion(&nsn).await,
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Set the completion status of a task, and propagate it to its subtasks or parent tasks as defined by `cascade`.
+    /// Returns the URLs of every modified task, that the next sync will push to the server
+    async fn set_completion_status_cascading(
+        &mut self,
+        task_url: &Url,
+        new_status: CompletionStatus,
+        cascade: CompletionCascade,
+    ) -> KFResult<Vec<Url>> {
+        let items = self.get_items_mut().await?;
+        crate::calendar::completion::set_completion_status(items, task_url, new_status, cascade)
+    }
+
+    /// Set the completion status of many tasks at once (e.g. to mark every task of a list as done), see [`crate::calendar::completion::set_completion_status_of`].
+    /// Returns the URLs of the tasks that have changed, that the next sync will push to the server
+    async fn set_completion_for(
+        &mut self,
+        urls: &[Url],
+        new_status: CompletionStatus,
+    ) -> KFResult<Vec<Url>> {
+        let items = self.get_items_mut().await?;
+        crate::calendar::completion::set_completion_status_of(items, urls, new_status)
+    }
+
+    /// The tasks of this calendar, in their manual order (see [`crate::calendar::sort_order`])
+    async fn tasks_in_sort_order(&self) -> KFResult<Vec<&Task>> {
+        let items = self.get_items().await?;
+        Ok(crate::calendar::sort_order::sorted_tasks(
+            items.into_values(),
+        ))
+    }
+
+    /// Move a task right after another one (or first, in case `after` is `None`) in the manual order of this calendar, see [`crate::calendar::sort_order::move_task`].
+    /// Returns the URLs of every modified task, that the next sync will push to the server
+    async fn move_task(&mut self, task_url: &Url, after: Option<&Url>) -> KFResult<Vec<Url>> {
+        let items = self.get_items_mut().await?;
+        crate::calendar::sort_order::move_task(items, task_url, after)
+    }
+
+    /// The total estimated effort of the tasks of this calendar, see [`crate::calendar::effort::calendar_estimated_effort`]
+    async fn total_estimated_effort(&self, include_completed: bool) -> KFResult<chrono::Duration> {
+        let items = self.get_items().await?;
+        Ok(crate::calendar::effort::calendar_estimated_effort(
+            items.into_values(),
+            include_completed,
+        ))
+    }
+
+    /// Group the items of this calendar that are probably duplicates of each other, see [`crate::calendar::duplicates::find_duplicates`]
+    async fn find_duplicates(&self) -> KFResult<Vec<DuplicateGroup>> {
+        let items = self.get_items().await?;
+        Ok(crate::calendar::duplicates::find_duplicates(
+            items.into_values(),
+        ))
+    }
+
+    /// Merge two duplicate items: the tasks related to `drop` are made related to `keep` instead, then `drop` is marked for deletion.
+    /// Returns the URLs of the tasks whose relationships have been rewritten, that the next sync will push to the server
+    async fn merge_items(&mut self, keep: &Url, drop: &Url) -> KFResult<Vec<Url>> {
+        let items = self.get_items_mut().await?;
+        let changed = crate::calendar::duplicates::rewrite_relationships(items, keep, drop)?;
+        self.mark_item_for_deletion(drop).await?;
+        Ok(changed)
+    }
+
+    /// Apply a partial update to a task. Returns the fields that have actually changed, that are now marked as locally modified
+    async fn patch_item(
+        &mut self,
+        task_url: &Url,
+        patch: &TaskPatch,
+    ) -> KFResult<HashSet<TaskField>> {
+        match self.get_item_by_url_mut(task_url).await {
+            None => Err(KFError::ItemDoesNotExist {
+                type_: None,
+                detail: "Can't patch item".into(),
+                url: task_url.clone(),
+            }),
+            Some(Item::Task(task)) => Ok(patch.apply(task)),
+            Some(item) => Err(KFError::ItemDoesNotExist {
+                type_: Some(crate::item::ItemType::Task),
+                detail: format!("Can't patch a {:?}", item.type_()),
+                url: task_url.clone(),
+            }),
+        }
+    }
+}
+
+/// Creation of calendars we have full knowledge of
+///
+/// This is kept apart from [`CompleteCalendar`], which would not be object-safe otherwise
+pub trait CompleteCalendarFactory: CompleteCalendar + Sized {
+    /// Create a new calendar
+    fn new(
+        name: String,
+        url: Url,
+        supported_components: SupportedComponents,
+        color: Option<Color>,
+    ) -> Self;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cache::Cache;
+    use crate::calendar::cached_calendar::CachedCalendar;
+    use crate::calendar::remote_calendar::RemoteCalendar;
+
+    #[tokio::test]
+    async fn test_trait_objects() {
+        let url: Url = "https://some.calend.ar/dyn/".parse().unwrap();
+        let mut calendars: Vec<Box<dyn CompleteCalendar + Send + Sync>> =
+            vec![Box::new(<CachedCalendar as CompleteCalendarFactory>::new(
+                "Dyn".into(),
+                url.clone(),
+                SupportedComponents::TODO,
+                None,
+            ))];
+        let task = Task::new("Boxed task".into(), false, &url).unwrap();
+        calendars[0].add_item(Item::Task(task)).await.unwrap();
+        assert_eq!(calendars[0].get_items().await.unwrap().len(), 1);
+        assert_eq!(calendars[0].tasks_in_sort_order().await.unwrap().len(), 1);
+
+        let remote: Box<dyn DavCalendar + Send + Sync> = Box::new(RemoteCalendar::new(
+            "Dyn".into(),
+            Resource::new(url.clone(), "user".into(), "password".into()),
+            SupportedComponents::TODO,
+            None,
+        ));
+        assert_eq!(remote.url(), &url);
+
+        let source: Box<dyn CalDavSource<CachedCalendar> + Send + Sync> =
+            Box::new(Cache::new(&std::path::PathBuf::from("test_cache/dyn")));
+        assert!(source.get_calendars().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_items_by_urls() {
+        let url: Url = "https://some.calend.ar/batch/".parse().unwrap();
+        let mut calendar = <CachedCalendar as CompleteCalendarFactory>::new(
+            "Batch".into(),
+            url.clone(),
+            SupportedComponents::TODO,
+            None,
+        );
+        let task = Task::new("Some task".into(), false, &url).unwrap();
+        let task_url = task.url().clone();
+        calendar.add_item(Item::Task(task)).await.unwrap();
+
+        let missing = url.join("missing.ics").unwrap();
+        let names: Vec<Option<&str>> = calendar
+            .get_items_by_urls(&[missing.clone(), task_url, missing])
+            .await
+            .into_iter()
+            .map(|item| item.map(|item| item.name()))
+            .collect();
+        assert_eq!(names, [None, Some("Some task"), None]);
+    }
 }
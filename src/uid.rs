@@ -0,0 +1,87 @@
+//! The source of new identifiers
+//!
+//! UIDs of new tasks, as well as the random parts of new item URLs, are read from the [`UidGenerator`] set in [`config::UID_GENERATOR`](crate::config::UID_GENERATOR).
+//! Tests can replace it by a [`SeededUidGenerator`] to get stable URLs across runs.
+
+use std::sync::Mutex;
+
+use uuid::{Builder, Uuid, Variant, Version};
+
+use crate::utils::lock_ignoring_poison;
+
+/// Something that generates unique identifiers
+pub trait UidGenerator: Send + Sync {
+    fn generate(&self) -> String;
+}
+
+/// The default generator, that produces random (v4) UUIDs
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomUidGenerator;
+
+impl UidGenerator for RandomUidGenerator {
+    fn generate(&self) -> String {
+        Uuid::new_v4().to_hyphenated().to_string()
+    }
+}
+
+/// A generator that produces the same sequence of UUIDs for a given seed
+#[derive(Debug)]
+pub struct SeededUidGenerator {
+    state: Mutex<u64>,
+}
+
+impl SeededUidGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Mutex::new(seed),
+        }
+    }
+
+    /// SplitMix64
+    fn next_u64(state: &mut u64) -> u64 {
+        *state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl UidGenerator for SeededUidGenerator {
+    fn generate(&self) -> String {
+        let mut state = lock_ignoring_poison(&self.state);
+        let high = Self::next_u64(&mut state);
+        let low = Self::next_u64(&mut state);
+        let bytes = (((high as u128) << 64) | low as u128).to_be_bytes();
+
+        // Still make it look like a valid v4 UUID
+        Builder::from_bytes(bytes)
+            .set_variant(Variant::RFC4122)
+            .set_version(Version::Random)
+            .build()
+            .to_hyphenated()
+            .to_string()
+    }
+}
+
+/// A new identifier, from the generator set in [`config::UID_GENERATOR`](crate::config::UID_GENERATOR)
+pub fn new_uid() -> String {
+    lock_ignoring_poison(&crate::config::UID_GENERATOR).generate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_uids() {
+        let first = SeededUidGenerator::new(42);
+        let second = SeededUidGenerator::new(42);
+        let uids: Vec<String> = (0..3).map(|_| first.generate()).collect();
+        assert_eq!(uids, (0..3).map(|_| second.generate()).collect::<Vec<_>>());
+        assert_ne!(uids[0], uids[1]);
+
+        let parsed = Uuid::parse_str(&uids[0]).unwrap();
+        assert_eq!(parsed.get_version(), Some(Version::Random));
+    }
+}
@@ -0,0 +1,180 @@
+//! Structured differences between the observable contents of two caches (or calendars)
+//!
+//! This is mostly useful in tests, to show exactly what diverged after a sync.
+
+use std::fmt;
+
+use url::Url;
+
+use crate::utils::NamespacedName;
+
+/// Which side of a comparison something is missing from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A field that has different values on both sides of a comparison
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldDifference {
+    pub field: &'static str,
+    pub left: String,
+    pub right: String,
+}
+
+impl FieldDifference {
+    pub fn new<L: fmt::Debug, R: fmt::Debug>(field: &'static str, left: L, right: R) -> Self {
+        Self {
+            field,
+            left: format!("{:?}", left),
+            right: format!("{:?}", right),
+        }
+    }
+}
+
+/// A single difference
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContentDifference {
+    MissingCalendar {
+        url: Url,
+        missing_from: Side,
+    },
+    /// An attribute of a calendar (name, color...) differs
+    CalendarAttribute {
+        calendar: Url,
+        difference: FieldDifference,
+    },
+    MissingItem {
+        calendar: Url,
+        url: Url,
+        missing_from: Side,
+    },
+    ItemField {
+        calendar: Url,
+        url: Url,
+        difference: FieldDifference,
+    },
+    MissingProperty {
+        calendar: Url,
+        name: NamespacedName,
+        missing_from: Side,
+    },
+    PropertyField {
+        calendar: Url,
+        name: NamespacedName,
+        difference: FieldDifference,
+    },
+}
+
+/// Every difference between two caches (or calendars), described by `left` and `right`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentDiff {
+    pub left: String,
+    pub right: String,
+    pub differences: Vec<ContentDifference>,
+}
+
+impl ContentDiff {
+    pub fn new(left: &str, right: &str) -> Self {
+        Self {
+            left: left.to_string(),
+            right: right.to_string(),
+            differences: Vec::new(),
+        }
+    }
+
+    /// Whether both sides have the same observable content
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, difference: ContentDifference) {
+        self.differences.push(difference);
+    }
+
+    pub(crate) fn extend(&mut self, other: ContentDiff) {
+        self.differences.extend(other.differences);
+    }
+
+    fn side(&self, side: Side) -> &str {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    fn fmt_field(&self, f: &mut fmt::Formatter<'_>, difference: &FieldDifference) -> fmt::Result {
+        write!(
+            f,
+            "{} differs ({}: {}, {}: {})",
+            difference.field, self.left, difference.left, self.right, difference.right
+        )
+    }
+}
+
+impl fmt::Display for ContentDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "{} and {} have the same content", self.left, self.right);
+        }
+        write!(f, "{} and {} differ:", self.left, self.right)?;
+        for difference in &self.differences {
+            write!(f, "\n  - ")?;
+            match difference {
+                ContentDifference::MissingCalendar { url, missing_from } => write!(
+                    f,
+                    "calendar {} is missing from {}",
+                    url,
+                    self.side(*missing_from)
+                )?,
+                ContentDifference::CalendarAttribute {
+                    calendar,
+                    difference,
+                } => {
+                    write!(f, "calendar {}: ", calendar)?;
+                    self.fmt_field(f, difference)?
+                }
+                ContentDifference::MissingItem {
+                    calendar,
+                    url,
+                    missing_from,
+                } => write!(
+                    f,
+                    "calendar {}: item {} is missing from {}",
+                    calendar,
+                    url,
+                    self.side(*missing_from)
+                )?,
+                ContentDifference::ItemField {
+                    calendar,
+                    url,
+                    difference,
+                } => {
+                    write!(f, "calendar {}: item {}: ", calendar, url)?;
+                    self.fmt_field(f, difference)?
+                }
+                ContentDifference::MissingProperty {
+                    calendar,
+                    name,
+                    missing_from,
+                } => write!(
+                    f,
+                    "calendar {}: property {} is missing from {}",
+                    calendar,
+                    name,
+                    self.side(*missing_from)
+                )?,
+                ContentDifference::PropertyField {
+                    calendar,
+                    name,
+                    difference,
+                } => {
+                    write!(f, "calendar {}: property {}: ", calendar, name)?;
+                    self.fmt_field(f, difference)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
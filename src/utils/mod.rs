@@ -6,60 +6,54 @@ use std::hash::Hash;
 use std::io::{stdin, stdout, Read, Write};
 use std::sync::Arc;
 
-use prop::{print_property, Property};
+use prop::Property;
 use serde::{Deserialize, Serialize};
-use sync::Syncable;
 use tokio::sync::Mutex;
 use url::Url;
 
+use crate::error::{KFError, KFResult};
 use crate::traits::CompleteCalendar;
 use crate::traits::DavCalendar;
 use crate::Item;
 
+#[cfg(any(test, feature = "integration_tests"))]
+pub mod diff;
 pub mod prop;
+pub mod report;
 pub(crate) mod req;
+pub(crate) mod runtime;
 pub mod sync;
 pub(crate) mod xml;
 
-/// A debug utility that pretty-prints calendars
+/// A debug utility that pretty-prints calendars, sorted by URL.
+///
+/// See [`report::render_calendar_list`] to get this as a `String`, or in other formats
 pub async fn print_calendar_list<C>(cals: &HashMap<Url, Arc<Mutex<C>>>)
 where
     C: CompleteCalendar,
 {
-    let ordered = {
-        let mut v: Vec<(&Url, &Arc<Mutex<C>>)> = cals.iter().collect();
-        v.sort_by_key(|x| x.0);
-        v
-    };
-
-    for (url, cal) in ordered {
-        println!("CAL {} ({})", cal.lock().await.name(), url);
-        match cal.lock().await.get_items().await {
-            Err(_err) => continue,
-            Ok(map) => {
-                for (_, item) in map {
-                    print_task(item);
-                }
-            }
-        }
-
-        for prop in cal.lock().await.get_properties().await.values() {
-            print_property(prop);
-        }
-    }
+    print!(
+        "{}",
+        report::render_calendar_list(cals, report::ReportFormat::Text).await
+    );
 }
 
-/// A debug utility that pretty-prints calendars
+/// A debug utility that pretty-prints calendars, sorted by URL
 pub async fn print_dav_calendar_list<C>(cals: &HashMap<Url, Arc<Mutex<C>>>)
 where
     C: DavCalendar,
 {
-    for (url, cal) in cals {
-        println!("CAL {} ({})", cal.lock().await.name(), url);
+    let mut ordered: Vec<(&Url, &Arc<Mutex<C>>)> = cals.iter().collect();
+    ordered.sort_by_key(|x| x.0);
+
+    for (url, cal) in ordered {
+        println!("CAL {} ({})", cal.lock().await.display_name(), url);
         match cal.lock().await.get_item_version_tags().await {
             Err(_err) => continue,
             Ok(map) => {
-                for (url, version_tag) in map {
+                let mut tags: Vec<_> = map.into_iter().collect();
+                tags.sort_by(|a, b| a.0.cmp(&b.0));
+                for (url, version_tag) in tags {
                     println!("    * {} (version {:?})", url, version_tag);
                 }
             }
@@ -68,10 +62,8 @@ where
 }
 
 pub fn print_task(item: &Item) {
-    if let Item::Task(task) = item {
-        let completion = if task.completed() { "✓" } else { " " };
-        let sync = task.sync_status().symbol();
-        println!("    {}{} {}\t{}", completion, sync, task.name(), task.url());
+    if item.is_task() {
+        println!("{}", report::ItemListing::new(item).to_text_line());
     }
 }
 
@@ -109,17 +101,50 @@ pub fn pause() {
     stdin().read_exact(&mut [0]).unwrap();
 }
 
-/// Generate a random URL with a given prefix
-pub fn random_url(parent_calendar: &Url) -> Url {
-    let random = uuid::Uuid::new_v4().to_hyphenated().to_string();
-    parent_calendar.join(&random).unwrap(/* this cannot panic since we've just created a string that is a valid URL */)
+/// Generate a random URL with a given prefix (see [`crate::uid`]), i.e. the URL of a new item in the calendar at `parent_calendar`.
+/// The prefix is deemed to be a collection, even if it lacks its trailing slash
+///
+/// This fails in case the prefix cannot be a base (e.g. `mailto:` URLs), or in case the configured [`UidGenerator`](crate::uid::UidGenerator) returns something that is not a valid URL path
+pub fn random_url(parent_calendar: &Url) -> KFResult<Url> {
+    let random = crate::uid::new_uid();
+    let mut parent = parent_calendar.clone();
+    if !parent.path().ends_with('/') {
+        parent.set_path(&format!("{}/", parent.path()));
+    }
+    parent
+        .join(&random)
+        .map_err(|source| KFError::InvalidItemUrl {
+            parent: parent_calendar.clone(),
+            source,
+        })
+}
+
+/// A hash of `content` (FNV-1a) that never changes, unlike the hashers of the standard library that may change across Rust versions
+pub(crate) fn stable_hash(content: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in content.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
 }
 
-/// Generate a random NamespacedName, under a namespace we control
+/// Lock a mutex, even in case another thread panicked while holding it.
+///
+/// This is only suitable for values that are always in a consistent state (e.g. that are replaced at once)
+pub(crate) fn lock_ignoring_poison<T: ?Sized>(
+    mutex: &std::sync::Mutex<T>,
+) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Generate a random NamespacedName, under a namespace we control (see [`crate::uid`])
 pub fn random_nsn() -> NamespacedName {
     NamespacedName {
         xmlns: "https://github.com/daladim/kitchen-fridge/__test_xmlns__/".to_string(),
-        name: uuid::Uuid::new_v4().to_hyphenated().to_string(),
+        name: crate::uid::new_uid(),
     }
 }
 
@@ -138,9 +163,13 @@ impl NamespacedName {
 
     /// Uses namespace mappings to simplify the representation of this name
     /// For example, https://example.com/api/item becomes b:item if namespace https://example.com/api/ has symbol b in the namespace mapping
-    pub fn with_symbolized_prefix(&self, namespaces: &Namespaces) -> String {
-        let sym = namespaces.sym(&self.xmlns).unwrap();
-        format!("{}:{}", sym, self.name)
+    ///
+    /// This fails in case the namespace of this name has not been added to the mapping
+    pub(crate) fn with_symbolized_prefix(&self, namespaces: &Namespaces) -> KFResult<String> {
+        let sym = namespaces
+            .sym(&self.xmlns)
+            .ok_or_else(|| KFError::UndeclaredNamespace(self.xmlns.clone()))?;
+        Ok(format!("{}:{}", sym, self.name))
     }
 }
 impl fmt::Display for NamespacedName {
@@ -173,7 +202,7 @@ impl Ord for NamespacedName {
 /// Utility to track XML namespace symbol mappings, as used in xmlns attribute declarations
 ///
 /// Includes a default mapping of xmlns:d="DAV:"
-pub struct Namespaces {
+pub(crate) struct Namespaces {
     available_syms: VecDeque<char>,
     mapping: HashMap<String, char>,
 }
@@ -191,16 +220,23 @@ impl Namespaces {
         }
     }
 
-    /// Maps the namespace to an unassigned symbol and returns it
-    pub fn add<S: ToString>(&mut self, ns: S) -> char {
+    /// Maps the namespace to an unassigned symbol and returns it.
+    ///
+    /// A namespace that is already mapped keeps its symbol. This fails in case there is no unassigned symbol left
+    pub fn add<S: ToString>(&mut self, ns: S) -> KFResult<char> {
+        let ns = ns.to_string();
+        if let Some(sym) = self.mapping.get(&ns) {
+            return Ok(*sym);
+        }
+
         let sym = self
             .available_syms
             .pop_back()
-            .expect("Ran out of namespace symbols");
+            .ok_or_else(|| KFError::OutOfNamespaceSymbols { xmlns: ns.clone() })?;
 
-        self.mapping.insert(ns.to_string(), sym);
+        self.mapping.insert(ns, sym);
 
-        sym
+        Ok(sym)
     }
 
     pub fn decl(&self) -> String {
@@ -1,7 +1,11 @@
+use std::collections::HashMap;
 use std::fmt;
 
+use csscolorparser::Color;
 use serde::{Deserialize, Serialize};
 
+use crate::error::{KFError, KFResult};
+
 use super::{
     sync::{SyncStatus, Syncable, VersionTag},
     NamespacedName,
@@ -14,6 +18,7 @@ lazy_static::lazy_static! {
     pub(crate) static ref PROP_ALLPROP: NamespacedName = NamespacedName::new("DAV:", "allprop");
 
     // CalDAV properties
+    pub(crate) static ref PROP_CALENDAR_TIMEZONE: NamespacedName = NamespacedName::new("urn:ietf:params:xml:ns:caldav", "calendar-timezone");
     pub(crate) static ref PROP_SUPPORTED_CALENDAR_COMPONENT_SET: NamespacedName = NamespacedName::new("urn:ietf:params:xml:ns:caldav", "supported-calendar-component-set");
 
     // iCal properties
@@ -73,13 +78,39 @@ impl Property {
     }
 
     pub fn mark_for_deletion(&mut self) {
-        self.sync_status = SyncStatus::LocallyDeleted(self.value.clone().into());
+        self.set_sync_status(SyncStatus::LocallyDeleted(self.value.clone().into()));
     }
 
     /// Mark the property as Synced with its own value as the version tag
     /// See RemoteCalendar::set_property for more information on why
     pub fn mark_synced_to_self(&mut self) {
-        self.sync_status = SyncStatus::Synced(VersionTag::from(self.value.clone()));
+        self.set_sync_status(SyncStatus::Synced(VersionTag::from(self.value.clone())));
+    }
+
+    /// Start building a property, whose name and value are validated by [`PropertyBuilder::build`]
+    pub fn builder<S1: ToString, S2: ToString>(xmlns: S1, name: S2) -> PropertyBuilder {
+        PropertyBuilder {
+            nsn: NamespacedName::new(xmlns, name),
+            value: PropertyValue::Text(String::new()),
+        }
+    }
+
+    /// The value of this property, as a value of the given kind. `None` in case it is not one
+    pub fn typed_value(&self, kind: PropertyKind) -> Option<PropertyValue> {
+        PropertyValue::from_raw(kind, &self.value)
+    }
+
+    /// Change the value of this property (see [`Self::set_value`]), after checking that it can be sent to a server
+    pub fn set_typed_value(&mut self, value: PropertyValue) -> KFResult<()> {
+        let raw = value.to_raw();
+        validate_value(&self.nsn, &value, &raw)?;
+        self.set_value(raw);
+        Ok(())
+    }
+
+    /// Set the sync status, without checking that it is a legal transition from the current one (see [`Task::relabel_sync_status`](crate::task::Task::relabel_sync_status))
+    pub(crate) fn relabel_sync_status(&mut self, new_status: SyncStatus) {
+        self.sync_status = new_status;
     }
 
     /// Set property value, but forces a "master" SyncStatus, just like CalDAV servers are always "masters"
@@ -101,6 +132,7 @@ impl Syncable for Property {
     }
 
     fn set_sync_status(&mut self, new_status: SyncStatus) {
+        self.sync_status.check_transition(&new_status, self);
         self.sync_status = new_status;
     }
 }
@@ -127,3 +159,239 @@ pub fn print_property(prop: &Property) {
     let sync = prop.sync_status.symbol();
     println!("     {} prop {}", sync, prop);
 }
+
+/// The outcome of [`DavCalendar::patch_properties`](crate::traits::DavCalendar::patch_properties), for every property
+#[derive(Debug, Default)]
+pub struct PropPatchOutcome {
+    /// The new sync status of every property that had to be set, or why it could not be set
+    pub set: HashMap<NamespacedName, KFResult<SyncStatus>>,
+    /// Whether every property that had to be removed could be removed
+    pub removed: HashMap<NamespacedName, KFResult<()>>,
+}
+
+/// The kinds of [`PropertyValue`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyKind {
+    Text,
+    Int,
+    Bool,
+    Color,
+    Hrefs,
+}
+
+/// The typed value of a [`Property`], that is stored as a raw string (see [`Self::to_raw`] and [`Self::from_raw`])
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyValue {
+    Text(String),
+    /// E.g. the `calendar-order` of a calendar
+    Int(i64),
+    /// Stored as `true` or `false`
+    Bool(bool),
+    /// Stored as `#RRGGBBAA`, e.g. the `calendar-color` of a calendar
+    Color(Color),
+    /// A list of hrefs, stored one per line.
+    /// Servers send them as `DAV:href` elements, which are only supported when reading properties: writing them sends the raw text instead
+    Hrefs(Vec<String>),
+}
+
+impl PropertyValue {
+    pub fn kind(&self) -> PropertyKind {
+        match self {
+            Self::Text(_) => PropertyKind::Text,
+            Self::Int(_) => PropertyKind::Int,
+            Self::Bool(_) => PropertyKind::Bool,
+            Self::Color(_) => PropertyKind::Color,
+            Self::Hrefs(_) => PropertyKind::Hrefs,
+        }
+    }
+
+    /// The string this value is stored as
+    pub fn to_raw(&self) -> String {
+        match self {
+            Self::Text(text) => text.clone(),
+            Self::Int(i) => i.to_string(),
+            Self::Bool(b) => b.to_string(),
+            Self::Color(color) => {
+                let (r, g, b, a) = color.rgba_u8();
+                format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a)
+            }
+            Self::Hrefs(hrefs) => hrefs.join("\n"),
+        }
+    }
+
+    /// Parse a raw string as a value of the given kind. `None` in case it is not one
+    pub fn from_raw(kind: PropertyKind, raw: &str) -> Option<Self> {
+        let trimmed = raw.trim();
+        match kind {
+            PropertyKind::Text => Some(Self::Text(raw.to_string())),
+            PropertyKind::Int => trimmed.parse().ok().map(Self::Int),
+            PropertyKind::Bool => match trimmed.to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" => Some(Self::Bool(true)),
+                "false" | "0" | "no" => Some(Self::Bool(false)),
+                _ => None,
+            },
+            PropertyKind::Color => csscolorparser::parse(trimmed).ok().map(Self::Color),
+            PropertyKind::Hrefs => Some(Self::Hrefs(
+                trimmed.split_whitespace().map(str::to_string).collect(),
+            )),
+        }
+    }
+}
+
+impl From<String> for PropertyValue {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for PropertyValue {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<i64> for PropertyValue {
+    fn from(i: i64) -> Self {
+        Self::Int(i)
+    }
+}
+
+impl From<bool> for PropertyValue {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<Color> for PropertyValue {
+    fn from(color: Color) -> Self {
+        Self::Color(color)
+    }
+}
+
+/// Builds a [`Property`] that can be sent to a server, see [`Property::builder`]
+#[derive(Clone, Debug)]
+pub struct PropertyBuilder {
+    nsn: NamespacedName,
+    value: PropertyValue,
+}
+
+impl PropertyBuilder {
+    /// The value of the property (an empty text by default)
+    pub fn value<V: Into<PropertyValue>>(mut self, value: V) -> Self {
+        self.value = value.into();
+        self
+    }
+
+    /// Check that the name and the value can be written in XML, and build a property that has not been synced yet
+    pub fn build(self) -> KFResult<Property> {
+        let invalid = |detail: &str| KFError::InvalidProperty {
+            nsn: self.nsn.clone(),
+            detail: detail.to_string(),
+        };
+        if self.nsn.xmlns.trim().is_empty() {
+            return Err(invalid("the namespace is empty"));
+        }
+        if !is_xml_name(&self.nsn.name) {
+            return Err(invalid("this is not a valid XML element name"));
+        }
+        let raw = self.value.to_raw();
+        validate_value(&self.nsn, &self.value, &raw)?;
+        Ok(Property::new_from_nsn(self.nsn, raw))
+    }
+}
+
+/// Check that a value can be sent to a server
+fn validate_value(nsn: &NamespacedName, value: &PropertyValue, raw: &str) -> KFResult<()> {
+    let invalid = |detail: String| KFError::InvalidProperty {
+        nsn: nsn.clone(),
+        detail,
+    };
+    if let Some(c) = raw.chars().find(|c| !is_xml_char(*c)) {
+        return Err(invalid(format!(
+            "character {:?} cannot be written in XML",
+            c
+        )));
+    }
+    if let PropertyValue::Hrefs(hrefs) = value {
+        if let Some(href) = hrefs
+            .iter()
+            .find(|href| href.is_empty() || href.contains(char::is_whitespace))
+        {
+            return Err(invalid(format!("invalid href {:?}", href)));
+        }
+    }
+    Ok(())
+}
+
+/// Whether a character is allowed in XML 1.0 documents
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
+}
+
+/// Whether this is a valid (non-prefixed) XML element name
+fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_values() {
+        let order = Property::builder("http://apple.com/ns/ical/", "calendar-order")
+            .value(3)
+            .build()
+            .unwrap();
+        assert_eq!(order.value(), "3");
+        assert_eq!(
+            order.typed_value(PropertyKind::Int),
+            Some(PropertyValue::Int(3))
+        );
+        assert_eq!(order.typed_value(PropertyKind::Bool), None);
+
+        let color = Property::builder("http://apple.com/ns/ical/", "calendar-color")
+            .value(csscolorparser::parse("#ff8000").unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(color.value(), "#FF8000FF");
+        assert!(color.typed_value(PropertyKind::Color).is_some());
+
+        let mut enabled = Property::builder("urn:test", "enabled")
+            .value(true)
+            .build()
+            .unwrap();
+        assert_eq!(enabled.value(), "true");
+        enabled.mark_synced_to_self();
+        enabled.set_typed_value(PropertyValue::Bool(false)).unwrap();
+        assert_eq!(enabled.typed_value(PropertyKind::Bool), Some(false.into()));
+        assert!(matches!(
+            enabled.sync_status(),
+            SyncStatus::LocallyModified(_)
+        ));
+
+        let hrefs = PropertyValue::Hrefs(vec!["/a/".to_string(), "/b/".to_string()]);
+        assert_eq!(
+            PropertyValue::from_raw(PropertyKind::Hrefs, &hrefs.to_raw()),
+            Some(hrefs)
+        );
+
+        for (name, value) in [
+            ("calendar order", PropertyValue::Int(1)),
+            ("1st", PropertyValue::Int(1)),
+            ("order", PropertyValue::Text("bell\u{7}".to_string())),
+            ("order", PropertyValue::Hrefs(vec!["/a b/".to_string()])),
+        ] {
+            assert!(matches!(
+                Property::builder("urn:test", name).value(value).build(),
+                Err(KFError::InvalidProperty { .. })
+            ));
+        }
+        assert!(Property::builder("", "order").build().is_err());
+    }
+}
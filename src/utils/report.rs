@@ -0,0 +1,303 @@
+//! Stable, diffable reports about the content of calendars
+//!
+//! Calendars, items and properties are sorted (by URL and by name), so that the same content is always rendered the same way, e.g. in tests or in bug reports.
+//! See also [`crate::cache::inspect`], which summarizes a whole [`Cache`](crate::cache::Cache).
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::cache::inspect::describe_sync_status;
+use crate::item::{Item, ItemType};
+use crate::traits::CompleteCalendar;
+use crate::utils::prop::Property;
+use crate::utils::sync::Syncable;
+
+/// How a report is rendered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// The same layout as the former `print_*` debug utilities
+    Text,
+    /// Pretty-printed JSON
+    Json,
+    /// Markdown tables, e.g. to be pasted in a bug report
+    Markdown,
+}
+
+/// The content of a calendar
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CalendarListing {
+    pub url: Url,
+    pub name: String,
+    /// The items, sorted by URL
+    pub items: Vec<ItemListing>,
+    /// The properties, sorted by namespace and name
+    pub properties: Vec<PropertyListing>,
+}
+
+/// An item of a [`CalendarListing`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemListing {
+    pub url: Url,
+    pub name: String,
+    /// `"task"` or `"event"`
+    pub item_type: String,
+    /// Whether this is a completed task
+    pub completed: bool,
+    /// One of `"not_synced"`, `"synced"`, `"locally_modified"`, `"locally_deleted"`
+    pub sync_status: String,
+    /// The version tag (etag) of the item when it was last synced, if any
+    pub version_tag: Option<String>,
+}
+
+/// A property of a [`CalendarListing`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PropertyListing {
+    pub xmlns: String,
+    pub name: String,
+    pub value: String,
+    /// One of `"not_synced"`, `"synced"`, `"locally_modified"`, `"locally_deleted"`
+    pub sync_status: String,
+}
+
+impl ItemListing {
+    pub fn new(item: &Item) -> Self {
+        let (sync_status, version_tag) = describe_sync_status(item.sync_status());
+        Self {
+            url: item.url().clone(),
+            name: item.name().to_string(),
+            item_type: match item.type_() {
+                ItemType::Calendar => "calendar",
+                ItemType::Event => "event",
+                ItemType::Task => "task",
+            }
+            .to_string(),
+            completed: match item {
+                Item::Task(task) => task.completed(),
+                Item::Event(_) => false,
+            },
+            sync_status: sync_status.to_string(),
+            version_tag,
+        }
+    }
+
+    /// The line of this item in [`ReportFormat::Text`] reports
+    pub fn to_text_line(&self) -> String {
+        let completion = if self.completed { "✓" } else { " " };
+        format!(
+            "    {}{} {}\t{}",
+            completion,
+            symbol(&self.sync_status),
+            self.name,
+            self.url
+        )
+    }
+}
+
+impl PropertyListing {
+    pub fn new(prop: &Property) -> Self {
+        Self {
+            xmlns: prop.xmlns().to_string(),
+            name: prop.name().to_string(),
+            value: prop.value().to_string(),
+            sync_status: describe_sync_status(prop.sync_status()).0.to_string(),
+        }
+    }
+}
+
+/// List the content of calendars, sorted by URL
+pub async fn calendar_listings<C>(cals: &HashMap<Url, Arc<Mutex<C>>>) -> Vec<CalendarListing>
+where
+    C: CompleteCalendar,
+{
+    let mut listings = Vec::new();
+    for (url, cal) in cals {
+        let cal = cal.lock().await;
+        let mut items: Vec<ItemListing> = match cal.get_items().await {
+            Err(_err) => Vec::new(),
+            Ok(map) => map.values().map(|item| ItemListing::new(item)).collect(),
+        };
+        items.sort_by(|a, b| a.url.cmp(&b.url));
+        let mut properties: Vec<(_, PropertyListing)> = cal
+            .get_properties()
+            .await
+            .iter()
+            .map(|(nsn, prop)| (nsn.clone(), PropertyListing::new(prop)))
+            .collect();
+        properties.sort_by(|a, b| a.0.cmp(&b.0));
+
+        listings.push(CalendarListing {
+            url: url.clone(),
+            name: cal.display_name().to_string(),
+            items,
+            properties: properties.into_iter().map(|(_, prop)| prop).collect(),
+        });
+    }
+    listings.sort_by(|a, b| a.url.cmp(&b.url));
+    listings
+}
+
+/// Render the content of calendars
+pub async fn render_calendar_list<C>(
+    cals: &HashMap<Url, Arc<Mutex<C>>>,
+    format: ReportFormat,
+) -> String
+where
+    C: CompleteCalendar,
+{
+    render(&calendar_listings(cals).await, format)
+}
+
+/// Render calendar listings, e.g. the ones returned by [`calendar_listings`]
+pub fn render(listings: &[CalendarListing], format: ReportFormat) -> String {
+    match format {
+        ReportFormat::Text => render_text(listings),
+        ReportFormat::Json => serde_json::to_string_pretty(listings)
+            .expect("listings only contain strings and booleans"),
+        ReportFormat::Markdown => render_markdown(listings),
+    }
+}
+
+fn render_text(listings: &[CalendarListing]) -> String {
+    let mut s = String::new();
+    for cal in listings {
+        writeln!(s, "CAL {} ({})", cal.name, cal.url).unwrap();
+        for item in &cal.items {
+            writeln!(s, "{}", item.to_text_line()).unwrap();
+        }
+        for prop in &cal.properties {
+            writeln!(
+                s,
+                "     {} prop {}:{}={}",
+                symbol(&prop.sync_status),
+                prop.xmlns,
+                prop.name,
+                prop.value
+            )
+            .unwrap();
+        }
+    }
+    s
+}
+
+fn render_markdown(listings: &[CalendarListing]) -> String {
+    let mut s = String::new();
+    for cal in listings {
+        writeln!(s, "## {}\n\n<{}>\n", md_cell(&cal.name), cal.url).unwrap();
+        if !cal.items.is_empty() {
+            writeln!(s, "| Done | Item | Type | Sync status | URL |").unwrap();
+            writeln!(s, "|---|---|---|---|---|").unwrap();
+            for item in &cal.items {
+                writeln!(
+                    s,
+                    "| {} | {} | {} | {} | {} |",
+                    if item.completed { "✓" } else { "" },
+                    md_cell(&item.name),
+                    item.item_type,
+                    item.sync_status,
+                    item.url
+                )
+                .unwrap();
+            }
+            s.push('\n');
+        }
+        if !cal.properties.is_empty() {
+            writeln!(s, "| Property | Value | Sync status |").unwrap();
+            writeln!(s, "|---|---|---|").unwrap();
+            for prop in &cal.properties {
+                writeln!(
+                    s,
+                    "| {}:{} | {} | {} |",
+                    md_cell(&prop.xmlns),
+                    md_cell(&prop.name),
+                    md_cell(&prop.value),
+                    prop.sync_status
+                )
+                .unwrap();
+            }
+            s.push('\n');
+        }
+    }
+    s
+}
+
+/// Text that can be put in a Markdown table cell
+fn md_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// The same symbols as [`SyncStatus::symbol`](crate::utils::sync::SyncStatus::symbol)
+fn symbol(sync_status: &str) -> char {
+    match sync_status {
+        "not_synced" => '.',
+        "synced" => '=',
+        "locally_modified" => '~',
+        "locally_deleted" => 'x',
+        _ => '?',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::calendar::cached_calendar::CachedCalendar;
+    use crate::calendar::SupportedComponents;
+    use crate::task::Task;
+    use crate::traits::{BaseCalendar, CompleteCalendarFactory};
+
+    #[tokio::test]
+    async fn test_stable_rendering() {
+        let mut cals = HashMap::new();
+        for name in ["b", "a"] {
+            let url: Url = format!("https://some.calend.ar/{}/", name).parse().unwrap();
+            let mut cal = CachedCalendar::new(
+                name.to_string(),
+                url.clone(),
+                SupportedComponents::TODO,
+                None,
+            );
+            for i in 0..5 {
+                let task = Task::new_with_parameters(
+                    format!("Task {}", i),
+                    format!("{}-{}", name, i),
+                    url.join(&format!("{}.ics", i)).unwrap(),
+                    crate::task::CompletionStatus::Uncompleted,
+                    Default::default(),
+                    None,
+                    chrono::Utc::now(),
+                    String::new(),
+                    Vec::new(),
+                    Vec::new(),
+                );
+                cal.add_item(Item::Task(task)).await.unwrap();
+            }
+            cal.add_property(Property::new("urn:test", "prop", "value".to_string()))
+                .await
+                .unwrap();
+            cals.insert(url, Arc::new(Mutex::new(cal)));
+        }
+
+        let text = render_calendar_list(&cals, ReportFormat::Text).await;
+        assert_eq!(text, render_calendar_list(&cals, ReportFormat::Text).await);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "CAL a (https://some.calend.ar/a/)");
+        assert_eq!(lines[1], "     . Task 0\thttps://some.calend.ar/a/0.ics");
+        assert_eq!(lines[6], "     . prop urn:test:prop=value");
+        assert_eq!(lines[7], "CAL b (https://some.calend.ar/b/)");
+
+        let json = render_calendar_list(&cals, ReportFormat::Json).await;
+        let parsed: Vec<CalendarListing> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, calendar_listings(&cals).await);
+
+        let markdown = render_calendar_list(&cals, ReportFormat::Markdown).await;
+        assert!(markdown.starts_with("## a\n\n<https://some.calend.ar/a/>\n"));
+        assert!(
+            markdown.contains("| Task 4 | task | not_synced | https://some.calend.ar/a/4.ics |")
+        );
+    }
+}
@@ -1,66 +1,26 @@
-use http::{header::CONTENT_TYPE, Method};
+use std::collections::HashMap;
+
 use minidom::Element;
+use reqwest::StatusCode;
 
 use crate::{
-    error::{HttpStatusConstraint, KFError, KFResult},
+    dav::{self, request, Multistatus},
+    error::{KFError, KFResult},
     resource::Resource,
-    utils::Namespaces,
 };
 
 use super::{
-    xml::{find_elem, find_elems},
+    xml::{find_elem, ElementStream},
     NamespacedName,
 };
 
-pub(crate) async fn sub_request(
-    resource: &Resource,
-    method: &str,
-    body: String,
-    depth: u32,
-) -> KFResult<String> {
-    let method: Method = method.parse().expect("invalid method name");
-
-    let url = resource.url();
-
-    let res = reqwest::Client::new()
-        .request(method.clone(), url.clone())
-        .header("Depth", depth)
-        .header(CONTENT_TYPE, "application/xml")
-        .basic_auth(resource.username(), Some(resource.password()))
-        .body(body)
-        .send()
-        .await
-        .map_err(|source| KFError::HttpRequestError {
-            url: url.clone(),
-            method: method.clone(),
-            source,
-        })?;
-
-    if !res.status().is_success() {
-        return Err(KFError::UnexpectedHTTPStatusCode {
-            expected: HttpStatusConstraint::Success,
-            got: res.status(),
-        });
-    }
-
-    let text = res
-        .text()
-        .await
-        .map_err(|source| KFError::HttpRequestError {
-            url: url.clone(),
-            method,
-            source,
-        })?;
-    Ok(text)
-}
-
 pub(crate) async fn sub_request_and_extract_elem(
     resource: &Resource,
     body: String,
     depth: u32,
     items: &[&str],
 ) -> KFResult<String> {
-    let text = sub_request(resource, "PROPFIND", body, depth).await?;
+    let text = request(resource, dav::method("PROPFIND"), body, depth).await?;
 
     let mut current_element: &Element = &text
         .parse()
@@ -86,59 +46,111 @@ pub(crate) async fn sub_request_and_extract_elems(
     depth: u32,
     item: &str,
 ) -> KFResult<Vec<Element>> {
-    let text = sub_request(resource, method, body, depth).await?;
-
-    let element: &Element = &text
-        .parse()
-        .map_err(|source| KFError::DOMParseError { text, source })?;
-    Ok(find_elems(element, item)
-        .iter()
-        .map(|elem| (*elem).clone())
-        .collect())
+    let mut elems = Vec::new();
+    sub_request_and_process_elems(resource, method, body, depth, item, |elem| {
+        elems.push(elem);
+        Ok(())
+    })
+    .await?;
+    Ok(elems)
 }
 
-/// Body of a PROPFIND call that queries the given properties
-///
-/// This will look something like:
+/// Send a request, and hand every `item` element of the reply to `process`, as soon as it has been parsed.
 ///
-/// <d:propfind xmlns:d="DAV:">
-///     <d:prop>
-///         <d:allprop/>
-///     </d:prop>
-/// </d:propfind>
-pub(crate) fn propfind_body(props: &[NamespacedName]) -> String {
-    let mut namespaces = Namespaces::new();
-    for p in props {
-        namespaces.add(&p.xmlns);
+/// Unlike [`sub_request_and_extract_elems`], the DOM of the whole reply is never built, which bounds the memory needed for huge multistatus replies
+pub(crate) async fn sub_request_and_process_elems<F>(
+    resource: &Resource,
+    method: &str,
+    body: String,
+    depth: u32,
+    item: &str,
+    mut process: F,
+) -> KFResult<()>
+where
+    F: FnMut(Element) -> KFResult<()>,
+{
+    let text = request(resource, dav::method(method), body, depth).await?;
+
+    for elem in ElementStream::new(&text, item) {
+        match elem {
+            Err(source) => {
+                return Err(KFError::DOMParseError {
+                    text: text.clone(),
+                    source,
+                })
+            }
+            Ok(elem) => process(elem)?,
+        }
     }
+    Ok(())
+}
 
-    let prop_names = {
-        let mut s = String::new();
-        for p in props {
-            s.push('<');
-            s.push_str(p.with_symbolized_prefix(&namespaces).as_str());
-            s.push('/');
-            s.push('>');
-            s.push('\n');
+/// Parse a 207 Multi-Status reply, and returns the status of every property it mentions
+pub(crate) fn parse_propstat_statuses(
+    text: String,
+) -> KFResult<HashMap<NamespacedName, StatusCode>> {
+    let multistatus = Multistatus::parse(&text)?;
+    let mut statuses = HashMap::new();
+    for propstat in multistatus.responses.iter().flat_map(|r| &r.propstats) {
+        for prop in &propstat.props {
+            statuses.insert(NamespacedName::new(prop.ns(), prop.name()), propstat.status);
         }
-        s
-    };
+    }
+    Ok(statuses)
+}
 
-    let d = namespaces.dav_sym();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    format!(
-        r#"
-<{}:propfind{}>
-    <{}:prop>
-{}
-    </{}:prop>
-</{}:propfind>
-"#,
-        d,
-        namespaces.decl(),
-        d,
-        prop_names,
-        d,
-        d,
-    )
+    use crate::dav::proppatch_body;
+    use crate::utils::prop::Property;
+    use crate::utils::xml::find_elems;
+
+    #[test]
+    fn test_proppatch_round_trip() {
+        let color = Property::new(
+            "http://apple.com/ns/ical/",
+            "calendar-color",
+            "#FF8000".to_string(),
+        );
+        let order = Property::new(
+            "http://apple.com/ns/ical/",
+            "calendar-order",
+            "3".to_string(),
+        );
+        let name = NamespacedName::new("DAV:", "displayname");
+
+        let body = proppatch_body(&[color, order], std::slice::from_ref(&name)).unwrap();
+        let parsed: Element = body.parse().unwrap();
+        assert_eq!(parsed.name(), "propertyupdate");
+        assert_eq!(find_elems(&parsed, "set").len(), 1);
+        assert_eq!(find_elems(&parsed, "remove").len(), 1);
+        assert_eq!(
+            find_elem(&parsed, "calendar-order").unwrap().ns(),
+            "http://apple.com/ns/ical/"
+        );
+
+        let reply = r#"<?xml version="1.0" encoding="utf-8" ?>
+            <d:multistatus xmlns:d="DAV:" xmlns:x="http://apple.com/ns/ical/">
+                <d:response>
+                    <d:href>/calendars/me/tasks/</d:href>
+                    <d:propstat>
+                        <d:prop><x:calendar-color/><x:calendar-order/></d:prop>
+                        <d:status>HTTP/1.1 424 Failed Dependency</d:status>
+                    </d:propstat>
+                    <d:propstat>
+                        <d:prop><d:displayname/></d:prop>
+                        <d:status>HTTP/1.1 403 Forbidden</d:status>
+                    </d:propstat>
+                </d:response>
+            </d:multistatus>"#;
+        let statuses = parse_propstat_statuses(reply.to_string()).unwrap();
+        assert_eq!(statuses.len(), 3);
+        assert_eq!(statuses[&name], StatusCode::FORBIDDEN);
+        assert_eq!(
+            statuses[&NamespacedName::new("http://apple.com/ns/ical/", "calendar-color")],
+            StatusCode::FAILED_DEPENDENCY
+        );
+    }
 }
@@ -0,0 +1,17 @@
+//! What differs between native targets (that run on Tokio) and web browsers (wasm32, where futures run on the JS event loop)
+
+use std::time::Duration;
+
+/// A monotonic instant. `std::time::Instant` panics in browsers
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::Instant;
+
+/// Wait for `duration`, without blocking the thread (or the event loop)
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(duration).await;
+}
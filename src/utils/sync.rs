@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 /// Describes whether this item has been synced already, or modified since the last time it was synced
@@ -28,6 +29,83 @@ impl SyncStatus {
             SyncStatus::LocallyDeleted(_) => 'x',
         }
     }
+
+    /// The version tag this status refers to, if any
+    pub fn version_tag(&self) -> Option<&VersionTag> {
+        match self {
+            SyncStatus::NotSynced => None,
+            SyncStatus::Synced(vt)
+            | SyncStatus::LocallyModified(vt)
+            | SyncStatus::LocallyDeleted(vt) => Some(vt),
+        }
+    }
+
+    /// Whether a value that has this status may take the `next` one.
+    ///
+    /// Values that have never been synced have no version tag, so they cannot become local changes or deletions of a server version (they are removed as soon as they are deleted).
+    /// Deleted values cannot become brand new values either, otherwise the server version would never be deleted.
+    /// Relabelling statuses against another baseline (e.g. when restoring a snapshot) is not a transition, see [`Self::check_transition`]
+    pub fn can_become(&self, next: &SyncStatus) -> bool {
+        !matches!(
+            (self, next),
+            (
+                SyncStatus::NotSynced,
+                SyncStatus::LocallyModified(_) | SyncStatus::LocallyDeleted(_)
+            ) | (SyncStatus::LocallyDeleted(_), SyncStatus::NotSynced)
+        )
+    }
+
+    /// In debug builds (and tests), assert that a value (described by `what`) can go from `self` to `next`, logging where the illegal transition comes from.
+    /// This does nothing in release builds
+    pub(crate) fn check_transition(&self, next: &SyncStatus, what: &dyn std::fmt::Display) {
+        if cfg!(debug_assertions) && !self.can_become(next) {
+            log::error!(
+                "Illegal sync status transition for {}: {} -> {}\n{}",
+                what,
+                self,
+                next,
+                std::backtrace::Backtrace::force_capture()
+            );
+            panic!(
+                "Illegal sync status transition for {}: {} -> {}",
+                what, self, next
+            );
+        }
+    }
+
+    /// The status an earlier copy of a value (that had the `snapshot` status, or did not exist) should take to replace its `current` version.
+    ///
+    /// In case a sync has happened since the copy was made, the version tags have changed: the restored value is then marked as a local change,
+    /// so that the next sync pushes it rather than overwriting it with the server version.
+    /// Returns `None` in case the value should not exist at all
+    pub(crate) fn restored(
+        snapshot: Option<&SyncStatus>,
+        current: Option<&SyncStatus>,
+    ) -> Option<SyncStatus> {
+        match (snapshot, current) {
+            (None, None) => None,
+            // This value has been created since. Values the server knows about must be deleted there as well
+            (None, Some(current)) => current
+                .version_tag()
+                .map(|vt| SyncStatus::LocallyDeleted(vt.clone())),
+            (Some(SyncStatus::LocallyDeleted(_)), None) => None,
+            // This value has been removed since, it must be created again
+            (Some(_), None) => Some(SyncStatus::NotSynced),
+            (Some(snapshot), Some(current)) => {
+                if snapshot.version_tag() == current.version_tag() {
+                    return Some(snapshot.clone());
+                }
+                match (snapshot, current.version_tag()) {
+                    (SyncStatus::LocallyDeleted(_), None) => None,
+                    (_, None) => Some(SyncStatus::NotSynced),
+                    (SyncStatus::LocallyDeleted(_), Some(vt)) => {
+                        Some(SyncStatus::LocallyDeleted(vt.clone()))
+                    }
+                    (_, Some(vt)) => Some(SyncStatus::LocallyModified(vt.clone())),
+                }
+            }
+        }
+    }
 }
 impl Default for SyncStatus {
     /// The default sync status is NotSynced
@@ -71,6 +149,9 @@ pub trait Syncable {
     }
 }
 
+/// The prefix of the pseudo version tags (see [`VersionTag::from_content`]). Well-formed etags are quoted, so that they never start with it
+const CONTENT_HASH_PREFIX: &str = "kf-content-hash:";
+
 /// A VersionTag is basically a CalDAV `ctag` or `etag`. Whenever it changes, this means the data has changed.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Eq, Hash)]
 pub struct VersionTag {
@@ -89,6 +170,24 @@ impl VersionTag {
         &self.tag
     }
 
+    /// A pseudo version tag, derived from the content of an item, for servers that do not provide etags.
+    ///
+    /// This uses a stable hash (FNV-1a), so that the same content always gives the same tag, even across versions of this crate
+    pub fn from_content(content: &str) -> Self {
+        Self {
+            tag: format!(
+                "{}{:016x}",
+                CONTENT_HASH_PREFIX,
+                crate::utils::stable_hash(content)
+            ),
+        }
+    }
+
+    /// Whether this is a pseudo version tag (see [`Self::from_content`]), that the server does not know about
+    pub fn is_content_hash(&self) -> bool {
+        self.tag.starts_with(CONTENT_HASH_PREFIX)
+    }
+
     /// Generate a random VersionTag
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
     pub fn random() -> Self {
@@ -96,3 +195,71 @@ impl VersionTag {
         Self { tag: random }
     }
 }
+
+/// Whether the sync of an item keeps failing, e.g. to show a badge next to it.
+/// See [`CompleteCalendar::item_sync_state`](crate::traits::CompleteCalendar::item_sync_state)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemSyncState {
+    /// How many syncs in a row have failed to upload or download this item. This is reset once a sync succeeds
+    pub failures: u32,
+    /// Why the last sync of this item failed
+    pub last_error: Option<String>,
+    /// When the last sync of this item failed
+    pub last_failure: Option<DateTime<Utc>>,
+}
+
+impl ItemSyncState {
+    /// Whether the last sync of this item has failed
+    pub fn is_failing(&self) -> bool {
+        self.failures > 0
+    }
+
+    pub(crate) fn record_failure(&mut self, error: String) {
+        self.failures += 1;
+        self.last_error = Some(error);
+        self.last_failure = Some(crate::clock::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_hash_version_tag() {
+        let tag = VersionTag::from_content("BEGIN:VCALENDAR");
+        assert_eq!(tag, VersionTag::from_content("BEGIN:VCALENDAR"));
+        assert_ne!(tag, VersionTag::from_content("BEGIN:VCALENDAR\r\n"));
+        assert!(tag.is_content_hash());
+        assert!(!VersionTag::from("\"1234\"".to_string()).is_content_hash());
+    }
+    #[test]
+    fn test_transitions() {
+        let vt = VersionTag::from("v1".to_string());
+        let synced = SyncStatus::Synced(vt.clone());
+        let modified = SyncStatus::LocallyModified(vt.clone());
+        let deleted = SyncStatus::LocallyDeleted(vt);
+
+        assert!(SyncStatus::NotSynced.can_become(&synced));
+        assert!(synced.can_become(&modified));
+        assert!(modified.can_become(&deleted));
+        assert!(deleted.can_become(&synced));
+        assert!(!SyncStatus::NotSynced.can_become(&modified));
+        assert!(!SyncStatus::NotSynced.can_become(&deleted));
+        assert!(!deleted.can_become(&SyncStatus::NotSynced));
+    }
+
+    #[test]
+    #[should_panic(expected = "Illegal sync status transition")]
+    fn test_illegal_transition_is_caught() {
+        let mut task = crate::task::Task::new(
+            "Never synced".to_string(),
+            false,
+            &"https://some.calend.ar/cal/".parse().unwrap(),
+        )
+        .unwrap();
+        task.set_sync_status(SyncStatus::LocallyDeleted(VersionTag::from(
+            "v1".to_string(),
+        )));
+    }
+}
@@ -1,4 +1,8 @@
+use std::collections::BTreeMap;
+
 use minidom::Element;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
 
 /// Walks an XML tree and returns every element that has the given name
 pub fn find_elems<S: AsRef<str>>(root: &Element, searched_name: S) -> Vec<&Element> {
@@ -35,3 +39,291 @@ pub fn find_elem<S: AsRef<str>>(root: &Element, searched_name: S) -> Option<&Ele
     }
     None
 }
+
+/// Escape a text, so that it can be inserted as the content of an XML element.
+///
+/// This is the counterpart of the unescaping done when replies are parsed, so that values read from a server can be sent back unchanged
+pub(crate) fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decode the body of a reply into a string.
+///
+/// The encoding is taken from the `charset` of its `Content-Type` header if any, or from its XML declaration. UTF-8 is assumed otherwise.
+/// Bytes that are invalid in this encoding are replaced with U+FFFD
+pub(crate) fn decode_body(bytes: &[u8], content_type: Option<&str>) -> String {
+    let label = content_type
+        .and_then(|ct| {
+            ct.split(';')
+                .filter_map(|param| param.trim().split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("charset"))
+                .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        })
+        .or_else(|| declared_encoding(bytes));
+
+    let encoding = label
+        .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    let (text, _, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        log::warn!(
+            "Reply is not valid {}, some characters have been replaced",
+            encoding.name()
+        );
+    }
+    text.into_owned()
+}
+
+/// The `encoding` of the XML declaration (`<?xml version="1.0" encoding="..."?>`) that starts a document, if any
+fn declared_encoding(bytes: &[u8]) -> Option<String> {
+    // The declaration is ASCII-only
+    let head = &bytes[..bytes.len().min(200)];
+    let head = String::from_utf8_lossy(head);
+    let decl = head.trim_start().strip_prefix("<?xml")?;
+    let decl = &decl[..decl.find("?>")?];
+    let value = decl
+        .split("encoding")
+        .nth(1)?
+        .trim_start()
+        .strip_prefix('=')?;
+    let value = value.trim_start();
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &value[1..];
+    Some(value[..value.find(quote)?].to_string())
+}
+
+/// Iterates over the elements that have a given name in an XML document, without building the DOM of the whole document.
+///
+/// Like [`find_elems`], this does not look for these elements inside the elements that have been found already.
+/// Only one of them is kept in memory at a time, which bounds the memory used to parse huge multistatus replies (that contain one `response` per item)
+pub(crate) struct ElementStream<'a> {
+    reader: Reader<&'a [u8]>,
+    searched_name: String,
+    /// The namespaces declared by every currently open element, from the root
+    namespaces: Vec<BTreeMap<Option<String>, String>>,
+    buf: Vec<u8>,
+}
+
+impl<'a> ElementStream<'a> {
+    pub(crate) fn new<S: ToString>(text: &'a str, searched_name: S) -> Self {
+        Self {
+            reader: Reader::from_str(text),
+            searched_name: searched_name.to_string(),
+            namespaces: Vec::new(),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Open an element, and return it (without its children)
+    fn open(&mut self, start: &BytesStart) -> Result<Element, minidom::Error> {
+        let (prefix, name) = split_name(std::str::from_utf8(start.name())?);
+        let mut declared = self.namespaces.last().cloned().unwrap_or_default();
+        let mut attributes = Vec::new();
+        for attr in start.attributes() {
+            let attr = attr?;
+            let key = std::str::from_utf8(attr.key)?.to_string();
+            let value = attr.unescape_and_decode_value(&self.reader)?;
+            if key == "xmlns" {
+                declared.insert(None, value);
+            } else if let Some(declared_prefix) = key.strip_prefix("xmlns:") {
+                declared.insert(Some(declared_prefix.to_string()), value);
+            } else {
+                attributes.push((key, value));
+            }
+        }
+        let namespace = declared
+            .get(&prefix.map(str::to_string))
+            .ok_or(minidom::Error::MissingNamespace)?
+            .clone();
+        self.namespaces.push(declared);
+
+        let mut builder = Element::builder(name, namespace);
+        for (key, value) in attributes {
+            builder = builder.attr(key, value);
+        }
+        Ok(builder.build())
+    }
+
+    /// Read the content of `elem`, until it is closed
+    fn read_children(&mut self, mut elem: Element) -> Result<Element, minidom::Error> {
+        let mut stack = Vec::new();
+        loop {
+            self.buf.clear();
+            let event = self.reader.read_event(&mut self.buf)?.into_owned();
+            match event {
+                Event::Start(start) => {
+                    let child = self.open(&start)?;
+                    stack.push(std::mem::replace(&mut elem, child));
+                }
+                Event::Empty(start) => {
+                    let child = self.open(&start)?;
+                    self.namespaces.pop();
+                    elem.append_child(child);
+                }
+                Event::End(_) => {
+                    self.namespaces.pop();
+                    match stack.pop() {
+                        None => return Ok(elem),
+                        Some(mut parent) => {
+                            parent.append_child(elem);
+                            elem = parent;
+                        }
+                    }
+                }
+                Event::Text(text) => {
+                    let text = text.unescape_and_decode(&self.reader)?;
+                    if !text.is_empty() {
+                        elem.append_text_node(text);
+                    }
+                }
+                Event::CData(text) => {
+                    elem.append_text_node(self.reader.decode(&text)?.to_string());
+                }
+                Event::Eof => return Err(minidom::Error::EndOfDocument),
+                Event::Comment(_) | Event::Decl(_) | Event::PI(_) | Event::DocType(_) => (),
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for ElementStream<'a> {
+    type Item = Result<Element, minidom::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            let event = match self.reader.read_event(&mut self.buf) {
+                Err(err) => return Some(Err(err.into())),
+                Ok(event) => event.into_owned(),
+            };
+            let result = match event {
+                Event::Start(start) => {
+                    let is_root = self.namespaces.is_empty();
+                    match self.open(&start) {
+                        Ok(elem) if !is_root && elem.name() == self.searched_name => {
+                            Some(self.read_children(elem))
+                        }
+                        Ok(_) => None,
+                        Err(err) => Some(Err(err)),
+                    }
+                }
+                Event::Empty(start) => {
+                    let is_root = self.namespaces.is_empty();
+                    let result = self.open(&start);
+                    self.namespaces.pop();
+                    match result {
+                        Ok(elem) if !is_root && elem.name() == self.searched_name => Some(Ok(elem)),
+                        Ok(_) => None,
+                        Err(err) => Some(Err(err)),
+                    }
+                }
+                Event::End(_) => {
+                    self.namespaces.pop();
+                    None
+                }
+                Event::Eof => return None,
+                _ => None,
+            };
+            if result.is_some() {
+                return result;
+            }
+        }
+    }
+}
+
+fn split_name(name: &str) -> (Option<&str>, &str) {
+    match name.split_once(':') {
+        Some((prefix, local)) => (Some(prefix), local),
+        None => (None, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_element_stream() {
+        let text = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">
+    <d:response>
+        <d:href>/cal/1.ics</d:href>
+        <d:propstat>
+            <d:prop><d:getetag>"a&amp;b"</d:getetag><cal:calendar-data><![CDATA[BEGIN:VCALENDAR]]></cal:calendar-data></d:prop>
+            <d:status>HTTP/1.1 200 OK</d:status>
+        </d:propstat>
+    </d:response>
+    <!-- a comment -->
+    <response xmlns="DAV:"><href>/cal/2.ics</href><getetag/></response>
+</d:multistatus>"#;
+
+        // minidom refuses comments
+        let dom: Element = text.replace("<!-- a comment -->", "").parse().unwrap();
+        let expected: Vec<Element> = find_elems(&dom, "response").into_iter().cloned().collect();
+        let streamed: Vec<Element> = ElementStream::new(text, "response")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(streamed, expected);
+        assert_eq!(streamed.len(), 2);
+        assert_eq!(
+            find_elem(&streamed[0], "getetag").unwrap().text(),
+            "\"a&b\""
+        );
+        assert_eq!(
+            find_elem(&streamed[0], "calendar-data").unwrap().text(),
+            "BEGIN:VCALENDAR"
+        );
+        assert!(find_elem(&streamed[1], "getetag").is_some());
+
+        let unbalanced = "<d:multistatus xmlns:d=\"DAV:\"><d:response><d:href>";
+        assert!(ElementStream::new(unbalanced, "response")
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_escape_text() {
+        let name = "Tom & Jerry <3 > \"l'été\" &amp;";
+        let xml = format!(
+            "<d:displayname xmlns:d=\"DAV:\">{}</d:displayname>",
+            escape_text(name)
+        );
+        let parsed: Element = xml.parse().unwrap();
+        assert_eq!(parsed.text(), name);
+    }
+
+    #[test]
+    fn test_decode_body() {
+        // "Équipe" in ISO-8859-1
+        let latin1 = b"<?xml version=\"1.0\" encoding='ISO-8859-1'?><a>\xC9quipe</a>";
+        assert_eq!(
+            decode_body(latin1, None),
+            "<?xml version=\"1.0\" encoding='ISO-8859-1'?><a>Équipe</a>"
+        );
+        assert_eq!(
+            decode_body(
+                b"<a>\xC9quipe</a>",
+                Some("application/xml; charset=\"iso-8859-1\"")
+            ),
+            "<a>Équipe</a>"
+        );
+        assert_eq!(
+            decode_body("<a>Équipe</a>".as_bytes(), None),
+            "<a>Équipe</a>"
+        );
+        assert_eq!(
+            decode_body("<a>日本語</a>".as_bytes(), Some("text/xml; charset=utf-8")),
+            "<a>日本語</a>"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Tests for syncs of several accounts
+#![cfg(feature = "local_calendar_mocks_remote_calendars")]
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use url::Url;
+
+use chrono::{TimeZone, Utc};
+
+use kitchen_fridge::accounts::unified::UnifiedView;
+use kitchen_fridge::accounts::AccountManager;
+use kitchen_fridge::cache::Cache;
+use kitchen_fridge::calendar::cached_calendar::CachedCalendar;
+use kitchen_fridge::calendar::SupportedComponents;
+use kitchen_fridge::mock_behaviour::MockBehaviour;
+use kitchen_fridge::provider::Provider;
+use kitchen_fridge::task::patch::TaskPatch;
+use kitchen_fridge::task::CompletionStatus;
+use kitchen_fridge::traits::BaseCalendar;
+use kitchen_fridge::traits::CalDavSource;
+use kitchen_fridge::traits::CompleteCalendar;
+use kitchen_fridge::utils::sync::SyncStatus;
+use kitchen_fridge::Item;
+use kitchen_fridge::Task;
+
+async fn mocked_account(
+    name: &str,
+    cal_url: &Url,
+) -> Provider<Cache, CachedCalendar, Cache, CachedCalendar> {
+    let mut remote = Cache::new(&PathBuf::from(format!(
+        "test_cache/accounts_{}_remote/",
+        name
+    )));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            name.to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    cal.lock()
+        .await
+        .add_item(Item::Task(
+            Task::new(format!("Task of {}", name), false, cal_url).unwrap(),
+        ))
+        .await
+        .unwrap();
+    let local = Cache::new(&PathBuf::from(format!(
+        "test_cache/accounts_{}_local/",
+        name
+    )));
+    Provider::new(remote, local)
+}
+
+#[tokio::test]
+async fn test_sync_several_accounts() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let work_url: Url = "https://work.calend.ar/tasks/".parse().unwrap();
+    let home_url: Url = "https://home.calend.ar/tasks/".parse().unwrap();
+    let mut accounts = AccountManager::new();
+    assert!(accounts
+        .add_account("work".to_string(), mocked_account("work", &work_url).await)
+        .is_none());
+    accounts.add_account("home".to_string(), mocked_account("home", &home_url).await);
+    assert_eq!(
+        accounts.account_ids().collect::<Vec<_>>(),
+        vec!["work", "home"]
+    );
+
+    let results = accounts.sync_all_concurrently().await;
+    assert_eq!(results.len(), 2);
+    assert!(results.values().all(|r| r.is_success()));
+
+    let calendars = accounts.calendars().await.unwrap();
+    let found: Vec<(&str, &Url)> = calendars
+        .iter()
+        .map(|c| (c.account_id.as_str(), &c.url))
+        .collect();
+    assert_eq!(found, vec![("work", &work_url), ("home", &home_url)]);
+    let home_cal = calendars[1].calendar.lock().await;
+    let items = home_cal.get_items().await.unwrap();
+    assert_eq!(
+        items.values().map(|i| i.name()).collect::<Vec<_>>(),
+        vec!["Task of home"]
+    );
+    drop(home_cal);
+
+    assert!(accounts.remove_account("work").is_some());
+    assert!(accounts.account("work").is_none());
+    let results = accounts.sync_all().await;
+    assert_eq!(results.keys().collect::<Vec<_>>(), vec!["home"]);
+}
+
+#[tokio::test]
+async fn test_unified_view() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let work_url: Url = "https://work.calend.ar/unified/".parse().unwrap();
+    let home_url: Url = "https://home.calend.ar/unified/".parse().unwrap();
+    let work = mocked_account("unified_work", &work_url).await;
+    let home = mocked_account("unified_home", &home_url).await;
+
+    // The same task is in both accounts, it has been more recently modified in the work account
+    let shared = |cal_url: &Url, name: &str, day: u32| {
+        Task::new_with_parameters(
+            name.to_string(),
+            "shared-uid".to_string(),
+            cal_url.join("shared.ics").unwrap(),
+            CompletionStatus::Uncompleted,
+            SyncStatus::random_synced(),
+            None,
+            Utc.ymd(2021, 1, day).and_hms(0, 0, 0),
+            "prod-id".to_string(),
+            Vec::new(),
+            Vec::new(),
+        )
+    };
+    let mut due_task = Task::new("Due task".to_string(), false, &work_url).unwrap();
+    TaskPatch {
+        due: Some(Some(Utc.ymd(2021, 6, 1).and_hms(12, 0, 0).into())),
+        categories: Some(vec!["Errands".to_string()]),
+        ..TaskPatch::default()
+    }
+    .apply(&mut due_task);
+    {
+        let remote = work.remote().get_calendar(&work_url).await.unwrap();
+        let mut remote = remote.lock().await;
+        remote
+            .add_item(Item::Task(shared(&work_url, "Shared (new)", 2)))
+            .await
+            .unwrap();
+        remote.add_item(Item::Task(due_task)).await.unwrap();
+    }
+    {
+        let remote = home.remote().get_calendar(&home_url).await.unwrap();
+        remote
+            .lock()
+            .await
+            .add_item(Item::Task(shared(&home_url, "Shared (old)", 1)))
+            .await
+            .unwrap();
+    }
+
+    let mut accounts = AccountManager::new();
+    accounts.add_account("work".to_string(), work);
+    accounts.add_account("home".to_string(), home);
+
+    let everything = Arc::new(Mutex::new(UnifiedView::all()));
+    let home_only = UnifiedView::new(vec![("home".to_string(), home_url.clone())]);
+    accounts.register_view(&everything);
+    assert!(everything.lock().await.tasks().is_empty());
+    accounts.sync_all().await;
+
+    let view = everything.lock().await;
+    let mut names: Vec<&str> = view.tasks().iter().map(|t| t.task.name()).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            "Due task",
+            "Shared (new)",
+            "Task of unified_home",
+            "Task of unified_work"
+        ]
+    );
+    let due = view.due_between(
+        Utc.ymd(2021, 6, 1).and_hms(0, 0, 0),
+        Utc.ymd(2021, 6, 2).and_hms(0, 0, 0),
+    );
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].account_id, "work");
+    // A UTC due date is the same instant in every time zone
+    let due = view.due_between_in(
+        Utc.ymd(2021, 6, 1).and_hms(0, 0, 0),
+        Utc.ymd(2021, 6, 2).and_hms(0, 0, 0),
+        &chrono::FixedOffset::east(13 * 3600),
+    );
+    assert_eq!(due.len(), 1);
+    assert_eq!(view.with_category("errands").len(), 1);
+
+    let mut home_only = home_only;
+    home_only.refresh(&accounts).await.unwrap();
+    let mut names: Vec<&str> = home_only.tasks().iter().map(|t| t.task.name()).collect();
+    names.sort();
+    assert_eq!(names, vec!["Shared (old)", "Task of unified_home"]);
+}
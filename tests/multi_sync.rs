@@ -0,0 +1,258 @@
+//! Tests for syncs between a local cache and several (mocked) remote sources
+#![cfg(feature = "local_calendar_mocks_remote_calendars")]
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use url::Url;
+
+use kitchen_fridge::cache::Cache;
+use kitchen_fridge::calendar::SupportedComponents;
+use kitchen_fridge::mock_behaviour::MockBehaviour;
+use kitchen_fridge::provider::hooks::{Decision, PlannedChange};
+use kitchen_fridge::provider::multi::MultiProvider;
+use kitchen_fridge::traits::BaseCalendar;
+use kitchen_fridge::traits::CalDavSource;
+use kitchen_fridge::traits::CompleteCalendar;
+use kitchen_fridge::utils::sync::SyncStatus;
+use kitchen_fridge::Item;
+use kitchen_fridge::Task;
+
+fn mocked_source(folder: &str) -> Cache {
+    let mut source = Cache::new(&PathBuf::from(folder));
+    source.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    source
+}
+
+/// The sorted names of the tasks of a calendar
+async fn task_names(source: &Cache, cal_url: &Url) -> Vec<String> {
+    let cal = match source.get_calendar(cal_url).await {
+        None => return Vec::new(),
+        Some(cal) => cal,
+    };
+    let cal = cal.lock().await;
+    let mut names: Vec<String> = cal
+        .get_items()
+        .await
+        .unwrap()
+        .values()
+        .map(|item| item.name().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn test_sync_with_two_remotes() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let cal_url: Url = "https://some.calend.ar/multi/".parse().unwrap();
+    let mut primary = mocked_source("test_cache/multi_primary/");
+    let secondary = mocked_source("test_cache/multi_secondary/");
+    let local = Cache::new(&PathBuf::from("test_cache/multi_local/"));
+
+    let cal = primary
+        .create_calendar(
+            cal_url.clone(),
+            "Shared".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let task_a = Task::new("Task A".to_string(), false, &cal_url).unwrap();
+    let url_a = task_a.url().clone();
+    cal.lock().await.add_item(Item::Task(task_a)).await.unwrap();
+
+    let mut provider = MultiProvider::new(primary, local);
+    provider.add_source("mirror".to_string(), secondary);
+
+    // The first sync mirrors the primary source to both the local cache and the secondary source
+    assert!(provider.sync().await);
+    assert_eq!(task_names(provider.local(), &cal_url).await, vec!["Task A"]);
+    assert_eq!(
+        task_names(provider.source("mirror").unwrap(), &cal_url).await,
+        vec!["Task A"]
+    );
+
+    // Local changes go to every source, changes from the secondary source eventually reach the primary one
+    provider
+        .local()
+        .get_calendar(&cal_url)
+        .await
+        .unwrap()
+        .lock()
+        .await
+        .get_item_by_url_mut(&url_a)
+        .await
+        .unwrap()
+        .unwrap_task_mut()
+        .set_name("Task A renamed".to_string());
+    let task_b = Task::new("Task B".to_string(), false, &cal_url).unwrap();
+    provider
+        .source("mirror")
+        .unwrap()
+        .get_calendar(&cal_url)
+        .await
+        .unwrap()
+        .lock()
+        .await
+        .add_item(Item::Task(task_b))
+        .await
+        .unwrap();
+
+    assert!(provider.sync().await);
+    assert_eq!(
+        task_names(provider.primary(), &cal_url).await,
+        vec!["Task A renamed"]
+    );
+    assert_eq!(
+        task_names(provider.local(), &cal_url).await,
+        vec!["Task A renamed", "Task B"]
+    );
+    assert!(provider.sync().await);
+    for source in [
+        provider.local(),
+        provider.primary(),
+        provider.source("mirror").unwrap(),
+    ] {
+        assert_eq!(
+            task_names(source, &cal_url).await,
+            vec!["Task A renamed", "Task B"]
+        );
+    }
+
+    // The local sync statuses still refer to the primary source
+    {
+        let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+        let local_cal = local_cal.lock().await;
+        let primary_cal = provider.primary().get_calendar(&cal_url).await.unwrap();
+        let primary_cal = primary_cal.lock().await;
+        let local_item = local_cal.get_item_by_url(&url_a).await.unwrap();
+        let primary_item = CompleteCalendar::get_item_by_url(&*primary_cal, &url_a)
+            .await
+            .unwrap();
+        assert!(matches!(local_item.sync_status(), SyncStatus::Synced(_)));
+        assert_eq!(local_item.sync_status(), primary_item.sync_status());
+    }
+
+    // Local deletions are propagated to every source
+    provider
+        .local()
+        .get_calendar(&cal_url)
+        .await
+        .unwrap()
+        .lock()
+        .await
+        .mark_item_for_deletion(&url_a)
+        .await
+        .unwrap();
+    assert!(provider.sync().await);
+    for source in [
+        provider.local(),
+        provider.primary(),
+        provider.source("mirror").unwrap(),
+    ] {
+        assert_eq!(task_names(source, &cal_url).await, vec!["Task B"]);
+    }
+}
+
+#[tokio::test]
+async fn test_multi_sync_locked_cache() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let local_path = PathBuf::from("test_cache/multi_locked_local/");
+    let mut provider = MultiProvider::new(
+        mocked_source("test_cache/multi_locked_primary/"),
+        Cache::new(&local_path),
+    );
+    provider.add_source(
+        "mirror".to_string(),
+        mocked_source("test_cache/multi_locked_secondary/"),
+    );
+
+    // Another process is syncing the same folder
+    let other = Cache::new(&local_path);
+    other.sync_started().unwrap();
+    let result = provider.sync_with_result(None).await;
+    assert!(!result.is_success());
+    assert!(result.is_locked());
+
+    other.sync_finished();
+    let result = provider.sync_with_result(None).await;
+    assert!(result.is_success());
+    assert!(provider.skipped().is_empty());
+}
+
+#[tokio::test]
+async fn test_multi_sync_destructive_change_guard() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let cal_url: Url = "https://some.calend.ar/multi-guarded/".parse().unwrap();
+    let mut primary = mocked_source("test_cache/multi_guarded_primary/");
+    let cal = primary
+        .create_calendar(
+            cal_url.clone(),
+            "Guarded".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let task_a = Task::new("Task A".to_string(), false, &cal_url).unwrap();
+    let url_a = task_a.url().clone();
+    cal.lock().await.add_item(Item::Task(task_a)).await.unwrap();
+    let task_b = Task::new("Task B".to_string(), false, &cal_url).unwrap();
+    cal.lock().await.add_item(Item::Task(task_b)).await.unwrap();
+
+    let mut provider = MultiProvider::new(
+        primary,
+        Cache::new(&PathBuf::from("test_cache/multi_guarded_local/")),
+    );
+    provider.add_source(
+        "mirror".to_string(),
+        mocked_source("test_cache/multi_guarded_secondary/"),
+    );
+    assert!(provider.sync().await);
+
+    // The guard is asked before deleting from the secondary source too
+    let asked = Arc::new(AtomicUsize::new(0));
+    let asked_by_guard = Arc::clone(&asked);
+    provider.set_destructive_change_guard(Some(Arc::new(
+        move |change: &PlannedChange| match change {
+            PlannedChange::DeleteRemoteItems { .. } => {
+                asked_by_guard.fetch_add(1, Ordering::SeqCst);
+                Decision::Skip
+            }
+            _ => Decision::Allow,
+        },
+    )));
+    provider
+        .local()
+        .get_calendar(&cal_url)
+        .await
+        .unwrap()
+        .lock()
+        .await
+        .mark_item_for_deletion(&url_a)
+        .await
+        .unwrap();
+    provider.sync().await;
+    assert_eq!(asked.load(Ordering::SeqCst), 2);
+    for source in [provider.primary(), provider.source("mirror").unwrap()] {
+        assert_eq!(task_names(source, &cal_url).await, vec!["Task A", "Task B"]);
+    }
+
+    // Skipped deletions are planned again at the next sync
+    provider.set_destructive_change_guard(None);
+    assert!(provider.sync().await);
+    for source in [
+        provider.local(),
+        provider.primary(),
+        provider.source("mirror").unwrap(),
+    ] {
+        assert_eq!(task_names(source, &cal_url).await, vec!["Task B"]);
+    }
+}
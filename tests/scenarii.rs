@@ -33,7 +33,9 @@ use kitchen_fridge::Item;
 use kitchen_fridge::Task;
 
 pub struct ItemState {
-    // TODO: if/when this crate supports Events as well, we could add such events here
+    // TODO: if/when this crate supports Events as well, we could add such events here.
+    //       `Event` cannot be built yet (`Event::new` and `Event::url` are unimplemented), so a `scenarii_events_basic` suite would need
+    //       event states (start and end times), reschedules on either side and conflicting reschedules, once events can be created and parsed
     /// The calendar it is in
     calendar: Url,
     /// Its name
@@ -125,7 +127,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     let third_cal = "https://some.calend.ar/calendar-3/".parse().unwrap();
 
     tasks.push(ItemScenario {
-        url: random_url(&first_cal),
+        url: random_url(&first_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: first_cal.clone(),
             name: String::from("Task A"),
@@ -141,7 +143,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&first_cal),
+        url: random_url(&first_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: first_cal.clone(),
             name: String::from("Task B"),
@@ -153,7 +155,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&first_cal),
+        url: random_url(&first_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: first_cal.clone(),
             name: String::from("Task C"),
@@ -165,7 +167,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&first_cal),
+        url: random_url(&first_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: first_cal.clone(),
             name: String::from("Task D"),
@@ -181,7 +183,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&first_cal),
+        url: random_url(&first_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: first_cal.clone(),
             name: String::from("Task E"),
@@ -197,7 +199,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&first_cal),
+        url: random_url(&first_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: first_cal.clone(),
             name: String::from("Task F"),
@@ -214,7 +216,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&second_cal),
+        url: random_url(&second_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: second_cal.clone(),
             name: String::from("Task G"),
@@ -230,7 +232,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&second_cal),
+        url: random_url(&second_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: second_cal.clone(),
             name: String::from("Task H"),
@@ -246,7 +248,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&second_cal),
+        url: random_url(&second_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: second_cal.clone(),
             name: String::from("Task I"),
@@ -263,7 +265,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&second_cal),
+        url: random_url(&second_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: second_cal.clone(),
             name: String::from("Task J"),
@@ -275,7 +277,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&second_cal),
+        url: random_url(&second_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: second_cal.clone(),
             name: String::from("Task K"),
@@ -291,7 +293,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&second_cal),
+        url: random_url(&second_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: second_cal.clone(),
             name: String::from("Task L"),
@@ -303,7 +305,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&second_cal),
+        url: random_url(&second_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: second_cal.clone(),
             name: String::from("Task M"),
@@ -319,7 +321,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&third_cal),
+        url: random_url(&third_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: third_cal.clone(),
             name: String::from("Task N"),
@@ -335,7 +337,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&third_cal),
+        url: random_url(&third_cal).unwrap(),
         initial_state: LocatedState::BothSynced(ItemState {
             calendar: third_cal.clone(),
             name: String::from("Task O"),
@@ -350,7 +352,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
         }),
     });
 
-    let url_p = random_url(&third_cal);
+    let url_p = random_url(&third_cal).unwrap();
     tasks.push(ItemScenario {
         url: url_p.clone(),
         initial_state: LocatedState::BothSynced(ItemState {
@@ -370,7 +372,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
         }),
     });
 
-    let url_q = random_url(&third_cal);
+    let url_q = random_url(&third_cal).unwrap();
     tasks.push(ItemScenario {
         url: url_q.clone(),
         initial_state: LocatedState::None,
@@ -397,7 +399,7 @@ pub fn item_scenarii_basic() -> Vec<ItemScenario> {
         }),
     });
 
-    let url_r = random_url(&third_cal);
+    let url_r = random_url(&third_cal).unwrap();
     tasks.push(ItemScenario {
         url: url_r.clone(),
         initial_state: LocatedState::None,
@@ -435,7 +437,7 @@ pub fn item_scenarii_first_sync_to_local() -> Vec<ItemScenario> {
     let cal2 = "https://some.calend.ar/second/".parse().unwrap();
 
     tasks.push(ItemScenario {
-        url: random_url(&cal1),
+        url: random_url(&cal1).unwrap(),
         initial_state: LocatedState::Remote(ItemState {
             calendar: cal1.clone(),
             name: String::from("Task A1"),
@@ -451,7 +453,7 @@ pub fn item_scenarii_first_sync_to_local() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&cal2),
+        url: random_url(&cal2).unwrap(),
         initial_state: LocatedState::Remote(ItemState {
             calendar: cal2.clone(),
             name: String::from("Task A2"),
@@ -467,7 +469,7 @@ pub fn item_scenarii_first_sync_to_local() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&cal1),
+        url: random_url(&cal1).unwrap(),
         initial_state: LocatedState::Remote(ItemState {
             calendar: cal1.clone(),
             name: String::from("Task B1"),
@@ -559,7 +561,7 @@ pub fn item_scenarii_first_sync_to_server() -> Vec<ItemScenario> {
     let cal4 = "https://some.calend.ar/fourth/".parse().unwrap();
 
     tasks.push(ItemScenario {
-        url: random_url(&cal3),
+        url: random_url(&cal3).unwrap(),
         initial_state: LocatedState::Local(ItemState {
             calendar: cal3.clone(),
             name: String::from("Task A3"),
@@ -575,7 +577,7 @@ pub fn item_scenarii_first_sync_to_server() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&cal4),
+        url: random_url(&cal4).unwrap(),
         initial_state: LocatedState::Local(ItemState {
             calendar: cal4.clone(),
             name: String::from("Task A4"),
@@ -591,7 +593,7 @@ pub fn item_scenarii_first_sync_to_server() -> Vec<ItemScenario> {
     });
 
     tasks.push(ItemScenario {
-        url: random_url(&cal3),
+        url: random_url(&cal3).unwrap(),
         initial_state: LocatedState::Local(ItemState {
             calendar: cal3.clone(),
             name: String::from("Task B3"),
@@ -683,7 +685,7 @@ pub fn item_scenarii_transient_task() -> Vec<ItemScenario> {
     let cal = "https://some.calend.ar/transient/".parse().unwrap();
 
     tasks.push(ItemScenario {
-        url: random_url(&cal),
+        url: random_url(&cal).unwrap(),
         initial_state: LocatedState::Local(ItemState {
             calendar: cal.clone(),
             name: String::from("A task, so that the calendar actually exists"),
@@ -698,7 +700,7 @@ pub fn item_scenarii_transient_task() -> Vec<ItemScenario> {
         }),
     });
 
-    let url_transient = random_url(&cal);
+    let url_transient = random_url(&cal).unwrap();
     tasks.push(ItemScenario {
         url: url_transient.clone(),
         initial_state: LocatedState::None,
@@ -729,6 +731,85 @@ pub fn item_scenarii_transient_task() -> Vec<ItemScenario> {
     tasks
 }
 
+/// This scenario tests tasks modified on both sides, to be synced with [`ConflictStrategy::Merge`](kitchen_fridge::provider::ConflictStrategy::Merge)
+pub fn item_scenarii_merge() -> Vec<ItemScenario> {
+    let mut tasks = Vec::new();
+
+    let cal = "https://some.calend.ar/merge/".parse().unwrap();
+
+    tasks.push(ItemScenario {
+        url: random_url(&cal).unwrap(),
+        initial_state: LocatedState::BothSynced(ItemState {
+            calendar: cal.clone(),
+            name: String::from("Task A"),
+            completed: false,
+        }),
+        local_changes_to_apply: vec![ItemChange::SetCompletion(true)],
+        remote_changes_to_apply: vec![ItemChange::Rename(String::from("Task A, remotely renamed"))],
+        // Disjoint changes: both are kept
+        after_sync: LocatedState::BothSynced(ItemState {
+            calendar: cal.clone(),
+            name: String::from("Task A, remotely renamed"),
+            completed: true,
+        }),
+    });
+
+    tasks.push(ItemScenario {
+        url: random_url(&cal).unwrap(),
+        initial_state: LocatedState::BothSynced(ItemState {
+            calendar: cal.clone(),
+            name: String::from("Task B"),
+            completed: false,
+        }),
+        local_changes_to_apply: vec![ItemChange::Rename(String::from("Task B, locally renamed"))],
+        remote_changes_to_apply: vec![ItemChange::SetCompletion(true)],
+        after_sync: LocatedState::BothSynced(ItemState {
+            calendar: cal.clone(),
+            name: String::from("Task B, locally renamed"),
+            completed: true,
+        }),
+    });
+
+    tasks.push(ItemScenario {
+        url: random_url(&cal).unwrap(),
+        initial_state: LocatedState::BothSynced(ItemState {
+            calendar: cal.clone(),
+            name: String::from("Task C"),
+            completed: false,
+        }),
+        local_changes_to_apply: vec![
+            ItemChange::Rename(String::from("Task C, locally renamed")),
+            ItemChange::SetCompletion(true),
+        ],
+        remote_changes_to_apply: vec![ItemChange::Rename(String::from("Task C, remotely renamed"))],
+        // Overlapping changes: the server wins
+        after_sync: LocatedState::BothSynced(ItemState {
+            calendar: cal.clone(),
+            name: String::from("Task C, remotely renamed"),
+            completed: false,
+        }),
+    });
+
+    tasks.push(ItemScenario {
+        url: random_url(&cal).unwrap(),
+        initial_state: LocatedState::BothSynced(ItemState {
+            calendar: cal.clone(),
+            name: String::from("Task D"),
+            completed: false,
+        }),
+        local_changes_to_apply: vec![ItemChange::Rename(String::from("Task D, renamed"))],
+        remote_changes_to_apply: vec![ItemChange::Rename(String::from("Task D, renamed"))],
+        // Both sides made the very same change
+        after_sync: LocatedState::BothSynced(ItemState {
+            calendar: cal.clone(),
+            name: String::from("Task D, renamed"),
+            completed: false,
+        }),
+    });
+
+    tasks
+}
+
 /// This scenario tests a task added and deleted before a sync happens
 pub fn prop_scenarii_transient_prop() -> Vec<PropScenario> {
     let mut tasks = Vec::new();
@@ -5,6 +5,8 @@ use std::sync::Arc;
 
 #[cfg(feature = "local_calendar_mocks_remote_calendars")]
 use kitchen_fridge::mock_behaviour::MockBehaviour;
+#[cfg(feature = "local_calendar_mocks_remote_calendars")]
+use kitchen_fridge::provider::ConflictStrategy;
 
 /// A test that simulates a regular synchronisation between a local cache and a server.
 /// Note that this uses a second cache to "mock" a server.
@@ -17,6 +19,9 @@ struct TestFlavour {
 
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
     mock_behaviour: Arc<Mutex<MockBehaviour>>,
+
+    #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+    conflict_strategy: ConflictStrategy,
 }
 
 #[cfg(not(feature = "local_calendar_mocks_remote_calendars"))]
@@ -69,6 +74,9 @@ impl TestFlavour {
     pub fn normal_with_errors12() -> Self {
         Self {}
     }
+    pub fn merge() -> Self {
+        Self {}
+    }
 
     pub async fn run(&self, _max_attempts: u32) {
         panic!("WARNING: This test required the \"integration_tests\" Cargo feature");
@@ -79,6 +87,7 @@ impl TestFlavour {
 impl TestFlavour {
     pub fn normal() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_basic(),
             prop_scenarii: scenarii::prop_scenarii_basic(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour::new())),
@@ -87,6 +96,7 @@ impl TestFlavour {
 
     pub fn first_sync_to_local() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_first_sync_to_local(),
             prop_scenarii: scenarii::prop_scenarii_first_sync_to_local(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour::new())),
@@ -95,6 +105,7 @@ impl TestFlavour {
 
     pub fn first_sync_to_server() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_first_sync_to_server(),
             prop_scenarii: scenarii::prop_scenarii_first_sync_to_server(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour::new())),
@@ -103,6 +114,7 @@ impl TestFlavour {
 
     pub fn transient() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_transient_task(),
             prop_scenarii: scenarii::prop_scenarii_transient_prop(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour::new())),
@@ -111,6 +123,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors1() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_basic(),
             prop_scenarii: scenarii::prop_scenarii_basic(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour::fail_now(10))),
@@ -119,6 +132,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors2() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_basic(),
             prop_scenarii: scenarii::prop_scenarii_basic(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -131,6 +145,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors3() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_first_sync_to_server(),
             prop_scenarii: scenarii::prop_scenarii_first_sync_to_server(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -143,6 +158,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors4() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_first_sync_to_server(),
             prop_scenarii: scenarii::prop_scenarii_first_sync_to_server(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -154,6 +170,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors5() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_basic(),
             prop_scenarii: scenarii::prop_scenarii_basic(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -165,6 +182,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors6() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_basic(),
             prop_scenarii: scenarii::prop_scenarii_basic(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -176,6 +194,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors7() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_basic(),
             prop_scenarii: scenarii::prop_scenarii_basic(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -187,6 +206,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors8() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_basic(),
             prop_scenarii: scenarii::prop_scenarii_basic(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -199,6 +219,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors9() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_basic(),
             prop_scenarii: scenarii::prop_scenarii_basic(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -211,6 +232,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors10() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_first_sync_to_server(),
             prop_scenarii: scenarii::prop_scenarii_first_sync_to_server(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -225,6 +247,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors11() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_basic(),
             prop_scenarii: scenarii::prop_scenarii_basic(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -240,6 +263,7 @@ impl TestFlavour {
 
     pub fn normal_with_errors12() -> Self {
         Self {
+            conflict_strategy: ConflictStrategy::RemoteWins,
             item_scenarii: scenarii::item_scenarii_basic(),
             prop_scenarii: scenarii::prop_scenarii_basic(),
             mock_behaviour: Arc::new(Mutex::new(MockBehaviour {
@@ -249,6 +273,15 @@ impl TestFlavour {
         }
     }
 
+    pub fn merge() -> Self {
+        Self {
+            conflict_strategy: ConflictStrategy::Merge,
+            item_scenarii: scenarii::item_scenarii_merge(),
+            prop_scenarii: Vec::new(),
+            mock_behaviour: Arc::new(Mutex::new(MockBehaviour::new())),
+        }
+    }
+
     pub async fn run(&self, max_attempts: u32) {
         self.mock_behaviour.lock().await.suspend();
 
@@ -258,6 +291,7 @@ impl TestFlavour {
             Arc::clone(&self.mock_behaviour),
         )
         .await;
+        provider.set_conflict_strategy(self.conflict_strategy);
         print_provider(&provider, "before sync").await;
 
         self.mock_behaviour.lock().await.resume();
@@ -273,11 +307,12 @@ impl TestFlavour {
         print_provider(&provider, "after sync").await;
 
         // Check the contents of both sources are the same after sync
-        assert!(provider
+        let diff = provider
             .remote()
-            .has_same_observable_content_as(provider.local(), "remote", "local")
+            .observable_diff(provider.local(), "remote", "local")
             .await
-            .unwrap());
+            .unwrap();
+        assert!(diff.is_empty(), "{}", diff);
 
         // But also explicitely check that every item is expected
         let expected_provider = scenarii::populate_test_provider_after_sync(
@@ -287,46 +322,42 @@ impl TestFlavour {
         )
         .await;
 
-        assert!(provider
+        let diff = provider
             .local()
-            .has_same_observable_content_as(
-                expected_provider.local(),
-                "local",
-                "expected after sync"
-            )
+            .observable_diff(expected_provider.local(), "local", "expected after sync")
             .await
-            .unwrap());
-        assert!(provider
+            .unwrap();
+        assert!(diff.is_empty(), "{}", diff);
+        let diff = provider
             .remote()
-            .has_same_observable_content_as(
-                expected_provider.remote(),
-                "remote",
-                "expected after sync"
-            )
+            .observable_diff(expected_provider.remote(), "remote", "expected after sync")
             .await
-            .unwrap());
+            .unwrap();
+        assert!(diff.is_empty(), "{}", diff);
 
         // Perform a second sync, even if no change has happened, just to check
         println!("Syncing again");
         provider.sync().await;
-        assert!(provider
+        let diff = provider
             .local()
-            .has_same_observable_content_as(
+            .observable_diff(
                 expected_provider.local(),
                 "local",
-                "expecgted after second sync"
+                "expecgted after second sync",
             )
             .await
-            .unwrap());
-        assert!(provider
+            .unwrap();
+        assert!(diff.is_empty(), "{}", diff);
+        let diff = provider
             .remote()
-            .has_same_observable_content_as(
+            .observable_diff(
                 expected_provider.remote(),
                 "remote",
-                "expected after second sync"
+                "expected after second sync",
             )
             .await
-            .unwrap());
+            .unwrap();
+        assert!(diff.is_empty(), "{}", diff);
     }
 }
 
@@ -431,6 +462,1150 @@ async fn test_errors_in_regular_sync12() {
     run_flavour(TestFlavour::normal_with_errors12(), 100).await;
 }
 
+#[tokio::test]
+#[cfg_attr(not(feature = "integration_tests"), ignore)]
+async fn test_sync_with_merged_conflicts() {
+    run_flavour(TestFlavour::merge(), 1).await;
+}
+
+/// Both sides have modified an item, but the remote version has not been derived from a newer revision than the local one
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_conflict_resolved_by_sequence() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::utils::sync::SyncStatus;
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/sequence/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/sequence_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let local = Cache::new(&PathBuf::from("test_cache/sequence_local/"));
+
+    let task = Task::new("Original".to_string(), false, &cal_url).unwrap();
+    let task_url = task.url().clone();
+    remote
+        .create_calendar(
+            cal_url.clone(),
+            "Sequence".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap()
+        .lock()
+        .await
+        .add_item(Item::Task(task))
+        .await
+        .unwrap();
+
+    let mut provider = Provider::new(remote, local);
+    assert!(provider.sync().await);
+
+    // The local version is a new revision, while the remote one has only been rewritten (e.g. by the server itself)
+    provider
+        .local()
+        .get_calendar(&cal_url)
+        .await
+        .unwrap()
+        .lock()
+        .await
+        .get_item_by_url_mut(&task_url)
+        .await
+        .unwrap()
+        .unwrap_task_mut()
+        .set_name("Renamed".to_string());
+    provider
+        .remote()
+        .get_calendar(&cal_url)
+        .await
+        .unwrap()
+        .lock()
+        .await
+        .get_item_by_url_mut(&task_url)
+        .await
+        .unwrap()
+        .set_sync_status(SyncStatus::random_synced());
+
+    assert!(provider.sync().await);
+    for source in [provider.local(), provider.remote()] {
+        let cal = source.get_calendar(&cal_url).await.unwrap();
+        let cal = cal.lock().await;
+        let item = CompleteCalendar::get_item_by_url(&*cal, &task_url)
+            .await
+            .unwrap();
+        assert_eq!(item.name(), "Renamed");
+        assert_eq!(item.sequence(), 1);
+        assert!(matches!(item.sync_status(), SyncStatus::Synced(_)));
+    }
+}
+
+/// Items modified in both sources keep their remote version, the local one is kept in the conflict journal
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_conflict_journal() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::utils::sync::SyncStatus;
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/journal/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/journal_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let local = Cache::new(&PathBuf::from("test_cache/journal_local/"));
+
+    let task = Task::new("Original".to_string(), false, &cal_url).unwrap();
+    let task_url = task.url().clone();
+    remote
+        .create_calendar(
+            cal_url.clone(),
+            "Journal".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap()
+        .lock()
+        .await
+        .add_item(Item::Task(task))
+        .await
+        .unwrap();
+
+    let mut provider = Provider::new(remote, local);
+    assert!(provider.sync().await);
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+    let remote_cal = provider.remote().get_calendar(&cal_url).await.unwrap();
+
+    // The same item keeps conflicting: only its latest conflict is kept
+    for round in 1..=3 {
+        local_cal
+            .lock()
+            .await
+            .get_item_by_url_mut(&task_url)
+            .await
+            .unwrap()
+            .unwrap_task_mut()
+            .set_name(format!("Local {}", round));
+        {
+            let mut remote_cal = remote_cal.lock().await;
+            let remote_item = remote_cal.get_item_by_url_mut(&task_url).await.unwrap();
+            remote_item
+                .unwrap_task_mut()
+                .set_name(format!("Remote {}", round));
+            remote_item.set_sync_status(SyncStatus::random_synced());
+        }
+
+        assert!(provider.sync().await);
+        let local_cal = local_cal.lock().await;
+        let item = local_cal.get_item_by_url(&task_url).await.unwrap();
+        assert_eq!(item.name(), format!("Remote {}", round));
+        let journal = local_cal.get_conflicts().await;
+        assert_eq!(journal.conflicts().len(), 1);
+        let conflict = journal.latest_for(&task_url).unwrap();
+        assert_eq!(conflict.local_version().name(), format!("Local {}", round));
+        assert_eq!(
+            conflict.remote_version().unwrap().name(),
+            format!("Remote {}", round)
+        );
+    }
+
+    // The discarded local version can be pushed again
+    local_cal
+        .lock()
+        .await
+        .reapply_conflict(&task_url)
+        .await
+        .unwrap();
+    assert!(provider.sync().await);
+    assert!(local_cal.lock().await.get_conflicts().await.is_empty());
+    let remote_cal = remote_cal.lock().await;
+    let item = remote_cal.get_item_by_url(&task_url).await.unwrap();
+    assert_eq!(item.name(), "Local 3");
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_purge_completed() {
+    use chrono::TimeZone;
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::task::CompletionStatus;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/purge/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/purge_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let local = Cache::new(&PathBuf::from("test_cache/purge_local/"));
+
+    let long_ago = chrono::Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+    let mut old_completed = Task::new("Old completed".to_string(), false, &cal_url).unwrap();
+    old_completed.set_completion_status(CompletionStatus::Completed(Some(long_ago)));
+    let old_url = old_completed.url().clone();
+    let recently_completed = Task::new("Recently completed".to_string(), true, &cal_url).unwrap();
+    let uncompleted = Task::new("Uncompleted".to_string(), false, &cal_url).unwrap();
+
+    let cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Purge".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    for task in [old_completed, recently_completed, uncompleted] {
+        cal.lock().await.add_item(Item::Task(task)).await.unwrap();
+    }
+
+    let mut provider = Provider::new(remote, local);
+    assert!(provider.sync().await);
+
+    let month = Duration::from_secs(30 * 24 * 3600);
+    assert_eq!(
+        provider.completed_tasks_to_purge(month).await.unwrap(),
+        vec![old_url.clone()]
+    );
+    // The dry run did not change anything
+    assert_eq!(
+        provider.completed_tasks_to_purge(month).await.unwrap(),
+        vec![old_url.clone()]
+    );
+    assert_eq!(
+        provider.purge_completed(month).await.unwrap(),
+        vec![old_url.clone()]
+    );
+    assert!(provider
+        .completed_tasks_to_purge(month)
+        .await
+        .unwrap()
+        .is_empty());
+
+    assert!(provider.sync().await);
+    for source in [provider.local(), provider.remote()] {
+        let cal = source.get_calendar(&cal_url).await.unwrap();
+        let cal = cal.lock().await;
+        let urls = CompleteCalendar::get_item_urls(&*cal).await.unwrap();
+        assert_eq!(urls.len(), 2);
+        assert!(!urls.contains(&old_url));
+    }
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_calendar_creation_policy() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::provider::CalendarCreationPolicy;
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let remote_cal: url::Url = "https://some.calend.ar/policy/remote/".parse().unwrap();
+    let local_cal: url::Url = "https://some.calend.ar/policy/local/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/policy_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let mut local = Cache::new(&PathBuf::from("test_cache/policy_local/"));
+    for (source, url) in [(&mut remote, &remote_cal), (&mut local, &local_cal)] {
+        source
+            .create_calendar(
+                url.clone(),
+                "Policy".to_string(),
+                SupportedComponents::TODO,
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    let mut provider = Provider::new(remote, local);
+    provider.set_calendar_creation_policy(CalendarCreationPolicy::NeverCreate);
+    assert!(provider.sync().await);
+    assert!(provider.local().get_calendar(&remote_cal).await.is_none());
+    assert!(provider.remote().get_calendar(&local_cal).await.is_none());
+
+    provider.set_calendar_creation_policy(CalendarCreationPolicy::LocalOnly);
+    assert!(provider.sync().await);
+    assert!(provider.local().get_calendar(&remote_cal).await.is_some());
+    assert!(provider.remote().get_calendar(&local_cal).await.is_none());
+
+    provider.set_calendar_creation_policy(CalendarCreationPolicy::CreateBoth);
+    assert!(provider.sync().await);
+    assert!(provider.remote().get_calendar(&local_cal).await.is_some());
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_remote_calendar_deletion() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::provider::RemoteCalendarDeletionPolicy;
+    use kitchen_fridge::traits::BaseCalendar;
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/deleted/".parse().unwrap();
+    let local_path = PathBuf::from("test_cache/remote_deletion_local/");
+    let mocked_remote = || {
+        let mut remote = Cache::new(&PathBuf::from("test_cache/remote_deletion_remote/"));
+        remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+        remote
+    };
+
+    let mut remote = mocked_remote();
+    let task = Task::new("Task".to_string(), false, &cal_url).unwrap();
+    remote
+        .create_calendar(
+            cal_url.clone(),
+            "Deleted".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap()
+        .lock()
+        .await
+        .add_item(Item::Task(task))
+        .await
+        .unwrap();
+    let mut provider = Provider::new(remote, Cache::new(&local_path));
+    assert!(provider.sync().await);
+    provider.local().save_to_folder().await.unwrap();
+
+    // The calendar is then deleted from the server
+    let mut provider = Provider::new(mocked_remote(), Cache::from_folder(&local_path).unwrap());
+    let asked = Arc::new(AtomicUsize::new(0));
+    let asked_clone = Arc::clone(&asked);
+    provider.set_remote_calendar_deletion_policy(RemoteCalendarDeletionPolicy::Confirm(Arc::new(
+        move |_url, name| {
+            assert_eq!(name, "Deleted");
+            asked_clone.fetch_add(1, Ordering::SeqCst);
+            false
+        },
+    )));
+    assert!(provider.sync().await);
+    assert_eq!(asked.load(Ordering::SeqCst), 1);
+    // The local copy is kept, but not created again on the server
+    assert!(provider.local().get_calendar(&cal_url).await.is_some());
+    assert!(provider.remote().get_calendar(&cal_url).await.is_none());
+
+    provider.set_remote_calendar_deletion_policy(RemoteCalendarDeletionPolicy::DeleteLocally);
+    assert!(provider.sync().await);
+    assert!(provider.local().get_calendar(&cal_url).await.is_none());
+    assert!(provider.remote().get_calendar(&cal_url).await.is_none());
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_checkpointed_first_sync() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/huge/".parse().unwrap();
+    let remote_path = PathBuf::from("test_cache/checkpoint_remote/");
+    let local_path = PathBuf::from("test_cache/checkpoint_local/");
+    let mocked_remote = |behaviour: MockBehaviour| {
+        let remote = Cache::from_folder(&remote_path).unwrap();
+        let behaviour = Some(Arc::new(Mutex::new(behaviour)));
+        // Mock behaviours are not persisted
+        remote
+            .get_calendar_sync(&cal_url)
+            .unwrap()
+            .try_lock()
+            .unwrap()
+            .set_mock_behaviour(behaviour);
+        remote
+    };
+
+    let mut remote = Cache::new(&remote_path);
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Huge".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    for i in 0..70 {
+        let task = Task::new(format!("Task {}", i), false, &cal_url).unwrap();
+        cal.lock().await.add_item(Item::Task(task)).await.unwrap();
+    }
+    remote.save_to_folder().await.unwrap();
+
+    // The first sync is interrupted after 60 downloads...
+    let mut provider = Provider::new(
+        mocked_remote(MockBehaviour {
+            get_item_by_url_behaviour: (60, 1000),
+            ..MockBehaviour::default()
+        }),
+        Cache::new(&local_path),
+    );
+    provider.set_checkpoint_interval(Some(30));
+    assert!(!provider.sync().await);
+
+    // ...but the downloaded items have been persisted, even though the local cache has never been saved
+    let local = Cache::from_folder(&local_path).unwrap();
+    let checkpointed = local.get_calendar_sync(&cal_url).unwrap();
+    assert_eq!(
+        checkpointed.lock().await.get_items().await.unwrap().len(),
+        60
+    );
+
+    // Resuming only downloads the missing items
+    let mut provider = Provider::new(
+        mocked_remote(MockBehaviour {
+            get_item_by_url_behaviour: (10, 1000),
+            ..MockBehaviour::default()
+        }),
+        local,
+    );
+    assert!(provider.sync().await);
+    let cal_local = provider.local().get_calendar(&cal_url).await.unwrap();
+    assert_eq!(cal_local.lock().await.get_items().await.unwrap().len(), 70);
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_max_download_bytes() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/metered/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/metered_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Metered".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    for i in 0..70 {
+        let task = Task::new(format!("Task {}", i), false, &cal_url).unwrap();
+        cal.lock().await.add_item(Item::Task(task)).await.unwrap();
+    }
+
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/metered_local/")),
+    );
+    provider.set_max_download_bytes(Some(1));
+    let result = provider.sync_with_result(None).await;
+    assert!(result.is_success());
+    assert!(!result.is_complete());
+    assert!(result.metrics().download_limit_reached);
+    assert!(result.metrics().bytes_received > 0);
+    let cal_local = provider.local().get_calendar(&cal_url).await.unwrap();
+    let downloaded = cal_local.lock().await.get_items().await.unwrap().len();
+    assert!(0 < downloaded && downloaded < 70);
+
+    // The next syncs resume the downloads
+    provider.set_max_download_bytes(None);
+    let result = provider.sync_with_result(None).await;
+    assert!(result.is_complete());
+    assert!(result.metrics().bytes_received > 0);
+    assert_eq!(cal_local.lock().await.get_items().await.unwrap().len(), 70);
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_calendar_metadata_update() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/renamed/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/renamed_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let cal_remote = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Old name".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/renamed_local/")),
+    );
+    assert!(provider.sync().await);
+    let cal_local = provider.local().get_calendar(&cal_url).await.unwrap();
+    assert_eq!(cal_local.lock().await.name(), "Old name");
+
+    let color = csscolorparser::parse("#ff8000").unwrap();
+    cal_remote.lock().await.set_metadata(
+        "New name".to_string(),
+        SupportedComponents::TODO | SupportedComponents::EVENT,
+        Some(color.clone()),
+    );
+    assert!(provider.sync().await);
+    let cal_local = cal_local.lock().await;
+    assert_eq!(cal_local.name(), "New name");
+    assert_eq!(cal_local.color(), Some(&color));
+    assert_eq!(
+        cal_local.supported_components(),
+        SupportedComponents::TODO | SupportedComponents::EVENT
+    );
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_remove_calendar_local_only() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/unsubscribed/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/unsubscribed_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    remote
+        .create_calendar(
+            cal_url.clone(),
+            "Huge calendar".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/unsubscribed_local/")),
+    );
+    assert!(provider.sync().await);
+    assert!(provider.local().get_calendar(&cal_url).await.is_some());
+
+    let removed = provider.remove_calendar_local_only(&cal_url).await.unwrap();
+    assert!(removed.is_some());
+    assert!(provider.sync().await);
+    assert!(provider.local().get_calendar(&cal_url).await.is_none());
+    assert!(provider.remote().get_calendar(&cal_url).await.is_some());
+    assert!(provider.local().ignored_calendars().contains(&cal_url));
+
+    assert!(provider.stop_ignoring_calendar(&cal_url));
+    assert!(provider.sync().await);
+    assert!(provider.local().get_calendar(&cal_url).await.is_some());
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_item_url_policy_detection() {
+    use kitchen_fridge::calendar::item_url_policy::ItemUrlPolicy;
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/layout/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/layout_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Layout".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let server_policy = ItemUrlPolicy::flat()
+        .with_uid_as_file_name()
+        .with_ics_extension();
+    for i in 0..3 {
+        let task =
+            Task::new_with_url_policy(format!("Task {}", i), false, &cal_url, &server_policy)
+                .unwrap();
+        cal.lock().await.add_item(Item::Task(task)).await.unwrap();
+    }
+
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/layout_local/")),
+    );
+    assert!(provider.sync().await);
+    let cal_local = provider.local().get_calendar(&cal_url).await.unwrap();
+    let cal_local = cal_local.lock().await;
+    assert_eq!(cal_local.item_url_policy(), Some(&server_policy));
+
+    let policy = cal_local.item_url_policy().unwrap();
+    let task =
+        Task::new_with_url_policy("New".to_string(), false, cal_local.url(), policy).unwrap();
+    assert_eq!(
+        task.url().as_str(),
+        format!("{}{}.ics", cal_url, task.uid())
+    );
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_finished_summary() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::provider::sync_progress::{feedback_channel, SyncEvent};
+    use kitchen_fridge::traits::BaseCalendar;
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/summary/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/summary_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Summary".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    for i in 0..2 {
+        let task = Task::new(format!("Task {}", i), false, &cal_url).unwrap();
+        cal.lock().await.add_item(Item::Task(task)).await.unwrap();
+    }
+
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/summary_local/")),
+    );
+    let (sender, receiver) = feedback_channel();
+    assert!(provider.sync_with_feedback(sender).await);
+    let summary = match &*receiver.borrow() {
+        SyncEvent::Finished { success, summary } => {
+            assert!(success);
+            summary.clone()
+        }
+        other => panic!("Unexpected last event {:?}", other),
+    };
+    assert_eq!(summary.remote_additions, 2);
+    assert_eq!(summary.local_additions, 0);
+    assert!(summary.conflicts.is_empty());
+
+    let cal_local = provider.local().get_calendar(&cal_url).await.unwrap();
+    let task = Task::new("Local task".to_string(), false, &cal_url).unwrap();
+    cal_local
+        .lock()
+        .await
+        .add_item(Item::Task(task))
+        .await
+        .unwrap();
+    let result = provider.sync_with_result(None).await;
+    assert!(result.is_success());
+    assert_eq!(result.summary().local_additions, 1);
+    assert_eq!(result.summary().remote_additions, 0);
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_retry_skipped() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::provider::sync_progress::Skipped;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let failing_url: url::Url = "https://some.calend.ar/failing/".parse().unwrap();
+    let other_url: url::Url = "https://some.calend.ar/other/".parse().unwrap();
+    let mock = Arc::new(Mutex::new(MockBehaviour::new()));
+    let mut remote = Cache::new(&PathBuf::from("test_cache/retry_remote/"));
+    remote.set_mock_behaviour(Some(mock.clone()));
+    for url in [&failing_url, &other_url] {
+        remote
+            .create_calendar(
+                url.clone(),
+                url.path().to_string(),
+                SupportedComponents::TODO,
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/retry_local/")),
+    );
+    assert!(provider.sync().await);
+
+    let task = Task::new("Upload me".to_string(), false, &failing_url).unwrap();
+    let task_url = task.url().clone();
+    let local_cal = provider.local().get_calendar(&failing_url).await.unwrap();
+    local_cal
+        .lock()
+        .await
+        .add_item(Item::Task(task))
+        .await
+        .unwrap();
+
+    // The upload fails once
+    mock.lock().await.add_item_behaviour = (0, 1);
+    let result = provider.sync_with_result(None).await;
+    assert!(!result.is_success());
+    assert!(matches!(
+        result.skipped(),
+        [Skipped::Items { calendar, urls, .. }] if calendar == &failing_url && urls == &vec![task_url.clone()]
+    ));
+    assert_eq!(provider.skipped(), result.skipped());
+    // The failure is recorded on the item itself
+    let state = local_cal.lock().await.item_sync_state(&task_url).await;
+    assert_eq!(state.failures, 1);
+    assert!(state.last_error.unwrap().contains("unable to add it"));
+    assert_eq!(
+        local_cal.lock().await.failing_items().await,
+        vec![task_url.clone()]
+    );
+
+    // Calendars that have not been skipped are not synced by a retry
+    let other_task = Task::new("Not yet".to_string(), false, &other_url).unwrap();
+    let other_task_url = other_task.url().clone();
+    let remote_other = provider.remote().get_calendar(&other_url).await.unwrap();
+    remote_other
+        .lock()
+        .await
+        .add_item(Item::Task(other_task))
+        .await
+        .unwrap();
+
+    let result = provider.retry_skipped().await;
+    assert!(result.is_success());
+    assert!(result.skipped().is_empty());
+    assert!(provider.skipped().is_empty());
+    assert!(!local_cal
+        .lock()
+        .await
+        .item_sync_state(&task_url)
+        .await
+        .is_failing());
+    let remote_cal = provider.remote().get_calendar(&failing_url).await.unwrap();
+    assert!(remote_cal
+        .lock()
+        .await
+        .get_item_by_url(&task_url)
+        .await
+        .is_some());
+    let local_other = provider.local().get_calendar(&other_url).await.unwrap();
+    assert!(local_other
+        .lock()
+        .await
+        .get_item_by_url(&other_task_url)
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_counterpart_calendars_get_properties() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::utils::prop::Property;
+    use kitchen_fridge::utils::sync::{SyncStatus, Syncable};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let remote_url: url::Url = "https://some.calend.ar/from-server/".parse().unwrap();
+    let local_url: url::Url = "https://some.calend.ar/from-client/".parse().unwrap();
+    let prop = |value: &str| Property::new("urn:test", "description", value.to_string());
+
+    let mut remote = Cache::new(&PathBuf::from("test_cache/counterpart_props_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let cal = remote
+        .create_calendar(
+            remote_url.clone(),
+            "From server".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    cal.lock()
+        .await
+        .set_property(prop("server side"))
+        .await
+        .unwrap();
+
+    let mut local = Cache::new(&PathBuf::from("test_cache/counterpart_props_local/"));
+    let cal = local
+        .create_calendar(
+            local_url.clone(),
+            "From client".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    cal.lock()
+        .await
+        .add_property(prop("client side"))
+        .await
+        .unwrap();
+
+    let mut provider = Provider::new(remote, local);
+    let result = provider.sync_with_result(None).await;
+    assert!(result.is_success());
+    assert!(result.issues().is_empty());
+
+    for (url, value) in [(&remote_url, "server side"), (&local_url, "client side")] {
+        let local_cal = provider.local().get_calendar(url).await.unwrap();
+        let local_cal = local_cal.lock().await;
+        let local_prop = local_cal
+            .get_property_by_name(prop(value).nsn())
+            .await
+            .unwrap();
+        assert_eq!(local_prop.value(), value);
+        assert!(matches!(local_prop.sync_status(), SyncStatus::Synced(_)));
+
+        let remote_cal = provider.remote().get_calendar(url).await.unwrap();
+        let remote_cal = remote_cal.lock().await;
+        let remote_prop = remote_cal
+            .get_property_by_name(prop(value).nsn())
+            .await
+            .unwrap();
+        assert_eq!(remote_prop.value(), value);
+    }
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_calendar_deleted_during_sync() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::provider::RemoteCalendarDeletionPolicy;
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/vanishing/".parse().unwrap();
+    let mock = Arc::new(Mutex::new(MockBehaviour::new()));
+    let mut remote = Cache::new(&PathBuf::from("test_cache/vanishing_remote/"));
+    remote.set_mock_behaviour(Some(mock.clone()));
+    remote
+        .create_calendar(
+            cal_url.clone(),
+            "Vanishing".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/vanishing_local/")),
+    );
+    assert!(provider.sync().await);
+    provider.local().save_to_folder().await.unwrap();
+
+    // The server still lists the calendar, but it is gone by the time its items are listed
+    mock.lock().await.vanished_calendars.push(cal_url.clone());
+    provider.set_remote_calendar_deletion_policy(RemoteCalendarDeletionPolicy::Recreate);
+    let result = provider.sync_with_result(None).await;
+    assert!(!result.is_success());
+    assert_eq!(result.skipped().len(), 1);
+    assert!(provider.local().get_calendar(&cal_url).await.is_some());
+
+    provider.set_remote_calendar_deletion_policy(RemoteCalendarDeletionPolicy::DeleteLocally);
+    let result = provider.sync_with_result(None).await;
+    assert!(result.is_success());
+    assert!(result.skipped().is_empty());
+    assert!(provider.local().get_calendar(&cal_url).await.is_none());
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_dyn_provider() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::provider::dynamic::DynProvider;
+    use std::path::PathBuf;
+
+    /// The backends are picked at runtime, the application only holds a `DynProvider`
+    struct App {
+        provider: DynProvider,
+    }
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/dyn/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/dyn_provider_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    remote
+        .create_calendar(
+            cal_url.clone(),
+            "Dyn".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let local = Cache::new(&PathBuf::from("test_cache/dyn_provider_local/"));
+
+    let mut app = App {
+        provider: Provider::new(remote, local).into_dyn(),
+    };
+    assert!(app.provider.sync().await);
+    assert!(app.provider.skipped().is_empty());
+
+    let cals = app.provider.local_calendars().await.unwrap();
+    assert_eq!(cals.len(), 1);
+    assert_eq!(cals[&cal_url].lock().await.name(), "Dyn");
+    assert_eq!(app.provider.remote_calendars().await.unwrap().len(), 1);
+
+    // The sources can still be reached through their actual types
+    assert!(app.provider.local::<Cache>().is_some());
+    assert!(app.provider.remote::<kitchen_fridge::Client>().is_none());
+    app.provider
+        .local::<Cache>()
+        .unwrap()
+        .save_to_folder()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_rate_limited_downloads() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::Item;
+    use kitchen_fridge::task::Task;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/rate-limited/".parse().unwrap();
+    let mock = Arc::new(Mutex::new(MockBehaviour::new()));
+    let mut remote = Cache::new(&PathBuf::from("test_cache/rate_limited_remote/"));
+    remote.set_mock_behaviour(Some(mock.clone()));
+    let cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Rate-limited".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    for i in 0..7 {
+        let task = Task::new(format!("Task {}", i), false, &cal_url).unwrap();
+        cal.lock().await.add_item(Item::Task(task)).await.unwrap();
+    }
+
+    // The server rejects the first two batches, and tells when to come back
+    {
+        let mut mock = mock.lock().await;
+        mock.rate_limited_batches = 2;
+        mock.rate_limit_retry_after = Some(Duration::from_millis(10));
+    }
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/rate_limited_local/")),
+    );
+    let result = provider.sync_with_result(None).await;
+    assert!(result.is_success());
+    assert!(result.skipped().is_empty());
+    assert_eq!(result.metrics().rate_limit_wait, Duration::from_millis(20));
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+    assert_eq!(local_cal.lock().await.get_items().await.unwrap().len(), 7);
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_push_and_pull() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::Item;
+    use kitchen_fridge::task::Task;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::utils::sync::SyncStatus;
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/one-way/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/one_way_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    remote
+        .create_calendar(
+            cal_url.clone(),
+            "One way".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/one_way_local/")),
+    );
+    assert!(provider.sync().await);
+
+    let local_task = Task::new("Local".to_string(), false, &cal_url).unwrap();
+    let local_url = local_task.url().clone();
+    let remote_task = Task::new("Remote".to_string(), false, &cal_url).unwrap();
+    let remote_url = remote_task.url().clone();
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+    let remote_cal = provider.remote().get_calendar(&cal_url).await.unwrap();
+    local_cal
+        .lock()
+        .await
+        .add_item(Item::Task(local_task))
+        .await
+        .unwrap();
+    remote_cal
+        .lock()
+        .await
+        .add_item(Item::Task(remote_task))
+        .await
+        .unwrap();
+
+    // Pushing uploads the local task, but does not download the remote one
+    let result = provider.push().await;
+    assert!(result.is_success());
+    assert_eq!(result.summary().local_additions, 1);
+    assert_eq!(result.summary().remote_additions, 0);
+    assert_eq!(remote_cal.lock().await.get_items().await.unwrap().len(), 2);
+    {
+        let local_cal = local_cal.lock().await;
+        assert!(local_cal.get_item_by_url(&remote_url).await.is_none());
+        let pushed = local_cal.get_item_by_url(&local_url).await.unwrap();
+        assert!(matches!(pushed.sync_status(), SyncStatus::Synced(_)));
+    }
+
+    // Pulling downloads it, without pushing a local deletion
+    local_cal
+        .lock()
+        .await
+        .mark_item_for_deletion(&local_url)
+        .await
+        .unwrap();
+    let result = provider.pull().await;
+    assert!(result.is_success());
+    assert_eq!(result.summary().remote_additions, 1);
+    assert_eq!(result.summary().local_deletions, 0);
+    assert_eq!(remote_cal.lock().await.get_items().await.unwrap().len(), 2);
+    assert!(local_cal
+        .lock()
+        .await
+        .get_item_by_url(&remote_url)
+        .await
+        .is_some());
+
+    // A full sync finally pushes the deletion
+    assert!(provider.sync().await);
+    assert_eq!(remote_cal.lock().await.get_items().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_item_hooks() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::Item;
+    use kitchen_fridge::provider::hooks::ItemHooks;
+    use kitchen_fridge::task::Task;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::utils::sync::SyncStatus;
+    use std::path::PathBuf;
+
+    /// Private notes stay on this device, downloaded tasks are tagged
+    struct Hooks;
+    #[async_trait::async_trait]
+    impl ItemHooks for Hooks {
+        async fn before_upload(&self, item: &mut Item) {
+            if let Item::Task(task) = item {
+                task.set_x_property("X-PRIVATE-NOTE", None).unwrap();
+            }
+        }
+        async fn after_download(&self, item: &mut Item) {
+            if let Item::Task(task) = item {
+                task.set_x_property("X-DOWNLOADED", Some("1")).unwrap();
+            }
+        }
+    }
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/hooks/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/hooks_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let remote_cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Hooks".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let remote_task = Task::new("Remote".to_string(), false, &cal_url).unwrap();
+    let remote_url = remote_task.url().clone();
+    remote_cal
+        .lock()
+        .await
+        .add_item(Item::Task(remote_task))
+        .await
+        .unwrap();
+
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/hooks_local/")),
+    );
+    provider.set_item_hooks(Some(Arc::new(Hooks)));
+    assert!(provider.sync().await);
+
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+    let mut local_task = Task::new("Local".to_string(), false, &cal_url).unwrap();
+    local_task
+        .set_x_property("X-PRIVATE-NOTE", Some("secret"))
+        .unwrap();
+    let local_url = local_task.url().clone();
+    local_cal
+        .lock()
+        .await
+        .add_item(Item::Task(local_task))
+        .await
+        .unwrap();
+    assert!(provider.sync().await);
+
+    let local_cal = local_cal.lock().await;
+    let downloaded = local_cal.get_item_by_url(&remote_url).await.unwrap();
+    assert_eq!(
+        downloaded
+            .unwrap_task()
+            .get_x_property("X-DOWNLOADED")
+            .as_deref(),
+        Some("1")
+    );
+    assert!(matches!(downloaded.sync_status(), SyncStatus::Synced(_)));
+    let uploaded = local_cal.get_item_by_url(&local_url).await.unwrap();
+    assert_eq!(
+        uploaded
+            .unwrap_task()
+            .get_x_property("X-PRIVATE-NOTE")
+            .as_deref(),
+        Some("secret")
+    );
+    assert!(matches!(uploaded.sync_status(), SyncStatus::Synced(_)));
+    let on_server = remote_cal.lock().await;
+    let on_server = on_server.get_item_by_url(&local_url).await.unwrap();
+    assert_eq!(
+        on_server.unwrap_task().get_x_property("X-PRIVATE-NOTE"),
+        None
+    );
+}
+
 #[cfg(feature = "integration_tests")]
 use kitchen_fridge::{
     cache::Cache, calendar::cached_calendar::CachedCalendar, provider::Provider,
@@ -452,3 +1627,884 @@ async fn print_provider(
     println!("-----Local, {}-------", title);
     kitchen_fridge::utils::print_calendar_list(&cals_local).await;
 }
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_validation_policy() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::Item;
+    use kitchen_fridge::provider::ValidationPolicy;
+    use kitchen_fridge::task::Task;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::utils::sync::SyncStatus;
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/validation/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/validation_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let remote_cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Validation".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/validation_local/")),
+    );
+    assert!(provider.sync().await);
+
+    // A line break cannot be written as it is in an iCal file
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+    let task = Task::new("Two\nlines".to_string(), false, &cal_url).unwrap();
+    let url = task.url().clone();
+    local_cal
+        .lock()
+        .await
+        .add_item(Item::Task(task))
+        .await
+        .unwrap();
+
+    provider.set_validation_policy(ValidationPolicy::Reject);
+    assert!(!provider.sync().await);
+    assert_eq!(provider.skipped().len(), 1);
+    assert!(remote_cal
+        .lock()
+        .await
+        .get_item_by_url(&url)
+        .await
+        .is_none());
+
+    provider.set_validation_policy(ValidationPolicy::FixUp);
+    assert!(provider.sync().await);
+    assert!(provider.skipped().is_empty());
+    let on_server = remote_cal.lock().await;
+    let on_server = on_server.get_item_by_url(&url).await.unwrap();
+    assert_eq!(on_server.name(), "Two lines");
+    let local_cal = local_cal.lock().await;
+    let local = local_cal.get_item_by_url(&url).await.unwrap();
+    assert_eq!(local.name(), "Two\nlines");
+    assert!(matches!(local.sync_status(), SyncStatus::Synced(_)));
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_unsupported_component() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::{Item, ItemType};
+    use kitchen_fridge::provider::sync_progress::SyncIssue;
+    use kitchen_fridge::task::Task;
+    use kitchen_fridge::traits::BaseCalendar;
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/events-only/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/unsupported_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    remote
+        .create_calendar(
+            cal_url.clone(),
+            "Events".to_string(),
+            SupportedComponents::EVENT,
+            None,
+        )
+        .await
+        .unwrap();
+    // The local calendar wrongly accepts tasks
+    let mut local = Cache::new(&PathBuf::from("test_cache/unsupported_local/"));
+    let local_cal = local
+        .create_calendar(
+            cal_url.clone(),
+            "Events".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let task = Task::new("A task".to_string(), false, &cal_url).unwrap();
+    let task_url = task.url().clone();
+    local_cal
+        .lock()
+        .await
+        .add_item(Item::Task(task))
+        .await
+        .unwrap();
+
+    let mut provider = Provider::new(remote, local);
+    let result = provider.sync_with_result(None).await;
+    assert!(!result.is_success());
+    assert_eq!(
+        result.issues(),
+        &[SyncIssue::UnsupportedComponent {
+            calendar: cal_url,
+            url: task_url,
+            type_: ItemType::Task,
+        }]
+    );
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_items_outside_of_their_calendar() {
+    use chrono::Utc;
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::Item;
+    use kitchen_fridge::task::{CompletionStatus, Task};
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::utils::sync::SyncStatus;
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/elsewhere/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/elsewhere_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let remote_cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Elsewhere".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    // Some servers store items on other hosts, or in other collections
+    let item_urls: Vec<url::Url> = vec![
+        "https://cdn.example.org/store/1.ics".parse().unwrap(),
+        "https://some.calend.ar/store/elsewhere/2.ics"
+            .parse()
+            .unwrap(),
+    ];
+    for (i, url) in item_urls.iter().enumerate() {
+        let task = Task::new_with_parameters(
+            format!("Task {}", i),
+            format!("elsewhere-{}", i),
+            url.clone(),
+            CompletionStatus::Uncompleted,
+            SyncStatus::NotSynced,
+            None,
+            Utc::now(),
+            "prod_id".to_string(),
+            Vec::new(),
+            Vec::new(),
+        );
+        remote_cal
+            .lock()
+            .await
+            .add_item(Item::Task(task))
+            .await
+            .unwrap();
+    }
+
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/elsewhere_local/")),
+    );
+    assert!(provider.sync().await);
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+    assert_eq!(local_cal.lock().await.get_items().await.unwrap().len(), 2);
+    for url in &item_urls {
+        let local_cal = local_cal.lock().await;
+        let item = local_cal.get_item_by_url(url).await.unwrap();
+        assert_eq!(item.url(), url);
+        assert!(matches!(item.sync_status(), SyncStatus::Synced(_)));
+    }
+    assert!(provider.local().check_integrity().await.is_ok());
+
+    // Local changes are pushed to the items where they are
+    local_cal
+        .lock()
+        .await
+        .get_item_by_url_mut(&item_urls[0])
+        .await
+        .unwrap()
+        .unwrap_task_mut()
+        .set_name("Renamed".to_string());
+    assert!(provider.sync().await);
+    let remote_cal = remote_cal.lock().await;
+    assert_eq!(remote_cal.get_items().await.unwrap().len(), 2);
+    assert_eq!(
+        remote_cal
+            .get_item_by_url(&item_urls[0])
+            .await
+            .unwrap()
+            .unwrap_task()
+            .name(),
+        "Renamed"
+    );
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_destructive_change_guard() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::Item;
+    use kitchen_fridge::provider::hooks::{Decision, PlannedChange};
+    use kitchen_fridge::task::Task;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/guarded/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/guard_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let remote_cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Guarded".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let mut urls = Vec::new();
+    for i in 0..3 {
+        let task = Task::new(format!("Task {}", i), false, &cal_url).unwrap();
+        urls.push(task.url().clone());
+        remote_cal
+            .lock()
+            .await
+            .add_item(Item::Task(task))
+            .await
+            .unwrap();
+    }
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/guard_local/")),
+    );
+    assert!(provider.sync().await);
+
+    let decision = Arc::new(std::sync::Mutex::new(Decision::Skip));
+    let planned = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (d, p) = (decision.clone(), planned.clone());
+    provider.set_destructive_change_guard(Some(Arc::new(move |change: &PlannedChange| {
+        p.lock().unwrap().push(change.clone());
+        *d.lock().unwrap()
+    })));
+
+    // Two items are deleted from the server
+    let mut deleted = urls[1..].to_vec();
+    deleted.sort();
+    for url in &deleted {
+        remote_cal
+            .lock()
+            .await
+            .immediately_delete_item(url)
+            .await
+            .unwrap();
+    }
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+
+    // Skipping them is not a failure, and the next sync asks again
+    assert!(provider.sync().await);
+    assert_eq!(local_cal.lock().await.get_items().await.unwrap().len(), 3);
+    assert_eq!(
+        planned.lock().unwrap().as_slice(),
+        &[PlannedChange::DeleteLocalItems {
+            calendar: cal_url.clone(),
+            urls: deleted.clone(),
+        }]
+    );
+
+    *decision.lock().unwrap() = Decision::Abort;
+    assert!(!provider.sync().await);
+    assert_eq!(local_cal.lock().await.get_items().await.unwrap().len(), 3);
+    assert_eq!(planned.lock().unwrap().len(), 2);
+
+    *decision.lock().unwrap() = Decision::Allow;
+    assert!(provider.sync().await);
+    assert_eq!(local_cal.lock().await.get_items().await.unwrap().len(), 1);
+
+    // Whole calendars are guarded as well
+    *decision.lock().unwrap() = Decision::Skip;
+    local_cal.lock().await.mark_for_deletion().await;
+    assert!(provider.sync().await);
+    assert!(provider.remote().get_calendar(&cal_url).await.is_some());
+    assert_eq!(
+        planned.lock().unwrap().last(),
+        Some(&PlannedChange::DeleteRemoteCalendar {
+            url: cal_url.clone(),
+            name: "Guarded".to_string(),
+        })
+    );
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_reverted_local_changes() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::Item;
+    use kitchen_fridge::task::Task;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::utils::prop::Property;
+    use kitchen_fridge::utils::sync::{SyncStatus, Syncable};
+    use kitchen_fridge::utils::NamespacedName;
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/reverted/".parse().unwrap();
+    let behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+    let mut remote = Cache::new(&PathBuf::from("test_cache/reverted_remote/"));
+    remote.set_mock_behaviour(Some(behaviour.clone()));
+    let remote_cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Reverted".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let task = Task::new("Original".to_string(), false, &cal_url).unwrap();
+    let task_url = task.url().clone();
+    let nsn = NamespacedName::new("urn:example", "note");
+    {
+        let mut remote_cal = remote_cal.lock().await;
+        remote_cal.add_item(Item::Task(task)).await.unwrap();
+        remote_cal
+            .set_property(Property::new_from_nsn(nsn.clone(), "original"))
+            .await
+            .unwrap();
+    }
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/reverted_local/")),
+    );
+    assert!(provider.sync().await);
+    let sent_before = behaviour.lock().await.transfers.sent();
+
+    // Both are changed, then changed back
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+    {
+        let mut local_cal = local_cal.lock().await;
+        let task = local_cal
+            .get_item_by_url_mut(&task_url)
+            .await
+            .unwrap()
+            .unwrap_task_mut();
+        task.set_name("Renamed".to_string());
+        task.set_name("Original".to_string());
+        let prop = local_cal.get_property_by_name_mut(&nsn).await.unwrap();
+        prop.set_value("changed".to_string());
+        prop.set_value("original".to_string());
+        assert!(matches!(prop.sync_status(), SyncStatus::LocallyModified(_)));
+    }
+
+    let result = provider.sync_with_result(None).await;
+    assert!(result.is_success());
+    assert_eq!(result.summary().local_changes, 0);
+    assert_eq!(behaviour.lock().await.transfers.sent(), sent_before);
+    let local_cal = local_cal.lock().await;
+    let item = local_cal.get_item_by_url(&task_url).await.unwrap();
+    assert!(matches!(item.sync_status(), SyncStatus::Synced(_)));
+    let prop = local_cal.get_property_by_name(&nsn).await.unwrap();
+    assert!(matches!(prop.sync_status(), SyncStatus::Synced(_)));
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_strict_sync() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::provider::sync_progress::{Skipped, SyncIssue};
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/strict/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/strict_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let remote_cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Strict".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/strict_local/")),
+    );
+    assert!(provider.sync().await);
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+
+    // Unrelated items use the same URL in both sources
+    let reused = Task::new("Reused".to_string(), false, &cal_url).unwrap();
+    let reused_url = reused.url().clone();
+    remote_cal
+        .lock()
+        .await
+        .add_item(Item::Task(reused.clone()))
+        .await
+        .unwrap();
+    local_cal
+        .lock()
+        .await
+        .add_item(Item::Task(reused))
+        .await
+        .unwrap();
+    let new_task = Task::new("New".to_string(), false, &cal_url).unwrap();
+    let new_url = new_task.url().clone();
+    local_cal
+        .lock()
+        .await
+        .add_item(Item::Task(new_task))
+        .await
+        .unwrap();
+
+    // A strict sync does not touch the calendar
+    provider.set_strict(true);
+    let result = provider.sync_with_result(None).await;
+    assert!(!result.is_success());
+    assert!(!result.issues().is_empty());
+    assert!(result
+        .issues()
+        .iter()
+        .all(|issue| matches!(issue, SyncIssue::Inconsistency { url, .. } if url == &reused_url)));
+    assert!(!result.skipped().is_empty());
+    assert!(result.skipped().iter().all(|skipped| matches!(
+        skipped,
+        Skipped::Calendar { url, reason } if url == &cal_url && reason.contains("Strict")
+    )));
+    assert!(remote_cal
+        .lock()
+        .await
+        .get_item_by_url(&new_url)
+        .await
+        .is_none());
+
+    // Otherwise, the sync works around it
+    provider.set_strict(false);
+    let result = provider.sync_with_result(None).await;
+    assert!(!result.is_success());
+    assert!(!result.issues().is_empty());
+    assert!(result.skipped().is_empty());
+    assert!(remote_cal
+        .lock()
+        .await
+        .get_item_by_url(&new_url)
+        .await
+        .is_some());
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_remote_token() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar, DavCalendar};
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/token/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/token_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let remote_cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Token".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/token_local/")),
+    );
+    assert!(provider.sync().await);
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+    let token = remote_cal.lock().await.current_token().await.unwrap();
+    assert!(token.is_some());
+    assert_eq!(local_cal.lock().await.remote_token(), token.as_deref());
+
+    // Something changes on the server
+    let task = Task::new("New".to_string(), false, &cal_url).unwrap();
+    remote_cal
+        .lock()
+        .await
+        .add_item(Item::Task(task))
+        .await
+        .unwrap();
+    let new_token = remote_cal.lock().await.current_token().await.unwrap();
+    assert_ne!(new_token, token);
+    assert_ne!(local_cal.lock().await.remote_token(), new_token.as_deref());
+
+    assert!(provider.sync().await);
+    assert_eq!(local_cal.lock().await.remote_token(), new_token.as_deref());
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_sync_state_export() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::provider::export::SyncStateExport;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar, DavCalendar};
+    use kitchen_fridge::utils::sync::{SyncStatus, Syncable};
+    use kitchen_fridge::{Item, Task};
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/export/".parse().unwrap();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/export_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let remote_cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Export".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    for name in &["Unchanged", "Renamed"] {
+        let task = Task::new(name.to_string(), false, &cal_url).unwrap();
+        remote_cal
+            .lock()
+            .await
+            .add_item(Item::Task(task))
+            .await
+            .unwrap();
+    }
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/export_local/")),
+    );
+    assert!(provider.sync().await);
+    let state = provider.export_sync_state().await.unwrap();
+    let state: SyncStateExport =
+        serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+
+    // The items are copied to a new cache, that knows nothing about their sync state
+    let old_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+    let mut migrated = Cache::new(&PathBuf::from("test_cache/export_migrated/"));
+    let new_cal = migrated
+        .create_calendar(
+            cal_url.clone(),
+            "Export".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    for item in old_cal.lock().await.get_items().await.unwrap().values() {
+        let mut task = item.unwrap_task().clone();
+        task.set_sync_status(SyncStatus::NotSynced);
+        if task.name() == "Renamed" {
+            task.set_name("Renamed on the new machine".to_string());
+        }
+        new_cal
+            .lock()
+            .await
+            .add_item(Item::Task(task))
+            .await
+            .unwrap();
+    }
+    *provider.local_mut() = migrated;
+
+    assert_eq!(provider.import_sync_state(&state).await.unwrap(), 2);
+    let token = remote_cal.lock().await.current_token().await.unwrap();
+    assert_eq!(new_cal.lock().await.remote_token(), token.as_deref());
+    for item in new_cal.lock().await.get_items().await.unwrap().values() {
+        match item.name() {
+            "Unchanged" => assert!(matches!(item.sync_status(), SyncStatus::Synced(_))),
+            _ => assert!(matches!(item.sync_status(), SyncStatus::LocallyModified(_))),
+        }
+    }
+
+    // Nothing is uploaded twice
+    assert!(provider.sync().await);
+    let mut remote_names: Vec<String> = remote_cal
+        .lock()
+        .await
+        .get_items()
+        .await
+        .unwrap()
+        .values()
+        .map(|item| item.name().to_string())
+        .collect();
+    remote_names.sort();
+    assert_eq!(
+        remote_names,
+        vec![
+            "Renamed on the new machine".to_string(),
+            "Unchanged".to_string()
+        ]
+    );
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_sync_locked_cache() {
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/locked_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let local_path = PathBuf::from("test_cache/locked_local/");
+    let mut provider = Provider::new(remote, Cache::new(&local_path));
+
+    // Another process is syncing the same folder
+    let other = Cache::new(&local_path);
+    other.sync_started().unwrap();
+    let result = provider.sync_with_result(None).await;
+    assert!(!result.is_success());
+    assert!(result.is_locked());
+
+    other.sync_finished();
+    let result = provider.sync_with_result(None).await;
+    assert!(result.is_success());
+    assert!(!result.is_locked());
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_sync_longer_than_stale_lock_age() {
+    use kitchen_fridge::cache::lock::{LOCK_FILE, STALE_LOCK_AGE};
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::Item;
+    use kitchen_fridge::provider::hooks::ItemHooks;
+    use kitchen_fridge::task::Task;
+    use kitchen_fridge::traits::BaseCalendar;
+    use std::path::PathBuf;
+
+    /// Every download takes longer than the stale lock age. Before that, it checks whether another process could take over the local cache
+    struct SlowDownloads {
+        folder: PathBuf,
+        could_take_over: std::sync::Mutex<Vec<bool>>,
+    }
+    #[async_trait::async_trait]
+    impl ItemHooks for SlowDownloads {
+        async fn after_download(&self, _item: &mut Item) {
+            let other = Cache::new(&self.folder);
+            let taken_over = other.sync_started().is_ok();
+            self.could_take_over.lock().unwrap().push(taken_over);
+            if taken_over {
+                other.sync_finished();
+                return;
+            }
+
+            let lock_path = self.folder.join(LOCK_FILE);
+            let mut holder: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(&lock_path).unwrap()).unwrap();
+            let long_ago =
+                chrono::Utc::now() - chrono::Duration::from_std(STALE_LOCK_AGE * 2).unwrap();
+            holder["refreshed_at"] = serde_json::to_value(long_ago).unwrap();
+            std::fs::write(&lock_path, serde_json::to_vec(&holder).unwrap()).unwrap();
+        }
+    }
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let mut remote = Cache::new(&PathBuf::from("test_cache/long_sync_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    for name in &["first", "second"] {
+        let cal_url: url::Url = format!("https://some.calend.ar/{}/", name).parse().unwrap();
+        let cal = remote
+            .create_calendar(
+                cal_url.clone(),
+                name.to_string(),
+                SupportedComponents::TODO,
+                None,
+            )
+            .await
+            .unwrap();
+        let task = Task::new(name.to_string(), false, &cal_url).unwrap();
+        cal.lock().await.add_item(Item::Task(task)).await.unwrap();
+    }
+    let local_path = PathBuf::from("test_cache/long_sync_local/");
+    let _ = std::fs::remove_dir_all(&local_path);
+    let mut provider = Provider::new(remote, Cache::new(&local_path));
+    let hooks = Arc::new(SlowDownloads {
+        folder: local_path.clone(),
+        could_take_over: std::sync::Mutex::new(Vec::new()),
+    });
+    provider.set_item_hooks(Some(hooks.clone()));
+
+    // The lock is refreshed once the first calendar is synced, so that the second one is still synced under the lock
+    assert!(provider.sync().await);
+    assert_eq!(*hooks.could_take_over.lock().unwrap(), vec![false, false]);
+    assert!(!local_path.join(LOCK_FILE).exists());
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_item_modified_during_upload() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::Item;
+    use kitchen_fridge::provider::hooks::ItemHooks;
+    use kitchen_fridge::task::Task;
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::utils::sync::SyncStatus;
+    use std::path::PathBuf;
+    use tokio::sync::Notify;
+
+    /// Lets the application run right before an item is uploaded
+    struct Uploading(Arc<Notify>);
+    #[async_trait::async_trait]
+    impl ItemHooks for Uploading {
+        async fn before_upload(&self, _item: &mut Item) {
+            self.0.notify_one();
+            tokio::task::yield_now().await;
+        }
+    }
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    let cal_url: url::Url = "https://some.calend.ar/uploading/".parse().unwrap();
+    let behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+    let mut remote = Cache::new(&PathBuf::from("test_cache/uploading_remote/"));
+    remote.set_mock_behaviour(Some(Arc::clone(&behaviour)));
+    let remote_cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Uploading".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let mut provider = Provider::new(
+        remote,
+        Cache::new(&PathBuf::from("test_cache/uploading_local/")),
+    );
+    assert!(provider.sync().await);
+
+    let local_cal = provider.local().get_calendar(&cal_url).await.unwrap();
+    let task = Task::new("Original".to_string(), false, &cal_url).unwrap();
+    let url = task.url().clone();
+    local_cal
+        .lock()
+        .await
+        .add_item(Item::Task(task))
+        .await
+        .unwrap();
+
+    // The application renames the task while the server is receiving it: the server answers once the rename is done
+    let uploading = Arc::new(Notify::new());
+    provider.set_item_hooks(Some(Arc::new(Uploading(Arc::clone(&uploading)))));
+    let editor = {
+        let local_cal = Arc::clone(&local_cal);
+        let url = url.clone();
+        tokio::spawn(async move {
+            uploading.notified().await;
+            let _server = behaviour.lock().await;
+            tokio::time::timeout(std::time::Duration::from_secs(5), local_cal.lock())
+                .await
+                .expect("the calendar stays locked during the upload")
+                .get_item_by_url_mut(&url)
+                .await
+                .unwrap()
+                .unwrap_task_mut()
+                .set_name("Renamed during the upload".to_string());
+        })
+    };
+    assert!(provider.sync().await);
+    editor.await.unwrap();
+
+    let name_on_server =
+        |cal: &CachedCalendar| cal.get_item_by_url_sync(&url).unwrap().name().to_string();
+    assert_eq!(name_on_server(&*remote_cal.lock().await), "Original");
+    {
+        let local_cal = local_cal.lock().await;
+        let item = local_cal.get_item_by_url(&url).await.unwrap();
+        assert_eq!(item.name(), "Renamed during the upload");
+        assert!(matches!(item.sync_status(), SyncStatus::LocallyModified(_)));
+    }
+
+    // The next sync pushes the rename
+    provider.set_item_hooks(None);
+    assert!(provider.sync().await);
+    assert_eq!(
+        name_on_server(&*remote_cal.lock().await),
+        "Renamed during the upload"
+    );
+    let local_cal = local_cal.lock().await;
+    let item = local_cal.get_item_by_url(&url).await.unwrap();
+    assert!(matches!(item.sync_status(), SyncStatus::Synced(_)));
+}
+
+#[tokio::test]
+#[cfg(feature = "integration_tests")]
+async fn test_urls_stored_in_another_encoding() {
+    use kitchen_fridge::calendar::SupportedComponents;
+    use kitchen_fridge::item::Item;
+    use kitchen_fridge::task::{CompletionStatus, Task};
+    use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+    use kitchen_fridge::utils::sync::SyncStatus;
+    use std::path::PathBuf;
+
+    let _ = env_logger::builder().is_test(true).try_init();
+    // Hrefs were stored as the server sent them before they were canonicalized
+    let stored_cal_url: url::Url = "https://some.calend.ar/calendars/user%40example.com/caf%c3%a9/"
+        .parse()
+        .unwrap();
+    let stored_url = stored_cal_url.join("t%c3%a2che.ics").unwrap();
+    let cal_url: url::Url = "https://some.calend.ar/calendars/user%40example.com/caf%C3%A9/"
+        .parse()
+        .unwrap();
+    let url = cal_url.join("t%C3%A2che.ics").unwrap();
+    let task = |url: &url::Url, sync_status| {
+        Item::Task(Task::new_with_parameters(
+            "Task".to_string(),
+            "some-uid".to_string(),
+            url.clone(),
+            CompletionStatus::Uncompleted,
+            sync_status,
+            None,
+            chrono::Utc::now(),
+            "prod_id".to_string(),
+            Vec::new(),
+            Vec::new(),
+        ))
+    };
+
+    let mut remote = Cache::new(&PathBuf::from("test_cache/encoding_remote/"));
+    remote.set_mock_behaviour(Some(Arc::new(Mutex::new(MockBehaviour::new()))));
+    let remote_cal = remote
+        .create_calendar(
+            cal_url.clone(),
+            "Café".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    let tag = remote_cal
+        .lock()
+        .await
+        .add_item(task(&url, SyncStatus::NotSynced))
+        .await
+        .unwrap();
+
+    let mut local = Cache::new(&PathBuf::from("test_cache/encoding_local/"));
+    let local_cal = local
+        .create_calendar(
+            stored_cal_url.clone(),
+            "Café".to_string(),
+            SupportedComponents::TODO,
+            None,
+        )
+        .await
+        .unwrap();
+    local_cal
+        .lock()
+        .await
+        .add_item(task(&stored_url, tag))
+        .await
+        .unwrap();
+    local_cal.lock().await.mark_synced().await;
+
+    let mut provider = Provider::new(remote, local);
+    let result = provider.sync_with_result(None).await;
+    assert!(result.is_success());
+    assert_eq!(result.summary().remote_additions, 0);
+    assert_eq!(result.summary().remote_deletions, 0);
+    assert!(provider.local().get_calendar(&cal_url).await.is_none());
+    let local_cal = local_cal.lock().await;
+    assert!(local_cal.get_item_by_url(&stored_url).await.is_some());
+    assert_eq!(local_cal.get_items().await.unwrap().len(), 1);
+}